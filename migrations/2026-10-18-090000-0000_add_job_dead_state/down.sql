@@ -0,0 +1,2 @@
+DROP INDEX backie_tasks_dead_at_idx;
+ALTER TABLE backie_tasks DROP COLUMN dead_at;
@@ -0,0 +1,11 @@
+-- Dead-letter state for background jobs. A job that fails more than
+-- max_retries times is marked dead instead of being retried forever.
+--
+-- Dead jobs also get done_at set, so the worker's claim query
+-- (done_at IS NULL) skips them without needing to know about this column.
+-- Retrying a dead job from the admin API clears both timestamps.
+ALTER TABLE backie_tasks ADD COLUMN dead_at TIMESTAMP WITH TIME ZONE;
+
+-- Only dead rows are ever looked up by this column, and they should be a
+-- tiny fraction of the table.
+CREATE INDEX backie_tasks_dead_at_idx ON backie_tasks (dead_at) WHERE dead_at IS NOT NULL;
@@ -0,0 +1,3 @@
+DROP INDEX backie_tasks_task_hash_idx;
+DROP INDEX backie_tasks_pending_task_hash_idx;
+ALTER TABLE backie_tasks ADD CONSTRAINT backie_tasks_task_hash_key UNIQUE (task_hash);
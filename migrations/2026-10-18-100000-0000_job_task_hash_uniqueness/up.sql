@@ -0,0 +1,16 @@
+-- task_hash identifies "the same job": an md5 of the task name and payload.
+-- The original table made it globally UNIQUE, which meant a job could
+-- never be enqueued again once an identical one had *ever* existed.
+--
+-- Uniqueness now only applies to jobs that are waiting for their first
+-- run. Running, retrying, finished and dead jobs don't block a fresh
+-- enqueue. Job types that allow duplicates salt their hash (see
+-- jobs::Uniqueness), so this index never rejects them.
+ALTER TABLE backie_tasks DROP CONSTRAINT backie_tasks_task_hash_key;
+
+CREATE UNIQUE INDEX backie_tasks_pending_task_hash_idx ON backie_tasks (task_hash)
+    WHERE running_at IS NULL AND done_at IS NULL AND retries = 0;
+
+-- The unique constraint doubled as the lookup index; keep one for
+-- admin searches by hash.
+CREATE INDEX backie_tasks_task_hash_idx ON backie_tasks (task_hash);
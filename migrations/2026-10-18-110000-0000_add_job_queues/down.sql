@@ -0,0 +1,3 @@
+DROP INDEX backie_tasks_claim_idx;
+ALTER TABLE backie_tasks DROP COLUMN priority;
+ALTER TABLE backie_tasks DROP COLUMN queue_name;
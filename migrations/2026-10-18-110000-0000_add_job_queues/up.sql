@@ -0,0 +1,11 @@
+-- Named queues and priorities for background jobs. Each queue gets its own
+-- workers (configured at startup), so a burst of one kind of job can't
+-- starve the others. Within a queue, higher priority runs first and ties
+-- go to the job that has been waiting longest.
+ALTER TABLE backie_tasks ADD COLUMN queue_name TEXT NOT NULL DEFAULT 'default';
+ALTER TABLE backie_tasks ADD COLUMN priority INTEGER NOT NULL DEFAULT 0;
+
+-- Matches the claim query: runnable jobs in one queue, best first.
+CREATE INDEX backie_tasks_claim_idx
+    ON backie_tasks (queue_name, priority DESC, scheduled_at)
+    WHERE done_at IS NULL AND running_at IS NULL;
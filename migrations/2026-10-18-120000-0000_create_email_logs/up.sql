@@ -0,0 +1,34 @@
+-- One row per outgoing email: what was sent to whom, and what happened to
+-- it. Written by the mailer after each delivery attempt, and updated later
+-- if the message bounces. Kept for auditing and for answering "did this
+-- user ever get their reset email?" — nothing reads it in a hot path.
+CREATE TABLE email_logs (
+    id BIGSERIAL PRIMARY KEY,
+
+    -- NULL for mail not tied to an account, and once the user is deleted;
+    -- the address is kept so the log still reads sensibly.
+    user_id INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    to_address TEXT NOT NULL,
+
+    -- Template the message came from: 'welcome', 'password_reset', ...
+    email_type VARCHAR(50) NOT NULL,
+    subject TEXT NOT NULL,
+
+    -- Our Message-ID header, without the angle brackets. Bounce reports
+    -- quote it, which is how they're matched back to this row.
+    message_id TEXT NOT NULL UNIQUE,
+
+    -- 'sent': handed to the transport. 'failed': the transport refused it
+    -- (error says why). 'bounced': sent, then reported undeliverable.
+    status VARCHAR(20) NOT NULL,
+    error TEXT,
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    bounced_at TIMESTAMPTZ,
+
+    CONSTRAINT email_logs_status_check
+        CHECK (status IN ('sent', 'failed', 'bounced'))
+);
+
+CREATE INDEX idx_email_logs_user_id ON email_logs(user_id);
+CREATE INDEX idx_email_logs_created_at ON email_logs(created_at DESC);
@@ -0,0 +1,4 @@
+DELETE FROM site_settings WHERE key = 'unconfirmed_user_access';
+DROP TABLE email_tokens;
+ALTER TABLE users DROP COLUMN email_confirmed_at;
+ALTER TABLE users DROP COLUMN email_confirmed;
@@ -0,0 +1,40 @@
+-- Email confirmation. New accounts start unconfirmed; following the link
+-- in the confirmation email flips email_confirmed. What an unconfirmed
+-- account may do is a site setting (below).
+ALTER TABLE users ADD COLUMN email_confirmed BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE users ADD COLUMN email_confirmed_at TIMESTAMPTZ;
+
+-- Accounts that predate confirmation were never asked to confirm; treat
+-- them as confirmed rather than locking anyone out.
+UPDATE users SET email_confirmed = TRUE, email_confirmed_at = NOW();
+
+-- Single-use tokens sent by email: confirmation links now, password
+-- resets later. Only a SHA-256 of the token is stored, so a leaked table
+-- can't be used to confirm or reset anything.
+CREATE TABLE email_tokens (
+    id BIGSERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+
+    -- Hex SHA-256 of the token in the emailed link.
+    token_hash TEXT NOT NULL UNIQUE,
+
+    -- What the token is good for: 'confirm_email', ...
+    purpose VARCHAR(30) NOT NULL,
+
+    -- The address the token was sent to. A confirmation token only
+    -- confirms this address, so changing email invalidates old links.
+    email VARCHAR(254) NOT NULL,
+
+    expires_at TIMESTAMPTZ NOT NULL,
+    -- Set when the token is used; a used token never works again.
+    consumed_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Resend rate limiting counts a user's recent tokens by purpose.
+CREATE INDEX idx_email_tokens_user_purpose ON email_tokens(user_id, purpose, created_at);
+
+-- 'none': unconfirmed users can't log in. 'read': they can log in but not
+-- create topics or posts. 'full': confirmation is optional.
+INSERT INTO site_settings (key, value) VALUES ('unconfirmed_user_access', 'read')
+ON CONFLICT (key) DO NOTHING;
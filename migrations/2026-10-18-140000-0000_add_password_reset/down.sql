@@ -0,0 +1,3 @@
+DELETE FROM email_tokens WHERE purpose = 'password_reset';
+ALTER TABLE users DROP COLUMN password_changed_at;
+ALTER TABLE users DROP COLUMN token_version;
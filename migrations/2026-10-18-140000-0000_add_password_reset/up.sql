@@ -0,0 +1,9 @@
+-- Password reset and change. Reset links reuse email_tokens with purpose
+-- 'password_reset'; nothing new is needed there.
+--
+-- JWTs are stateless, so to log a user out everywhere we version them:
+-- every token carries the token_version it was minted under, and a token
+-- whose version no longer matches the user's is rejected. Changing or
+-- resetting the password bumps the version.
+ALTER TABLE users ADD COLUMN token_version INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE users ADD COLUMN password_changed_at TIMESTAMPTZ;
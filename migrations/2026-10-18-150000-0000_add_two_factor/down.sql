@@ -0,0 +1,5 @@
+DELETE FROM site_settings WHERE key = 'enforce_2fa_for_staff';
+DROP TABLE user_backup_codes;
+ALTER TABLE users DROP COLUMN totp_last_used_step;
+ALTER TABLE users DROP COLUMN totp_enabled;
+ALTER TABLE users DROP COLUMN totp_secret;
@@ -0,0 +1,30 @@
+-- Two-factor authentication with TOTP (RFC 6238) authenticator apps.
+--
+-- Enrollment stores a fresh secret with totp_enabled still false; the
+-- user proves their app has it by entering a code, which flips the flag.
+-- Until then login is unaffected.
+ALTER TABLE users ADD COLUMN totp_secret TEXT;
+ALTER TABLE users ADD COLUMN totp_enabled BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- The 30-second time step of the last code accepted. A code for that
+-- step or an earlier one is refused, so a code seen over someone's
+-- shoulder can't be replayed within its window.
+ALTER TABLE users ADD COLUMN totp_last_used_step BIGINT;
+
+-- One-time backup codes for when the authenticator is lost. Like email
+-- tokens, only a SHA-256 of each code is stored.
+CREATE TABLE user_backup_codes (
+    id BIGSERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    code_hash TEXT NOT NULL,
+    -- Set when the code is used; a used code never works again.
+    used_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_user_backup_codes_user ON user_backup_codes(user_id);
+
+-- 'true': admins and moderators without 2FA are refused on staff-only
+-- routes until they enroll.
+INSERT INTO site_settings (key, value) VALUES ('enforce_2fa_for_staff', 'false')
+ON CONFLICT (key) DO NOTHING;
@@ -0,0 +1,13 @@
+-- Which users each post @mentions, as resolved when the post was cooked.
+--
+-- Edits diff against these rows so only newly added mentions notify, and
+-- a username change can find exactly the posts that mention that user
+-- instead of pattern-matching raw text (which confuses @bob and @bobby).
+CREATE TABLE post_mentions (
+    post_id INTEGER NOT NULL REFERENCES posts(id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (post_id, user_id)
+);
+
+CREATE INDEX idx_post_mentions_user ON post_mentions(user_id);
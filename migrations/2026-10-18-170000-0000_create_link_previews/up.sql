@@ -0,0 +1,16 @@
+-- Preview metadata for links posted on a line of their own, scraped from
+-- the page's Open Graph tags (or its oEmbed endpoint) by a background job.
+--
+-- One row per URL, shared by every post that links it. Failed fetches are
+-- stored too, with `error` set, so a dead link isn't refetched on every
+-- edit; any row older than the refresh window is fetched again.
+CREATE TABLE link_previews (
+    id SERIAL PRIMARY KEY,
+    url TEXT NOT NULL UNIQUE,
+    title TEXT,
+    description TEXT,
+    image_url TEXT,
+    site_name TEXT,
+    error TEXT,
+    fetched_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,3 @@
+DELETE FROM site_settings WHERE key = 'censored_words';
+DROP INDEX idx_posts_cook_version;
+ALTER TABLE posts DROP COLUMN cook_version;
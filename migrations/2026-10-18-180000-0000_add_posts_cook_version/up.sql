@@ -0,0 +1,10 @@
+-- Which version of the markdown pipeline produced a post's `cooked` HTML.
+-- Existing posts start at 0 so the first rebake after deploy picks them
+-- all up; the rebake job re-cooks anything below the current version.
+ALTER TABLE posts ADD COLUMN cook_version INTEGER NOT NULL DEFAULT 0;
+
+CREATE INDEX idx_posts_cook_version ON posts (cook_version);
+
+-- `|`-separated words the cooker blacks out in rendered posts.
+INSERT INTO site_settings (key, value) VALUES ('censored_words', '')
+ON CONFLICT (key) DO NOTHING;
@@ -0,0 +1,13 @@
+-- Emoji added by admins, used as `:name:` alongside the built-in set.
+--
+-- `url` points at an image hosted elsewhere; posts embed it directly.
+-- Names share the built-in shortcode charset and can't shadow a built-in
+-- emoji (checked by the API, since the built-in list lives in code).
+CREATE TABLE custom_emojis (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR(50) NOT NULL UNIQUE,
+    url TEXT NOT NULL,
+    group_name VARCHAR(50) NOT NULL DEFAULT 'custom',
+    user_id INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,9 @@
+INSERT INTO site_settings (key, value)
+SELECT 'censored_words', coalesce(string_agg(word, '|' ORDER BY id), '')
+FROM watched_words
+WHERE action = 'censor' AND NOT regex
+ON CONFLICT (key) DO NOTHING;
+
+DROP TABLE topic_tags;
+DROP TABLE post_flags;
+DROP TABLE watched_words;
@@ -0,0 +1,57 @@
+-- Words admins watch for in posts and topic titles, and what happens when
+-- one turns up:
+--
+--   block             the post or title is rejected with an error
+--   censor            blacked out in the cooked HTML
+--   replace           swapped for `replacement` in the cooked HTML (and
+--                     in titles)
+--   require_approval  the post is hidden until a moderator looks at it
+--   flag              the post is flagged for moderators
+--   tag               the topic gets the tag named in `replacement`
+--
+-- Words match as whole words, ignoring case. With `regex` set, `word` is
+-- a regular expression instead, matched as written.
+CREATE TABLE watched_words (
+    id SERIAL PRIMARY KEY,
+    word VARCHAR(100) NOT NULL,
+    action VARCHAR(20) NOT NULL CHECK (action IN (
+        'block', 'censor', 'replace', 'require_approval', 'flag', 'tag'
+    )),
+    replacement VARCHAR(100),
+    regex BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (word, action)
+);
+
+-- Flags raised on posts. `user_id` is NULL when the system raised the
+-- flag, e.g. for a watched word.
+CREATE TABLE post_flags (
+    id SERIAL PRIMARY KEY,
+    post_id INTEGER NOT NULL REFERENCES posts(id) ON DELETE CASCADE,
+    user_id INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    reason VARCHAR(50) NOT NULL,
+    message TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_post_flags_post_id ON post_flags (post_id);
+
+-- Tags on topics, by name.
+CREATE TABLE topic_tags (
+    topic_id INTEGER NOT NULL REFERENCES topics(id) ON DELETE CASCADE,
+    name VARCHAR(100) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (topic_id, name)
+);
+
+CREATE INDEX idx_topic_tags_name ON topic_tags (name);
+
+-- The `censored_words` setting becomes censor rows.
+INSERT INTO watched_words (word, action)
+SELECT DISTINCT lower(trim(w)), 'censor'
+FROM site_settings, unnest(string_to_array(value, '|')) AS w
+WHERE key = 'censored_words' AND trim(w) <> ''
+ON CONFLICT DO NOTHING;
+
+DELETE FROM site_settings WHERE key = 'censored_words';
@@ -0,0 +1,3 @@
+DELETE FROM site_settings WHERE key IN ('approve_unless_trust_level', 'approve_post_categories');
+
+DROP TABLE queued_posts;
@@ -0,0 +1,34 @@
+-- Posts held for a moderator before they go live. `reason` says why:
+--
+--   trust_level   the author is below `approve_unless_trust_level`
+--   watched_word  the post matched a `require_approval` watched word
+--   category      the topic is in one of `approve_post_categories`
+--
+-- Approving creates the real post (`post_id`); rejecting just records
+-- who and why. Either way the row stays, as a record of the review.
+CREATE TABLE queued_posts (
+    id SERIAL PRIMARY KEY,
+    topic_id INTEGER NOT NULL REFERENCES topics(id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    raw TEXT NOT NULL,
+    reply_to_post_number INTEGER,
+    reason VARCHAR(20) NOT NULL CHECK (reason IN ('trust_level', 'watched_word', 'category')),
+    status VARCHAR(20) NOT NULL DEFAULT 'pending'
+        CHECK (status IN ('pending', 'approved', 'rejected')),
+    post_id INTEGER REFERENCES posts(id) ON DELETE SET NULL,
+    reviewed_by_user_id INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    reviewed_at TIMESTAMPTZ,
+    reject_reason TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_queued_posts_status ON queued_posts (status, created_at);
+
+-- Posts by non-staff users below this trust level are queued. 0 queues
+-- nobody.
+INSERT INTO site_settings (key, value) VALUES ('approve_unless_trust_level', '0')
+ON CONFLICT (key) DO NOTHING;
+
+-- `|`-separated category ids whose posts are always queued.
+INSERT INTO site_settings (key, value) VALUES ('approve_post_categories', '')
+ON CONFLICT (key) DO NOTHING;
@@ -0,0 +1,15 @@
+-- Users watching a topic get a `posted` notification for every new post
+-- in it. Topic creators watch their topics from the start.
+CREATE TABLE topic_watches (
+    topic_id INTEGER NOT NULL REFERENCES topics(id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (topic_id, user_id)
+);
+
+CREATE INDEX idx_topic_watches_user_id ON topic_watches (user_id);
+
+-- Existing topics are watched by their creators, as new ones will be.
+INSERT INTO topic_watches (topic_id, user_id)
+SELECT id, user_id FROM topics
+ON CONFLICT DO NOTHING;
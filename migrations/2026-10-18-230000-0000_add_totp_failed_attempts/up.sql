@@ -0,0 +1,5 @@
+-- Wrong second-factor codes at login since the last right one. Reaching
+-- the limit locks the second step until totp_locked_until, however many
+-- pending tokens or IP addresses the guesses come from.
+ALTER TABLE users ADD COLUMN totp_failed_attempts INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE users ADD COLUMN totp_locked_until TIMESTAMPTZ;
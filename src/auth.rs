@@ -6,11 +6,21 @@ use serde::{Deserialize, Serialize};
 const JWT_SECRET: &str = "your-secret-key-change-this-in-production";
 const JWT_EXPIRATION_HOURS: i64 = 24;
 
+/// How long the password step of a two-factor login stays valid.
+pub const PENDING_2FA_EXPIRATION_MINUTES: i64 = 5;
+
+const PENDING_2FA_PURPOSE: &str = "2fa_pending";
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Claims {
     pub user_id: i32,
     pub username: String,
     pub exp: i64,
+    /// The user's `token_version` when the token was minted. Extractors
+    /// reject a token whose version is behind the user's, which is how a
+    /// password change logs out every other session.
+    #[serde(default)]
+    pub token_version: i32,
 }
 
 pub fn hash_password(password: &str) -> Result<String, bcrypt::BcryptError> {
@@ -21,7 +31,11 @@ pub fn verify_password(password: &str, hash: &str) -> Result<bool, bcrypt::Bcryp
     verify(password, hash)
 }
 
-pub fn generate_token(user_id: i32, username: String) -> Result<String, jsonwebtoken::errors::Error> {
+pub fn generate_token(
+    user_id: i32,
+    username: String,
+    token_version: i32,
+) -> Result<String, jsonwebtoken::errors::Error> {
     let expiration = Utc::now()
         .checked_add_signed(Duration::hours(JWT_EXPIRATION_HOURS))
         .expect("valid timestamp")
@@ -31,6 +45,7 @@ pub fn generate_token(user_id: i32, username: String) -> Result<String, jsonwebt
         user_id,
         username,
         exp: expiration,
+        token_version,
     };
 
     encode(
@@ -49,3 +64,50 @@ pub fn verify_token(token: &str) -> Result<Claims, jsonwebtoken::errors::Error>
 
     Ok(token_data.claims)
 }
+
+/// Claims of the short-lived token issued when a password checks out but
+/// the account has 2FA: it only proves the first factor, and is traded
+/// for a session token at `POST /auth/login/2fa`. It has no `username`,
+/// so [`verify_token`] never accepts it as a session.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingTwoFactorClaims {
+    pub user_id: i32,
+    pub purpose: String,
+    pub exp: i64,
+    pub token_version: i32,
+}
+
+pub fn generate_pending_2fa_token(
+    user_id: i32,
+    token_version: i32,
+) -> Result<String, jsonwebtoken::errors::Error> {
+    let expiration = Utc::now()
+        .checked_add_signed(Duration::minutes(PENDING_2FA_EXPIRATION_MINUTES))
+        .expect("valid timestamp")
+        .timestamp();
+
+    let claims = PendingTwoFactorClaims {
+        user_id,
+        purpose: PENDING_2FA_PURPOSE.to_string(),
+        exp: expiration,
+        token_version,
+    };
+
+    encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(JWT_SECRET.as_ref()),
+    )
+}
+
+/// `None` for expired or tampered tokens, and for session tokens.
+pub fn verify_pending_2fa_token(token: &str) -> Option<PendingTwoFactorClaims> {
+    let claims = decode::<PendingTwoFactorClaims>(
+        token,
+        &DecodingKey::from_secret(JWT_SECRET.as_ref()),
+        &Validation::default(),
+    )
+    .ok()?
+    .claims;
+    (claims.purpose == PENDING_2FA_PURPOSE).then_some(claims)
+}
@@ -30,3 +30,14 @@ pub fn get_setting(pool: &DbPool, key: &str) -> Option<String> {
         .ok()
         .map(|setting: SiteSetting| setting.value)
 }
+
+/// The `site_name` setting, for emails and other places the forum names
+/// itself. Falls back to the migration's default if the row is missing.
+pub fn site_name(conn: &mut PgConnection) -> QueryResult<String> {
+    let name = site_settings::table
+        .find("site_name")
+        .select(site_settings::value)
+        .first::<String>(conn)
+        .optional()?;
+    Ok(name.unwrap_or_else(|| "Discourse RS".to_string()))
+}
@@ -0,0 +1,339 @@
+//! The built-in emoji set: `:shortcode:` names, the Unicode they render as,
+//! and the picker group each belongs to. Custom emoji uploaded by admins
+//! live in the `custom_emojis` table and are merged in by
+//! `services::emoji`.
+//!
+//! Names are lowercase ASCII letters, digits, `_`, `+` and `-`. A few
+//! emoji have aliases (`:+1:` and `:thumbsup:`); clients should store the
+//! primary name, e.g. as a reaction key.
+
+/// One built-in emoji.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BuiltinEmoji {
+    pub name: &'static str,
+    pub unicode: &'static str,
+    pub group: &'static str,
+    pub aliases: &'static [&'static str],
+}
+
+/// Picker groups, in display order. Custom emoji go last.
+pub const GROUPS: &[&str] = &[
+    "smileys",
+    "people",
+    "nature",
+    "food",
+    "activities",
+    "travel",
+    "objects",
+    "symbols",
+    "flags",
+    CUSTOM_GROUP,
+];
+
+/// The group custom emoji are listed under unless an admin picks another.
+pub const CUSTOM_GROUP: &str = "custom";
+
+const fn e(
+    name: &'static str,
+    unicode: &'static str,
+    group: &'static str,
+    aliases: &'static [&'static str],
+) -> BuiltinEmoji {
+    BuiltinEmoji {
+        name,
+        unicode,
+        group,
+        aliases,
+    }
+}
+
+/// Every built-in emoji, grouped and in picker order.
+pub const BUILTIN: &[BuiltinEmoji] = &[
+    // smileys
+    e("grinning", "😀", "smileys", &[]),
+    e("smiley", "😃", "smileys", &[]),
+    e("smile", "😄", "smileys", &[]),
+    e("grin", "😁", "smileys", &[]),
+    e("laughing", "😆", "smileys", &["satisfied"]),
+    e("sweat_smile", "😅", "smileys", &[]),
+    e("rofl", "🤣", "smileys", &[]),
+    e("joy", "😂", "smileys", &[]),
+    e("slightly_smiling_face", "🙂", "smileys", &[]),
+    e("upside_down_face", "🙃", "smileys", &[]),
+    e("wink", "😉", "smileys", &[]),
+    e("blush", "😊", "smileys", &[]),
+    e("innocent", "😇", "smileys", &[]),
+    e("heart_eyes", "😍", "smileys", &[]),
+    e("star_struck", "🤩", "smileys", &[]),
+    e("kissing_heart", "😘", "smileys", &[]),
+    e("yum", "😋", "smileys", &[]),
+    e("stuck_out_tongue", "😛", "smileys", &[]),
+    e("stuck_out_tongue_winking_eye", "😜", "smileys", &[]),
+    e("thinking", "🤔", "smileys", &[]),
+    e("zipper_mouth_face", "🤐", "smileys", &[]),
+    e("raised_eyebrow", "🤨", "smileys", &[]),
+    e("neutral_face", "😐", "smileys", &[]),
+    e("expressionless", "😑", "smileys", &[]),
+    e("no_mouth", "😶", "smileys", &[]),
+    e("smirk", "😏", "smileys", &[]),
+    e("unamused", "😒", "smileys", &[]),
+    e("roll_eyes", "🙄", "smileys", &[]),
+    e("grimacing", "😬", "smileys", &[]),
+    e("relieved", "😌", "smileys", &[]),
+    e("pensive", "😔", "smileys", &[]),
+    e("sleepy", "😪", "smileys", &[]),
+    e("sleeping", "😴", "smileys", &[]),
+    e("mask", "😷", "smileys", &[]),
+    e("nerd_face", "🤓", "smileys", &[]),
+    e("sunglasses", "😎", "smileys", &[]),
+    e("confused", "😕", "smileys", &[]),
+    e("worried", "😟", "smileys", &[]),
+    e("slightly_frowning_face", "🙁", "smileys", &[]),
+    e("open_mouth", "😮", "smileys", &[]),
+    e("astonished", "😲", "smileys", &[]),
+    e("flushed", "😳", "smileys", &[]),
+    e("fearful", "😨", "smileys", &[]),
+    e("cry", "😢", "smileys", &[]),
+    e("sob", "😭", "smileys", &[]),
+    e("scream", "😱", "smileys", &[]),
+    e("confounded", "😖", "smileys", &[]),
+    e("disappointed", "😞", "smileys", &[]),
+    e("sweat", "😓", "smileys", &[]),
+    e("weary", "😩", "smileys", &[]),
+    e("yawning_face", "🥱", "smileys", &[]),
+    e("triumph", "😤", "smileys", &[]),
+    e("rage", "😡", "smileys", &["pout"]),
+    e("angry", "😠", "smileys", &[]),
+    e("skull", "💀", "smileys", &[]),
+    e("poop", "💩", "smileys", &["hankey", "shit"]),
+    e("clown_face", "🤡", "smileys", &[]),
+    e("ghost", "👻", "smileys", &[]),
+    e("alien", "👽", "smileys", &[]),
+    e("robot", "🤖", "smileys", &[]),
+    e("smiley_cat", "😺", "smileys", &[]),
+    e("see_no_evil", "🙈", "smileys", &[]),
+    // people
+    e("wave", "👋", "people", &[]),
+    e("raised_hand", "✋", "people", &["hand"]),
+    e("ok_hand", "👌", "people", &[]),
+    e("v", "✌️", "people", &[]),
+    e("crossed_fingers", "🤞", "people", &[]),
+    e("metal", "🤘", "people", &[]),
+    e("point_up", "☝️", "people", &[]),
+    e("point_right", "👉", "people", &[]),
+    e("point_left", "👈", "people", &[]),
+    e("+1", "👍", "people", &["thumbsup"]),
+    e("-1", "👎", "people", &["thumbsdown"]),
+    e("fist", "✊", "people", &[]),
+    e("clap", "👏", "people", &[]),
+    e("raised_hands", "🙌", "people", &[]),
+    e("pray", "🙏", "people", &[]),
+    e("handshake", "🤝", "people", &[]),
+    e("muscle", "💪", "people", &[]),
+    e("eyes", "👀", "people", &[]),
+    e("brain", "🧠", "people", &[]),
+    e("man_shrugging", "🤷‍♂️", "people", &[]),
+    e("woman_shrugging", "🤷‍♀️", "people", &["shrug"]),
+    e("man_facepalming", "🤦‍♂️", "people", &[]),
+    e("woman_facepalming", "🤦‍♀️", "people", &["facepalm"]),
+    // nature
+    e("dog", "🐶", "nature", &[]),
+    e("cat", "🐱", "nature", &[]),
+    e("mouse", "🐭", "nature", &[]),
+    e("rabbit", "🐰", "nature", &[]),
+    e("fox_face", "🦊", "nature", &[]),
+    e("bear", "🐻", "nature", &[]),
+    e("panda_face", "🐼", "nature", &[]),
+    e("penguin", "🐧", "nature", &[]),
+    e("bird", "🐦", "nature", &[]),
+    e("turtle", "🐢", "nature", &[]),
+    e("snake", "🐍", "nature", &[]),
+    e("crab", "🦀", "nature", &[]),
+    e("bug", "🐛", "nature", &[]),
+    e("bee", "🐝", "nature", &["honeybee"]),
+    e("unicorn", "🦄", "nature", &[]),
+    e("seedling", "🌱", "nature", &[]),
+    e("evergreen_tree", "🌲", "nature", &[]),
+    e("cactus", "🌵", "nature", &[]),
+    e("four_leaf_clover", "🍀", "nature", &[]),
+    e("rose", "🌹", "nature", &[]),
+    e("sunflower", "🌻", "nature", &[]),
+    e("sunny", "☀️", "nature", &[]),
+    e("cloud", "☁️", "nature", &[]),
+    e("zap", "⚡", "nature", &[]),
+    e("snowflake", "❄️", "nature", &[]),
+    e("rainbow", "🌈", "nature", &[]),
+    e("crescent_moon", "🌙", "nature", &[]),
+    e("star", "⭐", "nature", &[]),
+    e("fire", "🔥", "nature", &["flame"]),
+    e("droplet", "💧", "nature", &[]),
+    e("ocean", "🌊", "nature", &[]),
+    // food
+    e("apple", "🍎", "food", &[]),
+    e("banana", "🍌", "food", &[]),
+    e("strawberry", "🍓", "food", &[]),
+    e("avocado", "🥑", "food", &[]),
+    e("hot_pepper", "🌶️", "food", &[]),
+    e("bread", "🍞", "food", &[]),
+    e("cheese", "🧀", "food", &[]),
+    e("hamburger", "🍔", "food", &[]),
+    e("pizza", "🍕", "food", &[]),
+    e("taco", "🌮", "food", &[]),
+    e("ramen", "🍜", "food", &[]),
+    e("sushi", "🍣", "food", &[]),
+    e("popcorn", "🍿", "food", &[]),
+    e("cookie", "🍪", "food", &[]),
+    e("cake", "🍰", "food", &[]),
+    e("birthday", "🎂", "food", &[]),
+    e("coffee", "☕", "food", &[]),
+    e("tea", "🍵", "food", &[]),
+    e("beer", "🍺", "food", &[]),
+    e("beers", "🍻", "food", &[]),
+    e("wine_glass", "🍷", "food", &[]),
+    // activities
+    e("soccer", "⚽", "activities", &[]),
+    e("basketball", "🏀", "activities", &[]),
+    e("football", "🏈", "activities", &[]),
+    e("tennis", "🎾", "activities", &[]),
+    e("trophy", "🏆", "activities", &[]),
+    e("medal_sports", "🏅", "activities", &[]),
+    e("video_game", "🎮", "activities", &[]),
+    e("game_die", "🎲", "activities", &[]),
+    e("dart", "🎯", "activities", &[]),
+    e("jigsaw", "🧩", "activities", &[]),
+    e("art", "🎨", "activities", &[]),
+    e("guitar", "🎸", "activities", &[]),
+    e("musical_note", "🎵", "activities", &[]),
+    e("tada", "🎉", "activities", &["hooray"]),
+    e("confetti_ball", "🎊", "activities", &[]),
+    e("balloon", "🎈", "activities", &[]),
+    e("gift", "🎁", "activities", &[]),
+    // travel
+    e("car", "🚗", "travel", &["red_car"]),
+    e("bus", "🚌", "travel", &[]),
+    e("bike", "🚲", "travel", &[]),
+    e("airplane", "✈️", "travel", &[]),
+    e("rocket", "🚀", "travel", &[]),
+    e("ship", "🚢", "travel", &[]),
+    e("construction", "🚧", "travel", &[]),
+    e("house", "🏠", "travel", &[]),
+    e("office", "🏢", "travel", &[]),
+    e("mountain", "⛰️", "travel", &[]),
+    e("beach_umbrella", "🏖️", "travel", &[]),
+    e("earth_americas", "🌎", "travel", &[]),
+    e("world_map", "🗺️", "travel", &[]),
+    // objects
+    e("watch", "⌚", "objects", &[]),
+    e("iphone", "📱", "objects", &[]),
+    e("computer", "💻", "objects", &[]),
+    e("keyboard", "⌨️", "objects", &[]),
+    e("camera", "📷", "objects", &[]),
+    e("tv", "📺", "objects", &[]),
+    e("bulb", "💡", "objects", &[]),
+    e("flashlight", "🔦", "objects", &[]),
+    e("books", "📚", "objects", &[]),
+    e("memo", "📝", "objects", &["pencil"]),
+    e("pencil2", "✏️", "objects", &[]),
+    e("paperclip", "📎", "objects", &[]),
+    e("pushpin", "📌", "objects", &[]),
+    e("calendar", "📆", "objects", &[]),
+    e("chart_with_upwards_trend", "📈", "objects", &[]),
+    e("envelope", "✉️", "objects", &["email"]),
+    e("package", "📦", "objects", &[]),
+    e("lock", "🔒", "objects", &[]),
+    e("unlock", "🔓", "objects", &[]),
+    e("key", "🔑", "objects", &[]),
+    e("hammer", "🔨", "objects", &[]),
+    e("wrench", "🔧", "objects", &[]),
+    e("gear", "⚙️", "objects", &[]),
+    e("link", "🔗", "objects", &[]),
+    e("mag", "🔍", "objects", &[]),
+    e("bell", "🔔", "objects", &[]),
+    e("moneybag", "💰", "objects", &[]),
+    e("hourglass", "⌛", "objects", &[]),
+    e("bomb", "💣", "objects", &[]),
+    // symbols
+    e("heart", "❤️", "symbols", &[]),
+    e("orange_heart", "🧡", "symbols", &[]),
+    e("yellow_heart", "💛", "symbols", &[]),
+    e("green_heart", "💚", "symbols", &[]),
+    e("blue_heart", "💙", "symbols", &[]),
+    e("purple_heart", "💜", "symbols", &[]),
+    e("black_heart", "🖤", "symbols", &[]),
+    e("broken_heart", "💔", "symbols", &[]),
+    e("sparkling_heart", "💖", "symbols", &[]),
+    e("100", "💯", "symbols", &[]),
+    e("sparkles", "✨", "symbols", &[]),
+    e("boom", "💥", "symbols", &["collision"]),
+    e("zzz", "💤", "symbols", &[]),
+    e("speech_balloon", "💬", "symbols", &[]),
+    e("white_check_mark", "✅", "symbols", &[]),
+    e("heavy_check_mark", "✔️", "symbols", &[]),
+    e("x", "❌", "symbols", &[]),
+    e("warning", "⚠️", "symbols", &[]),
+    e("no_entry", "⛔", "symbols", &[]),
+    e("question", "❓", "symbols", &[]),
+    e("exclamation", "❗", "symbols", &["heavy_exclamation_mark"]),
+    e("recycle", "♻️", "symbols", &[]),
+    e("arrow_up", "⬆️", "symbols", &[]),
+    e("arrow_down", "⬇️", "symbols", &[]),
+    e("arrow_right", "➡️", "symbols", &[]),
+    e("arrow_left", "⬅️", "symbols", &[]),
+    e("new", "🆕", "symbols", &[]),
+    e("red_circle", "🔴", "symbols", &[]),
+    e("green_circle", "🟢", "symbols", &[]),
+    // flags
+    e("checkered_flag", "🏁", "flags", &[]),
+    e("triangular_flag_on_post", "🚩", "flags", &[]),
+    e("white_flag", "🏳️", "flags", &[]),
+    e("rainbow_flag", "🏳️‍🌈", "flags", &[]),
+    e("pirate_flag", "🏴‍☠️", "flags", &[]),
+    e("us", "🇺🇸", "flags", &[]),
+    e("gb", "🇬🇧", "flags", &["uk"]),
+    e("ca", "🇨🇦", "flags", &[]),
+    e("de", "🇩🇪", "flags", &[]),
+    e("fr", "🇫🇷", "flags", &[]),
+    e("jp", "🇯🇵", "flags", &[]),
+    e("eu", "🇪🇺", "flags", &[]),
+];
+
+/// The built-in emoji called `name`, by primary name or alias.
+pub fn builtin(name: &str) -> Option<&'static BuiltinEmoji> {
+    BUILTIN
+        .iter()
+        .find(|e| e.name == name || e.aliases.contains(&name))
+}
+
+/// Whether `name` is usable as a shortcode: 1–50 characters of lowercase
+/// ASCII letters, digits, `_`, `+` and `-`.
+pub fn is_valid_name(name: &str) -> bool {
+    (1..=50).contains(&name.len())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '_' | '+' | '-'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_names_and_aliases_are_unique_and_valid() {
+        let mut seen = HashSet::new();
+        for emoji in BUILTIN {
+            assert!(GROUPS.contains(&emoji.group), "{}", emoji.name);
+            for name in std::iter::once(&emoji.name).chain(emoji.aliases) {
+                assert!(is_valid_name(name), "{name}");
+                assert!(seen.insert(*name), "duplicate {name}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_lookup_by_alias() {
+        assert_eq!(builtin("thumbsup").map(|e| e.name), Some("+1"));
+        assert_eq!(builtin("smile").map(|e| e.unicode), Some("😄"));
+        assert_eq!(builtin("Smile"), None);
+    }
+}
@@ -0,0 +1,465 @@
+//! The error type route handlers return.
+//!
+//! Handlers return `Result<HttpResponse, ApiError>` and use `?` on pool,
+//! blocking and Diesel errors and on the service error enums; the `From`
+//! impls below decide the status. Every error body has the same shape:
+//!
+//! ```json
+//! {"error": "Post not found", "code": "not_found"}
+//! ```
+//!
+//! `code` is one of [`ErrorCode`] and is stable, so clients branch on it;
+//! `error` is a human-readable message and may change. A few codes add
+//! fields (`fields` for validation errors, `retry_after` when rate limited).
+//!
+//! Internal errors are logged with their detail and answered with a
+//! generic message, so database errors never reach the client.
+
+use actix_web::error::BlockingError;
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use diesel::r2d2;
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use serde::Serialize;
+use serde_json::Value;
+use std::fmt;
+use utoipa::ToSchema;
+use validator::ValidationErrors;
+
+use crate::services::email_confirmation::ConfirmError;
+use crate::services::likes::LikeError;
+use crate::services::passwords::PasswordError;
+use crate::services::queued_posts::QueueError;
+use crate::services::reads::ReadError;
+use crate::services::two_factor::TwoFactorError;
+use crate::services::watched_words::ContentError;
+
+/// Stable machine-readable error codes, sent as `code` in every error body.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// 400: the request is malformed (bad JSON, bad token in a link).
+    BadRequest,
+    /// 401: no valid credentials.
+    Unauthorized,
+    /// 403: authenticated, but not allowed.
+    Forbidden,
+    /// 403: the account must confirm its email address first.
+    EmailNotConfirmed,
+    /// 403: staff must enable two-factor authentication first.
+    TwoFactorRequired,
+    /// 404: the resource doesn't exist (or the caller can't see it).
+    NotFound,
+    /// 409: would duplicate something unique, e.g. a taken username.
+    Conflict,
+    /// 422: the body failed validation; see `fields`.
+    ValidationFailed,
+    /// 422: an id in the body refers to a record that doesn't exist.
+    InvalidReference,
+    /// 422: well-formed, but breaks a rule (e.g. liking your own post).
+    Unprocessable,
+    /// 429: too many requests; see `retry_after`.
+    RateLimited,
+    /// 500: something went wrong on our side.
+    InternalError,
+    /// 503: the database is unreachable; retry later.
+    ServiceUnavailable,
+}
+
+/// The body of every error response.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorResponse {
+    /// Human-readable message.
+    pub error: String,
+    pub code: ErrorCode,
+    /// Per-field validation errors (`validation_failed` only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub fields: Option<Value>,
+    /// Seconds until the request may be retried (`rate_limited` only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_after: Option<i64>,
+    /// Registered job names (unknown job type only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub available: Option<Vec<String>>,
+}
+
+#[derive(Debug)]
+pub enum ApiError {
+    BadRequest(String),
+    Unauthorized(String),
+    Forbidden(String),
+    EmailNotConfirmed(String),
+    TwoFactorRequired(String),
+    /// Usually built with [`ApiError::not_found`].
+    NotFound(String),
+    /// 404 for `POST /admin/jobs/{name}`, listing the names that exist.
+    UnknownJob {
+        name: String,
+        available: Vec<&'static str>,
+    },
+    Conflict(String),
+    Validation(ValidationErrors),
+    InvalidReference(String),
+    Unprocessable(String),
+    RateLimited {
+        message: String,
+        retry_after_secs: i64,
+    },
+    /// Carries the detail for the log; the client gets a generic message.
+    Internal(String),
+    Unavailable,
+}
+
+impl ApiError {
+    /// `not_found("Post")` reads "Post not found".
+    pub fn not_found(what: &str) -> Self {
+        ApiError::NotFound(format!("{what} not found"))
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            ApiError::BadRequest(_) => ErrorCode::BadRequest,
+            ApiError::Unauthorized(_) => ErrorCode::Unauthorized,
+            ApiError::Forbidden(_) => ErrorCode::Forbidden,
+            ApiError::EmailNotConfirmed(_) => ErrorCode::EmailNotConfirmed,
+            ApiError::TwoFactorRequired(_) => ErrorCode::TwoFactorRequired,
+            ApiError::NotFound(_) | ApiError::UnknownJob { .. } => ErrorCode::NotFound,
+            ApiError::Conflict(_) => ErrorCode::Conflict,
+            ApiError::Validation(_) => ErrorCode::ValidationFailed,
+            ApiError::InvalidReference(_) => ErrorCode::InvalidReference,
+            ApiError::Unprocessable(_) => ErrorCode::Unprocessable,
+            ApiError::RateLimited { .. } => ErrorCode::RateLimited,
+            ApiError::Internal(_) => ErrorCode::InternalError,
+            ApiError::Unavailable => ErrorCode::ServiceUnavailable,
+        }
+    }
+
+    /// The message sent to the client.
+    fn message(&self) -> String {
+        match self {
+            ApiError::BadRequest(m)
+            | ApiError::Unauthorized(m)
+            | ApiError::Forbidden(m)
+            | ApiError::EmailNotConfirmed(m)
+            | ApiError::TwoFactorRequired(m)
+            | ApiError::NotFound(m)
+            | ApiError::Conflict(m)
+            | ApiError::InvalidReference(m)
+            | ApiError::Unprocessable(m)
+            | ApiError::RateLimited { message: m, .. } => m.clone(),
+            ApiError::UnknownJob { name, .. } => format!("Unknown job type: {name}"),
+            ApiError::Validation(_) => "Validation failed".to_string(),
+            ApiError::Internal(_) => "Internal server error".to_string(),
+            ApiError::Unavailable => "Service temporarily unavailable".to_string(),
+        }
+    }
+
+    pub fn body(&self) -> ErrorResponse {
+        ErrorResponse {
+            error: self.message(),
+            code: self.code(),
+            fields: match self {
+                ApiError::Validation(errors) => Some(crate::validation::field_errors(errors)),
+                _ => None,
+            },
+            retry_after: match self {
+                ApiError::RateLimited {
+                    retry_after_secs, ..
+                } => Some(*retry_after_secs),
+                _ => None,
+            },
+            available: match self {
+                ApiError::UnknownJob { available, .. } => {
+                    Some(available.iter().map(|n| n.to_string()).collect())
+                }
+                _ => None,
+            },
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::Internal(detail) => write!(f, "internal error: {detail}"),
+            other => write!(f, "{}", other.message()),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_)
+            | ApiError::EmailNotConfirmed(_)
+            | ApiError::TwoFactorRequired(_) => StatusCode::FORBIDDEN,
+            ApiError::NotFound(_) | ApiError::UnknownJob { .. } => StatusCode::NOT_FOUND,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::Validation(_)
+            | ApiError::InvalidReference(_)
+            | ApiError::Unprocessable(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ApiError::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        if let ApiError::Internal(detail) = self {
+            log::error!("{detail}");
+        }
+        let mut response = HttpResponse::build(self.status_code());
+        if let ApiError::RateLimited {
+            retry_after_secs, ..
+        } = self
+        {
+            response.insert_header(("Retry-After", retry_after_secs.to_string()));
+        }
+        response.json(self.body())
+    }
+}
+
+impl From<DieselError> for ApiError {
+    fn from(e: DieselError) -> Self {
+        match e {
+            DieselError::NotFound => ApiError::not_found("Record"),
+            DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, info) => {
+                ApiError::Conflict(unique_violation_message(info.constraint_name()).to_string())
+            }
+            DieselError::DatabaseError(DatabaseErrorKind::ForeignKeyViolation, info) => {
+                ApiError::InvalidReference(foreign_key_message(
+                    info.table_name(),
+                    info.constraint_name(),
+                ))
+            }
+            other => ApiError::Internal(format!("database error: {other}")),
+        }
+    }
+}
+
+/// Messages for the unique constraints a client can trip; the rest are
+/// races the handlers already guard against.
+fn unique_violation_message(constraint: Option<&str>) -> &'static str {
+    match constraint {
+        Some("users_username_key") => "Username is already taken",
+        Some("users_email_key") => "Email is already registered",
+        Some("categories_slug_key") => "Category slug is already in use",
+        Some("custom_emojis_name_key") => "Emoji name is already taken",
+        Some("watched_words_word_action_key") => "That word is already watched with that action",
+        Some("posts_topic_id_post_number_key") => "Post number is already taken in this topic",
+        Some("backie_tasks_pending_task_hash_idx") => "An identical job is already pending",
+        _ => "Already exists",
+    }
+}
+
+/// `topics_category_id_fkey` on `topics` becomes "category_id doesn't
+/// match an existing record".
+fn foreign_key_message(table: Option<&str>, constraint: Option<&str>) -> String {
+    let column = match (table, constraint) {
+        (Some(table), Some(constraint)) => constraint
+            .strip_prefix(table)
+            .and_then(|c| c.strip_prefix('_'))
+            .and_then(|c| c.strip_suffix("_fkey")),
+        _ => None,
+    };
+    match column {
+        Some(column) => format!("{column} doesn't match an existing record"),
+        None => "Refers to a record that doesn't exist".to_string(),
+    }
+}
+
+impl From<r2d2::PoolError> for ApiError {
+    fn from(e: r2d2::PoolError) -> Self {
+        log::error!("Failed to get database connection: {e}");
+        ApiError::Unavailable
+    }
+}
+
+impl From<BlockingError> for ApiError {
+    fn from(e: BlockingError) -> Self {
+        ApiError::Internal(format!("blocking error: {e}"))
+    }
+}
+
+impl From<ValidationErrors> for ApiError {
+    fn from(e: ValidationErrors) -> Self {
+        ApiError::Validation(e)
+    }
+}
+
+impl From<LikeError> for ApiError {
+    fn from(e: LikeError) -> Self {
+        match e {
+            LikeError::PostNotFound => ApiError::not_found("Post"),
+            LikeError::SelfLike => {
+                ApiError::Unprocessable("You cannot like your own post".to_string())
+            }
+            LikeError::Db(e) => e.into(),
+        }
+    }
+}
+
+impl From<ContentError> for ApiError {
+    fn from(e: ContentError) -> Self {
+        match e {
+            ContentError::Blocked(words) => ApiError::Unprocessable(format!(
+                "Contains words that aren't allowed: {}",
+                words.join(", ")
+            )),
+            ContentError::Db(e) => e.into(),
+        }
+    }
+}
+
+impl From<QueueError> for ApiError {
+    fn from(e: QueueError) -> Self {
+        match e {
+            QueueError::NotFound => ApiError::not_found("Queued post"),
+            QueueError::AlreadyReviewed => {
+                ApiError::Conflict("Queued post has already been reviewed".to_string())
+            }
+            QueueError::Blocked(words) => ContentError::Blocked(words).into(),
+            QueueError::Db(e) => e.into(),
+        }
+    }
+}
+
+impl From<ReadError> for ApiError {
+    fn from(e: ReadError) -> Self {
+        match e {
+            ReadError::TopicNotFound => ApiError::not_found("Topic"),
+            ReadError::Db(e) => e.into(),
+        }
+    }
+}
+
+impl From<ConfirmError> for ApiError {
+    fn from(e: ConfirmError) -> Self {
+        match e {
+            ConfirmError::InvalidToken => {
+                ApiError::BadRequest("Invalid or expired confirmation token".to_string())
+            }
+            ConfirmError::Db(e) => e.into(),
+        }
+    }
+}
+
+impl From<PasswordError> for ApiError {
+    fn from(e: PasswordError) -> Self {
+        match e {
+            PasswordError::InvalidToken => {
+                ApiError::BadRequest("Invalid or expired password reset token".to_string())
+            }
+            PasswordError::WrongPassword => {
+                ApiError::Forbidden("Current password is incorrect".to_string())
+            }
+            PasswordError::Hash(e) => ApiError::Internal(format!("bcrypt error: {e}")),
+            PasswordError::Db(e) => e.into(),
+        }
+    }
+}
+
+impl From<TwoFactorError> for ApiError {
+    fn from(e: TwoFactorError) -> Self {
+        match e {
+            TwoFactorError::AlreadyEnabled => {
+                ApiError::Conflict("Two-factor authentication is already enabled".to_string())
+            }
+            TwoFactorError::NotEnrolled => ApiError::Unprocessable(
+                "Start two-factor enrollment before enabling it".to_string(),
+            ),
+            TwoFactorError::InvalidCode => {
+                ApiError::Unprocessable("Invalid two-factor code".to_string())
+            }
+            TwoFactorError::WrongPassword => {
+                ApiError::Forbidden("Current password is incorrect".to_string())
+            }
+            TwoFactorError::Hash(e) => ApiError::Internal(format!("bcrypt error: {e}")),
+            TwoFactorError::Db(e) => e.into(),
+        }
+    }
+}
+
+impl From<bcrypt::BcryptError> for ApiError {
+    fn from(e: bcrypt::BcryptError) -> Self {
+        ApiError::Internal(format!("bcrypt error: {e}"))
+    }
+}
+
+impl From<jsonwebtoken::errors::Error> for ApiError {
+    fn from(e: jsonwebtoken::errors::Error) -> Self {
+        ApiError::Internal(format!("failed to generate token: {e}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::body::to_bytes;
+    use serde_json::json;
+
+    async fn body_of(error: ApiError) -> (u16, Value) {
+        let response = error.error_response();
+        let status = response.status().as_u16();
+        let bytes = to_bytes(response.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    #[actix_web::test]
+    async fn not_found_names_the_resource() {
+        let (status, body) = body_of(ApiError::not_found("Post")).await;
+        assert_eq!(status, 404);
+        assert_eq!(
+            body,
+            json!({ "error": "Post not found", "code": "not_found" })
+        );
+    }
+
+    #[actix_web::test]
+    async fn internal_errors_hide_the_detail() {
+        let (status, body) = body_of(ApiError::Internal("secret table".to_string())).await;
+        assert_eq!(status, 500);
+        assert_eq!(body["code"], "internal_error");
+        assert!(!body.to_string().contains("secret"));
+    }
+
+    #[actix_web::test]
+    async fn rate_limits_carry_retry_after() {
+        let error = ApiError::RateLimited {
+            message: "Slow down".to_string(),
+            retry_after_secs: 30,
+        };
+        let response = error.error_response();
+        assert_eq!(response.headers().get("Retry-After").unwrap(), "30");
+        let (_, body) = body_of(error).await;
+        assert_eq!(body["retry_after"], 30);
+    }
+
+    #[test]
+    fn diesel_not_found_is_404() {
+        let error: ApiError = DieselError::NotFound.into();
+        assert_eq!(error.status_code(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn constraint_messages() {
+        assert_eq!(
+            unique_violation_message(Some("users_username_key")),
+            "Username is already taken"
+        );
+        assert_eq!(unique_violation_message(Some("other")), "Already exists");
+        assert_eq!(
+            foreign_key_message(Some("topics"), Some("topics_category_id_fkey")),
+            "category_id doesn't match an existing record"
+        );
+        assert_eq!(
+            foreign_key_message(None, None),
+            "Refers to a record that doesn't exist"
+        );
+    }
+}
@@ -1,8 +1,10 @@
-use actix_web::{dev::Payload, error::ErrorForbidden, FromRequest, HttpRequest};
+use actix_web::{dev::Payload, FromRequest, HttpRequest};
 use diesel::prelude::*;
 use futures::future::{err, ok, Ready};
 
+use crate::error::ApiError;
 use crate::schema::users;
+use crate::services::two_factor;
 use crate::DbPool;
 
 // Trust levels matching Discourse
@@ -20,6 +22,7 @@ pub struct CurrentUser {
     pub trust_level: i32,
     pub admin: bool,
     pub moderator: bool,
+    pub two_factor_enabled: bool,
 }
 
 impl CurrentUser {
@@ -40,39 +43,62 @@ impl CurrentUser {
     }
 }
 
+/// Guards answer 403 with an [`ApiError`] body, whether the token is
+/// missing or the user lacks the role.
+fn forbidden(message: &str) -> actix_web::Error {
+    ApiError::Forbidden(message.to_string()).into()
+}
+
 /// Helper to extract user from request
 fn extract_user(req: &HttpRequest) -> Result<CurrentUser, actix_web::Error> {
     // Get the pool from app data
     let pool = req
         .app_data::<actix_web::web::Data<DbPool>>()
-        .ok_or_else(|| ErrorForbidden("Database not configured"))?;
+        .ok_or_else(|| ApiError::Internal("DbPool missing from app data".to_string()))?;
 
     // Get the auth header
     let auth_header = req
         .headers()
         .get("Authorization")
         .and_then(|h| h.to_str().ok())
-        .ok_or_else(|| ErrorForbidden("Missing authorization header"))?;
+        .ok_or_else(|| forbidden("Missing authorization header"))?;
 
     // Parse the Bearer token
     let token = auth_header
         .strip_prefix("Bearer ")
-        .ok_or_else(|| ErrorForbidden("Invalid authorization header format"))?;
+        .ok_or_else(|| forbidden("Invalid authorization header format"))?;
 
     // Decode the JWT
     let claims = crate::auth::verify_token(token)
-        .map_err(|_| ErrorForbidden("Invalid token"))?;
+        .map_err(|_| forbidden("Invalid token"))?;
 
     // Get user from database
-    let mut conn = pool
-        .get()
-        .map_err(|_| ErrorForbidden("Database connection failed"))?;
-
-    let (username, trust_level, admin, moderator): (String, i32, bool, bool) = users::table
+    let mut conn = pool.get().map_err(ApiError::from)?;
+
+    let (username, trust_level, admin, moderator, token_version, two_factor_enabled): (
+        String,
+        i32,
+        bool,
+        bool,
+        i32,
+        bool,
+    ) = users::table
         .find(claims.user_id)
-        .select((users::username, users::trust_level, users::admin, users::moderator))
+        .select((
+            users::username,
+            users::trust_level,
+            users::admin,
+            users::moderator,
+            users::token_version,
+            users::totp_enabled,
+        ))
         .first(&mut conn)
-        .map_err(|_| ErrorForbidden("User not found"))?;
+        .map_err(|_| forbidden("User not found"))?;
+
+    // Tokens minted before the last password change are revoked.
+    if token_version != claims.token_version {
+        return Err(forbidden("Invalid token"));
+    }
 
     Ok(CurrentUser {
         user_id: claims.user_id,
@@ -80,9 +106,30 @@ fn extract_user(req: &HttpRequest) -> Result<CurrentUser, actix_web::Error> {
         trust_level,
         admin,
         moderator,
+        two_factor_enabled,
     })
 }
 
+/// Staff-only guards refuse staff without 2FA while the
+/// `enforce_2fa_for_staff` setting is on. Enrollment itself is behind
+/// plain authentication, so they can still turn it on.
+fn require_two_factor(req: &HttpRequest, user: &CurrentUser) -> Result<(), actix_web::Error> {
+    if user.two_factor_enabled {
+        return Ok(());
+    }
+    let pool = req
+        .app_data::<actix_web::web::Data<DbPool>>()
+        .ok_or_else(|| ApiError::Internal("DbPool missing from app data".to_string()))?;
+    let mut conn = pool.get().map_err(ApiError::from)?;
+    if two_factor::enforced_for_staff(&mut conn).map_err(ApiError::from)? {
+        return Err(ApiError::TwoFactorRequired(
+            "Staff accounts must enable two-factor authentication".to_string(),
+        )
+        .into());
+    }
+    Ok(())
+}
+
 // ============================================================================
 // Guard Extractors
 // ============================================================================
@@ -115,9 +162,12 @@ impl FromRequest for ModeratorGuard {
         match extract_user(req) {
             Ok(user) => {
                 if user.is_moderator() {
-                    ok(ModeratorGuard(user))
+                    match require_two_factor(req, &user) {
+                        Ok(()) => ok(ModeratorGuard(user)),
+                        Err(e) => err(e),
+                    }
                 } else {
-                    err(ErrorForbidden("Moderator access required"))
+                    err(forbidden("Moderator access required"))
                 }
             }
             Err(e) => err(e),
@@ -137,9 +187,12 @@ impl FromRequest for AdminGuard {
         match extract_user(req) {
             Ok(user) => {
                 if user.is_admin() {
-                    ok(AdminGuard(user))
+                    match require_two_factor(req, &user) {
+                        Ok(()) => ok(AdminGuard(user)),
+                        Err(e) => err(e),
+                    }
                 } else {
-                    err(ErrorForbidden("Admin access required"))
+                    err(forbidden("Admin access required"))
                 }
             }
             Err(e) => err(e),
@@ -159,9 +212,12 @@ impl FromRequest for StaffGuard {
         match extract_user(req) {
             Ok(user) => {
                 if user.is_staff() {
-                    ok(StaffGuard(user))
+                    match require_two_factor(req, &user) {
+                        Ok(()) => ok(StaffGuard(user)),
+                        Err(e) => err(e),
+                    }
                 } else {
-                    err(ErrorForbidden("Staff access required"))
+                    err(forbidden("Staff access required"))
                 }
             }
             Err(e) => err(e),
@@ -183,7 +239,7 @@ impl FromRequest for TrustLevel1Guard {
                 if user.has_trust_level(TRUST_LEVEL_BASIC) {
                     ok(TrustLevel1Guard(user))
                 } else {
-                    err(ErrorForbidden("Trust level 1 required"))
+                    err(forbidden("Trust level 1 required"))
                 }
             }
             Err(e) => err(e),
@@ -205,7 +261,7 @@ impl FromRequest for TrustLevel2Guard {
                 if user.has_trust_level(TRUST_LEVEL_MEMBER) {
                     ok(TrustLevel2Guard(user))
                 } else {
-                    err(ErrorForbidden("Trust level 2 required"))
+                    err(forbidden("Trust level 2 required"))
                 }
             }
             Err(e) => err(e),
@@ -227,7 +283,7 @@ impl FromRequest for TrustLevel3Guard {
                 if user.has_trust_level(TRUST_LEVEL_REGULAR) {
                     ok(TrustLevel3Guard(user))
                 } else {
-                    err(ErrorForbidden("Trust level 3 required"))
+                    err(forbidden("Trust level 3 required"))
                 }
             }
             Err(e) => err(e),
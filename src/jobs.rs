@@ -1,170 +1,174 @@
+//! Background jobs backed by the `backie_tasks` table. [`JobQueue`] puts
+//! jobs in; a [`WorkerPool`] claims and runs them, looking each stored
+//! `task_name` up in a [`JobRegistry`].
+
+mod admin;
+mod builtin;
+mod registry;
+mod retry;
+mod worker;
+
 use diesel::prelude::*;
-use serde::{Deserialize, Serialize};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::future::Future;
 use std::sync::Arc;
-use std::time::Duration;
-use tokio::time::sleep;
 
-use crate::schema::backie_tasks;
 use crate::DbPool;
+use crate::schema::backie_tasks;
 
-// Job trait - implement this for each job type
-pub trait Job: Send + Sync + 'static {
-    fn job_name(&self) -> &'static str;
-    fn execute(&self, pool: &DbPool) -> Result<(), String>;
-    fn to_json(&self) -> Result<serde_json::Value, String>;
-}
-
-// Example job: Welcome email
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct WelcomeEmailJob {
-    pub user_id: i32,
-    pub username: String,
-    pub email: String,
+pub use admin::{
+    JobActionError, JobFilter, JobState, JobTypeStats, JobView, cancel_job, find_job, job_stats,
+    list_jobs, retry_job, run_job_now,
+};
+pub use builtin::{
+    CheckTrustLevelPromotionJob, ConfirmEmailJob, FetchLinkPreviewsJob, PasswordResetEmailJob,
+    PropagateUsernameJob, RebakePostsJob, WelcomeEmailJob,
+};
+pub use registry::{DuplicateJobName, EnqueueError, JobRegistry};
+pub use retry::{
+    FailureOutcome, RETRY_BASE_DELAY_SECS, RETRY_MAX_DELAY_SECS, backoff_secs, discard_dead,
+    list_dead, record_failure, retry_dead, retry_delay,
+};
+pub use worker::{
+    DEFAULT_POLL_INTERVAL_SECS, DEFAULT_SHUTDOWN_TIMEOUT_SECS, QueueConfig, REAPER_GRACE_SECS,
+    REAPER_INTERVAL_SECS, WorkerPool, WorkerPoolHandle, claim_next, reap_stuck, release_abandoned,
+    run_with_timeout,
+};
+
+/// Default per-run time limit, used unless a job overrides `timeout_msecs`.
+pub const DEFAULT_TIMEOUT_MSECS: i64 = 30_000;
+
+/// Queue for jobs that don't pick one. Also the catch-all for anything
+/// that should run promptly but isn't user-facing delivery.
+pub const DEFAULT_QUEUE: &str = "default";
+
+/// Outgoing email. Kept apart so deliveries aren't held up behind bulk
+/// work, and so a slow mail server only ties up this queue's workers.
+pub const MAIL_QUEUE: &str = "mail";
+
+/// Bulk, deferrable work: reindexing, rewriting posts, backfills.
+pub const LOW_QUEUE: &str = "low";
+
+/// Postgres channel workers `LISTEN` on. The payload is the queue name a
+/// job became runnable on.
+pub const NOTIFY_CHANNEL: &str = "backie_tasks";
+
+/// Wake the workers for `queue`. Notifications are only delivered on
+/// commit, so calling this inside a transaction is fine: workers won't
+/// look before the job is visible.
+pub fn notify_queue(conn: &mut PgConnection, queue: &str) -> QueryResult<()> {
+    diesel::sql_query("SELECT pg_notify($1, $2)")
+        .bind::<diesel::sql_types::Text, _>(NOTIFY_CHANNEL)
+        .bind::<diesel::sql_types::Text, _>(queue)
+        .execute(conn)
+        .map(|_| ())
 }
 
-impl Job for WelcomeEmailJob {
-    fn job_name(&self) -> &'static str {
-        "welcome_email"
-    }
-
-    fn execute(&self, _pool: &DbPool) -> Result<(), String> {
-        log::info!(
-            "Sending welcome email to user {} ({}) at {}",
-            self.user_id,
-            self.username,
-            self.email
-        );
+/// A background job. Implementors are stored as JSON and rebuilt with
+/// `from_json` when a worker claims them, so every type a worker may see
+/// must be added to the [`JobRegistry`].
+pub trait Job: Serialize + DeserializeOwned + Send + Sync + 'static {
+    /// Stored as `task_name`; must be unique across registered jobs.
+    const NAME: &'static str;
 
-        // Simulate email sending
-        std::thread::sleep(Duration::from_secs(2));
+    /// Which queue the job runs on. Only workers configured for this
+    /// queue will claim it (see [`QueueConfig`]).
+    const QUEUE: &'static str = DEFAULT_QUEUE;
 
-        log::info!("Welcome email sent successfully to {}", self.email);
-        Ok(())
-    }
+    /// Run the job. Blocking work (Diesel queries included) belongs in
+    /// [`with_conn`] or `spawn_blocking`, not directly in this future.
+    fn execute(&self, pool: &DbPool) -> impl Future<Output = Result<(), String>> + Send;
 
     fn to_json(&self) -> Result<serde_json::Value, String> {
         serde_json::to_value(self).map_err(|e| e.to_string())
     }
-}
-
-// Example job: Process topic
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ProcessTopicJob {
-    pub topic_id: i32,
-    pub action: String,
-}
 
-impl Job for ProcessTopicJob {
-    fn job_name(&self) -> &'static str {
-        "process_topic"
+    fn from_json(payload: serde_json::Value) -> Result<Self, String> {
+        serde_json::from_value(payload).map_err(|e| format!("Failed to deserialize job: {}", e))
     }
 
-    fn execute(&self, _pool: &DbPool) -> Result<(), String> {
-        log::info!(
-            "Processing topic {} with action: {}",
-            self.topic_id,
-            self.action
-        );
-
-        // Simulate processing
-        std::thread::sleep(Duration::from_secs(1));
+    /// How long a single run may take before the worker gives up on it
+    /// and counts the run as failed.
+    fn timeout_msecs(&self) -> i64 {
+        DEFAULT_TIMEOUT_MSECS
+    }
 
-        log::info!("Topic {} processed successfully", self.topic_id);
-        Ok(())
+    /// What `JobQueue::enqueue` does when an identical job (same name and
+    /// payload) is already waiting to run.
+    fn uniqueness(&self) -> Uniqueness {
+        Uniqueness::Allow
     }
 
-    fn to_json(&self) -> Result<serde_json::Value, String> {
-        serde_json::to_value(self).map_err(|e| e.to_string())
+    /// Higher runs first among the queue's ready jobs.
+    fn priority(&self) -> i32 {
+        0
     }
 }
 
-// Trust-level promotion check. Enqueued after activity that could move a
-// user up a level (post create, etc). Idempotent — runs evaluate() which
-// no-ops if no level change is warranted.
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct CheckTrustLevelPromotionJob {
-    pub user_id: i32,
+/// Per-job-type duplicate handling. "Identical" means same `job_name` and
+/// same payload; "pending" means queued but not yet attempted. Running,
+/// retrying and finished jobs never count as duplicates, so a new enqueue
+/// always gets a run that sees the latest state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Uniqueness {
+    /// Every enqueue inserts a new job.
+    Allow,
+    /// Enqueue is a no-op while an identical job is pending.
+    DropWhilePending,
+    /// Enqueue replaces the pending duplicate: the job is rescheduled to
+    /// run now with the new job's queue, priority, timeout and retry
+    /// settings.
+    Replace,
 }
 
-impl Job for CheckTrustLevelPromotionJob {
-    fn job_name(&self) -> &'static str {
-        "check_trust_level_promotion"
-    }
+/// Result of [`JobQueue::enqueue`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EnqueueOutcome {
+    /// A job was queued (or a pending duplicate was replaced).
+    Queued { task_hash: String },
+    /// An identical job was already pending; nothing was queued.
+    AlreadyPending { task_hash: String },
+}
 
-    fn execute(&self, pool: &DbPool) -> Result<(), String> {
-        let mut conn = pool.get().map_err(|e| e.to_string())?;
-        let outcome = crate::services::trust_levels::evaluate(&mut conn, self.user_id)
-            .map_err(|e| format!("trust-level evaluation failed: {e}"))?;
-        if outcome.changed() {
-            log::info!(
-                "Promoted user {} from TL{} to TL{}",
-                self.user_id,
-                outcome.previous,
-                outcome.current
-            );
-        }
-        Ok(())
+impl EnqueueOutcome {
+    pub fn is_new(&self) -> bool {
+        matches!(self, EnqueueOutcome::Queued { .. })
     }
 
-    fn to_json(&self) -> Result<serde_json::Value, String> {
-        serde_json::to_value(self).map_err(|e| e.to_string())
+    pub fn task_hash(&self) -> &str {
+        match self {
+            EnqueueOutcome::Queued { task_hash } | EnqueueOutcome::AlreadyPending { task_hash } => {
+                task_hash
+            }
+        }
     }
 }
 
-// Username propagation job - updates @mentions in posts when username changes
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct PropagateUsernameJob {
-    pub user_id: i32,
-    pub old_username: String,
-    pub new_username: String,
+/// Identity hash for a job: md5 of its name and payload. serde_json keeps
+/// object keys sorted, so equal payloads always serialize identically.
+pub fn task_hash(task_name: &str, payload: &serde_json::Value) -> String {
+    format!("{:x}", md5::compute(format!("{task_name}:{payload}")))
 }
 
-impl Job for PropagateUsernameJob {
-    fn job_name(&self) -> &'static str {
-        "propagate_username"
-    }
-
-    fn execute(&self, pool: &DbPool) -> Result<(), String> {
-        log::info!(
-            "Propagating username change: @{} -> @{} for user {}",
-            self.old_username,
-            self.new_username,
-            self.user_id
-        );
-
+/// Check out a connection and run `f` on the blocking thread pool, so
+/// synchronous Diesel work doesn't stall the async worker running a job.
+pub async fn with_conn<T, F>(pool: &DbPool, f: F) -> Result<T, String>
+where
+    T: Send + 'static,
+    F: FnOnce(&mut PgConnection) -> Result<T, String> + Send + 'static,
+{
+    let pool = pool.clone();
+    tokio::task::spawn_blocking(move || {
         let mut conn = pool.get().map_err(|e| e.to_string())?;
-
-        // Update @mentions in posts.raw
-        // Match @username at word boundaries
-        let old_mention = format!("@{}", self.old_username);
-        let new_mention = format!("@{}", self.new_username);
-
-        let updated = diesel::sql_query(
-            "UPDATE posts SET raw = REPLACE(raw, $1, $2), updated_at = NOW() WHERE raw LIKE $3"
-        )
-        .bind::<diesel::sql_types::Text, _>(&old_mention)
-        .bind::<diesel::sql_types::Text, _>(&new_mention)
-        .bind::<diesel::sql_types::Text, _>(format!("%{}%", old_mention))
-        .execute(&mut conn)
-        .map_err(|e| e.to_string())?;
-
-        log::info!(
-            "Updated {} posts with username change @{} -> @{}",
-            updated,
-            self.old_username,
-            self.new_username
-        );
-
-        Ok(())
-    }
-
-    fn to_json(&self) -> Result<serde_json::Value, String> {
-        serde_json::to_value(self).map_err(|e| e.to_string())
-    }
+        f(&mut conn)
+    })
+    .await
+    .map_err(|e| format!("job panicked: {e}"))?
 }
 
 // Database model for jobs
-#[derive(Queryable, Selectable)]
+#[derive(Debug, Queryable, QueryableByName, Selectable, Serialize)]
 #[diesel(table_name = backie_tasks)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct JobRecord {
@@ -180,6 +184,9 @@ pub struct JobRecord {
     pub running_at: Option<chrono::NaiveDateTime>,
     pub done_at: Option<chrono::NaiveDateTime>,
     pub error: Option<String>,
+    pub dead_at: Option<chrono::NaiveDateTime>,
+    pub queue_name: String,
+    pub priority: i32,
 }
 
 #[derive(Insertable)]
@@ -191,6 +198,8 @@ pub struct NewJob {
     pub timeout_msecs: i64,
     pub max_retries: i32,
     pub scheduled_at: chrono::NaiveDateTime,
+    pub queue_name: String,
+    pub priority: i32,
 }
 
 // Job queue for enqueueing jobs
@@ -204,166 +213,88 @@ impl JobQueue {
         Self { pool }
     }
 
-    pub fn enqueue<J: Job>(&self, job: J) -> Result<String, String> {
+    /// Queue `job` to run as soon as a worker is free, applying the job's
+    /// [`Uniqueness`] policy. The outcome says whether anything was queued.
+    pub fn enqueue<J: Job>(&self, job: J) -> Result<EnqueueOutcome, String> {
         let mut conn = self.pool.get().map_err(|e| e.to_string())?;
 
         let payload = job.to_json()?;
-        let task_hash = format!("{:x}", md5::compute(format!("{:?}", payload)));
+        let hash = task_hash(J::NAME, &payload);
+        let uniqueness = job.uniqueness();
 
         let new_job = NewJob {
-            task_name: job.job_name().to_string(),
-            task_hash: task_hash.clone(),
+            task_name: J::NAME.to_string(),
+            // Salting keeps duplicates clear of the pending-uniqueness index.
+            task_hash: match uniqueness {
+                Uniqueness::Allow => format!("{hash}-{}", uuid::Uuid::new_v4().simple()),
+                _ => hash.clone(),
+            },
             payload,
-            timeout_msecs: 30000, // 30 seconds
+            timeout_msecs: job.timeout_msecs(),
             max_retries: 3,
             scheduled_at: chrono::Utc::now().naive_utc(),
+            queue_name: J::QUEUE.to_string(),
+            priority: job.priority(),
         };
 
-        diesel::insert_into(backie_tasks::table)
-            .values(&new_job)
-            .execute(&mut conn)
-            .map_err(|e| e.to_string())?;
-
-        log::info!("Enqueued job {} with hash {}", job.job_name(), task_hash);
-        Ok(task_hash)
-    }
-}
-
-// Worker pool for processing jobs
-pub struct WorkerPool {
-    pool: Arc<DbPool>,
-    worker_count: usize,
-}
-
-impl WorkerPool {
-    pub fn new(pool: Arc<DbPool>, worker_count: usize) -> Self {
-        Self { pool, worker_count }
-    }
-
-    pub async fn run(self) {
-        log::info!("Starting job worker pool with {} workers", self.worker_count);
-
-        let mut handles = vec![];
-
-        for worker_id in 0..self.worker_count {
-            let pool = Arc::clone(&self.pool);
-            let handle = tokio::spawn(async move {
-                Self::worker_loop(worker_id, pool).await;
-            });
-            handles.push(handle);
-        }
-
-        // Wait for all workers
-        for handle in handles {
-            let _ = handle.await;
-        }
-    }
-
-    async fn worker_loop(worker_id: usize, pool: Arc<DbPool>) {
-        log::info!("Worker {} started", worker_id);
-
-        loop {
-            // Poll for jobs every 5 seconds
-            sleep(Duration::from_secs(5)).await;
-
-            match Self::claim_and_execute_job(worker_id, &pool).await {
-                Ok(executed) => {
-                    if executed {
-                        log::debug!("Worker {} executed a job", worker_id);
-                    }
-                }
-                Err(e) => {
-                    log::error!("Worker {} error: {}", worker_id, e);
-                }
+        // Must match the predicate of backie_tasks_pending_task_hash_idx
+        // for Postgres to pick that index as the conflict target.
+        let pending = backie_tasks::running_at
+            .is_null()
+            .and(backie_tasks::done_at.is_null())
+            .and(backie_tasks::retries.eq(0));
+
+        let inserted = match uniqueness {
+            Uniqueness::Allow => diesel::insert_into(backie_tasks::table)
+                .values(&new_job)
+                .execute(&mut conn),
+            Uniqueness::DropWhilePending => diesel::insert_into(backie_tasks::table)
+                .values(&new_job)
+                .on_conflict(backie_tasks::task_hash)
+                .filter_target(pending)
+                .do_nothing()
+                .execute(&mut conn),
+            Uniqueness::Replace => {
+                use diesel::upsert::excluded;
+                diesel::insert_into(backie_tasks::table)
+                    .values(&new_job)
+                    .on_conflict(backie_tasks::task_hash)
+                    .filter_target(pending)
+                    .do_update()
+                    .set((
+                        backie_tasks::payload.eq(excluded(backie_tasks::payload)),
+                        backie_tasks::timeout_msecs.eq(excluded(backie_tasks::timeout_msecs)),
+                        backie_tasks::max_retries.eq(excluded(backie_tasks::max_retries)),
+                        backie_tasks::scheduled_at.eq(excluded(backie_tasks::scheduled_at)),
+                        backie_tasks::queue_name.eq(excluded(backie_tasks::queue_name)),
+                        backie_tasks::priority.eq(excluded(backie_tasks::priority)),
+                        backie_tasks::created_at.eq(diesel::dsl::now),
+                    ))
+                    .execute(&mut conn)
             }
         }
-    }
-
-    async fn claim_and_execute_job(worker_id: usize, pool: &Arc<DbPool>) -> Result<bool, String> {
-        use crate::schema::backie_tasks::dsl::*;
-
-        let mut conn = pool.get().map_err(|e| e.to_string())?;
-
-        // Claim a job using FOR UPDATE SKIP LOCKED
-        let job: Option<JobRecord> = conn.transaction(|conn| {
-            backie_tasks
-                .filter(done_at.is_null())
-                .filter(running_at.is_null())
-                .filter(scheduled_at.le(chrono::Utc::now().naive_utc()))
-                .order(scheduled_at.asc())
-                .limit(1)
-                .for_update()
-                .skip_locked()
-                .select(JobRecord::as_select())
-                .first(conn)
-                .optional()
-        }).map_err(|e| e.to_string())?;
-
-        if let Some(job_record) = job {
-            log::info!("Worker {} claimed job {} ({})", worker_id, job_record.task_name, job_record.id);
-
-            // Mark as running
-            diesel::update(backie_tasks)
-                .filter(id.eq(job_record.id))
-                .set(running_at.eq(Some(chrono::Utc::now().naive_utc())))
-                .execute(&mut conn)
-                .map_err(|e| e.to_string())?;
-
-            // Execute the job based on task_name
-            let result = Self::execute_job_by_name(&job_record.task_name, &job_record.payload, pool);
-
-            // Update job status
-            match result {
-                Ok(_) => {
-                    diesel::update(backie_tasks)
-                        .filter(id.eq(job_record.id))
-                        .set(done_at.eq(Some(chrono::Utc::now().naive_utc())))
-                        .execute(&mut conn)
-                        .map_err(|e| e.to_string())?;
-                    log::info!("Worker {} completed job {}", worker_id, job_record.id);
-                }
-                Err(e) => {
-                    diesel::update(backie_tasks)
-                        .filter(id.eq(job_record.id))
-                        .set((
-                            error.eq(Some(e.clone())),
-                            done_at.eq(Some(chrono::Utc::now().naive_utc())),
-                        ))
-                        .execute(&mut conn)
-                        .map_err(|e| e.to_string())?;
-                    log::error!("Worker {} failed job {}: {}", worker_id, job_record.id, e);
-                }
-            }
+        .map_err(|e| e.to_string())?;
 
-            Ok(true)
-        } else {
-            Ok(false)
+        if inserted == 0 {
+            log::debug!(
+                "Skipped job {} with hash {}: already pending",
+                J::NAME,
+                hash
+            );
+            return Ok(EnqueueOutcome::AlreadyPending {
+                task_hash: new_job.task_hash,
+            });
         }
-    }
 
-    fn execute_job_by_name(task_name: &str, payload: &serde_json::Value, pool: &DbPool) -> Result<(), String> {
-        match task_name {
-            "welcome_email" => {
-                let job: WelcomeEmailJob = serde_json::from_value(payload.clone())
-                    .map_err(|e| format!("Failed to deserialize job: {}", e))?;
-                job.execute(pool)
-            }
-            "process_topic" => {
-                let job: ProcessTopicJob = serde_json::from_value(payload.clone())
-                    .map_err(|e| format!("Failed to deserialize job: {}", e))?;
-                job.execute(pool)
-            }
-            "propagate_username" => {
-                let job: PropagateUsernameJob = serde_json::from_value(payload.clone())
-                    .map_err(|e| format!("Failed to deserialize job: {}", e))?;
-                job.execute(pool)
-            }
-            "check_trust_level_promotion" => {
-                let job: CheckTrustLevelPromotionJob = serde_json::from_value(payload.clone())
-                    .map_err(|e| format!("Failed to deserialize job: {}", e))?;
-                job.execute(pool)
-            }
-            _ => Err(format!("Unknown job type: {}", task_name)),
+        // A lost wakeup only delays the job until the next poll, so this
+        // doesn't fail the enqueue.
+        if let Err(e) = notify_queue(&mut conn, &new_job.queue_name) {
+            log::warn!("Failed to notify queue {}: {}", new_job.queue_name, e);
         }
+
+        log::info!("Enqueued job {} with hash {}", J::NAME, new_job.task_hash);
+        Ok(EnqueueOutcome::Queued {
+            task_hash: new_job.task_hash,
+        })
     }
 }
@@ -0,0 +1,263 @@
+//! Read-side queries and manual actions behind the admin job endpoints.
+
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use diesel::sql_types::{BigInt, Double, Nullable, Text};
+use serde::{Deserialize, Serialize};
+
+use super::{JobRecord, notify_queue};
+use crate::schema::backie_tasks;
+
+/// Where a job is in its lifecycle, derived from its timestamps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    /// Waiting for its first run.
+    Pending,
+    /// Claimed by a worker.
+    Running,
+    /// Failed at least once and waiting for its next retry.
+    Failed,
+    /// Finished successfully.
+    Done,
+    /// Out of retries (or cancelled); won't run unless retried.
+    Dead,
+}
+
+impl JobState {
+    pub fn of(job: &JobRecord) -> Self {
+        if job.dead_at.is_some() {
+            JobState::Dead
+        } else if job.done_at.is_some() {
+            JobState::Done
+        } else if job.running_at.is_some() {
+            JobState::Running
+        } else if job.retries > 0 {
+            JobState::Failed
+        } else {
+            JobState::Pending
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobState::Pending => "pending",
+            JobState::Running => "running",
+            JobState::Failed => "failed",
+            JobState::Done => "done",
+            JobState::Dead => "dead",
+        }
+    }
+}
+
+/// A job as the admin API shows it: the stored row plus its state.
+#[derive(Debug, Serialize)]
+pub struct JobView {
+    #[serde(flatten)]
+    pub job: JobRecord,
+    pub state: JobState,
+}
+
+impl From<JobRecord> for JobView {
+    fn from(job: JobRecord) -> Self {
+        let state = JobState::of(&job);
+        JobView { job, state }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct JobFilter {
+    pub state: Option<JobState>,
+    /// Exact `task_name`.
+    pub name: Option<String>,
+}
+
+/// Jobs matching `filter`, newest first.
+pub fn list_jobs(
+    conn: &mut PgConnection,
+    filter: &JobFilter,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<JobView>, DieselError> {
+    let mut query = backie_tasks::table.into_boxed();
+
+    if let Some(name) = &filter.name {
+        query = query.filter(backie_tasks::task_name.eq(name.clone()));
+    }
+
+    // Mirrors JobState::of.
+    query = match filter.state {
+        None => query,
+        Some(JobState::Pending) => query
+            .filter(backie_tasks::done_at.is_null())
+            .filter(backie_tasks::running_at.is_null())
+            .filter(backie_tasks::retries.eq(0)),
+        Some(JobState::Failed) => query
+            .filter(backie_tasks::done_at.is_null())
+            .filter(backie_tasks::running_at.is_null())
+            .filter(backie_tasks::retries.gt(0)),
+        Some(JobState::Running) => query
+            .filter(backie_tasks::done_at.is_null())
+            .filter(backie_tasks::running_at.is_not_null()),
+        Some(JobState::Done) => query
+            .filter(backie_tasks::done_at.is_not_null())
+            .filter(backie_tasks::dead_at.is_null()),
+        Some(JobState::Dead) => query.filter(backie_tasks::dead_at.is_not_null()),
+    };
+
+    let jobs = query
+        .order(backie_tasks::created_at.desc())
+        .limit(limit)
+        .offset(offset)
+        .select(JobRecord::as_select())
+        .load(conn)?;
+    Ok(jobs.into_iter().map(JobView::from).collect())
+}
+
+pub fn find_job(
+    conn: &mut PgConnection,
+    job_id: uuid::Uuid,
+) -> Result<Option<JobView>, DieselError> {
+    let job = backie_tasks::table
+        .find(job_id)
+        .select(JobRecord::as_select())
+        .first(conn)
+        .optional()?;
+    Ok(job.map(JobView::from))
+}
+
+/// Counts by state and timings for one job type.
+#[derive(Debug, Serialize, QueryableByName)]
+pub struct JobTypeStats {
+    #[diesel(sql_type = Text)]
+    pub task_name: String,
+    #[diesel(sql_type = BigInt)]
+    pub pending: i64,
+    #[diesel(sql_type = BigInt)]
+    pub running: i64,
+    #[diesel(sql_type = BigInt)]
+    pub failed: i64,
+    #[diesel(sql_type = BigInt)]
+    pub done: i64,
+    #[diesel(sql_type = BigInt)]
+    pub dead: i64,
+    /// Mean time from `scheduled_at` to being claimed, over finished runs.
+    #[diesel(sql_type = Nullable<Double>)]
+    pub avg_wait_ms: Option<f64>,
+    /// Mean run time of successful jobs.
+    #[diesel(sql_type = Nullable<Double>)]
+    pub avg_run_ms: Option<f64>,
+}
+
+/// Per-type counts and average latency, across every job still in the
+/// table. Types are ordered by name.
+pub fn job_stats(conn: &mut PgConnection) -> Result<Vec<JobTypeStats>, DieselError> {
+    diesel::sql_query(
+        "SELECT task_name,
+                COUNT(*) FILTER (WHERE done_at IS NULL AND running_at IS NULL AND retries = 0)
+                    AS pending,
+                COUNT(*) FILTER (WHERE done_at IS NULL AND running_at IS NOT NULL) AS running,
+                COUNT(*) FILTER (WHERE done_at IS NULL AND running_at IS NULL AND retries > 0)
+                    AS failed,
+                COUNT(*) FILTER (WHERE done_at IS NOT NULL AND dead_at IS NULL) AS done,
+                COUNT(*) FILTER (WHERE dead_at IS NOT NULL) AS dead,
+                (AVG(EXTRACT(EPOCH FROM running_at - scheduled_at))
+                    FILTER (WHERE done_at IS NOT NULL AND dead_at IS NULL) * 1000)::float8
+                    AS avg_wait_ms,
+                (AVG(EXTRACT(EPOCH FROM done_at - running_at))
+                    FILTER (WHERE done_at IS NOT NULL AND dead_at IS NULL) * 1000)::float8
+                    AS avg_run_ms
+         FROM backie_tasks
+         GROUP BY task_name
+         ORDER BY task_name",
+    )
+    .load(conn)
+}
+
+#[derive(Debug)]
+pub enum JobActionError {
+    NotFound,
+    /// The action doesn't apply to a job in this state.
+    InvalidState(JobState),
+    Db(DieselError),
+}
+
+impl From<DieselError> for JobActionError {
+    fn from(e: DieselError) -> Self {
+        match e {
+            DieselError::NotFound => JobActionError::NotFound,
+            other => JobActionError::Db(other),
+        }
+    }
+}
+
+/// Lock `job_id` and check it's in one of `allowed` states.
+fn lock_in_state(
+    conn: &mut PgConnection,
+    job_id: uuid::Uuid,
+    allowed: &[JobState],
+) -> Result<JobRecord, JobActionError> {
+    let job = backie_tasks::table
+        .find(job_id)
+        .select(JobRecord::as_select())
+        .for_update()
+        .first(conn)?;
+    let state = JobState::of(&job);
+    if !allowed.contains(&state) {
+        return Err(JobActionError::InvalidState(state));
+    }
+    Ok(job)
+}
+
+/// Stop a job that hasn't started (or is waiting to retry) from running.
+/// It's moved to the dead state rather than deleted, so it can still be
+/// inspected or retried. Running jobs can't be cancelled.
+pub fn cancel_job(conn: &mut PgConnection, job_id: uuid::Uuid) -> Result<JobView, JobActionError> {
+    conn.transaction(|conn| {
+        lock_in_state(conn, job_id, &[JobState::Pending, JobState::Failed])?;
+        let now = chrono::Utc::now().naive_utc();
+        let job = diesel::update(backie_tasks::table.find(job_id))
+            .set((
+                backie_tasks::done_at.eq(Some(now)),
+                backie_tasks::dead_at.eq(Some(now)),
+                backie_tasks::error.eq(Some("cancelled by admin")),
+            ))
+            .returning(JobRecord::as_returning())
+            .get_result(conn)?;
+        Ok(job.into())
+    })
+}
+
+/// Run a finished or dead job again, with a fresh retry budget. Fails
+/// with a unique violation if an identical job is already pending.
+pub fn retry_job(conn: &mut PgConnection, job_id: uuid::Uuid) -> Result<JobView, JobActionError> {
+    conn.transaction(|conn| {
+        lock_in_state(conn, job_id, &[JobState::Done, JobState::Dead])?;
+        let job: JobRecord = diesel::update(backie_tasks::table.find(job_id))
+            .set((
+                backie_tasks::retries.eq(0),
+                backie_tasks::scheduled_at.eq(chrono::Utc::now().naive_utc()),
+                backie_tasks::running_at.eq(None::<chrono::NaiveDateTime>),
+                backie_tasks::done_at.eq(None::<chrono::NaiveDateTime>),
+                backie_tasks::dead_at.eq(None::<chrono::NaiveDateTime>),
+                backie_tasks::error.eq(None::<String>),
+            ))
+            .returning(JobRecord::as_returning())
+            .get_result(conn)?;
+        notify_queue(conn, &job.queue_name)?;
+        Ok(job.into())
+    })
+}
+
+/// Make a pending job, or one waiting out its retry backoff, runnable now.
+pub fn run_job_now(conn: &mut PgConnection, job_id: uuid::Uuid) -> Result<JobView, JobActionError> {
+    conn.transaction(|conn| {
+        lock_in_state(conn, job_id, &[JobState::Pending, JobState::Failed])?;
+        let job: JobRecord = diesel::update(backie_tasks::table.find(job_id))
+            .set(backie_tasks::scheduled_at.eq(chrono::Utc::now().naive_utc()))
+            .returning(JobRecord::as_returning())
+            .get_result(conn)?;
+        notify_queue(conn, &job.queue_name)?;
+        Ok(job.into())
+    })
+}
@@ -0,0 +1,283 @@
+//! The job types this crate ships. Each one is registered in
+//! [`JobRegistry::builtin`](super::JobRegistry::builtin).
+
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use super::{DEFAULT_TIMEOUT_MSECS, Job, JobQueue, LOW_QUEUE, MAIL_QUEUE, Uniqueness, with_conn};
+use crate::DbPool;
+use crate::mailer::{self, Recipient, Template};
+use crate::schema::posts;
+use crate::services::link_previews;
+use crate::{markdown, onebox};
+use crate::services::email_confirmation::CONFIRM_TOKEN_TTL_HOURS;
+use crate::services::mentions;
+use crate::services::passwords::RESET_TOKEN_TTL_MINUTES;
+
+// Confirmation link for a new account's email address, enqueued by
+// registration and by resend requests. Carries the raw token because only
+// its hash is stored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfirmEmailJob {
+    pub user_id: i32,
+    pub username: String,
+    pub email: String,
+    pub token: String,
+}
+
+impl Job for ConfirmEmailJob {
+    const NAME: &'static str = "confirm_email";
+    const QUEUE: &'static str = MAIL_QUEUE;
+
+    async fn execute(&self, pool: &DbPool) -> Result<(), String> {
+        let recipient = Recipient {
+            user_id: Some(self.user_id),
+            address: self.email.clone(),
+        };
+        let template = Template::ConfirmEmail {
+            username: self.username.clone(),
+            confirm_url: format!("{}/confirm-email/{}", mailer::base_url(), self.token),
+            expires_in_hours: CONFIRM_TOKEN_TTL_HOURS,
+        };
+        mailer::deliver(pool, recipient, template).await?;
+        Ok(())
+    }
+}
+
+// Password reset link, enqueued by forgot-password requests. Carries the
+// raw token for the same reason as `ConfirmEmailJob`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PasswordResetEmailJob {
+    pub user_id: i32,
+    pub username: String,
+    pub email: String,
+    pub token: String,
+}
+
+impl Job for PasswordResetEmailJob {
+    const NAME: &'static str = "password_reset_email";
+    const QUEUE: &'static str = MAIL_QUEUE;
+
+    async fn execute(&self, pool: &DbPool) -> Result<(), String> {
+        let recipient = Recipient {
+            user_id: Some(self.user_id),
+            address: self.email.clone(),
+        };
+        let template = Template::PasswordReset {
+            username: self.username.clone(),
+            reset_url: format!("{}/password-reset/{}", mailer::base_url(), self.token),
+            expires_in_minutes: RESET_TOKEN_TTL_MINUTES,
+        };
+        mailer::deliver(pool, recipient, template).await?;
+        Ok(())
+    }
+}
+
+// Welcome email, enqueued once the account's email is confirmed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WelcomeEmailJob {
+    pub user_id: i32,
+    pub username: String,
+    pub email: String,
+}
+
+impl Job for WelcomeEmailJob {
+    const NAME: &'static str = "welcome_email";
+    const QUEUE: &'static str = MAIL_QUEUE;
+
+    async fn execute(&self, pool: &DbPool) -> Result<(), String> {
+        let recipient = Recipient {
+            user_id: Some(self.user_id),
+            address: self.email.clone(),
+        };
+        let template = Template::Welcome {
+            username: self.username.clone(),
+        };
+        let log = mailer::deliver(pool, recipient, template).await?;
+        log::info!(
+            "Sent welcome email to user {} ({})",
+            self.user_id,
+            log.message_id
+        );
+        Ok(())
+    }
+
+    fn uniqueness(&self) -> Uniqueness {
+        Uniqueness::DropWhilePending
+    }
+}
+
+// Trust-level promotion check. Enqueued after activity that could move a
+// user up a level (post create, etc). Idempotent — runs evaluate() which
+// no-ops if no level change is warranted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CheckTrustLevelPromotionJob {
+    pub user_id: i32,
+}
+
+impl Job for CheckTrustLevelPromotionJob {
+    const NAME: &'static str = "check_trust_level_promotion";
+
+    async fn execute(&self, pool: &DbPool) -> Result<(), String> {
+        let user_id = self.user_id;
+        let outcome = with_conn(pool, move |conn| {
+            crate::services::trust_levels::evaluate(conn, user_id)
+                .map_err(|e| format!("trust-level evaluation failed: {e}"))
+        })
+        .await?;
+        if outcome.changed() {
+            log::info!(
+                "Promoted user {} from TL{} to TL{}",
+                self.user_id,
+                outcome.previous,
+                outcome.current
+            );
+        }
+        Ok(())
+    }
+
+    fn uniqueness(&self) -> Uniqueness {
+        Uniqueness::DropWhilePending
+    }
+}
+
+// Link preview job. Enqueued when a saved post has links on lines of their
+// own; looks up the ones without a fresh cached preview, stores the
+// results and re-cooks the post so the previews show up. A post has at most
+// MAX_ONEBOXES_PER_POST such links and each lookup is bounded, so the
+// timeout covers every lookup running to its limit.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FetchLinkPreviewsJob {
+    pub post_id: i32,
+}
+
+impl Job for FetchLinkPreviewsJob {
+    const NAME: &'static str = "fetch_link_previews";
+
+    fn timeout_msecs(&self) -> i64 {
+        (markdown::MAX_ONEBOXES_PER_POST as u64 * onebox::LOOKUP_TIMEOUT_SECS * 1000) as i64
+            + DEFAULT_TIMEOUT_MSECS
+    }
+
+    async fn execute(&self, pool: &DbPool) -> Result<(), String> {
+        let post_id = self.post_id;
+        let urls = with_conn(pool, move |conn| {
+            let raw: Option<String> = posts::table
+                .find(post_id)
+                .select(posts::raw)
+                .first(conn)
+                .optional()
+                .map_err(|e| e.to_string())?;
+            let urls = raw.map(|raw| markdown::extract_onebox_urls(&raw)).unwrap_or_default();
+            link_previews::stale(conn, &urls).map_err(|e| e.to_string())
+        })
+        .await?;
+        if urls.is_empty() {
+            return Ok(());
+        }
+
+        for url in urls {
+            let outcome = onebox::lookup(&url).await;
+            if let Err(e) = &outcome {
+                log::info!("No preview for {url}: {e}");
+            }
+            with_conn(pool, move |conn| {
+                link_previews::save(conn, &url, &outcome).map_err(|e| e.to_string())
+            })
+            .await?;
+        }
+
+        with_conn(pool, move |conn| {
+            crate::services::posts::recook(conn, post_id)
+                .optional()
+                .map_err(|e| e.to_string())
+        })
+        .await?;
+        Ok(())
+    }
+
+    fn uniqueness(&self) -> Uniqueness {
+        Uniqueness::DropWhilePending
+    }
+}
+
+// Rebake job. Enqueued at startup; re-cooks posts whose `cook_version` is
+// behind the current pipeline, one batch per run, and queues the next
+// batch until none are left.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RebakePostsJob {
+    pub after_id: i32,
+}
+
+impl RebakePostsJob {
+    pub const BATCH_SIZE: i64 = 100;
+}
+
+impl Job for RebakePostsJob {
+    const NAME: &'static str = "rebake_posts";
+    const QUEUE: &'static str = LOW_QUEUE;
+
+    async fn execute(&self, pool: &DbPool) -> Result<(), String> {
+        let after_id = self.after_id;
+        let last = with_conn(pool, move |conn| {
+            crate::services::posts::rebake_batch(conn, after_id, Self::BATCH_SIZE)
+                .map_err(|e| e.to_string())
+        })
+        .await?;
+        if let Some(after_id) = last {
+            JobQueue::new(Arc::new(pool.clone())).enqueue(RebakePostsJob { after_id })?;
+        }
+        Ok(())
+    }
+
+    fn uniqueness(&self) -> Uniqueness {
+        Uniqueness::DropWhilePending
+    }
+}
+
+// Username propagation job - rewrites @mentions of a renamed user in the
+// posts recorded as mentioning them, then re-cooks those posts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PropagateUsernameJob {
+    pub user_id: i32,
+    pub old_username: String,
+    pub new_username: String,
+}
+
+impl Job for PropagateUsernameJob {
+    const NAME: &'static str = "propagate_username";
+    const QUEUE: &'static str = LOW_QUEUE;
+
+    async fn execute(&self, pool: &DbPool) -> Result<(), String> {
+        log::info!(
+            "Propagating username change: @{} -> @{} for user {}",
+            self.old_username,
+            self.new_username,
+            self.user_id
+        );
+
+        let (user_id, old, new) = (
+            self.user_id,
+            self.old_username.clone(),
+            self.new_username.clone(),
+        );
+        let updated = with_conn(pool, move |conn| {
+            mentions::propagate_rename(conn, user_id, &old, &new).map_err(|e| e.to_string())
+        })
+        .await?;
+
+        log::info!(
+            "Updated {} posts with username change @{} -> @{}",
+            updated,
+            self.old_username,
+            self.new_username
+        );
+
+        Ok(())
+    }
+
+    fn uniqueness(&self) -> Uniqueness {
+        Uniqueness::DropWhilePending
+    }
+}
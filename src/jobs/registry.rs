@@ -0,0 +1,310 @@
+//! Maps `task_name` values in `backie_tasks` back to job types, so workers
+//! can deserialize and run a claimed job without a hand-maintained match,
+//! and admins can enqueue a job by name.
+
+use futures::future::BoxFuture;
+use std::collections::HashMap;
+use std::fmt;
+
+use super::{
+    CheckTrustLevelPromotionJob, ConfirmEmailJob, EnqueueOutcome, FetchLinkPreviewsJob, Job,
+    JobQueue, PasswordResetEmailJob, PropagateUsernameJob, QueueConfig, RebakePostsJob,
+    WelcomeEmailJob,
+};
+use crate::DbPool;
+
+type Runner = fn(serde_json::Value, DbPool) -> BoxFuture<'static, Result<(), String>>;
+type Enqueuer = fn(&JobQueue, serde_json::Value) -> Result<EnqueueOutcome, EnqueueError>;
+
+/// Deserialize a payload as `J` and run it. One copy is monomorphized per
+/// registered job type; the registry stores it as a plain fn pointer.
+fn run<J: Job>(payload: serde_json::Value, pool: DbPool) -> BoxFuture<'static, Result<(), String>> {
+    Box::pin(async move {
+        let job = J::from_json(payload)?;
+        job.execute(&pool).await
+    })
+}
+
+/// Check a payload deserializes as `J`, then enqueue it with `J`'s own
+/// queue, priority and uniqueness policy.
+fn enqueue<J: Job>(
+    queue: &JobQueue,
+    payload: serde_json::Value,
+) -> Result<EnqueueOutcome, EnqueueError> {
+    let job = J::from_json(payload).map_err(EnqueueError::InvalidPayload)?;
+    queue.enqueue(job).map_err(EnqueueError::Queue)
+}
+
+/// Why [`JobRegistry::enqueue`] refused or failed to queue a job.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EnqueueError {
+    /// No job type is registered under that name.
+    UnknownJob,
+    /// The payload isn't a valid instance of the job type.
+    InvalidPayload(String),
+    /// The job was valid but couldn't be stored.
+    Queue(String),
+}
+
+/// Two job types registered under the same [`Job::NAME`]. Their stored
+/// jobs would be indistinguishable, so this is a startup error.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateJobName(pub &'static str);
+
+impl fmt::Display for DuplicateJobName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "job name {:?} is registered twice", self.0)
+    }
+}
+
+impl std::error::Error for DuplicateJobName {}
+
+#[derive(Clone, Copy)]
+struct Entry {
+    queue: &'static str,
+    run: Runner,
+    enqueue: Enqueuer,
+}
+
+#[derive(Clone, Default)]
+pub struct JobRegistry {
+    entries: HashMap<&'static str, Entry>,
+}
+
+impl JobRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A registry holding every job type this crate ships.
+    pub fn builtin() -> Result<Self, DuplicateJobName> {
+        let mut registry = Self::new();
+        registry
+            .register::<ConfirmEmailJob>()?
+            .register::<WelcomeEmailJob>()?
+            .register::<PasswordResetEmailJob>()?
+            .register::<CheckTrustLevelPromotionJob>()?
+            .register::<PropagateUsernameJob>()?
+            .register::<FetchLinkPreviewsJob>()?
+            .register::<RebakePostsJob>()?;
+        Ok(registry)
+    }
+
+    /// Register `J` under [`Job::NAME`]. Fails if the name is taken.
+    pub fn register<J: Job>(&mut self) -> Result<&mut Self, DuplicateJobName> {
+        if self.entries.contains_key(J::NAME) {
+            return Err(DuplicateJobName(J::NAME));
+        }
+        self.entries.insert(
+            J::NAME,
+            Entry {
+                queue: J::QUEUE,
+                run: run::<J>,
+                enqueue: enqueue::<J>,
+            },
+        );
+        Ok(self)
+    }
+
+    pub fn contains(&self, task_name: &str) -> bool {
+        self.entries.contains_key(task_name)
+    }
+
+    /// Registered job names, sorted.
+    pub fn names(&self) -> Vec<&'static str> {
+        let mut names: Vec<_> = self.entries.keys().copied().collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// Registered jobs, as `(name, queue)`, whose queue has no workers in
+    /// `queues`. Sorted by name.
+    pub fn unserved(&self, queues: &[QueueConfig]) -> Vec<(&'static str, &'static str)> {
+        let mut unserved: Vec<_> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| {
+                !queues
+                    .iter()
+                    .any(|q| q.name == entry.queue && q.workers > 0)
+            })
+            .map(|(&name, entry)| (name, entry.queue))
+            .collect();
+        unserved.sort_unstable();
+        unserved
+    }
+
+    /// Enqueue a job given only its name and JSON payload. The payload is
+    /// deserialized as the registered type first, so a bad one is rejected
+    /// here rather than failing in a worker.
+    pub fn enqueue(
+        &self,
+        queue: &JobQueue,
+        task_name: &str,
+        payload: serde_json::Value,
+    ) -> Result<EnqueueOutcome, EnqueueError> {
+        match self.entries.get(task_name) {
+            Some(entry) => (entry.enqueue)(queue, payload),
+            None => Err(EnqueueError::UnknownJob),
+        }
+    }
+
+    /// Run a stored job. Unknown names and undeserializable payloads fail
+    /// like any other run, so they go through the normal retry path.
+    pub fn execute(
+        &self,
+        task_name: &str,
+        payload: serde_json::Value,
+        pool: DbPool,
+    ) -> BoxFuture<'static, Result<(), String>> {
+        match self.entries.get(task_name) {
+            Some(entry) => (entry.run)(payload, pool),
+            None => {
+                let error = format!("Unknown job type: {}", task_name);
+                Box::pin(async move { Err(error) })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jobs::{DEFAULT_QUEUE, LOW_QUEUE};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize)]
+    struct EchoJob {
+        fail_with: Option<String>,
+    }
+
+    impl Job for EchoJob {
+        const NAME: &'static str = "echo";
+
+        async fn execute(&self, _pool: &DbPool) -> Result<(), String> {
+            match &self.fail_with {
+                Some(message) => Err(message.clone()),
+                None => Ok(()),
+            }
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct OtherEchoJob;
+
+    impl Job for OtherEchoJob {
+        const NAME: &'static str = "echo";
+
+        async fn execute(&self, _pool: &DbPool) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    /// A pool that never connects; these jobs don't touch the database.
+    fn idle_pool() -> DbPool {
+        let manager = diesel::r2d2::ConnectionManager::new("postgres://unused");
+        diesel::r2d2::Pool::builder().build_unchecked(manager)
+    }
+
+    #[test]
+    fn builtin_names_are_unique() {
+        let registry = JobRegistry::builtin().unwrap();
+        assert!(registry.contains("welcome_email"));
+        assert!(registry.contains("check_trust_level_promotion"));
+    }
+
+    #[test]
+    fn unserved_lists_jobs_whose_queue_has_no_workers() {
+        let registry = JobRegistry::builtin().unwrap();
+        assert!(registry.unserved(&QueueConfig::defaults()).is_empty());
+
+        let unserved = registry.unserved(&[
+            QueueConfig::new(DEFAULT_QUEUE, 2),
+            QueueConfig::new(LOW_QUEUE, 0),
+        ]);
+        let names: Vec<_> = unserved.iter().map(|(name, _)| *name).collect();
+        assert_eq!(
+            names,
+            vec![
+                "confirm_email",
+                "password_reset_email",
+                "propagate_username",
+                "rebake_posts",
+                "welcome_email",
+            ]
+        );
+    }
+
+    #[test]
+    fn names_are_sorted() {
+        let mut registry = JobRegistry::new();
+        registry
+            .register::<EchoJob>()
+            .unwrap()
+            .register::<WelcomeEmailJob>()
+            .unwrap();
+        assert_eq!(registry.names(), vec!["echo", "welcome_email"]);
+    }
+
+    #[test]
+    fn enqueue_rejects_unknown_name_and_bad_payload() {
+        let mut registry = JobRegistry::new();
+        registry.register::<EchoJob>().unwrap();
+        // Neither case gets as far as the database.
+        let queue = JobQueue::new(std::sync::Arc::new(idle_pool()));
+
+        let unknown = registry.enqueue(&queue, "nope", serde_json::json!({}));
+        assert_eq!(unknown, Err(EnqueueError::UnknownJob));
+
+        let bad = registry.enqueue(&queue, "echo", serde_json::json!({ "fail_with": 3 }));
+        assert!(matches!(bad, Err(EnqueueError::InvalidPayload(_))));
+    }
+
+    #[test]
+    fn duplicate_name_is_rejected() {
+        let mut registry = JobRegistry::new();
+        registry.register::<EchoJob>().unwrap();
+        let err = registry.register::<OtherEchoJob>().err();
+        assert_eq!(err, Some(DuplicateJobName("echo")));
+    }
+
+    #[tokio::test]
+    async fn execute_deserializes_and_runs_job() {
+        let mut registry = JobRegistry::new();
+        registry.register::<EchoJob>().unwrap();
+
+        let ok = registry
+            .execute(
+                "echo",
+                serde_json::json!({ "fail_with": null }),
+                idle_pool(),
+            )
+            .await;
+        assert_eq!(ok, Ok(()));
+
+        let failed = registry
+            .execute(
+                "echo",
+                serde_json::json!({ "fail_with": "nope" }),
+                idle_pool(),
+            )
+            .await;
+        assert_eq!(failed, Err("nope".to_string()));
+    }
+
+    #[tokio::test]
+    async fn execute_rejects_bad_payload_and_unknown_name() {
+        let mut registry = JobRegistry::new();
+        registry.register::<EchoJob>().unwrap();
+
+        let bad = registry
+            .execute("echo", serde_json::json!({ "fail_with": 3 }), idle_pool())
+            .await;
+        assert!(bad.unwrap_err().starts_with("Failed to deserialize job"));
+
+        let unknown = registry
+            .execute("nope", serde_json::json!({}), idle_pool())
+            .await;
+        assert_eq!(unknown, Err("Unknown job type: nope".to_string()));
+    }
+}
@@ -0,0 +1,185 @@
+//! Retry policy. A failed job is rescheduled with exponential backoff until
+//! it has been retried `max_retries` times; the failure after that marks it
+//! dead. Dead jobs stay in the table for an admin to retry or discard.
+
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+
+use super::{JobRecord, notify_queue};
+use crate::schema::backie_tasks;
+
+/// Delay ceiling for the first retry. Doubles with each further attempt.
+pub const RETRY_BASE_DELAY_SECS: i64 = 10;
+
+/// Upper bound on a single backoff, so a job with a generous
+/// `max_retries` still comes back within the hour.
+pub const RETRY_MAX_DELAY_SECS: i64 = 60 * 60;
+
+/// Backoff ceiling for the retry following `retries` previous retries:
+/// 10s, 20s, 40s, ... capped at [`RETRY_MAX_DELAY_SECS`].
+pub fn backoff_secs(retries: i32) -> i64 {
+    let exponent = retries.clamp(0, 30) as u32;
+    RETRY_BASE_DELAY_SECS
+        .saturating_mul(1 << exponent)
+        .min(RETRY_MAX_DELAY_SECS)
+}
+
+/// Backoff with "equal jitter": half of the ceiling is fixed and the other
+/// half random, so jobs that failed together (e.g. during a DB blip) don't
+/// all retry in the same instant.
+pub fn retry_delay(retries: i32) -> chrono::Duration {
+    let ceiling = backoff_secs(retries);
+    let fixed = ceiling / 2;
+    let jitter = rand::random_range(0..=ceiling - fixed);
+    chrono::Duration::seconds(fixed + jitter)
+}
+
+#[derive(Debug, PartialEq)]
+pub enum FailureOutcome {
+    /// The job was put back in the queue and will run again at this time.
+    Rescheduled(chrono::NaiveDateTime),
+    /// Retries are exhausted; the job was moved to the dead state.
+    Dead,
+    /// The run was no longer the job's current one: the reaper or a
+    /// shutdown had already requeued it, and maybe another worker claimed
+    /// it again. Nothing was recorded.
+    Stale,
+}
+
+/// Record a failed run of `job`. Reschedules it with backoff while it has
+/// retries left, otherwise marks it dead. `error` always holds the most
+/// recent failure message. Only touches the row while it still holds the
+/// run `job` was claimed for; see [`FailureOutcome::Stale`].
+pub fn record_failure(
+    conn: &mut PgConnection,
+    job: &JobRecord,
+    message: &str,
+) -> Result<FailureOutcome, DieselError> {
+    let now = chrono::Utc::now().naive_utc();
+    let current_run = backie_tasks::table
+        .find(job.id)
+        .filter(backie_tasks::running_at.is_not_distinct_from(job.running_at))
+        .filter(backie_tasks::done_at.is_null());
+
+    if job.retries < job.max_retries {
+        let next_run = now + retry_delay(job.retries);
+        let updated = diesel::update(current_run)
+            .set((
+                backie_tasks::retries.eq(job.retries + 1),
+                backie_tasks::scheduled_at.eq(next_run),
+                backie_tasks::running_at.eq(None::<chrono::NaiveDateTime>),
+                backie_tasks::error.eq(Some(message)),
+            ))
+            .execute(conn)?;
+        return Ok(if updated == 0 {
+            FailureOutcome::Stale
+        } else {
+            FailureOutcome::Rescheduled(next_run)
+        });
+    }
+
+    let updated = diesel::update(current_run)
+        .set((
+            backie_tasks::done_at.eq(Some(now)),
+            backie_tasks::dead_at.eq(Some(now)),
+            backie_tasks::error.eq(Some(message)),
+        ))
+        .execute(conn)?;
+    Ok(if updated == 0 {
+        FailureOutcome::Stale
+    } else {
+        FailureOutcome::Dead
+    })
+}
+
+/// Dead jobs, most recently failed first.
+pub fn list_dead(
+    conn: &mut PgConnection,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<JobRecord>, DieselError> {
+    backie_tasks::table
+        .filter(backie_tasks::dead_at.is_not_null())
+        .order(backie_tasks::dead_at.desc())
+        .limit(limit)
+        .offset(offset)
+        .select(JobRecord::as_select())
+        .load(conn)
+}
+
+/// Put a dead job back in the queue with a fresh retry budget. Returns
+/// `None` if the job doesn't exist or isn't dead, and a unique violation
+/// if an identical job is already pending (see [`Uniqueness`](super::Uniqueness)).
+pub fn retry_dead(
+    conn: &mut PgConnection,
+    job_id: uuid::Uuid,
+) -> Result<Option<JobRecord>, DieselError> {
+    let job = diesel::update(
+        backie_tasks::table
+            .filter(backie_tasks::id.eq(job_id))
+            .filter(backie_tasks::dead_at.is_not_null()),
+    )
+    .set((
+        backie_tasks::retries.eq(0),
+        backie_tasks::scheduled_at.eq(chrono::Utc::now().naive_utc()),
+        backie_tasks::running_at.eq(None::<chrono::NaiveDateTime>),
+        backie_tasks::done_at.eq(None::<chrono::NaiveDateTime>),
+        backie_tasks::dead_at.eq(None::<chrono::NaiveDateTime>),
+        backie_tasks::error.eq(None::<String>),
+    ))
+    .returning(JobRecord::as_returning())
+    .get_result(conn)
+    .optional()?;
+
+    if let Some(job) = &job
+        && let Err(e) = notify_queue(conn, &job.queue_name)
+    {
+        log::warn!("Failed to notify queue {}: {}", job.queue_name, e);
+    }
+    Ok(job)
+}
+
+/// Delete a dead job for good. Returns false if the job doesn't exist or
+/// isn't dead, so a live job can never be discarded through this path.
+pub fn discard_dead(conn: &mut PgConnection, job_id: uuid::Uuid) -> Result<bool, DieselError> {
+    let deleted = diesel::delete(
+        backie_tasks::table
+            .filter(backie_tasks::id.eq(job_id))
+            .filter(backie_tasks::dead_at.is_not_null()),
+    )
+    .execute(conn)?;
+    Ok(deleted > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_per_retry() {
+        assert_eq!(backoff_secs(0), 10);
+        assert_eq!(backoff_secs(1), 20);
+        assert_eq!(backoff_secs(2), 40);
+        assert_eq!(backoff_secs(3), 80);
+    }
+
+    #[test]
+    fn backoff_is_capped() {
+        assert_eq!(backoff_secs(20), RETRY_MAX_DELAY_SECS);
+        assert_eq!(backoff_secs(i32::MAX), RETRY_MAX_DELAY_SECS);
+    }
+
+    #[test]
+    fn retry_delay_stays_within_jitter_window() {
+        for retries in 0..8 {
+            let ceiling = backoff_secs(retries);
+            for _ in 0..50 {
+                let secs = retry_delay(retries).num_seconds();
+                assert!(
+                    secs >= ceiling / 2 && secs <= ceiling,
+                    "{secs} outside window"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,670 @@
+//! Claiming, running and recovering jobs.
+
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use futures::StreamExt;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{Notify, watch};
+use tokio::task::JoinHandle;
+use tokio::time::sleep;
+use tokio_postgres::AsyncMessage;
+
+use super::{
+    DEFAULT_QUEUE, FailureOutcome, JobRecord, JobRegistry, LOW_QUEUE, MAIL_QUEUE, NOTIFY_CHANNEL,
+    record_failure,
+};
+use crate::DbPool;
+use crate::schema::backie_tasks;
+
+/// Claim the next runnable job on `queue` and mark it running in a single
+/// statement, so a crash can't leave a job locked-but-not-running or two
+/// workers holding the same job. The highest priority ready job wins, then
+/// the one scheduled earliest. `SKIP LOCKED` lets concurrent workers pass
+/// over a row another worker is claiming instead of queueing behind it.
+pub fn claim_next(conn: &mut PgConnection, queue: &str) -> Result<Option<JobRecord>, DieselError> {
+    diesel::sql_query(
+        "UPDATE backie_tasks
+         SET running_at = NOW()
+         WHERE id = (
+             SELECT id FROM backie_tasks
+             WHERE done_at IS NULL
+               AND running_at IS NULL
+               AND queue_name = $1
+               AND scheduled_at <= NOW()
+             ORDER BY priority DESC, scheduled_at
+             LIMIT 1
+             FOR UPDATE SKIP LOCKED
+         )
+         RETURNING *",
+    )
+    .bind::<diesel::sql_types::Text, _>(queue)
+    .get_result(conn)
+    .optional()
+}
+
+/// Extra time past a job's own timeout before the reaper treats it as
+/// stuck. The worker's in-process timeout normally records the failure
+/// first; the reaper is for runs whose worker died (crash, OOM, deploy).
+pub const REAPER_GRACE_SECS: i64 = 60;
+
+/// How often each worker pool sweeps for stuck jobs.
+pub const REAPER_INTERVAL_SECS: u64 = 60;
+
+/// Recover jobs whose `running_at` is older than their timeout plus
+/// [`REAPER_GRACE_SECS`]. A stuck run counts as a failed attempt: jobs
+/// with retries left are requeued to run immediately, the rest are marked
+/// dead. Counting the attempt keeps a job that crashes its worker from
+/// being retried forever. Returns the number of jobs recovered.
+pub fn reap_stuck(conn: &mut PgConnection) -> Result<usize, DieselError> {
+    const STUCK: &str = "done_at IS NULL
+        AND running_at IS NOT NULL
+        AND running_at < NOW()
+            - make_interval(secs => timeout_msecs / 1000.0 + $1)";
+
+    conn.transaction(|conn| {
+        let dead = diesel::sql_query(format!(
+            "UPDATE backie_tasks
+             SET done_at = NOW(),
+                 dead_at = NOW(),
+                 error = 'abandoned: no result after ' || timeout_msecs || 'ms'
+             WHERE {STUCK} AND retries >= max_retries"
+        ))
+        .bind::<diesel::sql_types::Double, _>(REAPER_GRACE_SECS as f64)
+        .execute(conn)?;
+
+        let requeued = diesel::sql_query(format!(
+            "UPDATE backie_tasks
+             SET running_at = NULL,
+                 retries = retries + 1,
+                 scheduled_at = NOW(),
+                 error = 'abandoned: no result after ' || timeout_msecs || 'ms'
+             WHERE {STUCK}"
+        ))
+        .bind::<diesel::sql_types::Double, _>(REAPER_GRACE_SECS as f64)
+        .execute(conn)?;
+
+        Ok(dead + requeued)
+    })
+}
+
+/// Run a job future on its own task, giving up after `timeout`. A timed
+/// out run (or one whose caller is dropped) is cancelled at its next
+/// `.await`; blocking sections it handed
+/// to `spawn_blocking` can't be interrupted and finish in the background
+/// with their result discarded, so jobs must be safe to run twice. A panic
+/// in the job is reported as an error rather than taking the worker down.
+pub async fn run_with_timeout<F>(timeout: Duration, job: F) -> Result<(), String>
+where
+    F: Future<Output = Result<(), String>> + Send + 'static,
+{
+    let mut handle = AbortOnDrop(tokio::spawn(job));
+    match tokio::time::timeout(timeout, &mut handle.0).await {
+        Ok(Ok(result)) => result,
+        Ok(Err(join_error)) => Err(format!("job panicked: {join_error}")),
+        Err(_) => Err(format!("timed out after {}ms", timeout.as_millis())),
+    }
+}
+
+/// One worker's fixed settings.
+struct Worker {
+    id: String,
+    queue: String,
+    wakeup: Arc<Notify>,
+    poll_interval: Duration,
+    stop: watch::Receiver<bool>,
+    in_flight: InFlight,
+}
+
+/// How many workers to run for one named queue.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueueConfig {
+    pub name: String,
+    pub workers: usize,
+}
+
+impl QueueConfig {
+    pub fn new(name: impl Into<String>, workers: usize) -> Self {
+        Self {
+            name: name.into(),
+            workers,
+        }
+    }
+
+    /// One entry per queue the built-in jobs use.
+    pub fn defaults() -> Vec<Self> {
+        vec![
+            Self::new(DEFAULT_QUEUE, 2),
+            Self::new(MAIL_QUEUE, 1),
+            Self::new(LOW_QUEUE, 1),
+        ]
+    }
+
+    /// Parse a comma-separated list of `name:workers` pairs, as in the
+    /// `JOB_QUEUES` environment variable: `default:2,mail:1,low:1`. Every
+    /// listed queue needs at least one worker.
+    pub fn parse_list(spec: &str) -> Result<Vec<Self>, String> {
+        let mut queues: Vec<Self> = vec![];
+        for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (name, workers) = entry
+                .split_once(':')
+                .ok_or_else(|| format!("expected name:workers, got {:?}", entry))?;
+            let name = name.trim();
+            if name.is_empty() {
+                return Err(format!("missing queue name in {:?}", entry));
+            }
+            let workers: usize = workers
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid worker count in {:?}", entry))?;
+            if workers == 0 {
+                return Err(format!("queue {:?} needs at least one worker", name));
+            }
+            if queues.iter().any(|q| q.name == name) {
+                return Err(format!("queue {:?} is listed twice", name));
+            }
+            queues.push(Self::new(name, workers));
+        }
+        if queues.is_empty() {
+            return Err("no queues configured".to_string());
+        }
+        Ok(queues)
+    }
+}
+
+/// How long an idle worker waits before checking its queue again when no
+/// notification arrives. Only a safety net: enqueues wake workers through
+/// `NOTIFY`, but retries coming due and reaped jobs don't.
+pub const DEFAULT_POLL_INTERVAL_SECS: u64 = 10;
+
+/// Delay before the listener reconnects after losing its connection.
+const LISTEN_RECONNECT_SECS: u64 = 5;
+
+/// How long [`WorkerPoolHandle::shutdown`] waits for running jobs by
+/// default before abandoning them.
+pub const DEFAULT_SHUTDOWN_TIMEOUT_SECS: u64 = 30;
+
+/// Put jobs this process was running back in the queue after shutdown cut
+/// them off. Like a reaped run this counts as an attempt (which keeps the
+/// job clear of the pending-uniqueness index), but it never uses up the
+/// last retry: being interrupted by a deploy is not the job's fault.
+/// Returns the number of jobs requeued.
+pub fn release_abandoned(
+    conn: &mut PgConnection,
+    job_ids: &[uuid::Uuid],
+) -> Result<usize, DieselError> {
+    diesel::sql_query(
+        "UPDATE backie_tasks
+         SET running_at = NULL,
+             retries = LEAST(retries + 1, max_retries),
+             scheduled_at = NOW(),
+             error = 'abandoned: worker shut down'
+         WHERE id = ANY($1)
+           AND done_at IS NULL
+           AND running_at IS NOT NULL",
+    )
+    .bind::<diesel::sql_types::Array<diesel::sql_types::Uuid>, _>(job_ids)
+    .execute(conn)
+}
+
+/// Aborts the task when dropped, so a job doesn't outlive the worker
+/// waiting on it.
+struct AbortOnDrop<T>(JoinHandle<T>);
+
+impl<T> Drop for AbortOnDrop<T> {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Ids of the jobs this pool's workers are running right now.
+type InFlight = Arc<Mutex<HashSet<uuid::Uuid>>>;
+
+// Worker pool for processing jobs. Each configured queue gets its own
+// workers, so one queue backing up never delays another. Workers drain
+// their queue, then sleep until a `NOTIFY` for it or the poll interval.
+pub struct WorkerPool {
+    pool: Arc<DbPool>,
+    registry: Arc<JobRegistry>,
+    queues: Vec<QueueConfig>,
+    database_url: Option<String>,
+    poll_interval: Duration,
+    shutdown_timeout: Duration,
+}
+
+impl WorkerPool {
+    pub fn new(pool: Arc<DbPool>, registry: JobRegistry, queues: Vec<QueueConfig>) -> Self {
+        Self {
+            pool,
+            registry: Arc::new(registry),
+            queues,
+            database_url: None,
+            poll_interval: Duration::from_secs(DEFAULT_POLL_INTERVAL_SECS),
+            shutdown_timeout: Duration::from_secs(DEFAULT_SHUTDOWN_TIMEOUT_SECS),
+        }
+    }
+
+    /// Open a dedicated connection to `database_url` and `LISTEN` for
+    /// enqueue notifications. Without it, workers only poll.
+    pub fn listen(mut self, database_url: impl Into<String>) -> Self {
+        self.database_url = Some(database_url.into());
+        self
+    }
+
+    pub fn poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// How long shutdown waits for running jobs before abandoning them.
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.shutdown_timeout = timeout;
+        self
+    }
+
+    /// Spawn the workers, reaper and listener. They run until
+    /// [`WorkerPoolHandle::shutdown`] is called.
+    pub fn start(self) -> WorkerPoolHandle {
+        for queue in &self.queues {
+            log::info!(
+                "Starting {} worker(s) for job queue {:?}",
+                queue.workers,
+                queue.name
+            );
+        }
+        for (job, queue) in self.registry.unserved(&self.queues) {
+            log::warn!(
+                "Job {:?} runs on queue {:?}, which has no workers; its jobs will pile up",
+                job,
+                queue
+            );
+        }
+
+        let (stop_tx, stop_rx) = watch::channel(false);
+        let in_flight = InFlight::default();
+        let mut workers = vec![];
+        let mut background = vec![];
+
+        let pool = Arc::clone(&self.pool);
+        background.push(tokio::spawn(async move {
+            Self::reaper_loop(pool).await;
+        }));
+
+        let wakeups: Arc<HashMap<String, Arc<Notify>>> = Arc::new(
+            self.queues
+                .iter()
+                .map(|queue| (queue.name.clone(), Arc::new(Notify::new())))
+                .collect(),
+        );
+
+        if let Some(database_url) = self.database_url.clone() {
+            let wakeups = Arc::clone(&wakeups);
+            background.push(tokio::spawn(async move {
+                Self::listener_loop(database_url, wakeups).await;
+            }));
+        }
+
+        for queue in &self.queues {
+            for n in 0..queue.workers {
+                let worker = Worker {
+                    id: format!("{}-{}", queue.name, n),
+                    queue: queue.name.clone(),
+                    wakeup: Arc::clone(&wakeups[&queue.name]),
+                    poll_interval: self.poll_interval,
+                    stop: stop_rx.clone(),
+                    in_flight: Arc::clone(&in_flight),
+                };
+                let pool = Arc::clone(&self.pool);
+                let registry = Arc::clone(&self.registry);
+                workers.push(tokio::spawn(async move {
+                    Self::worker_loop(worker, pool, registry).await;
+                }));
+            }
+        }
+
+        WorkerPoolHandle {
+            stop: stop_tx,
+            workers,
+            background,
+            in_flight,
+            pool: self.pool,
+            shutdown_timeout: self.shutdown_timeout,
+        }
+    }
+
+    async fn worker_loop(mut worker: Worker, pool: Arc<DbPool>, registry: Arc<JobRegistry>) {
+        log::info!("Worker {} started", worker.id);
+
+        loop {
+            if *worker.stop.borrow() {
+                break;
+            }
+
+            // Register for wakeups before looking, so a job enqueued
+            // between an empty claim and the wait below isn't missed.
+            let notified = worker.wakeup.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            match Self::claim_and_execute_job(&worker, &pool, &registry).await {
+                // Keep draining until the queue is empty.
+                Ok(true) => continue,
+                Ok(false) => {}
+                Err(e) => {
+                    log::error!("Worker {} error: {}", worker.id, e);
+                }
+            }
+
+            tokio::select! {
+                _ = &mut notified => {}
+                _ = sleep(worker.poll_interval) => {}
+                // Checked at the top of the loop. An error means the
+                // handle is gone, which is a stop too.
+                changed = worker.stop.changed() => if changed.is_err() { break; },
+            }
+        }
+
+        log::info!("Worker {} stopped", worker.id);
+    }
+
+    async fn listener_loop(database_url: String, wakeups: Arc<HashMap<String, Arc<Notify>>>) {
+        loop {
+            if let Err(e) = Self::listen_for_jobs(&database_url, &wakeups).await {
+                log::error!("Job listener error: {}", e);
+            }
+
+            // Anything enqueued while disconnected went unannounced.
+            for wakeup in wakeups.values() {
+                wakeup.notify_waiters();
+            }
+            sleep(Duration::from_secs(LISTEN_RECONNECT_SECS)).await;
+        }
+    }
+
+    /// Hold a `LISTEN` connection open, waking a queue's workers for each
+    /// notification naming it. Returns when the connection drops.
+    async fn listen_for_jobs(
+        database_url: &str,
+        wakeups: &Arc<HashMap<String, Arc<Notify>>>,
+    ) -> Result<(), String> {
+        let (client, mut connection) = tokio_postgres::connect(database_url, tokio_postgres::NoTls)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        // The connection only makes progress while polled, and that's also
+        // where notifications surface.
+        let wakeups = Arc::clone(wakeups);
+        let messages = tokio::spawn(async move {
+            let mut messages = futures::stream::poll_fn(move |cx| connection.poll_message(cx));
+            while let Some(message) = messages.next().await {
+                if let AsyncMessage::Notification(notification) = message?
+                    && let Some(wakeup) = wakeups.get(notification.payload())
+                {
+                    wakeup.notify_waiters();
+                }
+            }
+            Ok::<(), tokio_postgres::Error>(())
+        });
+
+        client
+            .batch_execute(&format!("LISTEN {}", NOTIFY_CHANNEL))
+            .await
+            .map_err(|e| e.to_string())?;
+        log::info!("Listening for jobs on channel {}", NOTIFY_CHANNEL);
+
+        // `client` must stay alive until here: dropping it closes the
+        // connection.
+        let result = messages.await.map_err(|e| e.to_string())?;
+        drop(client);
+        result.map_err(|e| e.to_string())
+    }
+
+    async fn reaper_loop(pool: Arc<DbPool>) {
+        loop {
+            sleep(Duration::from_secs(REAPER_INTERVAL_SECS)).await;
+
+            let reaped = pool
+                .get()
+                .map_err(|e| e.to_string())
+                .and_then(|mut conn| reap_stuck(&mut conn).map_err(|e| e.to_string()));
+            match reaped {
+                Ok(0) => {}
+                Ok(n) => log::warn!("Reaper recovered {} stuck job(s)", n),
+                Err(e) => log::error!("Reaper error: {}", e),
+            }
+        }
+    }
+
+    async fn claim_and_execute_job(
+        worker: &Worker,
+        pool: &Arc<DbPool>,
+        registry: &JobRegistry,
+    ) -> Result<bool, String> {
+        let worker_id = &worker.id;
+        let job = {
+            let mut conn = pool.get().map_err(|e| e.to_string())?;
+            claim_next(&mut conn, &worker.queue).map_err(|e| e.to_string())?
+        };
+
+        if let Some(job_record) = job {
+            // Tracked until its result is recorded, so shutdown knows what
+            // to hand back to the queue if it has to abandon this run.
+            worker.in_flight.lock().unwrap().insert(job_record.id);
+
+            log::info!(
+                "Worker {} claimed job {} ({})",
+                worker_id,
+                job_record.task_name,
+                job_record.id
+            );
+
+            // No connection is held while the job runs; it checks out its own.
+            let timeout = Duration::from_millis(job_record.timeout_msecs.max(0) as u64);
+            let job = registry.execute(
+                &job_record.task_name,
+                job_record.payload.clone(),
+                DbPool::clone(pool),
+            );
+            let result = run_with_timeout(timeout, job).await;
+
+            let recorded = Self::record_result(worker_id, pool, &job_record, result);
+            worker.in_flight.lock().unwrap().remove(&job_record.id);
+            recorded?;
+
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    fn record_result(
+        worker_id: &str,
+        pool: &DbPool,
+        job_record: &JobRecord,
+        result: Result<(), String>,
+    ) -> Result<(), String> {
+        let mut conn = pool.get().map_err(|e| e.to_string())?;
+
+        // Update job status
+        match result {
+            Ok(_) => {
+                // Only if the row still holds this run; see FailureOutcome::Stale.
+                let updated = diesel::update(
+                    backie_tasks::table
+                        .find(job_record.id)
+                        .filter(
+                            backie_tasks::running_at.is_not_distinct_from(job_record.running_at),
+                        )
+                        .filter(backie_tasks::done_at.is_null()),
+                )
+                .set((
+                    backie_tasks::done_at.eq(Some(chrono::Utc::now().naive_utc())),
+                    backie_tasks::error.eq(None::<String>),
+                ))
+                .execute(&mut conn)
+                .map_err(|e| e.to_string())?;
+                if updated == 0 {
+                    log::warn!(
+                        "Worker {} completed job {} after it was requeued; not recording the result",
+                        worker_id,
+                        job_record.id
+                    );
+                } else {
+                    log::info!("Worker {} completed job {}", worker_id, job_record.id);
+                }
+            }
+            Err(e) => {
+                match record_failure(&mut conn, job_record, &e).map_err(|e| e.to_string())? {
+                    FailureOutcome::Rescheduled(at) => log::warn!(
+                        "Worker {} failed job {} (attempt {}/{}), retrying at {}: {}",
+                        worker_id,
+                        job_record.id,
+                        job_record.retries + 1,
+                        job_record.max_retries + 1,
+                        at,
+                        e
+                    ),
+                    FailureOutcome::Dead => log::error!(
+                        "Worker {} failed job {} after {} retries, marked dead: {}",
+                        worker_id,
+                        job_record.id,
+                        job_record.retries,
+                        e
+                    ),
+                    FailureOutcome::Stale => log::warn!(
+                        "Worker {} failed job {} after it was requeued; not recording the failure: {}",
+                        worker_id,
+                        job_record.id,
+                        e
+                    ),
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A started [`WorkerPool`].
+pub struct WorkerPoolHandle {
+    stop: watch::Sender<bool>,
+    workers: Vec<JoinHandle<()>>,
+    background: Vec<JoinHandle<()>>,
+    in_flight: InFlight,
+    pool: Arc<DbPool>,
+    shutdown_timeout: Duration,
+}
+
+impl WorkerPoolHandle {
+    /// Stop claiming jobs and wait for running ones to finish, up to the
+    /// pool's shutdown timeout. Jobs still running after that are
+    /// cancelled and requeued via [`release_abandoned`]. Returns how many
+    /// were abandoned.
+    pub async fn shutdown(self) -> usize {
+        log::info!(
+            "Stopping job workers; waiting up to {}s for running jobs",
+            self.shutdown_timeout.as_secs()
+        );
+        let _ = self.stop.send(true);
+        for task in &self.background {
+            task.abort();
+        }
+
+        let aborts: Vec<_> = self.workers.iter().map(|w| w.abort_handle()).collect();
+        let finished = tokio::time::timeout(
+            self.shutdown_timeout,
+            futures::future::join_all(self.workers),
+        )
+        .await;
+        if finished.is_ok() {
+            log::info!("Job workers stopped");
+            return 0;
+        }
+
+        // Aborting a worker drops its job's task, which cancels the job.
+        for abort in &aborts {
+            abort.abort();
+        }
+        let abandoned: Vec<uuid::Uuid> = self.in_flight.lock().unwrap().drain().collect();
+        if abandoned.is_empty() {
+            return 0;
+        }
+
+        let released = self
+            .pool
+            .get()
+            .map_err(|e| e.to_string())
+            .and_then(|mut conn| {
+                release_abandoned(&mut conn, &abandoned).map_err(|e| e.to_string())
+            });
+        match released {
+            Ok(n) => log::warn!("Shutdown abandoned {} running job(s); requeued them", n),
+            // The reaper will recover them once their timeout passes.
+            Err(e) => log::error!("Failed to requeue abandoned jobs: {}", e),
+        }
+        abandoned.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_list_reads_name_worker_pairs() {
+        let queues = QueueConfig::parse_list("default:2, mail:1,low:3").unwrap();
+        assert_eq!(
+            queues,
+            vec![
+                QueueConfig::new("default", 2),
+                QueueConfig::new("mail", 1),
+                QueueConfig::new("low", 3),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_list_rejects_malformed_specs() {
+        assert!(QueueConfig::parse_list("").is_err());
+        assert!(QueueConfig::parse_list("default").is_err());
+        assert!(QueueConfig::parse_list(":2").is_err());
+        assert!(QueueConfig::parse_list("default:two").is_err());
+        assert!(QueueConfig::parse_list("default:1,default:2").is_err());
+        assert!(QueueConfig::parse_list("default:2,low:0").is_err());
+    }
+
+    #[tokio::test]
+    async fn run_with_timeout_returns_job_result() {
+        let ok = run_with_timeout(Duration::from_secs(1), async { Ok(()) }).await;
+        assert_eq!(ok, Ok(()));
+
+        let failed =
+            run_with_timeout(Duration::from_secs(1), async { Err("nope".to_string()) }).await;
+        assert_eq!(failed, Err("nope".to_string()));
+    }
+
+    #[tokio::test]
+    async fn run_with_timeout_cancels_slow_job() {
+        let finished = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let flag = Arc::clone(&finished);
+        let result = run_with_timeout(Duration::from_millis(10), async move {
+            sleep(Duration::from_millis(100)).await;
+            flag.store(true, std::sync::atomic::Ordering::SeqCst);
+            Ok(())
+        })
+        .await;
+        assert_eq!(result, Err("timed out after 10ms".to_string()));
+
+        // The job was cancelled, not left running in the background.
+        sleep(Duration::from_millis(200)).await;
+        assert!(!finished.load(std::sync::atomic::Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn run_with_timeout_reports_panics() {
+        let result = run_with_timeout(Duration::from_secs(1), async { panic!("kaboom") }).await;
+        assert!(result.unwrap_err().starts_with("job panicked"));
+    }
+}
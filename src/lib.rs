@@ -6,17 +6,23 @@
 
 pub mod auth;
 pub mod config;
+pub mod emoji;
+pub mod error;
 pub mod guardian;
 pub mod jobs;
+pub mod mailer;
 pub mod markdown;
 pub mod middleware;
 pub mod models;
 pub mod moderation;
+pub mod onebox;
 pub mod openapi;
 pub mod pagination;
+pub mod rate_limit;
 pub mod routes;
 pub mod schema;
 pub mod services;
+pub mod validation;
 
 use diesel::pg::PgConnection;
 use diesel::r2d2::{self, ConnectionManager};
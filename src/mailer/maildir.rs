@@ -0,0 +1,51 @@
+//! Writes each message into a Maildir, for development: point a mail
+//! client (or `mutt -f tmp/mail`) at the directory to read what was sent.
+
+use futures::future::BoxFuture;
+use std::path::{Path, PathBuf};
+
+use super::{Email, MailError, Mailer};
+
+#[derive(Debug, Clone)]
+pub struct MaildirMailer {
+    root: PathBuf,
+}
+
+impl MaildirMailer {
+    /// The `cur`, `new` and `tmp` subdirectories are created on first send.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+}
+
+impl Mailer for MaildirMailer {
+    fn send<'a>(&'a self, email: &'a Email) -> BoxFuture<'a, Result<(), MailError>> {
+        Box::pin(async move {
+            let bytes = email.to_message()?.formatted();
+            let io_err = |e: std::io::Error| MailError::Transport(e.to_string());
+
+            for sub in ["cur", "new", "tmp"] {
+                tokio::fs::create_dir_all(self.root.join(sub))
+                    .await
+                    .map_err(io_err)?;
+            }
+
+            // Maildir delivery: write under tmp/, then rename into new/ so
+            // readers never see a partial message.
+            let name = format!(
+                "{}.{}.discourse-rs",
+                chrono::Utc::now().timestamp(),
+                uuid::Uuid::new_v4().simple()
+            );
+            let tmp = self.root.join("tmp").join(&name);
+            tokio::fs::write(&tmp, bytes).await.map_err(io_err)?;
+            tokio::fs::rename(&tmp, self.root.join("new").join(&name))
+                .await
+                .map_err(io_err)
+        })
+    }
+}
@@ -0,0 +1,48 @@
+//! Keeps messages in memory. The default when no transport is installed,
+//! and what tests install to check what would have been sent.
+
+use futures::future::BoxFuture;
+use std::sync::{Arc, Mutex};
+
+use super::{Email, MailError, Mailer};
+
+/// Cloning shares the outbox, so a test can keep one handle and install
+/// the other.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryMailer {
+    outbox: Arc<Mutex<Vec<Email>>>,
+}
+
+impl MemoryMailer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Everything sent so far, oldest first.
+    pub fn sent(&self) -> Vec<Email> {
+        self.outbox
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Empty the outbox, returning what was in it.
+    pub fn take(&self) -> Vec<Email> {
+        std::mem::take(&mut *self.outbox.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+}
+
+impl Mailer for MemoryMailer {
+    fn send<'a>(&'a self, email: &'a Email) -> BoxFuture<'a, Result<(), MailError>> {
+        Box::pin(async move {
+            // Same validation as the real transports, so tests catch
+            // addresses they'd reject.
+            email.to_message()?;
+            self.outbox
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .push(email.clone());
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,37 @@
+//! Relays through an SMTP server.
+
+use futures::future::BoxFuture;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Tokio1Executor};
+
+use super::{Email, MailError, Mailer};
+
+#[derive(Clone)]
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl SmtpMailer {
+    /// `smtps://` connects over TLS, `smtp://...?tls=required` upgrades
+    /// with STARTTLS, and plain `smtp://` sends in the clear (only for a
+    /// local relay or mail catcher). Credentials go in the userinfo part.
+    /// Connections are pooled and opened on first send.
+    pub fn from_url(url: &str) -> Result<Self, String> {
+        let transport = AsyncSmtpTransport::<Tokio1Executor>::from_url(url)
+            .map_err(|e| format!("invalid SMTP_URL: {}", e))?
+            .build();
+        Ok(Self { transport })
+    }
+}
+
+impl Mailer for SmtpMailer {
+    fn send<'a>(&'a self, email: &'a Email) -> BoxFuture<'a, Result<(), MailError>> {
+        Box::pin(async move {
+            let message = email.to_message()?;
+            self.transport
+                .send(message)
+                .await
+                .map(|_| ())
+                .map_err(|e| MailError::Transport(e.to_string()))
+        })
+    }
+}
@@ -0,0 +1,353 @@
+//! The messages we send. Each [`Template`] renders a subject plus a
+//! plain-text and an HTML body from the same data, so the two parts never
+//! drift apart. Everything interpolated into HTML is escaped.
+
+use std::fmt::Write;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DigestTopic {
+    pub title: String,
+    pub url: String,
+    pub posts_count: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Template {
+    /// Sent at registration and on resend. `confirm_url` carries the token.
+    ConfirmEmail {
+        username: String,
+        confirm_url: String,
+        expires_in_hours: i64,
+    },
+    /// Sent once, after the address is confirmed.
+    Welcome { username: String },
+    /// A link to set a new password. `reset_url` carries the token.
+    PasswordReset {
+        username: String,
+        reset_url: String,
+        expires_in_minutes: i64,
+    },
+    /// Activity the user missed since their last visit.
+    Digest {
+        username: String,
+        topics: Vec<DigestTopic>,
+    },
+    /// A single notification (reply, mention, ...) delivered by email.
+    /// `message` is the one-line summary shown in the notification list.
+    Notification {
+        username: String,
+        message: String,
+        url: String,
+    },
+}
+
+/// A rendered message, before addressing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rendered {
+    pub subject: String,
+    pub text: String,
+    pub html: String,
+}
+
+impl Template {
+    /// Stored as `email_logs.email_type`.
+    pub fn email_type(&self) -> &'static str {
+        match self {
+            Template::ConfirmEmail { .. } => "confirm_email",
+            Template::Welcome { .. } => "welcome",
+            Template::PasswordReset { .. } => "password_reset",
+            Template::Digest { .. } => "digest",
+            Template::Notification { .. } => "notification",
+        }
+    }
+
+    /// `base_url` is the forum's public root, without a trailing slash.
+    pub fn render(&self, site_name: &str, base_url: &str) -> Rendered {
+        match self {
+            Template::ConfirmEmail {
+                username,
+                confirm_url,
+                expires_in_hours,
+            } => Rendered {
+                subject: format!("[{}] Confirm your email address", site_name),
+                text: format!(
+                    "Hi {username},\n\n\
+                     Follow this link to confirm your email address and finish \
+                     setting up your account:\n\n\
+                     {confirm_url}\n\n\
+                     The link expires in {expires_in_hours} hours. If you didn't sign \
+                     up, ignore this email.\n"
+                ),
+                html: layout(
+                    site_name,
+                    base_url,
+                    &format!(
+                        "<p>Hi {},</p>\n\
+                         <p>Follow this link to confirm your email address and finish \
+                         setting up your account:</p>\n\
+                         {}\n\
+                         <p>The link expires in {} hours. If you didn't sign up, ignore \
+                         this email.</p>",
+                        escape(username),
+                        button(confirm_url, "Confirm email"),
+                        expires_in_hours,
+                    ),
+                ),
+            },
+            Template::Welcome { username } => Rendered {
+                subject: format!("Welcome to {}!", site_name),
+                text: format!(
+                    "Hi {username},\n\n\
+                     Thanks for joining {site_name}. Jump in and say hello:\n\n\
+                     {base_url}\n"
+                ),
+                html: layout(
+                    site_name,
+                    base_url,
+                    &format!(
+                        "<p>Hi {},</p>\n\
+                         <p>Thanks for joining {}. Jump in and say hello:</p>\n\
+                         {}",
+                        escape(username),
+                        escape(site_name),
+                        button(base_url, "Visit the forum"),
+                    ),
+                ),
+            },
+            Template::PasswordReset {
+                username,
+                reset_url,
+                expires_in_minutes,
+            } => Rendered {
+                subject: format!("[{}] Reset your password", site_name),
+                text: format!(
+                    "Hi {username},\n\n\
+                     Someone asked to reset the password for your account. \
+                     Follow this link to choose a new one:\n\n\
+                     {reset_url}\n\n\
+                     The link works once and expires in {expires_in_minutes} minutes. \
+                     If you didn't ask for this, ignore this email; your password \
+                     won't change.\n"
+                ),
+                html: layout(
+                    site_name,
+                    base_url,
+                    &format!(
+                        "<p>Hi {},</p>\n\
+                         <p>Someone asked to reset the password for your account. \
+                         Follow this link to choose a new one:</p>\n\
+                         {}\n\
+                         <p>The link works once and expires in {} minutes. \
+                         If you didn't ask for this, ignore this email; your password \
+                         won't change.</p>",
+                        escape(username),
+                        button(reset_url, "Reset password"),
+                        expires_in_minutes,
+                    ),
+                ),
+            },
+            Template::Digest { username, topics } => {
+                let mut text = format!("Hi {username},\n\nHere's what you missed:\n\n");
+                let mut items = String::new();
+                for topic in topics {
+                    let _ = writeln!(
+                        text,
+                        "- {} ({} {})\n  {}",
+                        topic.title,
+                        topic.posts_count,
+                        posts_noun(topic.posts_count),
+                        topic.url
+                    );
+                    let _ = writeln!(
+                        items,
+                        "<li><a href=\"{}\">{}</a> ({} {})</li>",
+                        escape(&topic.url),
+                        escape(&topic.title),
+                        topic.posts_count,
+                        posts_noun(topic.posts_count)
+                    );
+                }
+                Rendered {
+                    subject: format!("[{}] Summary", site_name),
+                    text,
+                    html: layout(
+                        site_name,
+                        base_url,
+                        &format!(
+                            "<p>Hi {},</p>\n<p>Here's what you missed:</p>\n<ul>\n{}</ul>",
+                            escape(username),
+                            items
+                        ),
+                    ),
+                }
+            }
+            Template::Notification {
+                username,
+                message,
+                url,
+            } => Rendered {
+                subject: format!("[{}] {}", site_name, message),
+                text: format!("Hi {username},\n\n{message}\n\n{url}\n"),
+                html: layout(
+                    site_name,
+                    base_url,
+                    &format!(
+                        "<p>Hi {},</p>\n<p>{}</p>\n{}",
+                        escape(username),
+                        escape(message),
+                        button(url, "View"),
+                    ),
+                ),
+            },
+        }
+    }
+}
+
+fn posts_noun(count: i32) -> &'static str {
+    if count == 1 { "post" } else { "posts" }
+}
+
+/// Shared HTML wrapper. Inline styles only: most mail clients drop
+/// `<style>` blocks.
+fn layout(site_name: &str, base_url: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n\
+         <html>\n\
+         <body style=\"font-family: sans-serif; color: #222; max-width: 600px;\">\n\
+         <h2><a href=\"{}\" style=\"color: #222; text-decoration: none;\">{}</a></h2>\n\
+         {}\n\
+         </body>\n\
+         </html>\n",
+        escape(base_url),
+        escape(site_name),
+        body
+    )
+}
+
+fn button(url: &str, label: &str) -> String {
+    format!(
+        "<p><a href=\"{}\" style=\"background: #0088cc; color: #fff; padding: 8px 16px; \
+         text-decoration: none; border-radius: 4px;\">{}</a></p>",
+        escape(url),
+        escape(label)
+    )
+}
+
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SITE: &str = "Test Forum";
+    const BASE: &str = "https://forum.example";
+
+    #[test]
+    fn welcome_names_site_and_user() {
+        let rendered = Template::Welcome {
+            username: "alice".to_string(),
+        }
+        .render(SITE, BASE);
+        assert_eq!(rendered.subject, "Welcome to Test Forum!");
+        assert!(rendered.text.contains("Hi alice,"));
+        assert!(rendered.text.contains(BASE));
+        assert!(rendered.html.contains("<p>Hi alice,</p>"));
+    }
+
+    #[test]
+    fn confirm_email_links_to_confirm_url() {
+        let rendered = Template::ConfirmEmail {
+            username: "alice".to_string(),
+            confirm_url: "https://forum.example/confirm-email/abc".to_string(),
+            expires_in_hours: 48,
+        }
+        .render(SITE, BASE);
+        assert_eq!(rendered.subject, "[Test Forum] Confirm your email address");
+        assert!(
+            rendered
+                .text
+                .contains("https://forum.example/confirm-email/abc")
+        );
+        assert!(
+            rendered
+                .html
+                .contains("href=\"https://forum.example/confirm-email/abc\"")
+        );
+    }
+
+    #[test]
+    fn password_reset_links_to_reset_url() {
+        let rendered = Template::PasswordReset {
+            username: "alice".to_string(),
+            reset_url: "https://forum.example/reset?token=a&b".to_string(),
+            expires_in_minutes: 60,
+        }
+        .render(SITE, BASE);
+        assert!(
+            rendered
+                .text
+                .contains("https://forum.example/reset?token=a&b")
+        );
+        assert!(
+            rendered
+                .html
+                .contains("href=\"https://forum.example/reset?token=a&amp;b\"")
+        );
+        assert!(rendered.text.contains("60 minutes"));
+    }
+
+    #[test]
+    fn digest_lists_every_topic() {
+        let rendered = Template::Digest {
+            username: "alice".to_string(),
+            topics: vec![
+                DigestTopic {
+                    title: "First".to_string(),
+                    url: "https://forum.example/t/1".to_string(),
+                    posts_count: 1,
+                },
+                DigestTopic {
+                    title: "Second".to_string(),
+                    url: "https://forum.example/t/2".to_string(),
+                    posts_count: 4,
+                },
+            ],
+        }
+        .render(SITE, BASE);
+        assert!(rendered.text.contains("- First (1 post)"));
+        assert!(rendered.text.contains("- Second (4 posts)"));
+        assert_eq!(rendered.html.matches("<li>").count(), 2);
+    }
+
+    #[test]
+    fn user_content_is_escaped_in_html_only() {
+        let rendered = Template::Notification {
+            username: "alice".to_string(),
+            message: "<script>alert(1)</script> replied".to_string(),
+            url: "https://forum.example/t/1\" onclick=\"x".to_string(),
+        }
+        .render("<b>Forum</b>", BASE);
+        assert!(!rendered.html.contains("<script>"));
+        assert!(!rendered.html.contains("<b>Forum</b>"));
+        assert!(!rendered.html.contains("\" onclick"));
+        assert!(
+            rendered
+                .html
+                .contains("&lt;script&gt;alert(1)&lt;/script&gt; replied")
+        );
+        assert!(rendered.text.contains("<script>alert(1)</script> replied"));
+    }
+}
@@ -5,7 +5,7 @@ use std::env;
 use utoipa::OpenApi;
 use utoipa_swagger_ui::SwaggerUi;
 
-use discourse_rs::{jobs, openapi, routes, DbPool};
+use discourse_rs::{jobs, mailer, openapi, rate_limit, routes, DbPool};
 
 #[get("/")]
 async fn index() -> impl Responder {
@@ -33,15 +33,33 @@ async fn main() -> std::io::Result<()> {
         .build(manager)
         .expect("Failed to create pool");
 
+    // Jobs send mail through whatever transport is installed here.
+    mailer::install(mailer::from_env().expect("Invalid mail settings"));
+
     // Set up background job queue and worker pool
     let pool_arc = std::sync::Arc::new(pool.clone());
     let job_queue = jobs::JobQueue::new(pool_arc.clone());
-    let worker_pool = jobs::WorkerPool::new(pool_arc, 4);
-
-    // Start worker pool in background
-    tokio::spawn(async move {
-        worker_pool.run().await;
-    });
+    // Posts cooked by an older markdown pipeline are re-cooked in the
+    // background; this is a no-op once they're all current.
+    if let Err(e) = job_queue.enqueue(jobs::RebakePostsJob { after_id: 0 }) {
+        log::warn!("Failed to enqueue rebake: {e}");
+    }
+    let registry = jobs::JobRegistry::builtin().expect("Failed to register background jobs");
+    let queues = match env::var("JOB_QUEUES") {
+        Ok(spec) => jobs::QueueConfig::parse_list(&spec).expect("Invalid JOB_QUEUES"),
+        Err(_) => jobs::QueueConfig::defaults(),
+    };
+    // Both the HTTP server and the job workers get this long to finish
+    // in-flight work after a shutdown signal.
+    let shutdown_timeout = env::var("SHUTDOWN_TIMEOUT_SECS")
+        .ok()
+        .map(|secs| secs.parse::<u64>().expect("Invalid SHUTDOWN_TIMEOUT_SECS"))
+        .unwrap_or(jobs::DEFAULT_SHUTDOWN_TIMEOUT_SECS);
+    let registry_data = web::Data::new(registry.clone());
+    let worker_pool = jobs::WorkerPool::new(pool_arc, registry, queues)
+        .listen(&database_url)
+        .shutdown_timeout(std::time::Duration::from_secs(shutdown_timeout))
+        .start();
 
     log::info!("Starting server at http://127.0.0.1:8080");
 
@@ -49,16 +67,18 @@ async fn main() -> std::io::Result<()> {
 
     // Rate limiting: 60 requests per minute per IP
     let governor_conf = GovernorConfigBuilder::default()
-        .per_second(1)
+        .key_extractor(rate_limit::PeerIp)
+        .seconds_per_request(1)
         .burst_size(60)
         .finish()
         .unwrap();
 
-    HttpServer::new(move || {
+    let server = HttpServer::new(move || {
         App::new()
             .wrap(Governor::new(&governor_conf))
             .app_data(web::Data::new(pool.clone()))
             .app_data(job_queue_data.clone())
+            .app_data(registry_data.clone())
             .service(index)
             .service(health)
             .service(web::scope("/api").configure(routes::config))
@@ -68,6 +88,46 @@ async fn main() -> std::io::Result<()> {
             )
     })
     .bind(("127.0.0.1", 8080))?
-    .run()
-    .await
+    .shutdown_timeout(shutdown_timeout)
+    // Signals are handled below so the workers stop claiming at the same
+    // time the server stops accepting connections.
+    .disable_signals()
+    .run();
+
+    let server_handle = server.handle();
+    let stopping = tokio::spawn(async move {
+        shutdown_signal().await;
+        log::info!("Shutdown signal received");
+        tokio::join!(server_handle.stop(true), worker_pool.shutdown());
+    });
+
+    server.await?;
+    // The server only stops from the task above; let it finish draining
+    // the workers before the process exits.
+    let _ = stopping.await;
+    Ok(())
+}
+
+/// Resolves on Ctrl-C, or SIGTERM on Unix.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to listen for Ctrl-C");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
 }
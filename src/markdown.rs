@@ -1,7 +1,321 @@
-use comrak::{markdown_to_html, Options};
+//! Cooking: raw markdown in, the HTML stored in `posts.cooked` out.
+//!
+//! A [`Cooker`] parses the post with comrak and runs an ordered list of
+//! [`Hook`]s over the syntax tree before formatting it. Working on the tree
+//! rather than on HTML lets hooks rewrite text (e.g. turn `@name` into a
+//! profile link) without touching code spans, code blocks or existing
+//! links. The formatted HTML, user-written tags and hook output alike,
+//! then goes through an allowlist sanitizer ([`sanitize`]), which is the
+//! only thing standing between a post and the reader's browser.
+//!
+//! Each post records the [`COOK_VERSION`] it was cooked with; bump it
+//! whenever a change here alters the output for existing posts, and
+//! `RebakePostsJob` re-cooks them.
+
+mod emoji;
+mod hashtags;
+mod mentions;
+mod oneboxes;
+mod quotes;
+mod sanitize;
+mod watched_words;
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::sync::LazyLock;
+
+use comrak::nodes::{Ast, AstNode, NodeHtmlBlock, NodeValue};
+use comrak::{Arena, Options, format_html, parse_document};
+
+pub use emoji::Emoji;
+pub use hashtags::Hashtags;
+pub use mentions::{Mentions, replace_mention};
+pub use oneboxes::Oneboxes;
+pub use quotes::Quotes;
+pub use sanitize::sanitize;
+pub use watched_words::{WatchedWords, censor_in_text, replace_in_text};
+
+/// Version of the standard pipeline's output. Posts cooked with an older
+/// version get re-cooked by `RebakePostsJob`.
+pub const COOK_VERSION: i32 = 6;
+
+/// Link previews per post, which bounds the lookups one post can cost.
+pub const MAX_ONEBOXES_PER_POST: usize = 10;
+
+/// What the renderer knows about the world outside the post. The default
+/// context resolves nothing, so `@names` stay plain text.
+#[derive(Debug, Default, Clone)]
+pub struct RenderContext {
+    /// Mentionable users, keyed by lowercased username, mapping to the
+    /// username as it should be displayed.
+    pub mentions: HashMap<String, String>,
+    /// Linkable `#hashtags`, keyed by lowercased slug.
+    pub hashtags: HashMap<String, Hashtag>,
+    /// Quoted posts that exist and are visible, as `(topic_id,
+    /// post_number)`. Quotes of anything else render without a link.
+    pub quotes: HashSet<(i32, i32)>,
+    /// Fetched previews, keyed by URL, for links that sit alone in a
+    /// paragraph. Links without one stay ordinary links.
+    pub oneboxes: HashMap<String, Onebox>,
+    /// Custom emoji, keyed by shortcode, mapping to their image URL.
+    /// Built-in emoji need no entry.
+    pub custom_emoji: HashMap<String, String>,
+    /// Watched words to black out, combined into one pattern. `None`
+    /// censors nothing.
+    pub censor: Option<regex::Regex>,
+    /// Watched words to swap for replacement text, with that text.
+    pub replace: Vec<(regex::Regex, String)>,
+    /// Mark external links `rel="nofollow ugc"`, for authors the forum
+    /// doesn't vouch for yet.
+    pub nofollow: bool,
+    /// The forum's own root URL. Absolute links under it aren't external.
+    pub base_url: Option<String>,
+}
+
+/// What a `#slug` hashtag resolves to: a category, or a tag.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hashtag {
+    pub kind: HashtagKind,
+    pub slug: String,
+    pub name: String,
+    /// Six hex digits, as in `Category.color`. Tags have no color.
+    pub color: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashtagKind {
+    Category,
+    Tag,
+}
+
+/// Preview metadata for a link, as scraped from the page it points to.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Onebox {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub image_url: Option<String>,
+    pub site_name: Option<String>,
+}
+
+/// The post a `[quote]` block says it came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct QuoteRef {
+    pub topic_id: i32,
+    pub post_number: i32,
+}
+
+/// One step of the cooking pipeline.
+pub trait Hook: Send + Sync {
+    /// Short, stable name, for logs.
+    fn name(&self) -> &'static str;
+
+    /// Rewrite the raw source before it's parsed, for syntax that isn't
+    /// markdown. Runs for every hook, in order, before any `process`.
+    fn preprocess(&self, raw: String) -> String {
+        raw
+    }
+
+    /// Rewrite the parsed document.
+    fn process<'a>(&self, doc: &Doc<'a>, ctx: &RenderContext);
+}
+
+/// A parsed post, for hooks to walk and rewrite.
+pub struct Doc<'a> {
+    pub arena: &'a Arena<AstNode<'a>>,
+    pub root: &'a AstNode<'a>,
+}
+
+impl<'a> Doc<'a> {
+    /// A new, detached node with `near`'s source position.
+    pub fn node(&self, near: &'a AstNode<'a>, value: NodeValue) -> &'a AstNode<'a> {
+        let start = near.data.borrow().sourcepos.start;
+        self.arena
+            .alloc(AstNode::new(RefCell::new(Ast::new(value, start))))
+    }
+
+    /// A raw HTML block. Only for markup a hook builds itself: it is
+    /// emitted verbatim.
+    pub fn html_block(&self, near: &'a AstNode<'a>, literal: String) -> &'a AstNode<'a> {
+        self.node(
+            near,
+            NodeValue::HtmlBlock(NodeHtmlBlock {
+                block_type: 6,
+                literal,
+            }),
+        )
+    }
+
+    /// Text nodes that aren't already part of a link or image. Code spans
+    /// and blocks carry their content in their own node types, so they
+    /// never show up here.
+    pub fn plain_text_nodes(&self) -> Vec<&'a AstNode<'a>> {
+        self.root
+            .descendants()
+            .filter(|node| matches!(node.data.borrow().value, NodeValue::Text(_)))
+            .filter(|node| {
+                !node.ancestors().any(|a| {
+                    matches!(
+                        a.data.borrow().value,
+                        NodeValue::Link(_) | NodeValue::Image(_)
+                    )
+                })
+            })
+            .collect()
+    }
+
+    /// Swap parts of a text node for inline HTML. `replace` gets the text
+    /// and returns byte ranges in it (in order, non-overlapping) with the
+    /// HTML to put in their place; the text around them is kept.
+    pub fn splice_text(
+        &self,
+        node: &'a AstNode<'a>,
+        replace: impl FnOnce(&str) -> Vec<(std::ops::Range<usize>, String)>,
+    ) {
+        let text = match &node.data.borrow().value {
+            NodeValue::Text(text) => text.clone(),
+            _ => return,
+        };
+        let pieces = replace(&text);
+        if pieces.is_empty() {
+            return;
+        }
+        let mut last = 0;
+        for (range, html) in pieces {
+            if range.start > last {
+                node.insert_before(
+                    self.node(node, NodeValue::Text(text[last..range.start].to_string())),
+                );
+            }
+            node.insert_before(self.node(node, NodeValue::HtmlInline(html)));
+            last = range.end;
+        }
+        node.data.borrow_mut().value = NodeValue::Text(text[last..].to_string());
+    }
+}
+
+/// An ordered pipeline of hooks around comrak.
+pub struct Cooker {
+    hooks: Vec<Box<dyn Hook>>,
+}
+
+static STANDARD: LazyLock<Cooker> = LazyLock::new(|| {
+    Cooker::new()
+        .hook(Mentions)
+        .hook(Hashtags)
+        .hook(Emoji)
+        .hook(Quotes)
+        .hook(Oneboxes)
+        .hook(WatchedWords)
+});
+
+impl Default for Cooker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Cooker {
+    /// Plain markdown, no hooks.
+    pub fn new() -> Self {
+        Self { hooks: Vec::new() }
+    }
+
+    /// The pipeline posts are cooked with: mentions, hashtags, emoji,
+    /// quotes, link previews, then watched words.
+    pub fn standard() -> &'static Cooker {
+        &STANDARD
+    }
+
+    /// Append a hook; hooks run in the order they're added.
+    pub fn hook(mut self, hook: impl Hook + 'static) -> Self {
+        self.hooks.push(Box::new(hook));
+        self
+    }
+
+    pub fn hook_names(&self) -> Vec<&'static str> {
+        self.hooks.iter().map(|h| h.name()).collect()
+    }
+
+    /// Render `raw` to sanitized HTML, resolving whatever `ctx` knows
+    /// about.
+    pub fn cook(&self, raw: &str, ctx: &RenderContext) -> String {
+        let options = options();
+        let arena = Arena::new();
+        let doc = self.parse(&arena, raw, &options);
+        for hook in &self.hooks {
+            hook.process(&doc, ctx);
+        }
+
+        let mut html = Vec::new();
+        format_html(doc.root, &options, &mut html).expect("writing to a Vec can't fail");
+        sanitize(
+            &String::from_utf8(html).expect("comrak emits UTF-8"),
+            ctx,
+        )
+    }
+
+    /// Preprocess and parse `raw`.
+    fn parse<'a>(&self, arena: &'a Arena<AstNode<'a>>, raw: &str, options: &Options) -> Doc<'a> {
+        let raw = self
+            .hooks
+            .iter()
+            .fold(raw.to_string(), |raw, hook| hook.preprocess(raw));
+        let root = parse_document(arena, &raw, options);
+        Doc { arena, root }
+    }
+
+    /// Parse `raw` and hand the tree to `f`, for the `extract_*` helpers.
+    fn inspect<T>(&self, raw: &str, f: impl for<'a> FnOnce(&Doc<'a>) -> T) -> T {
+        let arena = Arena::new();
+        f(&self.parse(&arena, raw, &options()))
+    }
+}
 
 /// Render raw markdown to cooked HTML
 pub fn render(raw: &str) -> String {
+    render_with(raw, &RenderContext::default())
+}
+
+/// Render raw markdown to cooked HTML with the standard pipeline, linking
+/// whatever `ctx` resolves.
+pub fn render_with(raw: &str, ctx: &RenderContext) -> String {
+    Cooker::standard().cook(raw, ctx)
+}
+
+/// Lowercased, de-duplicated usernames `@mentioned` in `raw`, in order of
+/// first appearance. Mentions inside code or links don't count.
+pub fn extract_mentions(raw: &str) -> Vec<String> {
+    Cooker::standard().inspect(raw, mentions::names)
+}
+
+/// The posts `raw` quotes with a full `post:N, topic:N` reference,
+/// de-duplicated, in order of first appearance.
+pub fn extract_quotes(raw: &str) -> Vec<QuoteRef> {
+    quotes::sources(raw)
+}
+
+/// De-duplicated http(s) URLs that sit alone in their own paragraph, the
+/// ones that get a preview card once their metadata has been fetched. Only
+/// the first [`MAX_ONEBOXES_PER_POST`] count; later ones stay plain links.
+pub fn extract_onebox_urls(raw: &str) -> Vec<String> {
+    let mut urls = Cooker::standard().inspect(raw, oneboxes::urls);
+    urls.truncate(MAX_ONEBOXES_PER_POST);
+    urls
+}
+
+/// Lowercased, de-duplicated slugs `#tagged` in `raw`, in order of first
+/// appearance. Hashtags inside code or links don't count.
+pub fn extract_hashtags(raw: &str) -> Vec<String> {
+    Cooker::standard().inspect(raw, hashtags::slugs)
+}
+
+/// De-duplicated `:shortcodes:` in `raw` that aren't built-in emoji, the
+/// ones to look up as custom emoji.
+pub fn extract_custom_emoji(raw: &str) -> Vec<String> {
+    Cooker::standard().inspect(raw, emoji::custom_names)
+}
+
+fn options() -> Options {
     let mut options = Options::default();
 
     // GitHub Flavored Markdown extensions
@@ -14,11 +328,26 @@ pub fn render(raw: &str) -> String {
     // Parsing options
     options.parse.smart = true;
 
-    // Render options
-    options.render.unsafe_ = false; // Sanitize HTML
-    options.render.escape = true;
+    // Render options. Raw HTML, the user's and the hooks', is passed through
+    // by the formatter and filtered afterwards by `sanitize`.
+    options.render.unsafe_ = true;
 
-    markdown_to_html(raw, &options)
+    options
+}
+
+fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
 }
 
 #[cfg(test)]
@@ -66,4 +395,46 @@ mod tests {
         let cooked = render(raw);
         assert!(!cooked.contains("<script>"));
     }
+
+    #[test]
+    fn test_inline_html_is_filtered() {
+        let cooked = render("a <b onclick=\"x()\">b</b> <kbd>Ctrl</kbd> c");
+        assert!(cooked.contains("a <b>b</b> <kbd>Ctrl</kbd> c"));
+        assert!(!cooked.contains("onclick"));
+    }
+
+    #[test]
+    fn test_javascript_links_are_blanked() {
+        let cooked = render("[click](javascript:alert(1))");
+        assert!(!cooked.contains("javascript:"));
+    }
+
+    struct Shout;
+
+    impl Hook for Shout {
+        fn name(&self) -> &'static str {
+            "shout"
+        }
+
+        fn process<'a>(&self, doc: &Doc<'a>, _ctx: &RenderContext) {
+            for node in doc.plain_text_nodes() {
+                if let NodeValue::Text(text) = &mut node.data.borrow_mut().value {
+                    *text = text.to_uppercase();
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_hooks_run_in_order() {
+        assert_eq!(
+            Cooker::standard().hook_names(),
+            vec!["mentions", "hashtags", "emoji", "quotes", "oneboxes", "watched_words"]
+        );
+
+        let cooker = Cooker::new().hook(Shout);
+        assert_eq!(cooker.hook_names(), vec!["shout"]);
+        let cooked = cooker.cook("hi `code` [link](https://example.com)", &Default::default());
+        assert!(cooked.contains("HI <code>code</code> <a href=\"https://example.com\">link</a>"));
+    }
 }
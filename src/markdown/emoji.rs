@@ -0,0 +1,125 @@
+//! `:shortcode:` → the built-in emoji's Unicode, or an `<img>` for a
+//! custom emoji the context knows. Unknown shortcodes stay text.
+
+use comrak::nodes::NodeValue;
+
+use super::{Doc, Hook, RenderContext, escape_html};
+use crate::emoji;
+
+pub struct Emoji;
+
+impl Hook for Emoji {
+    fn name(&self) -> &'static str {
+        "emoji"
+    }
+
+    fn process<'a>(&self, doc: &Doc<'a>, ctx: &RenderContext) {
+        for node in doc.plain_text_nodes() {
+            doc.splice_text(node, |text| {
+                shortcode_spans(text)
+                    .into_iter()
+                    .filter_map(|range| {
+                        // The span excludes the colons; the replacement covers them.
+                        let name = &text[range.clone()];
+                        let html = match emoji::builtin(name) {
+                            Some(builtin) => builtin.unicode.to_string(),
+                            None => {
+                                let url = ctx.custom_emoji.get(name)?;
+                                format!(
+                                    "<img src=\"{}\" title=\":{name}:\" class=\"emoji emoji-custom\" alt=\":{name}:\">",
+                                    escape_html(url)
+                                )
+                            }
+                        };
+                        Some((range.start - 1..range.end + 1, html))
+                    })
+                    .collect()
+            });
+        }
+    }
+}
+
+/// Shortcodes in the document that aren't built-in, de-duplicated, in order
+/// of first appearance: the ones that might be custom emoji.
+pub(super) fn custom_names(doc: &Doc<'_>) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for node in doc.plain_text_nodes() {
+        if let NodeValue::Text(text) = &node.data.borrow().value {
+            for range in shortcode_spans(text) {
+                let name = &text[range];
+                if emoji::builtin(name).is_none() && !names.iter().any(|n| n == name) {
+                    names.push(name.to_string());
+                }
+            }
+        }
+    }
+    names
+}
+
+/// Byte ranges of the names (without colons) of everything in `text` that
+/// looks like `:name:`. The opening colon must not follow a letter or
+/// digit, so times like `10:30:00` aren't shortcodes.
+fn shortcode_spans(text: &str) -> Vec<std::ops::Range<usize>> {
+    let bytes = text.as_bytes();
+    let is_name_byte =
+        |b: u8| b.is_ascii_lowercase() || b.is_ascii_digit() || matches!(b, b'_' | b'+' | b'-');
+    let mut spans = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b':' || (i > 0 && bytes[i - 1].is_ascii_alphanumeric()) {
+            i += 1;
+            continue;
+        }
+        let start = i + 1;
+        let mut end = start;
+        while end < bytes.len() && is_name_byte(bytes[end]) {
+            end += 1;
+        }
+        if end > start && bytes.get(end) == Some(&b':') && emoji::is_valid_name(&text[start..end]) {
+            spans.push(start..end);
+            i = end + 1;
+        } else {
+            i = start;
+        }
+    }
+    spans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{extract_custom_emoji, render, render_with};
+    use super::*;
+
+    #[test]
+    fn test_builtin_shortcodes_become_unicode() {
+        let cooked = render("nice :+1: :thumbsup::tada: :nope: `:smile:`");
+        assert!(cooked.contains("nice 👍 👍🎉 :nope: <code>:smile:</code>"));
+    }
+
+    #[test]
+    fn test_extract_custom_emoji_skips_builtins_and_code() {
+        let raw = ":smile: :parrot: `:blob:` :parrot: :Blob: :blob:";
+        assert_eq!(extract_custom_emoji(raw), vec!["parrot", "blob"]);
+    }
+
+    #[test]
+    fn test_times_are_not_shortcodes() {
+        assert!(render("at 10:30:00, ok").contains("10:30:00"));
+    }
+
+    #[test]
+    fn test_custom_emoji_render_as_images() {
+        let ctx = RenderContext {
+            custom_emoji: [(
+                "party_parrot".to_string(),
+                "https://cdn.example.com/parrot.gif".to_string(),
+            )]
+            .into(),
+            ..Default::default()
+        };
+        let cooked = render_with("yes :party_parrot:", &ctx);
+        assert!(cooked.contains(
+            r#"yes <img src="https://cdn.example.com/parrot.gif" title=":party_parrot:" class="emoji emoji-custom" alt=":party_parrot:">"#
+        ));
+    }
+}
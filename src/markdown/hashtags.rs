@@ -0,0 +1,163 @@
+//! `#slug` → a link to the category, in the category's color, or to the
+//! tag, for slugs the context knows.
+
+use comrak::nodes::NodeValue;
+
+use super::{Doc, Hashtag, HashtagKind, Hook, RenderContext, escape_html};
+
+pub struct Hashtags;
+
+impl Hook for Hashtags {
+    fn name(&self) -> &'static str {
+        "hashtags"
+    }
+
+    fn process<'a>(&self, doc: &Doc<'a>, ctx: &RenderContext) {
+        if ctx.hashtags.is_empty() {
+            return;
+        }
+        for node in doc.plain_text_nodes() {
+            doc.splice_text(node, |text| {
+                hashtag_spans(text)
+                    .into_iter()
+                    .filter_map(|range| {
+                        let tag = ctx.hashtags.get(&text[range.clone()].to_ascii_lowercase())?;
+                        // The span excludes the `#`, which goes inside the link.
+                        Some((range.start - 1..range.end, hashtag_html(tag)))
+                    })
+                    .collect()
+            });
+        }
+    }
+}
+
+fn hashtag_html(tag: &Hashtag) -> String {
+    let (path, data_type) = match tag.kind {
+        HashtagKind::Category => ("c", "category"),
+        HashtagKind::Tag => ("tag", "tag"),
+    };
+    let style = tag
+        .color
+        .as_ref()
+        .map(|color| format!(" style=\"color: #{color}\""))
+        .unwrap_or_default();
+    format!(
+        "<a class=\"hashtag\" href=\"/{path}/{slug}\" data-type=\"{data_type}\" title=\"{name}\"{style}>#{slug}</a>",
+        slug = tag.slug,
+        name = escape_html(&tag.name),
+    )
+}
+
+pub(super) fn slugs(doc: &Doc<'_>) -> Vec<String> {
+    let mut slugs: Vec<String> = Vec::new();
+    for node in doc.plain_text_nodes() {
+        if let NodeValue::Text(text) = &node.data.borrow().value {
+            for range in hashtag_spans(text) {
+                let slug = text[range].to_ascii_lowercase();
+                if !slugs.contains(&slug) {
+                    slugs.push(slug);
+                }
+            }
+        }
+    }
+    slugs
+}
+
+/// Byte ranges of the slugs (without the `#`) of everything in `text` that
+/// looks like a hashtag. The `#` must not follow a word character or `/`,
+/// so `C#` and `page#anchor` aren't hashtags, and trailing hyphens are left
+/// out, so "see #support-" links `support`.
+fn hashtag_spans(text: &str) -> Vec<std::ops::Range<usize>> {
+    let bytes = text.as_bytes();
+    let mut spans = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'#' {
+            i += 1;
+            continue;
+        }
+        let preceded_by_word = i > 0
+            && (bytes[i - 1].is_ascii_alphanumeric() || matches!(bytes[i - 1], b'_' | b'/' | b'#'));
+        let start = i + 1;
+        let mut end = start;
+        while end < bytes.len() && (bytes[end].is_ascii_alphanumeric() || bytes[end] == b'-') {
+            end += 1;
+        }
+        i = end.max(start);
+        // A slug can't run into other word characters, e.g. `#foo_bar`.
+        let followed_by_word = bytes.get(end).is_some_and(|&b| b == b'_');
+        if preceded_by_word || followed_by_word {
+            continue;
+        }
+        let mut slug_end = end;
+        while slug_end > start && bytes[slug_end - 1] == b'-' {
+            slug_end -= 1;
+        }
+        if slug_end > start && bytes[start] != b'-' {
+            spans.push(start..slug_end);
+        }
+    }
+    spans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{extract_hashtags, render_with};
+    use super::*;
+
+    fn ctx() -> RenderContext {
+        RenderContext {
+            hashtags: [
+                (
+                    "support".to_string(),
+                    Hashtag {
+                        kind: HashtagKind::Category,
+                        slug: "support".to_string(),
+                        name: "Support & Help".to_string(),
+                        color: Some("0088CC".to_string()),
+                    },
+                ),
+                (
+                    "bugs".to_string(),
+                    Hashtag {
+                        kind: HashtagKind::Tag,
+                        slug: "bugs".to_string(),
+                        name: "Bugs".to_string(),
+                        color: None,
+                    },
+                ),
+            ]
+            .into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_known_hashtags_link_with_the_category_color() {
+        let cooked = render_with("ask in #Support.", &ctx());
+        assert!(cooked.contains(
+            r##"ask in <a class="hashtag" href="/c/support" data-type="category" title="Support &amp; Help" style="color: #0088CC">#support</a>."##
+        ));
+    }
+
+    #[test]
+    fn test_tag_hashtags_link_to_the_tag_without_color() {
+        let cooked = render_with("see #Bugs", &ctx());
+        assert!(cooked.contains(
+            r##"see <a class="hashtag" href="/tag/bugs" data-type="tag" title="Bugs">#bugs</a>"##
+        ));
+    }
+
+    #[test]
+    fn test_unknown_hashtags_and_code_stay_text() {
+        let cooked = render_with("#nope `#support`\n\n    #support", &ctx());
+        assert!(!cooked.contains("hashtag"));
+        assert!(cooked.contains("#nope"));
+    }
+
+    #[test]
+    fn test_extract_hashtags() {
+        let raw = "#support, #Support and #dev-ops- but not C# or a/#b or `#code` or #foo_bar";
+        assert_eq!(extract_hashtags(raw), vec!["support", "dev-ops"]);
+    }
+}
@@ -0,0 +1,216 @@
+//! `@username` → a link to the user's profile, for users the context knows.
+
+use std::collections::HashSet;
+use std::ops::Range;
+
+use comrak::nodes::NodeValue;
+use comrak::{Arena, parse_document};
+
+use super::{Doc, Hook, RenderContext, options};
+use crate::validation::{USERNAME_MAX_LENGTH, USERNAME_MIN_LENGTH};
+
+pub struct Mentions;
+
+impl Hook for Mentions {
+    fn name(&self) -> &'static str {
+        "mentions"
+    }
+
+    fn process<'a>(&self, doc: &Doc<'a>, ctx: &RenderContext) {
+        if ctx.mentions.is_empty() {
+            return;
+        }
+        for node in doc.plain_text_nodes() {
+            doc.splice_text(node, |text| {
+                mention_spans(text)
+                    .into_iter()
+                    .filter_map(|range| {
+                        let username = ctx
+                            .mentions
+                            .get(&text[range.clone()].to_ascii_lowercase())?;
+                        // The span excludes the `@`, which goes inside the link.
+                        Some((
+                            range.start - 1..range.end,
+                            format!("<a class=\"mention\" href=\"/u/{username}\">@{username}</a>"),
+                        ))
+                    })
+                    .collect()
+            });
+        }
+    }
+}
+
+pub(super) fn names(doc: &Doc<'_>) -> Vec<String> {
+    let mut seen = HashSet::new();
+    let mut names = Vec::new();
+    for node in doc.plain_text_nodes() {
+        if let NodeValue::Text(text) = &node.data.borrow().value {
+            for range in mention_spans(text) {
+                let name = text[range].to_ascii_lowercase();
+                if seen.insert(name.clone()) {
+                    names.push(name);
+                }
+            }
+        }
+    }
+    names
+}
+
+/// Rewrite `@old` to `@new` in raw markdown, leaving longer names that
+/// merely start with `old` (and email addresses) alone. Case-insensitive
+/// on `old`, since that's how mentions resolve. Like mentions themselves,
+/// it skips code, links and raw HTML.
+pub fn replace_mention(raw: &str, old: &str, new: &str) -> String {
+    let skipped = not_plain_text(raw);
+    let mut out = String::with_capacity(raw.len());
+    let mut last = 0;
+    for range in mention_spans(raw) {
+        if skipped.iter().any(|s| s.contains(&range.start)) {
+            continue;
+        }
+        if raw[range.clone()].eq_ignore_ascii_case(old) {
+            out.push_str(&raw[last..range.start]);
+            out.push_str(new);
+            last = range.end;
+        }
+    }
+    out.push_str(&raw[last..]);
+    out
+}
+
+/// Byte ranges of `raw` the parser reads as code, links or raw HTML, from
+/// the nodes' source positions. Inline nodes cover their own columns;
+/// blocks cover their whole lines.
+fn not_plain_text(raw: &str) -> Vec<Range<usize>> {
+    let arena = Arena::new();
+    let root = parse_document(&arena, raw, &options());
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(raw.match_indices('\n').map(|(i, _)| i + 1))
+        .collect();
+    // Lines and columns are 1-based, columns counted in bytes.
+    let offset = |line: usize, column: usize| {
+        line_starts
+            .get(line - 1)
+            .map_or(raw.len(), |start| start + column - 1)
+            .min(raw.len())
+    };
+    root.descendants()
+        .filter_map(|node| {
+            let ast = node.data.borrow();
+            let (start, end) = (ast.sourcepos.start, ast.sourcepos.end);
+            // Bare autolinks come without a position, but `mention_spans`
+            // already skips names inside URLs and email addresses.
+            if start.line == 0 {
+                return None;
+            }
+            match ast.value {
+                NodeValue::Code(_)
+                | NodeValue::HtmlInline(_)
+                | NodeValue::Link(_)
+                | NodeValue::Image(_) => {
+                    Some(offset(start.line, start.column)..offset(end.line, end.column) + 1)
+                }
+                NodeValue::CodeBlock(_) | NodeValue::HtmlBlock(_) => {
+                    Some(offset(start.line, 1)..offset(end.line + 1, 1))
+                }
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Byte ranges of the names (without the `@`) of everything in `text` that
+/// looks like a mention. The `@` must not follow a word character, so
+/// `bob@example.com` isn't one; the name uses the username charset minus a
+/// trailing `.` or `-`, so "thanks @bob." mentions `bob`.
+fn mention_spans(text: &str) -> Vec<std::ops::Range<usize>> {
+    let bytes = text.as_bytes();
+    let is_name_byte = |b: u8| b.is_ascii_alphanumeric() || matches!(b, b'_' | b'.' | b'-');
+    let mut spans = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'@' {
+            i += 1;
+            continue;
+        }
+        let preceded_by_word = i > 0
+            && (bytes[i - 1].is_ascii_alphanumeric()
+                || matches!(bytes[i - 1], b'_' | b'.' | b'@' | b'/'));
+        let start = i + 1;
+        let mut end = start;
+        while end < bytes.len() && is_name_byte(bytes[end]) {
+            end += 1;
+        }
+        i = end.max(start);
+        if preceded_by_word {
+            continue;
+        }
+        let mut name_end = end;
+        while name_end > start && matches!(bytes[name_end - 1], b'.' | b'-') {
+            name_end -= 1;
+        }
+        let len = (name_end - start) as u64;
+        if (USERNAME_MIN_LENGTH..=USERNAME_MAX_LENGTH).contains(&len) {
+            spans.push(start..name_end);
+        }
+    }
+    spans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{extract_mentions, render_with};
+    use super::*;
+
+    fn ctx(names: &[&str]) -> RenderContext {
+        RenderContext {
+            mentions: names
+                .iter()
+                .map(|n| (n.to_ascii_lowercase(), n.to_string()))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_mentions_are_linked_with_canonical_case() {
+        let cooked = render_with("hey @bob and @BOBBY.", &ctx(&["Bob", "bobby"]));
+        assert!(cooked.contains(r#"<a class="mention" href="/u/Bob">@Bob</a>"#));
+        assert!(cooked.contains(r#"<a class="mention" href="/u/bobby">@bobby</a>."#));
+    }
+
+    #[test]
+    fn test_unknown_mentions_stay_text() {
+        let cooked = render_with("hi @nobody", &ctx(&["bob"]));
+        assert!(cooked.contains("hi @nobody"));
+        assert!(!cooked.contains("mention"));
+    }
+
+    #[test]
+    fn test_mentions_in_code_are_ignored() {
+        let raw = "`@bob` and\n\n```\n@bob\n```";
+        assert!(extract_mentions(raw).is_empty());
+        assert!(!render_with(raw, &ctx(&["bob"])).contains("mention"));
+    }
+
+    #[test]
+    fn test_extract_mentions() {
+        let raw = "@alice, @Bob_Smith and @alice again; mail bob@example.com. @ab is too short";
+        assert_eq!(extract_mentions(raw), vec!["alice", "bob_smith"]);
+    }
+
+    #[test]
+    fn test_replace_mention_is_exact() {
+        let raw = "@bob @Bob @bobby bob@example.com @bob.";
+        assert_eq!(
+            replace_mention(raw, "bob", "robert"),
+            "@robert @robert @bobby bob@example.com @robert."
+        );
+
+        let raw = "@bob `@bob` [@bob](/x) <b title=\"@bob\">@bob</b>\n\n```\n@bob\n```\n\n    @bob\n\n- **@bob**";
+        assert_eq!(
+            replace_mention(raw, "bob", "robert"),
+            "@robert `@bob` [@bob](/x) <b title=\"@bob\">@robert</b>\n\n```\n@bob\n```\n\n    @bob\n\n- **@robert**"
+        );
+    }
+}
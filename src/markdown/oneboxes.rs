@@ -0,0 +1,135 @@
+//! Links alone in a paragraph → preview cards, for links whose metadata
+//! has been fetched (see `crate::onebox`).
+
+use std::collections::HashSet;
+
+use comrak::nodes::{AstNode, NodeValue};
+
+use super::{Doc, Hook, Onebox, RenderContext, escape_html};
+
+pub struct Oneboxes;
+
+impl Hook for Oneboxes {
+    fn name(&self) -> &'static str {
+        "oneboxes"
+    }
+
+    /// Replace bare-link paragraphs that have a fetched preview with a card.
+    fn process<'a>(&self, doc: &Doc<'a>, ctx: &RenderContext) {
+        if ctx.oneboxes.is_empty() {
+            return;
+        }
+        for (node, url) in candidates(doc.root) {
+            let Some(onebox) = ctx.oneboxes.get(&url) else {
+                continue;
+            };
+            node.insert_before(doc.html_block(node, onebox_html(&url, onebox)));
+            node.detach();
+        }
+    }
+}
+
+pub(super) fn urls(doc: &Doc<'_>) -> Vec<String> {
+    let mut seen = HashSet::new();
+    candidates(doc.root)
+        .into_iter()
+        .map(|(_, url)| url)
+        .filter(|url| seen.insert(url.clone()))
+        .collect()
+}
+
+/// Paragraphs made of nothing but one bare http(s) link, i.e. one whose
+/// text is its URL, with that URL.
+fn candidates<'a>(root: &'a AstNode<'a>) -> Vec<(&'a AstNode<'a>, String)> {
+    root.descendants()
+        .filter(|node| matches!(node.data.borrow().value, NodeValue::Paragraph))
+        .filter_map(|node| {
+            let link = node.first_child()?;
+            if link.next_sibling().is_some() {
+                return None;
+            }
+            let url = match &link.data.borrow().value {
+                NodeValue::Link(l) => l.url.clone(),
+                _ => return None,
+            };
+            let text = link.first_child()?;
+            if text.next_sibling().is_some() {
+                return None;
+            }
+            let bare = matches!(&text.data.borrow().value, NodeValue::Text(t) if *t == url);
+            let web = url.starts_with("http://") || url.starts_with("https://");
+            (bare && web).then_some((node, url))
+        })
+        .collect()
+}
+
+fn onebox_html(url: &str, onebox: &Onebox) -> String {
+    let url = escape_html(url);
+    let link = |text: &str| format!("<a href=\"{url}\">{text}</a>");
+
+    let mut html = format!("<aside class=\"onebox\" data-url=\"{url}\">\n");
+    if let Some(site_name) = &onebox.site_name {
+        html.push_str(&format!(
+            "<header class=\"source\">{}</header>\n",
+            link(&escape_html(site_name))
+        ));
+    }
+    html.push_str("<article class=\"onebox-body\">\n");
+    if let Some(image_url) = &onebox.image_url {
+        html.push_str(&format!(
+            "<img src=\"{}\" class=\"thumbnail\" alt=\"\">\n",
+            escape_html(image_url)
+        ));
+    }
+    let title = onebox.title.as_deref().unwrap_or(&url);
+    html.push_str(&format!("<h3>{}</h3>\n", link(&escape_html(title))));
+    if let Some(description) = &onebox.description {
+        html.push_str(&format!("<p>{}</p>\n", escape_html(description)));
+    }
+    html.push_str("</article>\n</aside>\n");
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{MAX_ONEBOXES_PER_POST, extract_onebox_urls, render, render_with};
+    use super::*;
+
+    #[test]
+    fn test_bare_links_alone_are_onebox_candidates() {
+        let raw = "https://example.com/a\n\nsee https://example.com/b\n\n[named](https://example.com/c)\n\n`https://example.com/d`\n\nhttps://example.com/a";
+        assert_eq!(extract_onebox_urls(raw), vec!["https://example.com/a"]);
+    }
+
+    #[test]
+    fn test_onebox_candidates_are_capped() {
+        let raw: Vec<String> = (0..MAX_ONEBOXES_PER_POST + 5)
+            .map(|i| format!("https://example.com/{i}"))
+            .collect();
+        let urls = extract_onebox_urls(&raw.join("\n\n"));
+        assert_eq!(urls, raw[..MAX_ONEBOXES_PER_POST]);
+    }
+
+    #[test]
+    fn test_onebox_renders_escaped_card() {
+        let mut ctx = RenderContext::default();
+        ctx.oneboxes.insert(
+            "https://example.com/a".to_string(),
+            Onebox {
+                title: Some("A <b>title</b>".to_string()),
+                description: Some("About \"a\"".to_string()),
+                image_url: Some("https://example.com/a.png".to_string()),
+                site_name: Some("Example".to_string()),
+            },
+        );
+        let cooked = render_with("before\n\nhttps://example.com/a\n\nafter", &ctx);
+        assert!(cooked.contains(r#"<aside class="onebox" data-url="https://example.com/a">"#));
+        assert!(cooked.contains("A &lt;b&gt;title&lt;/b&gt;"));
+        assert!(cooked.contains("<p>About \"a\"</p>"));
+        assert!(cooked.contains(r#"<img src="https://example.com/a.png""#));
+        assert!(cooked.contains("<p>before</p>") && cooked.contains("<p>after</p>"));
+
+        // Without a preview the link stays a link.
+        assert!(render("https://example.com/a").contains(r#"<a href="https://example.com/a""#));
+    }
+}
@@ -0,0 +1,291 @@
+//! Discourse-style `[quote="user, post:3, topic:12"]` blocks. They aren't
+//! markdown, so `preprocess` rewrites them into blockquotes and `process`
+//! dresses those up as attributed quotes linking back to the source post.
+
+use std::collections::HashSet;
+
+use comrak::nodes::{AstNode, NodeValue};
+
+use super::{Doc, Hook, QuoteRef, RenderContext, escape_html};
+
+pub struct Quotes;
+
+impl Hook for Quotes {
+    fn name(&self) -> &'static str {
+        "quotes"
+    }
+
+    fn preprocess(&self, raw: String) -> String {
+        expand(&raw).0
+    }
+
+    /// Replace each marker paragraph with the opening of an `<aside
+    /// class="quote">` and close it after the blockquote that follows.
+    fn process<'a>(&self, doc: &Doc<'a>, ctx: &RenderContext) {
+        let markers: Vec<(&'a AstNode<'a>, QuoteTag)> = doc
+            .root
+            .descendants()
+            .filter_map(|node| marker_tag(node).map(|tag| (node, tag)))
+            .collect();
+
+        for (node, tag) in markers {
+            let body = node
+                .next_sibling()
+                .filter(|n| matches!(n.data.borrow().value, NodeValue::BlockQuote));
+            match body {
+                Some(body) => body.insert_after(doc.html_block(node, "</aside>\n".to_string())),
+                None => node.insert_after(
+                    doc.html_block(node, "<blockquote>\n</blockquote>\n</aside>\n".to_string()),
+                ),
+            }
+            node.insert_before(doc.html_block(node, open_html(&tag, ctx)));
+            node.detach();
+        }
+    }
+}
+
+/// Brackets the line that stands in for a `[quote]` tag. A private-use
+/// character, stripped from the input first so it can't be forged.
+const QUOTE_MARK: char = '\u{E000}';
+
+/// What a `[quote=...]` tag said about its source.
+#[derive(Debug, Default, Clone)]
+struct QuoteTag {
+    username: Option<String>,
+    source: Option<QuoteRef>,
+}
+
+/// Parse an opening tag line: `[quote]`, `[quote=bob]` or
+/// `[quote="bob, post:3, topic:12"]`.
+fn parse_quote_tag(line: &str) -> Option<QuoteTag> {
+    let inner = line.trim().strip_prefix("[quote")?.strip_suffix(']')?;
+    if inner.is_empty() {
+        return Some(QuoteTag::default());
+    }
+    let attrs = inner.strip_prefix('=')?;
+    let attrs = attrs
+        .strip_prefix('"')
+        .and_then(|a| a.strip_suffix('"'))
+        .unwrap_or(attrs);
+
+    let mut tag = QuoteTag::default();
+    let (mut topic_id, mut post_number) = (None, None);
+    for (i, part) in attrs.split(',').map(str::trim).enumerate() {
+        match part.split_once(':') {
+            Some(("post", n)) => post_number = n.trim().parse().ok(),
+            Some(("topic", n)) => topic_id = n.trim().parse().ok(),
+            Some(_) => {}
+            None if i == 0 && !part.is_empty() => tag.username = Some(part.to_string()),
+            None => {}
+        }
+    }
+    if let (Some(topic_id), Some(post_number)) = (topic_id, post_number) {
+        tag.source = Some(QuoteRef {
+            topic_id,
+            post_number,
+        });
+    }
+    Some(tag)
+}
+
+/// Whether `line` opens or closes a fenced code block.
+fn is_fence(line: &str) -> bool {
+    let trimmed = line.trim_start();
+    line.len() - trimmed.len() < 4 && (trimmed.starts_with("```") || trimmed.starts_with("~~~"))
+}
+
+/// Rewrite each matched `[quote]`…`[/quote]` pair (tags on their own
+/// lines, outside code fences) into a marker paragraph followed by the body
+/// as a markdown blockquote. Nested quotes nest the blockquotes. Unmatched
+/// tags are left as text. The marker carries the opening tag hex-encoded,
+/// which smart punctuation can't mangle. Also returns the parsed tags.
+fn expand(raw: &str) -> (String, Vec<QuoteTag>) {
+    let raw = raw.replace(QUOTE_MARK, "");
+    let lines: Vec<&str> = raw.lines().collect();
+
+    // Pair up tags first so an unclosed [quote] changes nothing.
+    let mut opens: Vec<(usize, QuoteTag)> = Vec::new();
+    let mut pairs: Vec<(usize, usize, QuoteTag)> = Vec::new();
+    let mut in_fence = false;
+    for (i, line) in lines.iter().enumerate() {
+        if is_fence(line) {
+            in_fence = !in_fence;
+        } else if in_fence {
+            continue;
+        } else if let Some(tag) = parse_quote_tag(line) {
+            opens.push((i, tag));
+        } else if line.trim() == "[/quote]"
+            && let Some((open, tag)) = opens.pop()
+        {
+            pairs.push((open, i, tag));
+        }
+    }
+    if pairs.is_empty() {
+        return (raw, Vec::new());
+    }
+    pairs.sort_by_key(|(open, _, _)| *open);
+
+    let depth = |i: usize| pairs.iter().filter(|(o, c, _)| *o < i && i < *c).count();
+    let mut out = String::with_capacity(raw.len() + pairs.len() * 32);
+    for (i, line) in lines.iter().enumerate() {
+        let prefix = "> ".repeat(depth(i));
+        let blank = prefix.trim_end();
+        if pairs.iter().any(|(o, _, _)| *o == i) {
+            let tag = hex_encode(line.trim());
+            out.push_str(&format!(
+                "{blank}\n{prefix}{QUOTE_MARK}{tag}{QUOTE_MARK}\n{blank}\n"
+            ));
+        } else if pairs.iter().any(|(_, c, _)| *c == i) || line.trim().is_empty() {
+            out.push_str(&format!("{blank}\n"));
+        } else {
+            out.push_str(&format!("{prefix}{line}\n"));
+        }
+    }
+    (out, pairs.into_iter().map(|(_, _, tag)| tag).collect())
+}
+
+/// The sources of `raw`'s quotes, de-duplicated, in order.
+pub(super) fn sources(raw: &str) -> Vec<QuoteRef> {
+    let mut seen = HashSet::new();
+    expand(raw)
+        .1
+        .into_iter()
+        .filter_map(|q| q.source)
+        .filter(|q| seen.insert(*q))
+        .collect()
+}
+
+fn hex_encode(text: &str) -> String {
+    text.bytes().map(|b| format!("{b:02x}")).collect()
+}
+
+fn hex_decode(hex: &str) -> Option<String> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    let bytes = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    String::from_utf8(bytes).ok()
+}
+
+/// The tag a marker paragraph stands for.
+fn marker_tag(node: &AstNode<'_>) -> Option<QuoteTag> {
+    if !matches!(node.data.borrow().value, NodeValue::Paragraph) {
+        return None;
+    }
+    let child = node.first_child()?;
+    if child.next_sibling().is_some() {
+        return None;
+    }
+    let value = &child.data.borrow().value;
+    let NodeValue::Text(text) = value else {
+        return None;
+    };
+    let hex = text.strip_prefix(QUOTE_MARK)?.strip_suffix(QUOTE_MARK)?;
+    parse_quote_tag(&hex_decode(hex)?)
+}
+
+/// Opening markup for a quote: the `<aside>` and its title line.
+fn open_html(tag: &QuoteTag, ctx: &RenderContext) -> String {
+    let mut open = String::from("<aside class=\"quote\"");
+    if let Some(username) = &tag.username {
+        open.push_str(&format!(" data-username=\"{}\"", escape_html(username)));
+    }
+    let link = tag
+        .source
+        .filter(|q| ctx.quotes.contains(&(q.topic_id, q.post_number)));
+    if let Some(q) = link {
+        open.push_str(&format!(
+            " data-topic=\"{}\" data-post=\"{}\"",
+            q.topic_id, q.post_number
+        ));
+    }
+    open.push_str(">\n");
+    match (&tag.username, link) {
+        (Some(username), Some(q)) => open.push_str(&format!(
+            "<div class=\"title\"><a href=\"/t/{}/{}\">{}</a>:</div>\n",
+            q.topic_id,
+            q.post_number,
+            escape_html(username)
+        )),
+        (Some(username), None) => open.push_str(&format!(
+            "<div class=\"title\">{}:</div>\n",
+            escape_html(username)
+        )),
+        (None, _) => {}
+    }
+    open
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{extract_quotes, render, render_with};
+    use super::*;
+
+    #[test]
+    fn test_quote_is_attributed_and_linked() {
+        let raw = "[quote=\"bob, post:3, topic:12\"]\nsaid **this**\n[/quote]\n\nreply";
+        let mut ctx = RenderContext::default();
+        ctx.quotes.insert((12, 3));
+        let cooked = render_with(raw, &ctx);
+        assert!(cooked.contains(
+            r#"<aside class="quote" data-username="bob" data-topic="12" data-post="3">"#
+        ));
+        assert!(cooked.contains(r#"<a href="/t/12/3">bob</a>:"#));
+        assert!(
+            cooked.contains(
+                "<blockquote>\n<p>said <strong>this</strong></p>\n</blockquote>\n</aside>"
+            )
+        );
+        assert!(cooked.contains("<p>reply</p>"));
+    }
+
+    #[test]
+    fn test_quote_of_invisible_post_is_not_linked() {
+        let cooked = render("[quote=\"bob, post:3, topic:12\"]\nhi\n[/quote]");
+        assert!(cooked.contains(r#"<div class="title">bob:</div>"#));
+        assert!(!cooked.contains("/t/12/3"));
+    }
+
+    #[test]
+    fn test_nested_quotes() {
+        let raw = "[quote=\"a, post:1, topic:1\"]\nouter\n\n[quote=\"b, post:2, topic:1\"]\ninner\n[/quote]\n[/quote]";
+        let cooked = render(raw);
+        assert_eq!(cooked.matches("<aside class=\"quote\"").count(), 2);
+        assert_eq!(cooked.matches("</aside>").count(), 2);
+        assert!(cooked.find("inner").unwrap() > cooked.find("outer").unwrap());
+        assert_eq!(
+            extract_quotes(raw),
+            vec![
+                QuoteRef {
+                    topic_id: 1,
+                    post_number: 1
+                },
+                QuoteRef {
+                    topic_id: 1,
+                    post_number: 2
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_quote_tags_in_code_or_unclosed_stay_text() {
+        let fenced = "```\n[quote=\"bob, post:3, topic:12\"]\nhi\n[/quote]\n```";
+        assert!(!render(fenced).contains("<aside"));
+        assert!(extract_quotes(fenced).is_empty());
+
+        let unclosed = render("[quote=\"bob, post:3, topic:12\"]\nhi");
+        assert!(!unclosed.contains("<aside"));
+        assert!(unclosed.contains("[quote="));
+    }
+
+    #[test]
+    fn test_quote_username_is_escaped() {
+        let cooked = render("[quote=\"<img src=x onerror=alert(1)>\"]\nhi\n[/quote]");
+        assert!(!cooked.contains("<img"));
+        assert!(cooked.contains("&lt;img"));
+    }
+}
@@ -0,0 +1,415 @@
+//! The last step of cooking: an allowlist of tags, attributes and URL
+//! schemes over everything comrak and the hooks produced.
+//!
+//! Users may write harmless HTML (`<kbd>`, `<details>`, `<sup>`...), and the
+//! markup hooks emit has to survive too, so the lists below cover both.
+//! Anything not listed is dropped: unknown tags keep their text, `<script>`
+//! and `<style>` lose their contents as well, and so do attributes and URLs
+//! with schemes other than http, https and mailto. `class` and `style` are
+//! narrowed further to the values the hooks use.
+
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::sync::LazyLock;
+
+use ammonia::Builder;
+
+use super::RenderContext;
+
+const TAGS: &[&str] = &[
+    "a",
+    "abbr",
+    "article",
+    "aside",
+    "b",
+    "blockquote",
+    "br",
+    "code",
+    "dd",
+    "del",
+    "details",
+    "div",
+    "dl",
+    "dt",
+    "em",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "i",
+    "img",
+    "input",
+    "ins",
+    "kbd",
+    "li",
+    "mark",
+    "ol",
+    "p",
+    "pre",
+    "s",
+    "small",
+    "span",
+    "strike",
+    "strong",
+    "sub",
+    "summary",
+    "sup",
+    "table",
+    "tbody",
+    "td",
+    "th",
+    "thead",
+    "tr",
+    "u",
+    "ul",
+];
+
+const TAG_ATTRIBUTES: &[(&str, &[&str])] = &[
+    ("a", &["href", "class", "data-type", "style"]),
+    ("abbr", &["title"]),
+    ("article", &["class"]),
+    (
+        "aside",
+        &[
+            "class",
+            "data-username",
+            "data-topic",
+            "data-post",
+            "data-url",
+        ],
+    ),
+    ("code", &["class"]),
+    ("details", &["open"]),
+    ("div", &["class"]),
+    ("header", &["class"]),
+    ("img", &["src", "alt", "width", "height", "class"]),
+    ("input", &["type", "checked", "disabled"]),
+    ("ol", &["start"]),
+    ("span", &["class"]),
+    ("td", &["align"]),
+    ("th", &["align"]),
+];
+
+/// Classes the hooks put on each tag. `code` also takes comrak's
+/// `language-*` classes.
+const CLASSES: &[(&str, &[&str])] = &[
+    ("a", &["mention", "hashtag"]),
+    ("article", &["onebox-body"]),
+    ("aside", &["quote", "onebox"]),
+    ("div", &["title"]),
+    ("header", &["source"]),
+    ("img", &["emoji", "emoji-custom", "thumbnail"]),
+    ("span", &["censored"]),
+];
+
+static CLEANER: LazyLock<Builder<'static>> = LazyLock::new(|| {
+    let mut builder = Builder::default();
+    builder
+        .tags(TAGS.iter().copied().collect())
+        .tag_attributes(
+            TAG_ATTRIBUTES
+                .iter()
+                .map(|(tag, attrs)| (*tag, attrs.iter().copied().collect()))
+                .collect(),
+        )
+        .url_schemes(["http", "https", "mailto"].into())
+        .link_rel(None)
+        .attribute_filter(filter_attribute)
+        // Task list items are the only inputs comrak emits; anything else
+        // at least can't be typed into.
+        .set_tag_attribute_value("input", "disabled", "");
+    builder
+});
+
+/// Narrow `class`, `style` and `type` to the values cooking produces.
+fn filter_attribute<'u>(tag: &str, attribute: &str, value: &'u str) -> Option<Cow<'u, str>> {
+    match (tag, attribute) {
+        (_, "class") => {
+            let allowed: HashSet<&str> = CLASSES
+                .iter()
+                .find(|(t, _)| *t == tag)
+                .map(|(_, classes)| classes.iter().copied().collect())
+                .unwrap_or_default();
+            let kept: Vec<&str> = value
+                .split_ascii_whitespace()
+                .filter(|class| {
+                    allowed.contains(class) || (tag == "code" && class.starts_with("language-"))
+                })
+                .collect();
+            match kept.len() {
+                0 => None,
+                n if n == value.split_ascii_whitespace().count() => Some(value.into()),
+                _ => Some(kept.join(" ").into()),
+            }
+        }
+        // Hashtags are colored with a six-digit hex color and nothing else.
+        ("a", "style") => {
+            let hex = value.strip_prefix("color: #")?;
+            (hex.len() == 6 && hex.chars().all(|c| c.is_ascii_hexdigit())).then_some(value.into())
+        }
+        ("input", "type") => (value == "checkbox").then_some(value.into()),
+        _ => Some(value.into()),
+    }
+}
+
+/// Clean cooked HTML, then mark external links `rel="nofollow ugc"` if
+/// `ctx.nofollow` is set.
+pub fn sanitize(html: &str, ctx: &RenderContext) -> String {
+    let clean = CLEANER.clean(html).to_string();
+    if ctx.nofollow {
+        add_nofollow(&clean, ctx.base_url.as_deref())
+    } else {
+        clean
+    }
+}
+
+/// Whether `href` leaves the forum: an absolute http(s) or
+/// protocol-relative URL that isn't under `base_url`.
+fn is_external(href: &str, base_url: Option<&str>) -> bool {
+    let lower = href.to_ascii_lowercase();
+    let absolute = ["http://", "https://", "//"]
+        .iter()
+        .any(|prefix| lower.starts_with(prefix));
+    let internal = base_url.is_some_and(|base| {
+        let base = base.trim_end_matches('/').to_ascii_lowercase();
+        lower
+            .strip_prefix(&base)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with(['/', '?', '#']))
+    });
+    absolute && !internal
+}
+
+/// Add `rel="nofollow ugc"` to each `<a>` with an external `href`. Relies
+/// on the sanitizer's serialization: text has its `<` escaped, and every
+/// attribute value is double-quoted with `"` escaped, so tags can be found
+/// by scanning.
+fn add_nofollow(html: &str, base_url: Option<&str>) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        out.push_str(&rest[..start]);
+        let tag = &rest[start..start + tag_len(&rest[start..])];
+        rest = &rest[start + tag.len()..];
+
+        let external = tag.starts_with("<a ")
+            && attribute(tag, "href").is_some_and(|href| is_external(href, base_url));
+        match tag.strip_suffix('>') {
+            Some(open) if external => {
+                out.push_str(open);
+                out.push_str(" rel=\"nofollow ugc\">");
+            }
+            _ => out.push_str(tag),
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Length of the tag at the start of `html`, through its closing `>`,
+/// skipping any `>` inside quoted attribute values.
+fn tag_len(html: &str) -> usize {
+    let mut quoted = false;
+    for (i, c) in html.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '>' if !quoted => return i + 1,
+            _ => {}
+        }
+    }
+    html.len()
+}
+
+/// The raw (still escaped) value of `name` in a serialized start tag.
+fn attribute<'t>(tag: &'t str, name: &str) -> Option<&'t str> {
+    let mut rest = tag;
+    loop {
+        let at = rest.find(" ")?;
+        rest = &rest[at + 1..];
+        let (attr, after) = rest.split_once("=\"")?;
+        let (value, after) = after.split_once('"')?;
+        if attr == name {
+            return Some(value);
+        }
+        rest = after;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Cooker, Onebox, render, render_with};
+    use super::*;
+    use scraper::{Html, Selector};
+
+    /// Payloads that must come out inert, as markdown or raw HTML.
+    const XSS_CORPUS: &[&str] = &[
+        "<script>alert(1)</script>",
+        "<SCRIPT SRC=https://evil.example/x.js></SCRIPT>",
+        "<scr<script>ipt>alert(1)</script>",
+        "<img src=x onerror=alert(1)>",
+        "<img src=\"javascript:alert(1)\">",
+        "<img src=x srcset=\"javascript:alert(1)\">",
+        "<img src=\"data:image/svg+xml;base64,PHN2ZyBvbmxvYWQ9YWxlcnQoMSk+\">",
+        "<a href=\"javascript:alert(1)\">x</a>",
+        "<a href=\"JaVaScRiPt:alert(1)\">x</a>",
+        "<a href=\" javascript:alert(1)\">x</a>",
+        "<a href=\"&#106;avascript:alert(1)\">x</a>",
+        "<a href=\"java\tscript:alert(1)\">x</a>",
+        "<a href=\"vbscript:msgbox(1)\">x</a>",
+        "<a href=\"data:text/html;base64,PHNjcmlwdD5hbGVydCgxKTwvc2NyaXB0Pg==\">x</a>",
+        "[x](javascript:alert(1))",
+        "[x](JAVASCRIPT:alert(1))",
+        "![x](javascript:alert(1))",
+        "[x]: javascript:alert(1)\n\n[x]",
+        "<javascript:alert(1)>",
+        "<svg onload=alert(1)>",
+        "<svg><script>alert(1)</script></svg>",
+        "<math><mi xlink:href=\"javascript:alert(1)\">x</mi></math>",
+        "<iframe src=\"https://evil.example\"></iframe>",
+        "<iframe srcdoc=\"<script>alert(1)</script>\"></iframe>",
+        "<object data=\"x.swf\"></object>",
+        "<embed src=\"x.swf\">",
+        "<form action=\"https://evil.example\"><button formaction=\"javascript:alert(1)\">x</button></form>",
+        "<input type=\"text\" onfocus=alert(1) autofocus>",
+        "<details open ontoggle=alert(1)>x</details>",
+        "<div style=\"background:url(javascript:alert(1))\">x</div>",
+        "<a href=\"https://ok.example\" style=\"color: #000000; background: url(x)\">x</a>",
+        "<style>body { display: none }</style>",
+        "<link rel=stylesheet href=\"https://evil.example/x.css\">",
+        "<meta http-equiv=\"refresh\" content=\"0;url=javascript:alert(1)\">",
+        "<base href=\"javascript:/\">",
+        "<!--<script>alert(1)</script>-->",
+        "<noscript><p title=\"</noscript><img src=x onerror=alert(1)>\"></noscript>",
+        "<table background=\"javascript:alert(1)\"><tr><td>x</td></tr></table>",
+        "<aside class=\"quote\" data-username=\"x\" onmouseover=\"alert(1)\">x</aside>",
+        "<span class=\"censored\" style=\"position: fixed\">x</span>",
+        "<a href=\"https://ok.example\" target=\"_blank\">x</a>",
+        "[quote=\"<script>alert(1)</script>, post:1, topic:1\"]\nx\n[/quote]",
+        "`<script>alert(1)</script>`",
+        "```\n<script>alert(1)</script>\n```",
+    ];
+
+    fn assert_inert(html: &str, payload: &str) {
+        let fragment = Html::parse_fragment(html);
+        let everything = Selector::parse("*").unwrap();
+        for element in fragment.select(&everything) {
+            let name = element.value().name();
+            assert!(
+                name == "html" || TAGS.contains(&name),
+                "<{name}> survived {payload:?}: {html}"
+            );
+            for (attr, value) in element.value().attrs() {
+                assert!(
+                    !attr.starts_with("on") && attr != "target" && attr != "srcset",
+                    "{attr} survived {payload:?}: {html}"
+                );
+                if matches!(attr, "href" | "src") {
+                    let value = value.trim().to_ascii_lowercase();
+                    assert!(
+                        !value.contains(':')
+                            || ["http:", "https:", "mailto:"]
+                                .iter()
+                                .any(|s| value.starts_with(s)),
+                        "{attr}={value:?} survived {payload:?}: {html}"
+                    );
+                }
+                if attr == "style" {
+                    assert!(
+                        !value.contains("url("),
+                        "style survived {payload:?}: {html}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_xss_corpus_is_inert() {
+        for payload in XSS_CORPUS {
+            assert_inert(&render(payload), payload);
+            assert_inert(
+                &render_with(
+                    payload,
+                    &RenderContext {
+                        quotes: [(1, 1)].into(),
+                        nofollow: true,
+                        ..Default::default()
+                    },
+                ),
+                payload,
+            );
+        }
+    }
+
+    #[test]
+    fn test_harmless_html_survives() {
+        let cooked = render(
+            "<details><summary>Spoiler</summary>It was <kbd>Ctrl</kbd>+<sup>2</sup></details>",
+        );
+        assert!(cooked.contains(
+            "<details><summary>Spoiler</summary>It was <kbd>Ctrl</kbd>+<sup>2</sup></details>"
+        ));
+    }
+
+    #[test]
+    fn test_hook_markup_survives() {
+        let mut ctx = RenderContext {
+            mentions: [("bob".to_string(), "bob".to_string())].into(),
+            quotes: [(3, 2)].into(),
+            ..Default::default()
+        };
+        ctx.oneboxes.insert(
+            "https://example.com/a".to_string(),
+            Onebox {
+                title: Some("A".to_string()),
+                image_url: Some("https://example.com/a.png".to_string()),
+                site_name: Some("Example".to_string()),
+                ..Default::default()
+            },
+        );
+        let raw = "@bob\n\n[quote=\"bob, post:2, topic:3\"]\nhi\n[/quote]\n\nhttps://example.com/a\n\n- [x] done\n\n```rust\nfn x() {}\n```";
+        let cooked = render_with(raw, &ctx);
+        // Cleaning the hook output again changes nothing.
+        assert_eq!(CLEANER.clean(&cooked).to_string(), cooked);
+        for fragment in [
+            r#"<a class="mention" href="/u/bob">@bob</a>"#,
+            r#"<aside class="quote" data-username="bob" data-topic="3" data-post="2">"#,
+            r#"<aside class="onebox" data-url="https://example.com/a">"#,
+            r#"<img src="https://example.com/a.png" class="thumbnail" alt="">"#,
+            r#"<input type="checkbox" checked="" disabled="">"#,
+            r#"<code class="language-rust">"#,
+        ] {
+            assert!(cooked.contains(fragment), "{fragment} missing: {cooked}");
+        }
+    }
+
+    #[test]
+    fn test_classes_are_narrowed() {
+        let cooked = render("<span class=\"censored huge\">x</span> <p class=\"evil\">y</p>");
+        assert!(cooked.contains("<span class=\"censored\">x</span>"));
+        assert!(cooked.contains("<p>y</p>"));
+    }
+
+    #[test]
+    fn test_nofollow_marks_only_external_links() {
+        let ctx = RenderContext {
+            nofollow: true,
+            base_url: Some("https://forum.example".to_string()),
+            ..Default::default()
+        };
+        let raw = "[out](https://elsewhere.example/?a=1&b=2) [home](https://forum.example/t/1) [rel](/t/2) <a href=\"//cdn.example/x\" title=\"a > b\">cdn</a>";
+        let cooked = render_with(raw, &ctx);
+        assert!(cooked.contains(
+            r#"<a href="https://elsewhere.example/?a=1&amp;b=2" rel="nofollow ugc">out</a>"#
+        ));
+        assert!(cooked.contains(r#"<a href="https://forum.example/t/1">home</a>"#));
+        assert!(cooked.contains(r#"<a href="/t/2">rel</a>"#));
+        assert!(cooked.contains(r#"title="a &gt; b" rel="nofollow ugc">cdn</a>"#));
+
+        let followed = Cooker::new().cook(raw, &RenderContext::default());
+        assert!(!followed.contains("nofollow"));
+    }
+}
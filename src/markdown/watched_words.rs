@@ -0,0 +1,141 @@
+//! Watched words in the post's text: `censor` words are blacked out, one
+//! `■` per character, and `replace` words are swapped for their
+//! replacement text.
+
+use regex::Regex;
+
+use super::{Doc, Hook, RenderContext, escape_html};
+
+pub struct WatchedWords;
+
+impl Hook for WatchedWords {
+    fn name(&self) -> &'static str {
+        "watched_words"
+    }
+
+    fn process<'a>(&self, doc: &Doc<'a>, ctx: &RenderContext) {
+        if ctx.censor.is_none() && ctx.replace.is_empty() {
+            return;
+        }
+        for node in doc.plain_text_nodes() {
+            doc.splice_text(node, |text| {
+                let mut hits: Vec<(std::ops::Range<usize>, String)> = Vec::new();
+                for m in ctx.censor.iter().flat_map(|re| re.find_iter(text)) {
+                    let blacked = "■".repeat(m.as_str().chars().count());
+                    hits.push((
+                        m.range(),
+                        format!("<span class=\"censored\">{blacked}</span>"),
+                    ));
+                }
+                for (re, replacement) in &ctx.replace {
+                    for m in re.find_iter(text) {
+                        hits.push((m.range(), escape_html(replacement)));
+                    }
+                }
+                non_overlapping(hits)
+            });
+        }
+    }
+}
+
+/// `hits` in order, keeping the earliest (then longest) of any that
+/// overlap. Censoring comes first in `hits`, so it wins ties.
+fn non_overlapping(
+    mut hits: Vec<(std::ops::Range<usize>, String)>,
+) -> Vec<(std::ops::Range<usize>, String)> {
+    hits.retain(|(range, _)| !range.is_empty());
+    // A stable sort keeps censor hits ahead of equal replace hits.
+    hits.sort_by_key(|(range, _)| (range.start, std::cmp::Reverse(range.end)));
+    let mut kept: Vec<(std::ops::Range<usize>, String)> = Vec::new();
+    for hit in hits {
+        if kept.last().is_none_or(|(last, _)| hit.0.start >= last.end) {
+            kept.push(hit);
+        }
+    }
+    kept
+}
+
+/// `text` with every `censor` match blacked out, for places that have no
+/// cooked HTML, like topic titles.
+pub fn censor_in_text(text: &str, censor: Option<&Regex>) -> String {
+    match censor {
+        Some(re) => re
+            .replace_all(text, |caps: &regex::Captures| {
+                "■".repeat(caps[0].chars().count())
+            })
+            .into_owned(),
+        None => text.to_string(),
+    }
+}
+
+/// `text` with every `replace` match swapped for its replacement, for
+/// places that have no cooked HTML, like topic titles.
+pub fn replace_in_text(text: &str, replace: &[(Regex, String)]) -> String {
+    replace
+        .iter()
+        .fold(text.to_string(), |text, (re, replacement)| {
+            re.replace_all(&text, regex::NoExpand(replacement))
+                .into_owned()
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::render_with;
+    use super::*;
+
+    fn ctx(censor: &str, replace: &[(&str, &str)]) -> RenderContext {
+        RenderContext {
+            censor: (!censor.is_empty()).then(|| Regex::new(censor).unwrap()),
+            replace: replace
+                .iter()
+                .map(|(re, with)| (Regex::new(re).unwrap(), with.to_string()))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_censored_words_are_blacked_out() {
+        let cooked = render_with("Darn it, DARN. darned `darn`", &ctx(r"(?i)\bdarn\b", &[]));
+        assert_eq!(
+            cooked
+                .matches("<span class=\"censored\">■■■■</span>")
+                .count(),
+            2
+        );
+        assert!(cooked.contains("darned"));
+        assert!(cooked.contains("<code>darn</code>"));
+    }
+
+    #[test]
+    fn test_multi_word_phrases() {
+        let cooked = render_with("a bad word here", &ctx(r"(?i)\bbad word\b", &[]));
+        assert!(cooked.contains("a <span class=\"censored\">■■■■■■■■</span> here"));
+    }
+
+    #[test]
+    fn test_replacements_are_escaped_and_censoring_wins_overlaps() {
+        let cooked = render_with(
+            "my cat is a dog",
+            &ctx(
+                r"\bdog\b",
+                &[(r"\bcat\b", "<kitty>"), (r"\bdog\b", "puppy")],
+            ),
+        );
+        assert!(cooked.contains("my &lt;kitty&gt; is a <span class=\"censored\">■■■</span>"));
+    }
+
+    #[test]
+    fn test_censor_in_text() {
+        let censor = Regex::new(r"(?i)\bdarn\b").unwrap();
+        assert_eq!(censor_in_text("Darn, darned", Some(&censor)), "■■■■, darned");
+        assert_eq!(censor_in_text("Darn", None), "Darn");
+    }
+
+    #[test]
+    fn test_replace_in_text() {
+        let replace = [(Regex::new(r"(?i)\bcolour\b").unwrap(), "$color".to_string())];
+        assert_eq!(replace_in_text("Colour me red", &replace), "$color me red");
+    }
+}
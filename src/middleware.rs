@@ -6,7 +6,7 @@
 //! and stacking multiple `scope("")` siblings makes only the first one
 //! reachable — see commit history for the bug this replaces.
 //!
-//! Three extractors are provided:
+//! Four extractors are provided:
 //!
 //! - `AuthUser`: required auth. Returns 401 if no valid Bearer token.
 //! - `MaybeAuthUser`: optional auth. Always succeeds; returns `Some(Claims)`
@@ -15,22 +15,47 @@
 //! - `ReadAuthUser`: respects the `require_auth_for_reads` site setting.
 //!   Behaves like `MaybeAuthUser` when the setting is false; behaves like
 //!   `AuthUser` when it's true.
+//! - `PostingUser`: required auth, plus a confirmed email address unless
+//!   the `unconfirmed_user_access` site setting allows unconfirmed posting.
+//!   Returns 403 for unconfirmed accounts. Use on creating and editing
+//!   topics and posts.
+//!
+//! A token only counts if it was minted under the user's current
+//! `token_version`; changing the password bumps the version, so every
+//! extractor treats older tokens as missing. Tokens of unconfirmed
+//! accounts count as missing too while `unconfirmed_user_access` is `none`.
 
-use actix_web::{FromRequest, HttpRequest, dev::Payload, error::ErrorUnauthorized, web};
+use actix_web::{FromRequest, HttpRequest, dev::Payload, web};
 use std::future::{Ready, ready};
 
 use crate::DbPool;
 use crate::auth::{Claims, verify_token};
 use crate::config::require_auth_for_reads;
+use crate::error::ApiError;
+use crate::services::{email_confirmation, passwords};
 
-/// Parse and verify a Bearer token from the request's Authorization header.
+/// Parse and verify a Bearer token from the request's Authorization header,
+/// and check it hasn't been revoked by a password change, nor belongs to an
+/// unconfirmed account while `unconfirmed_user_access` is `none`.
 fn claims_from_request(req: &HttpRequest) -> Option<Claims> {
     let header = req
         .headers()
         .get("Authorization")
         .and_then(|h| h.to_str().ok())?;
     let token = header.strip_prefix("Bearer ")?;
-    verify_token(token).ok()
+    let claims = verify_token(token).ok()?;
+
+    let mut conn = req.app_data::<web::Data<DbPool>>()?.get().ok()?;
+    if !passwords::token_is_current(&mut conn, &claims).ok()? {
+        return None;
+    }
+    email_confirmation::can_log_in(&mut conn, claims.user_id)
+        .ok()?
+        .then_some(claims)
+}
+
+fn missing_token() -> actix_web::Error {
+    ApiError::Unauthorized("Missing or invalid authorization token".to_string()).into()
 }
 
 /// Required-auth extractor. Errors 401 if no valid Bearer token is present.
@@ -43,9 +68,7 @@ impl FromRequest for AuthUser {
     fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
         match claims_from_request(req) {
             Some(claims) => ready(Ok(AuthUser(claims))),
-            None => ready(Err(ErrorUnauthorized(serde_json::json!({
-                "error": "Missing or invalid authorization token"
-            })))),
+            None => ready(Err(missing_token())),
         }
     }
 }
@@ -79,10 +102,44 @@ impl FromRequest for ReadAuthUser {
         let claims = claims_from_request(req);
 
         if require_auth && claims.is_none() {
-            return ready(Err(ErrorUnauthorized(serde_json::json!({
-                "error": "Authentication required"
-            }))));
+            return ready(Err(ApiError::Unauthorized(
+                "Authentication required".to_string(),
+            )
+            .into()));
         }
         ready(Ok(ReadAuthUser(claims)))
     }
 }
+
+/// Required-auth extractor for creating content. Like `AuthUser`, but an
+/// account with an unconfirmed email gets a 403 unless the
+/// `unconfirmed_user_access` setting is `full`.
+pub struct PostingUser(pub Claims);
+
+impl FromRequest for PostingUser {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let Some(claims) = claims_from_request(req) else {
+            return ready(Err(missing_token()));
+        };
+
+        let allowed = req
+            .app_data::<web::Data<DbPool>>()
+            .and_then(|pool| pool.get().ok())
+            .and_then(|mut conn| email_confirmation::can_post(&mut conn, claims.user_id).ok());
+
+        match allowed {
+            Some(true) => ready(Ok(PostingUser(claims))),
+            Some(false) => ready(Err(ApiError::EmailNotConfirmed(
+                "Confirm your email address before posting".to_string(),
+            )
+            .into())),
+            None => ready(Err(ApiError::Forbidden(
+                "Could not verify account".to_string(),
+            )
+            .into())),
+        }
+    }
+}
@@ -1,19 +1,37 @@
+pub mod backup_code;
 pub mod category;
+pub mod custom_emoji;
+pub mod email_log;
+pub mod email_token;
+pub mod link_preview;
 pub mod notification;
 pub mod post;
+pub mod post_flag;
 pub mod post_like;
+pub mod post_mention;
+pub mod queued_post;
 pub mod site_setting;
 pub mod topic;
 pub mod topic_view;
 pub mod user;
 pub mod user_stat;
+pub mod watched_word;
 
+pub use backup_code::{BackupCode, NewBackupCode};
 pub use category::{Category, NewCategory, UpdateCategory};
+pub use custom_emoji::{CreateCustomEmojiInput, CustomEmoji, NewCustomEmoji};
+pub use email_log::{EmailLog, NewEmailLog};
+pub use email_token::{EmailToken, NewEmailToken};
+pub use link_preview::{LinkPreview, NewLinkPreview};
 pub use notification::{NewNotification, Notification};
 pub use post::{CreatePostInput, NewPost, Post, UpdatePost, UpdatePostInput};
+pub use post_flag::{NewPostFlag, PostFlag};
 pub use post_like::{NewPostLike, PostLike};
+pub use post_mention::{NewPostMention, PostMention};
+pub use queued_post::{NewQueuedPost, QueuedPost, RejectQueuedPostInput};
 pub use site_setting::{SiteSetting, UpdateSiteSetting};
 pub use topic::{NewTopic, Topic, UpdateTopic};
 pub use topic_view::{NewTopicView, TopicView};
 pub use user::{NewUser, UpdateUser, User};
 pub use user_stat::{NewUserStat, UserStat};
+pub use watched_word::{NewWatchedWord, WatchedWord, WatchedWordInput};
@@ -0,0 +1,24 @@
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+
+use crate::schema::user_backup_codes;
+
+/// A one-time two-factor backup code. Never serialized: the raw codes are
+/// shown once, at generation.
+#[derive(Debug, Clone, Queryable, Selectable, Identifiable)]
+#[diesel(table_name = user_backup_codes)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct BackupCode {
+    pub id: i64,
+    pub user_id: i32,
+    pub code_hash: String,
+    pub used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = user_backup_codes)]
+pub struct NewBackupCode {
+    pub user_id: i32,
+    pub code_hash: String,
+}
@@ -2,8 +2,10 @@ use chrono::NaiveDateTime;
 use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
+use validator::Validate;
 
 use crate::schema::categories;
+use crate::validation::{validate_color, validate_not_blank, validate_slug};
 
 #[derive(Debug, Clone, Queryable, Selectable, Identifiable, Serialize, ToSchema)]
 #[diesel(table_name = categories)]
@@ -19,24 +21,30 @@ pub struct Category {
     pub updated_at: NaiveDateTime,
 }
 
-#[derive(Debug, Insertable, Deserialize, ToSchema)]
+#[derive(Debug, Insertable, Deserialize, ToSchema, Validate)]
 #[diesel(table_name = categories)]
 pub struct NewCategory {
+    #[validate(length(min = 1, max = 100), custom(function = "validate_not_blank"))]
     pub name: String,
+    #[validate(length(max = 100), custom(function = "validate_slug"))]
     pub slug: String,
     pub description: Option<String>,
     #[serde(default = "default_color")]
+    #[validate(custom(function = "validate_color"))]
     pub color: String,
     #[serde(default)]
     pub position: i32,
 }
 
-#[derive(Debug, AsChangeset, Deserialize, ToSchema)]
+#[derive(Debug, AsChangeset, Deserialize, ToSchema, Validate)]
 #[diesel(table_name = categories)]
 pub struct UpdateCategory {
+    #[validate(length(min = 1, max = 100), custom(function = "validate_not_blank"))]
     pub name: Option<String>,
+    #[validate(length(max = 100), custom(function = "validate_slug"))]
     pub slug: Option<String>,
     pub description: Option<String>,
+    #[validate(custom(function = "validate_color"))]
     pub color: Option<String>,
     pub position: Option<i32>,
 }
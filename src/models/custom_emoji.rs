@@ -0,0 +1,54 @@
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use validator::Validate;
+
+use crate::emoji::CUSTOM_GROUP;
+use crate::schema::custom_emojis;
+use crate::validation::{validate_emoji_name, validate_http_url, validate_not_blank};
+
+#[derive(Debug, Clone, Queryable, Selectable, Identifiable, Serialize, ToSchema)]
+#[diesel(table_name = custom_emojis)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct CustomEmoji {
+    pub id: i32,
+    pub name: String,
+    pub url: String,
+    pub group_name: String,
+    pub user_id: Option<i32>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = custom_emojis)]
+pub struct NewCustomEmoji {
+    pub name: String,
+    pub url: String,
+    pub group_name: String,
+    pub user_id: Option<i32>,
+}
+
+/// API input for adding a custom emoji. The image is hosted elsewhere;
+/// `url` is embedded in posts as-is.
+#[derive(Debug, Deserialize, ToSchema, Validate)]
+pub struct CreateCustomEmojiInput {
+    #[validate(custom(function = "validate_emoji_name"))]
+    pub name: String,
+    #[validate(length(max = 2000), custom(function = "validate_http_url"))]
+    pub url: String,
+    /// Picker group; defaults to `custom`.
+    #[validate(length(min = 1, max = 50), custom(function = "validate_not_blank"))]
+    pub group: Option<String>,
+}
+
+impl CreateCustomEmojiInput {
+    pub fn into_new_custom_emoji(self, user_id: i32) -> NewCustomEmoji {
+        NewCustomEmoji {
+            name: self.name,
+            url: self.url,
+            group_name: self.group.unwrap_or_else(|| CUSTOM_GROUP.to_string()),
+            user_id: Some(user_id),
+        }
+    }
+}
@@ -0,0 +1,34 @@
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::schema::email_logs;
+
+#[derive(Debug, Clone, Queryable, Selectable, Identifiable, Serialize, ToSchema)]
+#[diesel(table_name = email_logs)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct EmailLog {
+    pub id: i64,
+    pub user_id: Option<i32>,
+    pub to_address: String,
+    pub email_type: String,
+    pub subject: String,
+    pub message_id: String,
+    pub status: String,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub bounced_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = email_logs)]
+pub struct NewEmailLog {
+    pub user_id: Option<i32>,
+    pub to_address: String,
+    pub email_type: String,
+    pub subject: String,
+    pub message_id: String,
+    pub status: String,
+    pub error: Option<String>,
+}
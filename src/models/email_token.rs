@@ -0,0 +1,30 @@
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+
+use crate::schema::email_tokens;
+
+/// A single-use token sent by email. Never serialized: the raw token only
+/// exists in the email, and the hash is of no use to clients.
+#[derive(Debug, Clone, Queryable, Selectable, Identifiable)]
+#[diesel(table_name = email_tokens)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct EmailToken {
+    pub id: i64,
+    pub user_id: i32,
+    pub token_hash: String,
+    pub purpose: String,
+    pub email: String,
+    pub expires_at: DateTime<Utc>,
+    pub consumed_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = email_tokens)]
+pub struct NewEmailToken {
+    pub user_id: i32,
+    pub token_hash: String,
+    pub purpose: String,
+    pub email: String,
+    pub expires_at: DateTime<Utc>,
+}
@@ -0,0 +1,51 @@
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::markdown::Onebox;
+use crate::schema::link_previews;
+
+#[derive(Debug, Clone, Queryable, Selectable, Identifiable, Serialize, ToSchema)]
+#[diesel(table_name = link_previews)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct LinkPreview {
+    pub id: i32,
+    pub url: String,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub image_url: Option<String>,
+    pub site_name: Option<String>,
+    pub error: Option<String>,
+    pub fetched_at: DateTime<Utc>,
+}
+
+/// Also the changeset for refreshing an existing row, so every column is
+/// overwritten, `None`s included.
+#[derive(Debug, Insertable, AsChangeset)]
+#[diesel(table_name = link_previews)]
+#[diesel(treat_none_as_null = true)]
+pub struct NewLinkPreview {
+    pub url: String,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub image_url: Option<String>,
+    pub site_name: Option<String>,
+    pub error: Option<String>,
+    pub fetched_at: DateTime<Utc>,
+}
+
+impl LinkPreview {
+    /// The metadata to render, or `None` if the fetch failed.
+    pub fn onebox(&self) -> Option<Onebox> {
+        if self.error.is_some() {
+            return None;
+        }
+        Some(Onebox {
+            title: self.title.clone(),
+            description: self.description.clone(),
+            image_url: self.image_url.clone(),
+            site_name: self.site_name.clone(),
+        })
+    }
+}
@@ -2,8 +2,11 @@ use chrono::NaiveDateTime;
 use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
+use validator::Validate;
 
+use crate::markdown::COOK_VERSION;
 use crate::schema::posts;
+use crate::validation::{POST_MAX_LENGTH, validate_not_blank};
 
 #[derive(Debug, Clone, Queryable, Selectable, Identifiable, Associations, Serialize, ToSchema)]
 #[diesel(table_name = posts)]
@@ -26,6 +29,9 @@ pub struct Post {
     pub hidden_by_user_id: Option<i32>,
     pub deleted_by_user_id: Option<i32>,
     pub like_count: i32,
+    /// The [`crate::markdown::COOK_VERSION`] that produced `cooked`.
+    #[serde(skip)]
+    pub cook_version: i32,
 }
 
 #[derive(Debug, Insertable, Deserialize, ToSchema)]
@@ -37,6 +43,7 @@ pub struct NewPost {
     pub raw: String,
     pub cooked: String,
     pub reply_to_post_number: Option<i32>,
+    pub cook_version: i32,
 }
 
 #[derive(Debug, AsChangeset, Deserialize, ToSchema)]
@@ -44,21 +51,25 @@ pub struct NewPost {
 pub struct UpdatePost {
     pub raw: Option<String>,
     pub cooked: Option<String>,
+    pub cook_version: Option<i32>,
 }
 
 /// API input for creating a post (client only provides raw markdown)
-#[derive(Debug, Deserialize, ToSchema)]
+#[derive(Debug, Deserialize, ToSchema, Validate)]
 pub struct CreatePostInput {
     pub topic_id: i32,
     pub user_id: i32,
+    #[validate(range(min = 1))]
     pub post_number: i32,
+    #[validate(length(max = POST_MAX_LENGTH), custom(function = "validate_not_blank"))]
     pub raw: String,
     pub reply_to_post_number: Option<i32>,
 }
 
 impl CreatePostInput {
-    pub fn into_new_post(self) -> NewPost {
-        let cooked = crate::markdown::render(&self.raw);
+    /// `cooked` comes from the caller because rendering needs the database
+    /// (to resolve mentions); see `services::posts`.
+    pub fn into_new_post(self, cooked: String) -> NewPost {
         NewPost {
             topic_id: self.topic_id,
             user_id: self.user_id,
@@ -66,21 +77,24 @@ impl CreatePostInput {
             raw: self.raw,
             cooked,
             reply_to_post_number: self.reply_to_post_number,
+            cook_version: COOK_VERSION,
         }
     }
 }
 
 /// API input for updating a post (client only provides raw markdown)
-#[derive(Debug, Deserialize, ToSchema)]
+#[derive(Debug, Deserialize, ToSchema, Validate)]
 pub struct UpdatePostInput {
+    #[validate(length(max = POST_MAX_LENGTH), custom(function = "validate_not_blank"))]
     pub raw: Option<String>,
 }
 
 impl UpdatePostInput {
-    pub fn into_update_post(self) -> UpdatePost {
-        let cooked = self.raw.as_ref().map(|r| crate::markdown::render(r));
+    /// `cooked` must be given exactly when `raw` is.
+    pub fn into_update_post(self, cooked: Option<String>) -> UpdatePost {
         UpdatePost {
             raw: self.raw,
+            cook_version: cooked.as_ref().map(|_| COOK_VERSION),
             cooked,
         }
     }
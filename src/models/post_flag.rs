@@ -0,0 +1,29 @@
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::schema::post_flags;
+
+#[derive(Debug, Clone, Queryable, Selectable, Identifiable, Associations, Serialize, ToSchema)]
+#[diesel(table_name = post_flags)]
+#[diesel(belongs_to(super::post::Post))]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct PostFlag {
+    pub id: i32,
+    pub post_id: i32,
+    /// `None` when the system raised the flag.
+    pub user_id: Option<i32>,
+    pub reason: String,
+    pub message: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = post_flags)]
+pub struct NewPostFlag {
+    pub post_id: i32,
+    pub user_id: Option<i32>,
+    pub reason: String,
+    pub message: Option<String>,
+}
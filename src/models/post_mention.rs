@@ -0,0 +1,22 @@
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::schema::post_mentions;
+
+#[derive(Debug, Clone, Queryable, Selectable, Serialize, ToSchema)]
+#[diesel(table_name = post_mentions)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct PostMention {
+    pub post_id: i32,
+    pub user_id: i32,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = post_mentions)]
+pub struct NewPostMention {
+    pub post_id: i32,
+    pub user_id: i32,
+}
@@ -0,0 +1,57 @@
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use validator::Validate;
+
+use crate::schema::queued_posts;
+
+/// The author is below the `approve_unless_trust_level` setting.
+pub const REASON_TRUST_LEVEL: &str = "trust_level";
+/// The post matched a `require_approval` watched word.
+pub const REASON_WATCHED_WORD: &str = "watched_word";
+/// The topic is in one of the `approve_post_categories`.
+pub const REASON_CATEGORY: &str = "category";
+
+pub const STATUS_PENDING: &str = "pending";
+pub const STATUS_APPROVED: &str = "approved";
+pub const STATUS_REJECTED: &str = "rejected";
+
+#[derive(Debug, Clone, Queryable, Selectable, Identifiable, Serialize, ToSchema)]
+#[diesel(table_name = queued_posts)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct QueuedPost {
+    pub id: i32,
+    pub topic_id: i32,
+    pub user_id: i32,
+    pub raw: String,
+    pub reply_to_post_number: Option<i32>,
+    /// Why it was queued: `trust_level`, `watched_word` or `category`.
+    pub reason: String,
+    /// `pending`, `approved` or `rejected`.
+    pub status: String,
+    /// The post created on approval.
+    pub post_id: Option<i32>,
+    pub reviewed_by_user_id: Option<i32>,
+    pub reviewed_at: Option<DateTime<Utc>>,
+    pub reject_reason: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = queued_posts)]
+pub struct NewQueuedPost {
+    pub topic_id: i32,
+    pub user_id: i32,
+    pub raw: String,
+    pub reply_to_post_number: Option<i32>,
+    pub reason: String,
+}
+
+/// API input for rejecting a queued post. The reason is passed on to the
+/// author.
+#[derive(Debug, Default, Deserialize, ToSchema, Validate)]
+pub struct RejectQueuedPostInput {
+    #[validate(length(max = 1000))]
+    pub reason: Option<String>,
+}
@@ -2,8 +2,10 @@ use chrono::NaiveDateTime;
 use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
+use validator::Validate;
 
 use crate::schema::topics;
+use crate::validation::{TITLE_MAX_LENGTH, TITLE_MIN_LENGTH, validate_slug};
 
 #[derive(Debug, Clone, Queryable, Selectable, Identifiable, Associations, Serialize, ToSchema)]
 #[diesel(table_name = topics)]
@@ -24,10 +26,12 @@ pub struct Topic {
     pub updated_at: NaiveDateTime,
 }
 
-#[derive(Debug, Insertable, Deserialize, ToSchema)]
+#[derive(Debug, Insertable, Deserialize, ToSchema, Validate)]
 #[diesel(table_name = topics)]
 pub struct NewTopic {
+    #[validate(length(min = TITLE_MIN_LENGTH, max = TITLE_MAX_LENGTH))]
     pub title: String,
+    #[validate(length(max = 255), custom(function = "validate_slug"))]
     pub slug: String,
     pub user_id: i32,
     pub category_id: Option<i32>,
@@ -41,10 +45,12 @@ pub struct NewTopic {
     pub closed: bool,
 }
 
-#[derive(Debug, AsChangeset, Deserialize, ToSchema)]
+#[derive(Debug, AsChangeset, Deserialize, ToSchema, Validate)]
 #[diesel(table_name = topics)]
 pub struct UpdateTopic {
+    #[validate(length(min = TITLE_MIN_LENGTH, max = TITLE_MAX_LENGTH))]
     pub title: Option<String>,
+    #[validate(length(max = 255), custom(function = "validate_slug"))]
     pub slug: Option<String>,
     pub category_id: Option<i32>,
     pub pinned: Option<bool>,
@@ -1,9 +1,11 @@
-use chrono::NaiveDateTime;
+use chrono::{DateTime, NaiveDateTime, Utc};
 use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
+use validator::Validate;
 
 use crate::schema::users;
+use crate::validation::{USERNAME_MAX_LENGTH, USERNAME_MIN_LENGTH, validate_username};
 
 #[derive(Debug, Clone, Queryable, Selectable, Identifiable, Serialize, ToSchema)]
 #[diesel(table_name = users)]
@@ -22,12 +24,31 @@ pub struct User {
     pub updated_at: NaiveDateTime,
     pub likes_given: i32,
     pub likes_received: i32,
+    pub email_confirmed: bool,
+    pub email_confirmed_at: Option<DateTime<Utc>>,
+    /// Bumped to log the user out everywhere; see [`crate::auth::Claims`].
+    #[serde(skip_serializing)]
+    pub token_version: i32,
+    pub password_changed_at: Option<DateTime<Utc>>,
+    /// Base32 TOTP secret; set at enrollment, before it's enabled.
+    #[serde(skip_serializing)]
+    #[schema(value_type = Option<String>)]
+    pub totp_secret: Option<String>,
+    pub totp_enabled: bool,
+    #[serde(skip_serializing)]
+    #[schema(value_type = Option<i64>)]
+    pub totp_last_used_step: Option<i64>,
 }
 
-#[derive(Debug, Insertable, Deserialize, ToSchema)]
+#[derive(Debug, Insertable, Deserialize, ToSchema, Validate)]
 #[diesel(table_name = users)]
 pub struct NewUser {
+    #[validate(
+        length(min = USERNAME_MIN_LENGTH, max = USERNAME_MAX_LENGTH),
+        custom(function = "validate_username")
+    )]
     pub username: String,
+    #[validate(email, length(max = 254))]
     pub email: String,
     pub password_hash: String,
     #[serde(default)]
@@ -35,16 +56,25 @@ pub struct NewUser {
     #[serde(default)]
     pub moderator: bool,
     #[serde(default)]
+    #[validate(range(min = 0, max = 4))]
     pub trust_level: i32,
+    #[serde(default)]
+    pub email_confirmed: bool,
 }
 
-#[derive(Debug, AsChangeset, Deserialize, ToSchema)]
+#[derive(Debug, AsChangeset, Deserialize, ToSchema, Validate)]
 #[diesel(table_name = users)]
 pub struct UpdateUser {
+    #[validate(
+        length(min = USERNAME_MIN_LENGTH, max = USERNAME_MAX_LENGTH),
+        custom(function = "validate_username")
+    )]
     pub username: Option<String>,
+    #[validate(email, length(max = 254))]
     pub email: Option<String>,
     pub admin: Option<bool>,
     pub moderator: Option<bool>,
+    #[validate(range(min = 0, max = 4))]
     pub trust_level: Option<i32>,
 }
 
@@ -0,0 +1,109 @@
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use validator::Validate;
+
+use crate::schema::watched_words;
+use crate::validation::{validate_not_blank, validate_watched_word_action};
+
+/// Reject the post or title with an error.
+pub const ACTION_BLOCK: &str = "block";
+/// Black the word out in cooked posts.
+pub const ACTION_CENSOR: &str = "censor";
+/// Swap the word for `replacement` in cooked posts and titles.
+pub const ACTION_REPLACE: &str = "replace";
+/// Hide the post until a moderator approves it.
+pub const ACTION_REQUIRE_APPROVAL: &str = "require_approval";
+/// Flag the post for moderators.
+pub const ACTION_FLAG: &str = "flag";
+/// Tag the topic with the tag named in `replacement`.
+pub const ACTION_TAG: &str = "tag";
+
+pub const ACTIONS: &[&str] = &[
+    ACTION_BLOCK,
+    ACTION_CENSOR,
+    ACTION_REPLACE,
+    ACTION_REQUIRE_APPROVAL,
+    ACTION_FLAG,
+    ACTION_TAG,
+];
+
+#[derive(Debug, Clone, Queryable, Selectable, Identifiable, Serialize, ToSchema)]
+#[diesel(table_name = watched_words)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct WatchedWord {
+    pub id: i32,
+    pub word: String,
+    pub action: String,
+    /// The replacement text for `replace`, the tag name for `tag`.
+    pub replacement: Option<String>,
+    /// `word` is a regular expression rather than a literal word.
+    pub regex: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Insertable, AsChangeset)]
+#[diesel(table_name = watched_words)]
+#[diesel(treat_none_as_null = true)]
+pub struct NewWatchedWord {
+    pub word: String,
+    pub action: String,
+    pub replacement: Option<String>,
+    pub regex: bool,
+}
+
+/// API input for adding or replacing a watched word.
+#[derive(Debug, Deserialize, ToSchema, Validate)]
+pub struct WatchedWordInput {
+    #[validate(length(min = 1, max = 100), custom(function = "validate_not_blank"))]
+    pub word: String,
+    /// One of `block`, `censor`, `replace`, `require_approval`, `flag` or
+    /// `tag`.
+    #[validate(custom(function = "validate_watched_word_action"))]
+    pub action: String,
+    /// Required for `replace` (the replacement text) and `tag` (the tag
+    /// name); ignored otherwise.
+    #[validate(length(max = 100))]
+    pub replacement: Option<String>,
+    /// Treat `word` as a regular expression. Off by default.
+    #[serde(default)]
+    pub regex: bool,
+}
+
+impl WatchedWordInput {
+    /// Checks that depend on more than one field: `replace` and `tag` need
+    /// a replacement, and a regex has to compile.
+    pub fn check(&self) -> Result<(), String> {
+        let needs_replacement = self.action == ACTION_REPLACE || self.action == ACTION_TAG;
+        let replacement = self.replacement.as_deref().map(str::trim).unwrap_or("");
+        if needs_replacement && replacement.is_empty() {
+            return Err(format!("A {} word needs a replacement", self.action));
+        }
+        if self.regex {
+            regex::Regex::new(&self.word).map_err(|e| format!("Invalid regex: {e}"))?;
+        }
+        Ok(())
+    }
+
+    pub fn into_new_watched_word(self) -> NewWatchedWord {
+        let keeps_replacement = self.action == ACTION_REPLACE || self.action == ACTION_TAG;
+        NewWatchedWord {
+            // Literal words match ignoring case, so store them lowercased
+            // and let the unique constraint catch duplicates.
+            word: if self.regex {
+                self.word
+            } else {
+                self.word.trim().to_lowercase()
+            },
+            replacement: if keeps_replacement {
+                self.replacement.map(|r| r.trim().to_string())
+            } else {
+                None
+            },
+            action: self.action,
+            regex: self.regex,
+        }
+    }
+}
@@ -1,5 +1,5 @@
 use diesel::prelude::*;
-use serde::{Deserialize, Serialize};
+use serde::Serialize;
 
 use crate::schema::{moderation_actions, user_suspensions};
 use crate::DbPool;
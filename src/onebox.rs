@@ -0,0 +1,498 @@
+//! Link previews ("oneboxes"). [`lookup`] fetches a page through the
+//! installed [`Fetcher`] and pulls a title, description, image and site
+//! name out of its Open Graph tags, falling back to the page's oEmbed
+//! endpoint and then to plain `<title>` / `<meta name="description">`.
+//!
+//! Lookups run from `FetchLinkPreviewsJob`, never from a request handler:
+//! remote sites can be slow, and a failure only means the link stays a
+//! plain link.
+
+use futures::future::BoxFuture;
+use reqwest::Url;
+use scraper::{Html, Selector};
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use crate::markdown::Onebox;
+
+/// Bodies are cut off here; the tags we want are in the `<head>`.
+pub const MAX_BODY_BYTES: usize = 1024 * 1024;
+pub const FETCH_TIMEOUT_SECS: u64 = 10;
+pub const MAX_REDIRECTS: usize = 5;
+/// A whole [`lookup`], redirects and oEmbed included, gives up after this.
+pub const LOOKUP_TIMEOUT_SECS: u64 = 20;
+/// Longer descriptions are truncated (on a char boundary) to this many bytes.
+pub const MAX_DESCRIPTION_BYTES: usize = 300;
+
+/// A fetched document, after redirects.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Page {
+    /// Where the document ended up being served from, for resolving
+    /// relative URLs in it.
+    pub url: String,
+    pub content_type: String,
+    pub body: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FetchError {
+    /// Not an http(s) URL we can parse.
+    InvalidUrl(String),
+    /// The host resolves to a loopback, private or otherwise internal
+    /// address.
+    Forbidden(String),
+    /// The server answered with a non-success status.
+    Status(u16),
+    TooManyRedirects,
+    /// Connecting, sending or reading failed.
+    Transport(String),
+    /// Fetched fine but there was nothing to preview.
+    NoMetadata,
+    /// The lookup took longer than [`LOOKUP_TIMEOUT_SECS`].
+    TimedOut,
+}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FetchError::InvalidUrl(url) => write!(f, "invalid URL {}", url),
+            FetchError::Forbidden(host) => write!(f, "refusing to fetch from {}", host),
+            FetchError::Status(status) => write!(f, "HTTP status {}", status),
+            FetchError::TooManyRedirects => write!(f, "more than {} redirects", MAX_REDIRECTS),
+            FetchError::Transport(e) => write!(f, "transport error: {}", e),
+            FetchError::NoMetadata => write!(f, "no preview metadata"),
+            FetchError::TimedOut => write!(f, "timed out after {}s", LOOKUP_TIMEOUT_SECS),
+        }
+    }
+}
+
+impl std::error::Error for FetchError {}
+
+/// Something that can GET a URL. Boxed-future trait (like `Mailer`) so
+/// the implementation can be swapped at runtime behind an `Arc`.
+pub trait Fetcher: Send + Sync {
+    fn get<'a>(&'a self, url: &'a str) -> BoxFuture<'a, Result<Page, FetchError>>;
+}
+
+/// The real fetcher: plain HTTP(S) with a timeout, a size cap and a
+/// redirect limit. By default it refuses hosts that resolve to internal
+/// addresses, so posting a link can't be used to probe the server's
+/// network. Each hop connects to the address that was checked, so DNS
+/// can't change its answer in between.
+#[derive(Debug, Clone, Default)]
+pub struct HttpFetcher {
+    allow_private: bool,
+}
+
+impl HttpFetcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also fetch from loopback and private addresses. For tests that
+    /// serve pages from a local stub server.
+    pub fn allow_private_addresses(mut self) -> Self {
+        self.allow_private = true;
+        self
+    }
+
+    async fn resolve(&self, url: &Url) -> Result<SocketAddr, FetchError> {
+        let host = url
+            .host_str()
+            .ok_or_else(|| FetchError::InvalidUrl(url.to_string()))?;
+        let port = url
+            .port_or_known_default()
+            .ok_or_else(|| FetchError::InvalidUrl(url.to_string()))?;
+        let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.trim_matches(['[', ']']), port))
+            .await
+            .map_err(|e| FetchError::Transport(e.to_string()))?
+            .collect();
+        if !self.allow_private && addrs.iter().any(|a| !is_public(a.ip())) {
+            return Err(FetchError::Forbidden(host.to_string()));
+        }
+        addrs
+            .into_iter()
+            .next()
+            .ok_or_else(|| FetchError::Transport(format!("{host} has no addresses")))
+    }
+
+    async fn fetch(&self, url: &str) -> Result<Page, FetchError> {
+        let mut url = parse_web_url(url)?;
+        for _ in 0..=MAX_REDIRECTS {
+            let addr = self.resolve(&url).await?;
+            let host = url.host_str().unwrap_or_default().to_string();
+            let client = reqwest::Client::builder()
+                .redirect(reqwest::redirect::Policy::none())
+                .timeout(Duration::from_secs(FETCH_TIMEOUT_SECS))
+                .user_agent(concat!(
+                    "discourse-rs/",
+                    env!("CARGO_PKG_VERSION"),
+                    " (link preview)"
+                ))
+                .resolve(&host, addr)
+                .build()
+                .map_err(|e| FetchError::Transport(e.to_string()))?;
+            let mut resp = client
+                .get(url.clone())
+                .header(
+                    reqwest::header::ACCEPT,
+                    "text/html, application/json;q=0.9, */*;q=0.1",
+                )
+                .send()
+                .await
+                .map_err(|e| FetchError::Transport(e.to_string()))?;
+
+            if resp.status().is_redirection() {
+                let location = resp
+                    .headers()
+                    .get(reqwest::header::LOCATION)
+                    .and_then(|l| l.to_str().ok())
+                    .ok_or(FetchError::Status(resp.status().as_u16()))?;
+                let next = url
+                    .join(location)
+                    .map_err(|_| FetchError::InvalidUrl(location.to_string()))?;
+                url = parse_web_url(next.as_str())?;
+                continue;
+            }
+            if !resp.status().is_success() {
+                return Err(FetchError::Status(resp.status().as_u16()));
+            }
+
+            let content_type = resp
+                .headers()
+                .get(reqwest::header::CONTENT_TYPE)
+                .and_then(|c| c.to_str().ok())
+                .unwrap_or_default()
+                .to_string();
+            let mut body = Vec::new();
+            while let Some(chunk) = resp
+                .chunk()
+                .await
+                .map_err(|e| FetchError::Transport(e.to_string()))?
+            {
+                body.extend_from_slice(&chunk);
+                if body.len() >= MAX_BODY_BYTES {
+                    body.truncate(MAX_BODY_BYTES);
+                    break;
+                }
+            }
+            return Ok(Page {
+                url: url.to_string(),
+                content_type,
+                body: String::from_utf8_lossy(&body).into_owned(),
+            });
+        }
+        Err(FetchError::TooManyRedirects)
+    }
+}
+
+impl Fetcher for HttpFetcher {
+    fn get<'a>(&'a self, url: &'a str) -> BoxFuture<'a, Result<Page, FetchError>> {
+        Box::pin(self.fetch(url))
+    }
+}
+
+/// Process-wide fetcher. `None` until [`install`] is called, in which case
+/// [`current`] uses a default [`HttpFetcher`].
+static FETCHER: RwLock<Option<Arc<dyn Fetcher>>> = RwLock::new(None);
+
+/// Replace the fetcher used by [`lookup`]. Tests install one that may
+/// reach their local stub server.
+pub fn install(fetcher: Arc<dyn Fetcher>) {
+    *FETCHER.write().unwrap_or_else(|e| e.into_inner()) = Some(fetcher);
+}
+
+pub fn current() -> Arc<dyn Fetcher> {
+    let mut slot = FETCHER.write().unwrap_or_else(|e| e.into_inner());
+    slot.get_or_insert_with(|| Arc::new(HttpFetcher::new()))
+        .clone()
+}
+
+/// Fetch `url` and extract its preview. Open Graph wins; if the page has
+/// no `og:title` but advertises an oEmbed endpoint, that fills the gaps.
+pub async fn lookup(url: &str) -> Result<Onebox, FetchError> {
+    tokio::time::timeout(Duration::from_secs(LOOKUP_TIMEOUT_SECS), lookup_untimed(url))
+        .await
+        .unwrap_or(Err(FetchError::TimedOut))
+}
+
+async fn lookup_untimed(url: &str) -> Result<Onebox, FetchError> {
+    let fetcher = current();
+    let page = fetcher.get(url).await?;
+    let mut onebox = parse_page(&page);
+
+    if onebox.title.is_none()
+        && let Some(endpoint) = oembed_endpoint(&page)
+    {
+        // A broken oEmbed endpoint shouldn't cost us what the page had.
+        match fetcher.get(&endpoint).await {
+            Ok(json) => merge_oembed(&mut onebox, &json),
+            Err(e) => log::warn!("oEmbed lookup for {url} failed: {e}"),
+        }
+    }
+
+    if onebox.title.is_none() && onebox.description.is_none() {
+        return Err(FetchError::NoMetadata);
+    }
+    Ok(onebox)
+}
+
+/// Read the preview out of an HTML page.
+pub fn parse_page(page: &Page) -> Onebox {
+    let doc = Html::parse_document(&page.body);
+    let meta = |attr: &str, name: &str| {
+        let selector = Selector::parse(&format!("meta[{attr}=\"{name}\"]")).ok()?;
+        doc.select(&selector)
+            .filter_map(|el| el.value().attr("content"))
+            .map(|c| c.trim().to_string())
+            .find(|c| !c.is_empty())
+    };
+    let title_tag = Selector::parse("title").ok().and_then(|s| {
+        doc.select(&s)
+            .next()
+            .map(|el| el.text().collect::<String>().trim().to_string())
+            .filter(|t| !t.is_empty())
+    });
+
+    Onebox {
+        title: meta("property", "og:title").or(title_tag),
+        description: meta("property", "og:description")
+            .or_else(|| meta("name", "description"))
+            .map(|d| truncate(d, MAX_DESCRIPTION_BYTES)),
+        image_url: meta("property", "og:image").and_then(|i| absolute_web_url(&page.url, &i)),
+        site_name: meta("property", "og:site_name"),
+    }
+}
+
+/// The JSON oEmbed endpoint a page links to, made absolute.
+pub fn oembed_endpoint(page: &Page) -> Option<String> {
+    let doc = Html::parse_document(&page.body);
+    let selector =
+        Selector::parse("link[rel=\"alternate\"][type=\"application/json+oembed\"]").ok()?;
+    let href = doc
+        .select(&selector)
+        .find_map(|el| el.value().attr("href"))?;
+    absolute_web_url(&page.url, href)
+}
+
+/// Fill whatever `onebox` lacks from an oEmbed response.
+pub fn merge_oembed(onebox: &mut Onebox, response: &Page) {
+    let Ok(json) = serde_json::from_str::<serde_json::Value>(&response.body) else {
+        return;
+    };
+    let field = |key: &str| {
+        json.get(key)
+            .and_then(|v| v.as_str())
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+    };
+    onebox.title = onebox.title.take().or_else(|| field("title"));
+    onebox.site_name = onebox.site_name.take().or_else(|| field("provider_name"));
+    onebox.image_url = onebox
+        .image_url
+        .take()
+        .or_else(|| field("thumbnail_url").and_then(|i| absolute_web_url(&response.url, &i)));
+}
+
+fn parse_web_url(url: &str) -> Result<Url, FetchError> {
+    match Url::parse(url) {
+        Ok(u) if matches!(u.scheme(), "http" | "https") && u.host_str().is_some() => Ok(u),
+        _ => Err(FetchError::InvalidUrl(url.to_string())),
+    }
+}
+
+/// `href` resolved against `base`, if the result is an http(s) URL. Keeps
+/// `javascript:` and friends out of rendered previews.
+fn absolute_web_url(base: &str, href: &str) -> Option<String> {
+    let joined = Url::parse(base).ok()?.join(href).ok()?;
+    parse_web_url(joined.as_str()).ok().map(String::from)
+}
+
+fn truncate(mut text: String, max: usize) -> String {
+    if text.len() > max {
+        let mut end = max;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        text.truncate(end);
+        text.push('…');
+    }
+    text
+}
+
+/// Whether `ip` is reachable on the public internet, as opposed to
+/// loopback, private, link-local, shared (CGNAT) or unspecified space.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => {
+            let [a, b, ..] = v4.octets();
+            !(v4.is_private()
+                || v4.is_loopback()
+                || v4.is_link_local()
+                || v4.is_unspecified()
+                || v4.is_broadcast()
+                || v4.is_documentation()
+                || v4.is_multicast()
+                || a == 0
+                || (a == 100 && (64..128).contains(&b))
+                || (a == 198 && (b & 0xfe) == 18))
+        }
+        IpAddr::V6(v6) => {
+            if let Some(v4) = embedded_ipv4(v6) {
+                return is_public(IpAddr::V4(v4));
+            }
+            let first = v6.segments()[0];
+            !(v6.is_loopback()
+                || v6.is_unspecified()
+                || v6.is_multicast()
+                || (first & 0xfe00) == 0xfc00
+                || (first & 0xffc0) == 0xfe80)
+        }
+    }
+}
+
+/// The IPv4 address an IPv6 one stands for and reaches: IPv4-mapped
+/// (`::ffff:a.b.c.d`), IPv4-compatible (`::a.b.c.d`), NAT64
+/// (`64:ff9b::a.b.c.d`) or 6to4 (`2002:AABB:CCDD::`).
+fn embedded_ipv4(v6: Ipv6Addr) -> Option<Ipv4Addr> {
+    let v4 = |hi: u16, lo: u16| Ipv4Addr::from((u32::from(hi) << 16) | u32::from(lo));
+    match v6.segments() {
+        [0, 0, 0, 0, 0, 0xffff, hi, lo]
+        | [0, 0, 0, 0, 0, 0, hi, lo]
+        | [0x64, 0xff9b, 0, 0, 0, 0, hi, lo] => Some(v4(hi, lo)),
+        [0x2002, hi, lo, ..] => Some(v4(hi, lo)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(body: &str) -> Page {
+        Page {
+            url: "https://example.com/articles/1".to_string(),
+            content_type: "text/html".to_string(),
+            body: body.to_string(),
+        }
+    }
+
+    #[test]
+    fn open_graph_tags_win_over_fallbacks() {
+        let onebox = parse_page(&page(
+            r#"<html><head><title>Plain</title>
+            <meta name="description" content="plain description">
+            <meta property="og:title" content="OG title">
+            <meta property="og:description" content="OG description">
+            <meta property="og:image" content="/img/cover.png">
+            <meta property="og:site_name" content="Example">
+            </head></html>"#,
+        ));
+        assert_eq!(onebox.title.as_deref(), Some("OG title"));
+        assert_eq!(onebox.description.as_deref(), Some("OG description"));
+        assert_eq!(
+            onebox.image_url.as_deref(),
+            Some("https://example.com/img/cover.png")
+        );
+        assert_eq!(onebox.site_name.as_deref(), Some("Example"));
+    }
+
+    #[test]
+    fn falls_back_to_title_and_meta_description() {
+        let onebox = parse_page(&page(
+            r#"<title> Plain </title><meta name="description" content="About it">"#,
+        ));
+        assert_eq!(onebox.title.as_deref(), Some("Plain"));
+        assert_eq!(onebox.description.as_deref(), Some("About it"));
+    }
+
+    #[test]
+    fn script_image_urls_are_dropped() {
+        let onebox = parse_page(&page(
+            r#"<meta property="og:image" content="javascript:alert(1)">"#,
+        ));
+        assert_eq!(onebox.image_url, None);
+    }
+
+    #[test]
+    fn oembed_fills_missing_fields() {
+        let html =
+            page(r#"<link rel="alternate" type="application/json+oembed" href="/oembed?url=x">"#);
+        assert_eq!(
+            oembed_endpoint(&html).as_deref(),
+            Some("https://example.com/oembed?url=x")
+        );
+
+        let mut onebox = parse_page(&html);
+        let json = Page {
+            body: r#"{"title":"Video","provider_name":"Tube","thumbnail_url":"https://example.com/t.jpg"}"#.to_string(),
+            ..html
+        };
+        merge_oembed(&mut onebox, &json);
+        assert_eq!(onebox.title.as_deref(), Some("Video"));
+        assert_eq!(onebox.site_name.as_deref(), Some("Tube"));
+        assert_eq!(
+            onebox.image_url.as_deref(),
+            Some("https://example.com/t.jpg")
+        );
+    }
+
+    #[test]
+    fn long_descriptions_are_truncated() {
+        let long = "é".repeat(MAX_DESCRIPTION_BYTES);
+        let onebox = parse_page(&page(&format!(
+            r#"<meta property="og:description" content="{long}">"#
+        )));
+        let description = onebox.description.unwrap();
+        assert!(description.len() <= MAX_DESCRIPTION_BYTES + '…'.len_utf8());
+        assert!(description.ends_with('…'));
+    }
+
+    #[test]
+    fn internal_addresses_are_not_public() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "192.168.0.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.1.2.3",
+            "198.18.0.1",
+            "198.19.255.255",
+            "224.0.0.1",
+            "::1",
+            "fd00::1",
+            "ff02::1",
+            "::ffff:127.0.0.1",
+            "::127.0.0.1",
+            "::10.0.0.1",
+            "64:ff9b::a9fe:a9fe",
+            "64:ff9b::127.0.0.1",
+            "2002:7f00:1::",
+            "2002:a9fe:a9fe::1",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{ip}");
+        }
+        for ip in [
+            "93.184.216.34",
+            "198.20.0.1",
+            "2606:2800:220:1::1",
+            "::93.184.216.34",
+            "64:ff9b::93.184.216.34",
+            "2002:5db8:d822::1",
+        ] {
+            assert!(is_public(ip.parse().unwrap()), "{ip}");
+        }
+    }
+
+    #[actix_rt::test]
+    async fn default_fetcher_refuses_loopback() {
+        let err = HttpFetcher::new()
+            .get("http://127.0.0.1:9/")
+            .await
+            .unwrap_err();
+        assert_eq!(err, FetchError::Forbidden("127.0.0.1".to_string()));
+    }
+}
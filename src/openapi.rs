@@ -1,8 +1,13 @@
-use utoipa::OpenApi;
+use utoipa::{IntoResponses, OpenApi};
+
+use crate::error::{ErrorCode, ErrorResponse};
+use crate::routes;
+use crate::services::emoji::{EmojiList, EmojiView};
+use crate::services::watched_words::Matches;
 
 use crate::models::{
-    Category, CreatePostInput, NewCategory, NewTopic, NewUser, Notification, Post, Topic,
-    UpdateCategory, UpdatePostInput, UpdateTopic, UpdateUser, User,
+    Category, CreateCustomEmojiInput, CreatePostInput, CustomEmoji, NewCategory, NewTopic, NewUser, Notification, Post, QueuedPost, RejectQueuedPostInput, Topic,
+    UpdateCategory, UpdatePostInput, UpdateTopic, UpdateUser, User, WatchedWord, WatchedWordInput,
 };
 
 #[derive(OpenApi)]
@@ -10,27 +15,124 @@ use crate::models::{
     info(
         title = "Discourse-rs API",
         version = "0.1.0",
-        description = "A Discourse-inspired forum platform built in Rust",
+        description = "A Discourse-inspired forum platform built in Rust. \
+            Errors share one body, `ErrorResponse`, whose `code` is a stable `ErrorCode`.",
         license(name = "MIT")
     ),
+    servers((url = "/api")),
+    paths(
+        routes::auth::login, routes::auth::login_two_factor, routes::auth::register,
+        routes::auth::confirm_email, routes::auth::resend_confirmation,
+        routes::auth::forgot_password, routes::auth::reset_password,
+        routes::categories::list_categories, routes::categories::get_category,
+        routes::categories::create_category, routes::categories::update_category,
+        routes::categories::delete_category,
+        routes::email_logs::list_email_logs, routes::email_logs::record_bounce,
+        routes::emoji::list_emojis, routes::emoji::create_emoji, routes::emoji::delete_emoji,
+        routes::jobs::list_dead_jobs, routes::jobs::retry_dead_job, routes::jobs::discard_dead_job,
+        routes::jobs::list_jobs, routes::jobs::job_stats, routes::jobs::show_job,
+        routes::jobs::cancel_job, routes::jobs::retry_job, routes::jobs::run_job_now,
+        routes::jobs::enqueue_job,
+        routes::likes::like_post_route, routes::likes::unlike_post_route,
+        routes::moderation::lock_topic, routes::moderation::unlock_topic,
+        routes::moderation::pin_topic, routes::moderation::unpin_topic,
+        routes::moderation::close_topic, routes::moderation::open_topic,
+        routes::moderation::hide_post, routes::moderation::unhide_post,
+        routes::moderation::delete_post, routes::moderation::suspend_user,
+        routes::notifications::list_notifications, routes::notifications::unread_count,
+        routes::notifications::mark_as_read, routes::notifications::mark_all_read,
+        routes::posts::list_posts, routes::posts::list_topic_posts, routes::posts::create_post,
+        routes::posts::update_post, routes::posts::delete_post,
+        routes::queued_posts::list_queued_posts, routes::queued_posts::approve_queued_post,
+        routes::queued_posts::reject_queued_post,
+        routes::reads::record_topic_read,
+        routes::search::search,
+        routes::settings::list_settings, routes::settings::get_setting,
+        routes::settings::update_setting,
+        routes::topics::list_topics, routes::topics::get_topic, routes::topics::create_topic,
+        routes::topics::update_topic, routes::topics::list_topic_tags, routes::topics::watch_topic,
+        routes::topics::unwatch_topic, routes::topics::delete_topic,
+        routes::users::list_users, routes::users::get_user, routes::users::create_user,
+        routes::users::update_user, routes::users::delete_user, routes::users::change_password,
+        routes::users::two_factor_status, routes::users::enroll_totp, routes::users::enable_totp,
+        routes::users::disable_two_factor, routes::users::regenerate_backup_codes,
+        routes::watched_words::list_watched_words, routes::watched_words::create_watched_word,
+        routes::watched_words::update_watched_word, routes::watched_words::delete_watched_word,
+        routes::watched_words::test_watched_words,
+    ),
     tags(
         (name = "users", description = "User management endpoints"),
         (name = "topics", description = "Topic management endpoints"),
         (name = "posts", description = "Post management endpoints"),
         (name = "categories", description = "Category management endpoints"),
         (name = "notifications", description = "Notification endpoints"),
+        (name = "emoji", description = "Emoji endpoints"),
         (name = "search", description = "Search endpoints"),
         (name = "moderation", description = "Moderation endpoints"),
-        (name = "auth", description = "Authentication endpoints")
+        (name = "auth", description = "Authentication endpoints"),
+        (name = "settings", description = "Site setting endpoints"),
+        (name = "admin", description = "Site administration endpoints")
     ),
     components(
         schemas(
             User, NewUser, UpdateUser,
             Topic, NewTopic, UpdateTopic,
             Post, CreatePostInput, UpdatePostInput,
+            QueuedPost, RejectQueuedPostInput,
             Category, NewCategory, UpdateCategory,
-            Notification
+            Notification,
+            CustomEmoji, CreateCustomEmojiInput, EmojiList, EmojiView,
+            WatchedWord, WatchedWordInput, Matches,
+            ErrorResponse, ErrorCode
         )
     )
 )]
 pub struct ApiDoc;
+
+// Error responses for `#[utoipa::path]`'s `responses(...)`, one per status
+// `ApiError` answers with. Each has the shared `ErrorResponse` body; the
+// description lists the `ErrorCode`s that status can carry. Handlers list
+// the ones they can return, plus `Unavailable` whenever they use the
+// database; `RateLimited` is on every path, since the limit is app-wide.
+
+#[derive(IntoResponses)]
+#[response(status = 400, description = "Malformed request (`bad_request`)")]
+pub struct BadRequest(pub ErrorResponse);
+
+#[derive(IntoResponses)]
+#[response(status = 401, description = "Missing or invalid credentials (`unauthorized`)")]
+pub struct Unauthorized(pub ErrorResponse);
+
+#[derive(IntoResponses)]
+#[response(
+    status = 403,
+    description = "Not allowed (`forbidden`, `email_not_confirmed`, `two_factor_required`)"
+)]
+pub struct Forbidden(pub ErrorResponse);
+
+#[derive(IntoResponses)]
+#[response(status = 404, description = "Not found (`not_found`)")]
+pub struct NotFound(pub ErrorResponse);
+
+#[derive(IntoResponses)]
+#[response(status = 409, description = "Conflicts with existing data (`conflict`)")]
+pub struct Conflict(pub ErrorResponse);
+
+#[derive(IntoResponses)]
+#[response(
+    status = 422,
+    description = "Rejected body (`validation_failed`, `invalid_reference`, `unprocessable`)"
+)]
+pub struct Unprocessable(pub ErrorResponse);
+
+#[derive(IntoResponses)]
+#[response(
+    status = 429,
+    description = "Too many requests (`rate_limited`)",
+    headers(("Retry-After" = i64, description = "Seconds to wait"))
+)]
+pub struct RateLimited(pub ErrorResponse);
+
+#[derive(IntoResponses)]
+#[response(status = 503, description = "Database unavailable (`service_unavailable`)")]
+pub struct Unavailable(pub ErrorResponse);
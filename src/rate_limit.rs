@@ -0,0 +1,44 @@
+//! Per-IP rate limiting for the whole app (see `main.rs`).
+//!
+//! actix-governor keys on the peer IP as usual; [`PeerIp`] only swaps its
+//! plain-text 429 for the shared error body, so a rate-limited client gets
+//! `{"code": "rate_limited", "retry_after": ...}` like any other error.
+
+use actix_governor::governor::NotUntil;
+use actix_governor::governor::clock::{Clock, DefaultClock, QuantaInstant};
+use actix_governor::{KeyExtractor, PeerIpKeyExtractor};
+use actix_web::dev::ServiceRequest;
+use actix_web::{HttpResponse, HttpResponseBuilder};
+use std::net::IpAddr;
+
+use crate::error::ApiError;
+
+#[derive(Debug, Clone, Copy)]
+pub struct PeerIp;
+
+impl KeyExtractor for PeerIp {
+    type Key = IpAddr;
+    type KeyExtractionError = <PeerIpKeyExtractor as KeyExtractor>::KeyExtractionError;
+
+    fn extract(&self, req: &ServiceRequest) -> Result<Self::Key, Self::KeyExtractionError> {
+        PeerIpKeyExtractor.extract(req)
+    }
+
+    fn exceed_rate_limit_response(
+        &self,
+        negative: &NotUntil<QuantaInstant>,
+        mut response: HttpResponseBuilder,
+    ) -> HttpResponse {
+        // The builder already carries governor's Retry-After header.
+        let retry_after_secs = negative
+            .wait_time_from(DefaultClock::default().now())
+            .as_secs();
+        response.json(
+            ApiError::RateLimited {
+                message: "Too many requests".to_string(),
+                retry_after_secs: retry_after_secs as i64,
+            }
+            .body(),
+        )
+    }
+}
@@ -2,16 +2,20 @@ use actix_web::web;
 
 pub mod auth;
 pub mod categories;
+pub mod email_logs;
+pub mod emoji;
 pub mod jobs;
 pub mod likes;
 pub mod moderation;
 pub mod notifications;
 pub mod posts;
+pub mod queued_posts;
 pub mod reads;
 pub mod search;
 pub mod settings;
 pub mod topics;
 pub mod users;
+pub mod watched_words;
 
 pub fn config(cfg: &mut web::ServiceConfig) {
     cfg.configure(auth::configure)
@@ -20,10 +24,14 @@ pub fn config(cfg: &mut web::ServiceConfig) {
         .configure(categories::configure)
         .configure(topics::configure)
         .configure(posts::configure)
+        .configure(queued_posts::configure)
+        .configure(emoji::configure)
         .configure(likes::configure)
         .configure(reads::configure)
         .configure(jobs::configure)
+        .configure(email_logs::configure)
         .configure(moderation::configure)
         .configure(notifications::configure)
-        .configure(search::configure);
+        .configure(search::configure)
+        .configure(watched_words::configure);
 }
@@ -1,28 +1,89 @@
-use actix_web::{post, web, HttpResponse, Responder};
+use actix_web::http::StatusCode;
+use actix_web::{post, web, HttpResponse};
 use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
+use validator::Validate;
 
-use crate::auth::{generate_token, hash_password, verify_password};
+use crate::auth::{
+    generate_pending_2fa_token, generate_token, hash_password, verify_password,
+    verify_pending_2fa_token, PENDING_2FA_EXPIRATION_MINUTES,
+};
+use crate::error::ApiError;
+use crate::jobs::{ConfirmEmailJob, JobQueue, PasswordResetEmailJob, WelcomeEmailJob};
 use crate::models::{NewUser, User};
+use crate::openapi::{
+    BadRequest, Conflict, Forbidden, RateLimited, Unauthorized, Unavailable, Unprocessable,
+};
 use crate::schema::users;
+use crate::services::email_confirmation::{
+    self, Confirmation, ResendOutcome, UnconfirmedAccess,
+};
+use crate::services::passwords;
+use crate::services::two_factor::{self, LoginCheck};
+use crate::validation::{
+    USERNAME_MAX_LENGTH, USERNAME_MIN_LENGTH, ValidatedJson, validate_password, validate_username,
+};
 use crate::DbPool;
 
-#[derive(Debug, Deserialize)]
+/// Login only checks the fields are present: rules for new usernames and
+/// passwords mustn't lock out accounts created before they tightened.
+#[derive(Debug, Deserialize, Validate)]
 pub struct LoginRequest {
+    #[validate(length(min = 1))]
     pub username: String,
+    #[validate(length(min = 1))]
     pub password: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Validate)]
 pub struct RegisterRequest {
+    #[validate(
+        length(min = USERNAME_MIN_LENGTH, max = USERNAME_MAX_LENGTH),
+        custom(function = "validate_username")
+    )]
     pub username: String,
+    #[validate(email, length(max = 254))]
     pub email: String,
+    #[validate(custom(function = "validate_password"))]
     pub password: String,
 }
 
+#[derive(Debug, Deserialize, Validate)]
+pub struct ResendConfirmationRequest {
+    #[validate(email)]
+    pub email: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct ForgotPasswordRequest {
+    /// Username or email address.
+    #[validate(length(min = 1, max = 254))]
+    pub login: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct ResetPasswordRequest {
+    #[validate(length(min = 1))]
+    pub token: String,
+    #[validate(custom(function = "validate_password"))]
+    pub password: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct TwoFactorLoginRequest {
+    #[validate(length(min = 1))]
+    pub pending_token: String,
+    /// A TOTP code or an unused backup code.
+    #[validate(length(min = 1, max = 64))]
+    pub code: String,
+}
+
 #[derive(Debug, Serialize)]
 pub struct AuthResponse {
-    pub token: String,
+    /// Absent when the account can't log in yet (unconfirmed email and
+    /// `unconfirmed_user_access` is `none`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
     pub user: UserResponse,
 }
 
@@ -34,6 +95,36 @@ pub struct UserResponse {
     pub admin: bool,
     pub moderator: bool,
     pub trust_level: i32,
+    pub email_confirmed: bool,
+}
+
+/// Sent instead of a session token when the account has 2FA. The client
+/// asks for a code and posts it with `pending_token` to
+/// `/auth/login/2fa`.
+#[derive(Debug, Serialize)]
+pub struct TwoFactorChallenge {
+    pub two_factor_required: bool,
+    pub pending_token: String,
+    /// Seconds until `pending_token` expires.
+    pub expires_in: i64,
+}
+
+/// Log `user` in: a session token, or a [`TwoFactorChallenge`] if they
+/// have 2FA. Every route that logs someone in goes through here, so none
+/// of them skips the second factor.
+fn session_response(user: User, status: StatusCode) -> Result<HttpResponse, ApiError> {
+    if user.totp_enabled {
+        return Ok(HttpResponse::build(status).json(TwoFactorChallenge {
+            two_factor_required: true,
+            pending_token: generate_pending_2fa_token(user.id, user.token_version)?,
+            expires_in: PENDING_2FA_EXPIRATION_MINUTES * 60,
+        }));
+    }
+    let token = generate_token(user.id, user.username.clone(), user.token_version)?;
+    Ok(HttpResponse::build(status).json(AuthResponse {
+        token: Some(token),
+        user: user.into(),
+    }))
 }
 
 impl From<User> for UserResponse {
@@ -45,89 +136,146 @@ impl From<User> for UserResponse {
             admin: user.admin,
             moderator: user.moderator,
             trust_level: user.trust_level,
+            email_confirmed: user.email_confirmed,
         }
     }
 }
 
+#[utoipa::path(
+    tag = "auth",
+    responses(
+        (status = 200, description = "Session token, or a two-factor challenge"),
+        BadRequest,
+        Unauthorized,
+        Forbidden,
+        Unprocessable,
+        RateLimited,
+        Unavailable,
+    )
+)]
 #[post("/auth/login")]
-async fn login(pool: web::Data<DbPool>, credentials: web::Json<LoginRequest>) -> impl Responder {
-    let mut conn = match pool.get() {
-        Ok(conn) => conn,
-        Err(_) => {
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to get database connection"
-            }))
-        }
-    };
+async fn login(
+    pool: web::Data<DbPool>,
+    credentials: ValidatedJson<LoginRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
 
     let credentials = credentials.into_inner();
+    let username = credentials.username;
 
-    let result = web::block(move || {
-        users::table
-            .filter(users::username.eq(&credentials.username))
+    let (user, access) = web::block(move || {
+        let user: Option<User> = users::table
+            .filter(users::username.eq(&username))
             .select(User::as_select())
             .first(&mut conn)
+            .optional()?;
+        let access = UnconfirmedAccess::current(&mut conn)?;
+        Ok::<_, diesel::result::Error>((user, access))
     })
-    .await;
-
-    match result {
-        Ok(Ok(user)) => {
-            match verify_password(&credentials.password, &user.password_hash) {
-                Ok(true) => {
-                    match generate_token(user.id, user.username.clone()) {
-                        Ok(token) => HttpResponse::Ok().json(AuthResponse {
-                            token,
-                            user: user.into(),
-                        }),
-                        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
-                            "error": "Failed to generate token"
-                        })),
-                    }
-                }
-                Ok(false) => HttpResponse::Unauthorized().json(serde_json::json!({
-                    "error": "Invalid username or password"
-                })),
-                Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
-                    "error": "Failed to verify password"
-                })),
-            }
+    .await??;
+
+    let invalid = || ApiError::Unauthorized("Invalid username or password".to_string());
+    let user = user.ok_or_else(invalid)?;
+    if !verify_password(&credentials.password, &user.password_hash)? {
+        return Err(invalid());
+    }
+    // Checked after the password so this doesn't reveal which accounts
+    // are unconfirmed.
+    if !user.email_confirmed && !access.can_log_in() {
+        return Err(ApiError::EmailNotConfirmed(
+            "Confirm your email address before logging in".to_string(),
+        ));
+    }
+
+    session_response(user, StatusCode::OK)
+}
+
+/// The second step of logging in to an account with 2FA: trade the
+/// pending token from `/auth/login` and a TOTP or backup code for a
+/// session token.
+#[utoipa::path(
+    tag = "auth",
+    responses(
+        (status = 200, description = "Session token"),
+        BadRequest,
+        Unauthorized,
+        Unprocessable,
+        RateLimited,
+        Unavailable,
+    )
+)]
+#[post("/auth/login/2fa")]
+async fn login_two_factor(
+    pool: web::Data<DbPool>,
+    req: ValidatedJson<TwoFactorLoginRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let req = req.into_inner();
+    let pending = verify_pending_2fa_token(&req.pending_token).ok_or_else(|| {
+        ApiError::Unauthorized("Login attempt expired; log in again".to_string())
+    })?;
+
+    let mut conn = pool.get()?;
+    let (user, check) = web::block(move || {
+        let user: Option<User> = users::table
+            .find(pending.user_id)
+            .select(User::as_select())
+            .first(&mut conn)
+            .optional()?;
+        // A password change since the first step voids it.
+        let Some(user) = user.filter(|u| u.token_version == pending.token_version) else {
+            return Ok((None, LoginCheck::Invalid));
+        };
+        let check = two_factor::verify_login(&mut conn, user.id, &req.code)?;
+        Ok::<_, diesel::result::Error>((Some(user), check))
+    })
+    .await??;
+
+    let user = user.ok_or_else(|| {
+        ApiError::Unauthorized("Login attempt expired; log in again".to_string())
+    })?;
+    match check {
+        LoginCheck::Verified => {}
+        LoginCheck::Invalid => {
+            return Err(ApiError::Unauthorized(
+                "Invalid two-factor code".to_string(),
+            ));
+        }
+        LoginCheck::LockedOut { retry_after_secs } => {
+            return Err(ApiError::RateLimited {
+                message: "Too many wrong two-factor codes; try again later".to_string(),
+                retry_after_secs,
+            });
         }
-        Ok(Err(diesel::NotFound)) => HttpResponse::Unauthorized().json(serde_json::json!({
-            "error": "Invalid username or password"
-        })),
-        Ok(Err(_)) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Failed to load user"
-        })),
-        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Blocking error"
-        })),
     }
+
+    let token = generate_token(user.id, user.username.clone(), user.token_version)?;
+    Ok(HttpResponse::Ok().json(AuthResponse {
+        token: Some(token),
+        user: user.into(),
+    }))
 }
 
+#[utoipa::path(
+    tag = "auth",
+    responses(
+        (status = 201, description = "Account created"),
+        BadRequest,
+        Conflict,
+        Unprocessable,
+        RateLimited,
+        Unavailable,
+    )
+)]
 #[post("/auth/register")]
 async fn register(
     pool: web::Data<DbPool>,
-    new_user: web::Json<RegisterRequest>,
-) -> impl Responder {
-    let mut conn = match pool.get() {
-        Ok(conn) => conn,
-        Err(_) => {
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to get database connection"
-            }))
-        }
-    };
+    job_queue: Option<web::Data<JobQueue>>,
+    new_user: ValidatedJson<RegisterRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
 
     let new_user = new_user.into_inner();
-
-    let password_hash = match hash_password(&new_user.password) {
-        Ok(hash) => hash,
-        Err(_) => {
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to hash password"
-            }))
-        }
-    };
+    let password_hash = hash_password(&new_user.password)?;
 
     let new_user_data = NewUser {
         username: new_user.username,
@@ -136,39 +284,204 @@ async fn register(
         admin: false,
         moderator: false,
         trust_level: 0,
+        email_confirmed: false,
     };
 
-    let result = web::block(move || {
-        conn.transaction::<User, diesel::result::Error, _>(|conn| {
+    let (user, confirm_token, access) = web::block(move || {
+        conn.transaction::<_, diesel::result::Error, _>(|conn| {
             let user: User = diesel::insert_into(users::table)
                 .values(&new_user_data)
                 .returning(User::as_returning())
                 .get_result(conn)?;
             crate::services::user_stats::ensure_for(conn, user.id)?;
-            Ok(user)
+            let confirm_token = email_confirmation::issue_token(conn, &user)?;
+            let access = UnconfirmedAccess::current(conn)?;
+            Ok((user, confirm_token, access))
         })
     })
-    .await;
-
-    match result {
-        Ok(Ok(user)) => match generate_token(user.id, user.username.clone()) {
-            Ok(token) => HttpResponse::Created().json(AuthResponse {
-                token,
-                user: user.into(),
-            }),
-            Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to generate token"
-            })),
-        },
-        Ok(Err(_)) => HttpResponse::BadRequest().json(serde_json::json!({
-            "error": "Failed to create user (username or email may already exist)"
-        })),
-        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Blocking error"
-        })),
+    .await??;
+
+    // Best effort, after the commit: the account exists either way, and
+    // the user can ask for the email again.
+    if let Some(jq) = job_queue
+        && let Err(e) = jq.enqueue(ConfirmEmailJob {
+            user_id: user.id,
+            username: user.username.clone(),
+            email: user.email.clone(),
+            token: confirm_token,
+        })
+    {
+        log::error!("Failed to enqueue confirmation email: {e}");
     }
+
+    let token = if access.can_log_in() {
+        Some(generate_token(
+            user.id,
+            user.username.clone(),
+            user.token_version,
+        )?)
+    } else {
+        None
+    };
+    Ok(HttpResponse::Created().json(AuthResponse {
+        token,
+        user: user.into(),
+    }))
+}
+
+/// Confirm the email address a confirmation token was sent to, and log
+/// the user in. The welcome email goes out on first confirmation.
+#[utoipa::path(
+    tag = "auth",
+    responses(
+        (status = 200, description = "Session token, or a two-factor challenge"),
+        BadRequest,
+        RateLimited,
+        Unavailable,
+    )
+)]
+#[post("/auth/confirm/{token}")]
+async fn confirm_email(
+    pool: web::Data<DbPool>,
+    job_queue: Option<web::Data<JobQueue>>,
+    token: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
+
+    let token = token.into_inner();
+    let Confirmation {
+        user,
+        newly_confirmed,
+    } = web::block(move || email_confirmation::confirm(&mut conn, &token)).await??;
+
+    if newly_confirmed
+        && let Some(jq) = job_queue
+        && let Err(e) = jq.enqueue(WelcomeEmailJob {
+            user_id: user.id,
+            username: user.username.clone(),
+            email: user.email.clone(),
+        })
+    {
+        log::error!("Failed to enqueue welcome email: {e}");
+    }
+
+    session_response(user, StatusCode::OK)
+}
+
+/// Send another confirmation email. Answers the same way whether or not
+/// an unconfirmed account has the address, and when it was sent one too
+/// recently.
+#[utoipa::path(
+    tag = "auth",
+    responses(
+        (status = 202, description = "Sent if the address belongs to an unconfirmed account"),
+        BadRequest,
+        Unprocessable,
+        RateLimited,
+        Unavailable,
+    )
+)]
+#[post("/auth/confirm/resend")]
+async fn resend_confirmation(
+    pool: web::Data<DbPool>,
+    job_queue: Option<web::Data<JobQueue>>,
+    req: ValidatedJson<ResendConfirmationRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
+
+    let email = req.into_inner().email;
+    let outcome = web::block(move || email_confirmation::resend(&mut conn, &email)).await??;
+
+    if let ResendOutcome::Issued { user, token } = outcome
+        && let Some(jq) = job_queue
+        && let Err(e) = jq.enqueue(ConfirmEmailJob {
+            user_id: user.id,
+            username: user.username,
+            email: user.email,
+            token,
+        })
+    {
+        log::error!("Failed to enqueue confirmation email: {e}");
+    }
+    Ok(HttpResponse::Accepted().json(serde_json::json!({
+        "message": "If that address belongs to an unconfirmed account, a new confirmation email is on its way"
+    })))
+}
+
+/// Email a password reset link. Answers the same way whether or not the
+/// account exists, and when it was sent a link too recently.
+#[utoipa::path(
+    tag = "auth",
+    responses(
+        (status = 202, description = "Sent if the account exists"),
+        BadRequest,
+        Unprocessable,
+        RateLimited,
+        Unavailable,
+    )
+)]
+#[post("/auth/forgot_password")]
+async fn forgot_password(
+    pool: web::Data<DbPool>,
+    job_queue: Option<web::Data<JobQueue>>,
+    req: ValidatedJson<ForgotPasswordRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
+
+    let username_or_email = req.into_inner().login;
+    let issued =
+        web::block(move || passwords::request_reset(&mut conn, &username_or_email)).await??;
+
+    if let Some((user, token)) = issued
+        && let Some(jq) = job_queue
+        && let Err(e) = jq.enqueue(PasswordResetEmailJob {
+            user_id: user.id,
+            username: user.username,
+            email: user.email,
+            token,
+        })
+    {
+        log::error!("Failed to enqueue password reset email: {e}");
+    }
+    Ok(HttpResponse::Accepted().json(serde_json::json!({
+        "message": "If that account exists, a password reset email is on its way"
+    })))
+}
+
+/// Set a new password with the token from a reset email. Logs out every
+/// existing session and returns a fresh token.
+#[utoipa::path(
+    tag = "auth",
+    responses(
+        (status = 200, description = "Session token, or a two-factor challenge"),
+        BadRequest,
+        Unprocessable,
+        RateLimited,
+        Unavailable,
+    )
+)]
+#[post("/auth/reset_password")]
+async fn reset_password(
+    pool: web::Data<DbPool>,
+    req: ValidatedJson<ResetPasswordRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
+
+    let req = req.into_inner();
+    let user =
+        web::block(move || passwords::reset(&mut conn, &req.token, &req.password)).await??;
+
+    // A reset proves the email, not the second factor.
+    session_response(user, StatusCode::OK)
 }
 
 pub fn configure(cfg: &mut web::ServiceConfig) {
-    cfg.service(login).service(register);
+    // /auth/confirm/resend must come before /auth/confirm/{token}.
+    cfg.service(login)
+        .service(login_two_factor)
+        .service(register)
+        .service(resend_confirmation)
+        .service(confirm_email)
+        .service(forgot_password)
+        .service(reset_password);
 }
@@ -1,145 +1,149 @@
-use actix_web::{delete, get, post, put, web, HttpResponse, Responder};
+use actix_web::{delete, get, post, put, web, HttpResponse};
 use diesel::prelude::*;
 
+use crate::error::ApiError;
 use crate::guardian::ModeratorGuard;
 use crate::models::{Category, NewCategory, UpdateCategory};
+use crate::openapi::{
+    BadRequest, Conflict, Forbidden, NotFound, RateLimited, Unauthorized, Unavailable,
+    Unprocessable,
+};
 use crate::schema::categories;
+use crate::validation::ValidatedJson;
 use crate::DbPool;
 
 // Public endpoints - anyone can read categories
 
+#[utoipa::path(
+    tag = "categories",
+    responses(
+        (status = 200, description = "Categories by position", body = Vec<Category>),
+        RateLimited,
+        Unavailable,
+    )
+)]
 #[get("/categories")]
-async fn list_categories(pool: web::Data<DbPool>) -> impl Responder {
-    let mut conn = match pool.get() {
-        Ok(c) => c,
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": format!("Database connection failed: {}", e)
-            }))
-        }
-    };
+async fn list_categories(pool: web::Data<DbPool>) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
 
-    match categories::table
+    let cats = categories::table
         .select(Category::as_select())
         .order(categories::position.asc())
-        .load(&mut conn)
-    {
-        Ok(cats) => HttpResponse::Ok().json(cats),
-        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": format!("Failed to load categories: {}", e)
-        })),
-    }
+        .load(&mut conn)?;
+    Ok(HttpResponse::Ok().json(cats))
 }
 
+#[utoipa::path(
+    tag = "categories",
+    responses(
+        (status = 200, description = "The category", body = Category),
+        NotFound,
+        RateLimited,
+        Unavailable,
+    )
+)]
 #[get("/categories/{id}")]
-async fn get_category(pool: web::Data<DbPool>, category_id: web::Path<i32>) -> impl Responder {
-    let mut conn = match pool.get() {
-        Ok(c) => c,
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": format!("Database connection failed: {}", e)
-            }))
-        }
-    };
+async fn get_category(
+    pool: web::Data<DbPool>,
+    category_id: web::Path<i32>,
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
 
-    match categories::table
+    let category = categories::table
         .find(category_id.into_inner())
         .select(Category::as_select())
         .first(&mut conn)
-    {
-        Ok(category) => HttpResponse::Ok().json(category),
-        Err(diesel::NotFound) => HttpResponse::NotFound().json(serde_json::json!({
-            "error": "Category not found"
-        })),
-        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": format!("Failed to load category: {}", e)
-        })),
-    }
+        .optional()?
+        .ok_or_else(|| ApiError::not_found("Category"))?;
+    Ok(HttpResponse::Ok().json(category))
 }
 
 // Moderator-only endpoints - create, update, delete
 
+#[utoipa::path(
+    tag = "categories",
+    request_body = NewCategory,
+    responses(
+        (status = 201, description = "Created", body = Category),
+        BadRequest,
+        Unauthorized,
+        Forbidden,
+        Conflict,
+        Unprocessable,
+        RateLimited,
+        Unavailable,
+    )
+)]
 #[post("/categories")]
 async fn create_category(
     pool: web::Data<DbPool>,
     _guard: ModeratorGuard,
-    new_category: web::Json<NewCategory>,
-) -> impl Responder {
-    let mut conn = match pool.get() {
-        Ok(c) => c,
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": format!("Database connection failed: {}", e)
-            }))
-        }
-    };
+    new_category: ValidatedJson<NewCategory>,
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
 
-    match diesel::insert_into(categories::table)
+    let category = diesel::insert_into(categories::table)
         .values(new_category.into_inner())
-        .get_result::<Category>(&mut conn)
-    {
-        Ok(category) => HttpResponse::Created().json(category),
-        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": format!("Failed to create category: {}", e)
-        })),
-    }
+        .get_result::<Category>(&mut conn)?;
+    Ok(HttpResponse::Created().json(category))
 }
 
+#[utoipa::path(
+    tag = "categories",
+    request_body = UpdateCategory,
+    responses(
+        (status = 200, description = "Updated", body = Category),
+        BadRequest,
+        Unauthorized,
+        Forbidden,
+        NotFound,
+        Conflict,
+        Unprocessable,
+        RateLimited,
+        Unavailable,
+    )
+)]
 #[put("/categories/{id}")]
 async fn update_category(
     pool: web::Data<DbPool>,
     _guard: ModeratorGuard,
     category_id: web::Path<i32>,
-    update_data: web::Json<UpdateCategory>,
-) -> impl Responder {
-    let mut conn = match pool.get() {
-        Ok(c) => c,
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": format!("Database connection failed: {}", e)
-            }))
-        }
-    };
+    update_data: ValidatedJson<UpdateCategory>,
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
 
-    match diesel::update(categories::table.find(category_id.into_inner()))
+    let category = diesel::update(categories::table.find(category_id.into_inner()))
         .set(update_data.into_inner())
         .get_result::<Category>(&mut conn)
-    {
-        Ok(category) => HttpResponse::Ok().json(category),
-        Err(diesel::NotFound) => HttpResponse::NotFound().json(serde_json::json!({
-            "error": "Category not found"
-        })),
-        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": format!("Failed to update category: {}", e)
-        })),
-    }
+        .optional()?
+        .ok_or_else(|| ApiError::not_found("Category"))?;
+    Ok(HttpResponse::Ok().json(category))
 }
 
+#[utoipa::path(
+    tag = "categories",
+    responses(
+        (status = 200, description = "Deleted"),
+        Unauthorized,
+        Forbidden,
+        NotFound,
+        RateLimited,
+        Unavailable,
+    )
+)]
 #[delete("/categories/{id}")]
 async fn delete_category(
     pool: web::Data<DbPool>,
     _guard: ModeratorGuard,
     category_id: web::Path<i32>,
-) -> impl Responder {
-    let mut conn = match pool.get() {
-        Ok(c) => c,
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": format!("Database connection failed: {}", e)
-            }))
-        }
-    };
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
 
-    match diesel::delete(categories::table.find(category_id.into_inner())).execute(&mut conn) {
-        Ok(0) => HttpResponse::NotFound().json(serde_json::json!({
-            "error": "Category not found"
-        })),
-        Ok(_) => HttpResponse::Ok().json(serde_json::json!({
+    match diesel::delete(categories::table.find(category_id.into_inner())).execute(&mut conn)? {
+        0 => Err(ApiError::not_found("Category")),
+        _ => Ok(HttpResponse::Ok().json(serde_json::json!({
             "message": "Category deleted successfully"
-        })),
-        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": format!("Failed to delete category: {}", e)
-        })),
+        }))),
     }
 }
 
@@ -0,0 +1,107 @@
+use actix_web::{HttpResponse, get, post, web};
+use serde::Deserialize;
+use validator::Validate;
+
+use crate::DbPool;
+use crate::error::ApiError;
+use crate::guardian::AdminGuard;
+use crate::openapi::{
+    BadRequest, Forbidden, NotFound, RateLimited, Unauthorized, Unavailable, Unprocessable,
+};
+use crate::pagination::PaginationParams;
+use crate::services::email_logs;
+use crate::validation::ValidatedJson;
+
+#[derive(Deserialize)]
+struct EmailLogFilter {
+    status: Option<String>,
+    user_id: Option<i32>,
+}
+
+#[derive(Deserialize, Validate)]
+struct BounceRequest {
+    #[validate(length(min = 1, max = 255))]
+    message_id: String,
+    #[validate(length(max = 1000))]
+    reason: String,
+}
+
+/// GET /admin/email_logs?status=&user_id=
+///
+/// Delivery attempts newest first, optionally filtered by status (`sent`,
+/// `failed`, `bounced`) and recipient. Paginated.
+#[utoipa::path(
+    tag = "admin",
+    responses(
+        (status = 200, description = "Delivery attempts, newest first"),
+        Unauthorized,
+        Forbidden,
+        RateLimited,
+        Unavailable,
+    )
+)]
+#[get("/admin/email_logs")]
+async fn list_email_logs(
+    pool: web::Data<DbPool>,
+    _guard: AdminGuard,
+    filter: web::Query<EmailLogFilter>,
+    pagination: web::Query<PaginationParams>,
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
+
+    let filter = filter.into_inner();
+    let per_page = pagination.per_page();
+    let offset = pagination.offset();
+
+    let logs = web::block(move || {
+        email_logs::list(
+            &mut conn,
+            filter.status.as_deref(),
+            filter.user_id,
+            per_page,
+            offset,
+        )
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(logs))
+}
+
+/// POST /admin/email_logs/bounces
+///
+/// Record that a message we sent bounced, e.g. from a mail provider's
+/// bounce report. `message_id` is the `Message-ID` header, with or without
+/// angle brackets. 404 if we never sent it.
+#[utoipa::path(
+    tag = "admin",
+    responses(
+        (status = 200, description = "The updated log entry"),
+        BadRequest,
+        Unauthorized,
+        Forbidden,
+        NotFound,
+        Unprocessable,
+        RateLimited,
+        Unavailable,
+    )
+)]
+#[post("/admin/email_logs/bounces")]
+async fn record_bounce(
+    pool: web::Data<DbPool>,
+    _guard: AdminGuard,
+    req: ValidatedJson<BounceRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
+
+    let req = req.into_inner();
+    let log =
+        web::block(move || email_logs::record_bounce(&mut conn, &req.message_id, &req.reason))
+            .await??
+            .ok_or_else(|| ApiError::not_found("Email"))?;
+
+    Ok(HttpResponse::Ok().json(log))
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(list_email_logs).service(record_bounce);
+}
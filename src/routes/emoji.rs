@@ -0,0 +1,103 @@
+use actix_web::{HttpResponse, delete, get, post, web};
+
+use crate::DbPool;
+use crate::emoji;
+use crate::error::ApiError;
+use crate::guardian::AdminGuard;
+use crate::models::{CreateCustomEmojiInput, CustomEmoji};
+use crate::openapi::{
+    BadRequest, Conflict, Forbidden, NotFound, RateLimited, Unauthorized, Unavailable,
+    Unprocessable,
+};
+use crate::services::emoji::{self as emoji_service, EmojiList};
+use crate::validation::ValidatedJson;
+
+/// GET /emojis
+///
+/// Every emoji usable in posts, built-in and custom, with the group names
+/// a picker should show them under.
+#[utoipa::path(
+    tag = "emoji",
+    responses(
+        (status = 200, description = "Built-in and custom emoji", body = EmojiList),
+        RateLimited,
+        Unavailable,
+    )
+)]
+#[get("/emojis")]
+async fn list_emojis(pool: web::Data<DbPool>) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
+
+    let list = web::block(move || emoji_service::list(&mut conn)).await??;
+    Ok(HttpResponse::Ok().json(list))
+}
+
+/// POST /admin/emojis
+///
+/// Add a custom emoji. 409 if the name is taken, by another custom emoji
+/// or a built-in one.
+#[utoipa::path(
+    tag = "emoji",
+    request_body = CreateCustomEmojiInput,
+    responses(
+        (status = 201, description = "Created", body = CustomEmoji),
+        BadRequest,
+        Unauthorized,
+        Forbidden,
+        Conflict,
+        Unprocessable,
+        RateLimited,
+        Unavailable,
+    )
+)]
+#[post("/admin/emojis")]
+async fn create_emoji(
+    pool: web::Data<DbPool>,
+    guard: AdminGuard,
+    input: ValidatedJson<CreateCustomEmojiInput>,
+) -> Result<HttpResponse, ApiError> {
+    let input = input.into_inner();
+    if emoji::builtin(&input.name).is_some() {
+        return Err(ApiError::Conflict(
+            "Emoji name is already taken".to_string(),
+        ));
+    }
+    let mut conn = pool.get()?;
+
+    let new = input.into_new_custom_emoji(guard.0.user_id);
+    let emoji = web::block(move || emoji_service::create(&mut conn, new)).await??;
+    Ok(HttpResponse::Created().json(emoji))
+}
+
+/// DELETE /admin/emojis/{name}
+#[utoipa::path(
+    tag = "emoji",
+    responses(
+        (status = 204, description = "Deleted"),
+        Unauthorized,
+        Forbidden,
+        NotFound,
+        RateLimited,
+        Unavailable,
+    )
+)]
+#[delete("/admin/emojis/{name}")]
+async fn delete_emoji(
+    pool: web::Data<DbPool>,
+    _guard: AdminGuard,
+    name: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
+
+    let name = name.into_inner();
+    if !web::block(move || emoji_service::delete(&mut conn, &name)).await?? {
+        return Err(ApiError::not_found("Emoji"));
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(list_emojis)
+        .service(create_emoji)
+        .service(delete_emoji);
+}
@@ -1,71 +1,363 @@
-use actix_web::{post, web, HttpResponse, Responder};
-use serde::Deserialize;
+use actix_web::{HttpResponse, delete, get, post, web};
+use serde_json::json;
 
-use crate::jobs::{Job, JobQueue, ProcessTopicJob, WelcomeEmailJob};
+use crate::DbPool;
+use crate::error::ApiError;
+use crate::guardian::AdminGuard;
+use crate::jobs::{self, EnqueueError, JobActionError, JobFilter, JobQueue, JobRegistry};
+use crate::openapi::{
+    Conflict, Forbidden, NotFound, RateLimited, Unauthorized, Unavailable, Unprocessable,
+};
+use crate::pagination::PaginationParams;
 
-#[derive(Deserialize)]
-struct EnqueueWelcomeEmailRequest {
-    user_id: i32,
-    username: String,
-    email: String,
+/// GET /admin/jobs/dead
+///
+/// Jobs that exhausted their retries, most recently failed first. Each
+/// row carries the last error message.
+#[utoipa::path(
+    tag = "admin",
+    responses(
+        (status = 200, description = "Dead jobs, most recently failed first"),
+        Unauthorized,
+        Forbidden,
+        RateLimited,
+        Unavailable,
+    )
+)]
+#[get("/admin/jobs/dead")]
+async fn list_dead_jobs(
+    pool: web::Data<DbPool>,
+    _guard: AdminGuard,
+    pagination: web::Query<PaginationParams>,
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
+
+    let per_page = pagination.per_page();
+    let offset = pagination.offset();
+
+    let dead = web::block(move || jobs::list_dead(&mut conn, per_page, offset)).await??;
+    Ok(HttpResponse::Ok().json(dead))
 }
 
-#[derive(Deserialize)]
-struct EnqueueProcessTopicRequest {
-    topic_id: i32,
-    action: String,
+/// POST /admin/jobs/dead/:id/retry
+///
+/// Requeue a dead job to run immediately with its retry count reset.
+/// 409 if an identical job is already pending.
+#[utoipa::path(
+    tag = "admin",
+    responses(
+        (status = 200, description = "The requeued job"),
+        Unauthorized,
+        Forbidden,
+        NotFound,
+        Conflict,
+        RateLimited,
+        Unavailable,
+    )
+)]
+#[post("/admin/jobs/dead/{id}/retry")]
+async fn retry_dead_job(
+    pool: web::Data<DbPool>,
+    _guard: AdminGuard,
+    path: web::Path<uuid::Uuid>,
+) -> Result<HttpResponse, ApiError> {
+    let job_id = path.into_inner();
+
+    let mut conn = pool.get()?;
+
+    let job = web::block(move || jobs::retry_dead(&mut conn, job_id))
+        .await??
+        .ok_or_else(|| ApiError::not_found("Dead job"))?;
+    Ok(HttpResponse::Ok().json(job))
 }
 
-#[post("/jobs/welcome_email")]
-async fn enqueue_welcome_email(
-    queue: web::Data<JobQueue>,
-    req: web::Json<EnqueueWelcomeEmailRequest>,
-) -> impl Responder {
-    let job = WelcomeEmailJob {
-        user_id: req.user_id,
-        username: req.username.clone(),
-        email: req.email.clone(),
-    };
-
-    match queue.enqueue(job) {
-        Ok(task_hash) => HttpResponse::Ok().json(serde_json::json!({
-            "message": "Welcome email job enqueued successfully",
-            "task_hash": task_hash
-        })),
-        Err(e) => {
-            log::error!("Failed to enqueue welcome email job: {:?}", e);
-            HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": format!("Failed to enqueue job: {}", e)
-            }))
-        }
+/// DELETE /admin/jobs/dead/:id
+///
+/// Permanently discard a dead job. Only dead jobs can be removed here.
+#[utoipa::path(
+    tag = "admin",
+    responses(
+        (status = 204, description = "Discarded"),
+        Unauthorized,
+        Forbidden,
+        NotFound,
+        RateLimited,
+        Unavailable,
+    )
+)]
+#[delete("/admin/jobs/dead/{id}")]
+async fn discard_dead_job(
+    pool: web::Data<DbPool>,
+    _guard: AdminGuard,
+    path: web::Path<uuid::Uuid>,
+) -> Result<HttpResponse, ApiError> {
+    let job_id = path.into_inner();
+
+    let mut conn = pool.get()?;
+
+    if web::block(move || jobs::discard_dead(&mut conn, job_id)).await?? {
+        Ok(HttpResponse::NoContent().finish())
+    } else {
+        Err(ApiError::not_found("Dead job"))
     }
 }
 
-#[post("/jobs/process_topic")]
-async fn enqueue_process_topic(
+/// GET /admin/jobs?state=&name=
+///
+/// Jobs newest first, optionally filtered by state (`pending`, `running`,
+/// `failed`, `done`, `dead`) and exact job name. Paginated.
+#[utoipa::path(
+    tag = "admin",
+    responses(
+        (status = 200, description = "Jobs, newest first"),
+        Unauthorized,
+        Forbidden,
+        RateLimited,
+        Unavailable,
+    )
+)]
+#[get("/admin/jobs")]
+async fn list_jobs(
+    pool: web::Data<DbPool>,
+    _guard: AdminGuard,
+    filter: web::Query<JobFilter>,
+    pagination: web::Query<PaginationParams>,
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
+
+    let filter = filter.into_inner();
+    let per_page = pagination.per_page();
+    let offset = pagination.offset();
+
+    let found = web::block(move || jobs::list_jobs(&mut conn, &filter, per_page, offset)).await??;
+    Ok(HttpResponse::Ok().json(found))
+}
+
+/// GET /admin/jobs/stats
+///
+/// Per job type: how many jobs are in each state, and the average wait
+/// before a run starts and run time of successful jobs, in milliseconds.
+#[utoipa::path(
+    tag = "admin",
+    responses(
+        (status = 200, description = "Counts and timings per job type"),
+        Unauthorized,
+        Forbidden,
+        RateLimited,
+        Unavailable,
+    )
+)]
+#[get("/admin/jobs/stats")]
+async fn job_stats(pool: web::Data<DbPool>, _guard: AdminGuard) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
+
+    let stats = web::block(move || jobs::job_stats(&mut conn)).await??;
+    Ok(HttpResponse::Ok().json(stats))
+}
+
+/// GET /admin/jobs/:id
+///
+/// One job, including its payload and last error.
+#[utoipa::path(
+    tag = "admin",
+    responses(
+        (status = 200, description = "The job"),
+        Unauthorized,
+        Forbidden,
+        NotFound,
+        RateLimited,
+        Unavailable,
+    )
+)]
+#[get("/admin/jobs/{id}")]
+async fn show_job(
+    pool: web::Data<DbPool>,
+    _guard: AdminGuard,
+    path: web::Path<uuid::Uuid>,
+) -> Result<HttpResponse, ApiError> {
+    let job_id = path.into_inner();
+
+    let mut conn = pool.get()?;
+
+    let job = web::block(move || jobs::find_job(&mut conn, job_id))
+        .await??
+        .ok_or_else(|| ApiError::not_found("Job"))?;
+    Ok(HttpResponse::Ok().json(job))
+}
+
+/// Shared response mapping for the job actions below; the 409 names the
+/// action, so this stays here rather than in a `From` impl.
+fn job_action_response(
+    result: Result<jobs::JobView, JobActionError>,
+    action: &str,
+) -> Result<HttpResponse, ApiError> {
+    match result {
+        Ok(job) => Ok(HttpResponse::Ok().json(job)),
+        Err(JobActionError::NotFound) => Err(ApiError::not_found("Job")),
+        Err(JobActionError::InvalidState(state)) => Err(ApiError::Conflict(format!(
+            "Cannot {} a job that is {}",
+            action,
+            state.as_str()
+        ))),
+        Err(JobActionError::Db(e)) => Err(e.into()),
+    }
+}
+
+/// POST /admin/jobs/:id/cancel
+///
+/// Stop a pending or retrying job from running; it's kept as dead.
+#[utoipa::path(
+    tag = "admin",
+    responses(
+        (status = 200, description = "The cancelled job"),
+        Unauthorized,
+        Forbidden,
+        NotFound,
+        Conflict,
+        RateLimited,
+        Unavailable,
+    )
+)]
+#[post("/admin/jobs/{id}/cancel")]
+async fn cancel_job(
+    pool: web::Data<DbPool>,
+    _guard: AdminGuard,
+    path: web::Path<uuid::Uuid>,
+) -> Result<HttpResponse, ApiError> {
+    let job_id = path.into_inner();
+
+    let mut conn = pool.get()?;
+
+    let result = web::block(move || jobs::cancel_job(&mut conn, job_id)).await?;
+    job_action_response(result, "cancel")
+}
+
+/// POST /admin/jobs/:id/retry
+///
+/// Run a done or dead job again with a fresh retry budget.
+#[utoipa::path(
+    tag = "admin",
+    responses(
+        (status = 200, description = "The requeued job"),
+        Unauthorized,
+        Forbidden,
+        NotFound,
+        Conflict,
+        RateLimited,
+        Unavailable,
+    )
+)]
+#[post("/admin/jobs/{id}/retry")]
+async fn retry_job(
+    pool: web::Data<DbPool>,
+    _guard: AdminGuard,
+    path: web::Path<uuid::Uuid>,
+) -> Result<HttpResponse, ApiError> {
+    let job_id = path.into_inner();
+
+    let mut conn = pool.get()?;
+
+    let result = web::block(move || jobs::retry_job(&mut conn, job_id)).await?;
+    job_action_response(result, "retry")
+}
+
+/// POST /admin/jobs/:id/run_now
+///
+/// Skip the wait on a pending job or one backing off before a retry.
+#[utoipa::path(
+    tag = "admin",
+    responses(
+        (status = 200, description = "The rescheduled job"),
+        Unauthorized,
+        Forbidden,
+        NotFound,
+        Conflict,
+        RateLimited,
+        Unavailable,
+    )
+)]
+#[post("/admin/jobs/{id}/run_now")]
+async fn run_job_now(
+    pool: web::Data<DbPool>,
+    _guard: AdminGuard,
+    path: web::Path<uuid::Uuid>,
+) -> Result<HttpResponse, ApiError> {
+    let job_id = path.into_inner();
+
+    let mut conn = pool.get()?;
+
+    let result = web::block(move || jobs::run_job_now(&mut conn, job_id)).await?;
+    job_action_response(result, "run")
+}
+
+/// POST /admin/jobs/:name
+///
+/// Enqueue a registered job type by name; the body is its JSON payload.
+/// The job's own queue, priority and uniqueness policy apply, so an
+/// identical pending job makes this a no-op (200 with `"queued": false`).
+#[utoipa::path(
+    tag = "admin",
+    responses(
+        (status = 201, description = "Queued"),
+        (status = 200, description = "An identical job is already pending"),
+        Unauthorized,
+        Forbidden,
+        NotFound,
+        Unprocessable,
+        RateLimited,
+    )
+)]
+#[post("/admin/jobs/{name}")]
+async fn enqueue_job(
     queue: web::Data<JobQueue>,
-    req: web::Json<EnqueueProcessTopicRequest>,
-) -> impl Responder {
-    let job = ProcessTopicJob {
-        topic_id: req.topic_id,
-        action: req.action.clone(),
-    };
-
-    match queue.enqueue(job) {
-        Ok(task_hash) => HttpResponse::Ok().json(serde_json::json!({
-            "message": "Process topic job enqueued successfully",
-            "task_hash": task_hash
-        })),
-        Err(e) => {
-            log::error!("Failed to enqueue process topic job: {:?}", e);
-            HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": format!("Failed to enqueue job: {}", e)
-            }))
+    registry: web::Data<JobRegistry>,
+    guard: AdminGuard,
+    path: web::Path<String>,
+    payload: web::Json<serde_json::Value>,
+) -> Result<HttpResponse, ApiError> {
+    let name = path.into_inner();
+
+    match registry.enqueue(&queue, &name, payload.into_inner()) {
+        Ok(outcome) => {
+            log::info!(
+                "Admin {} enqueued job {} ({})",
+                guard.0.username,
+                name,
+                outcome.task_hash()
+            );
+            let body = json!({
+                "job": name,
+                "queued": outcome.is_new(),
+                "task_hash": outcome.task_hash(),
+            });
+            if outcome.is_new() {
+                Ok(HttpResponse::Created().json(body))
+            } else {
+                Ok(HttpResponse::Ok().json(body))
+            }
         }
+        Err(EnqueueError::UnknownJob) => Err(ApiError::UnknownJob {
+            name,
+            available: registry.names(),
+        }),
+        Err(EnqueueError::InvalidPayload(e)) => Err(ApiError::Unprocessable(e)),
+        Err(EnqueueError::Queue(e)) => Err(ApiError::Internal(format!(
+            "Failed to enqueue job {name}: {e}"
+        ))),
     }
 }
 
 pub fn configure(cfg: &mut web::ServiceConfig) {
-    cfg.service(enqueue_welcome_email)
-        .service(enqueue_process_topic);
+    // The fixed /admin/jobs/* paths must be registered before
+    // /admin/jobs/{id}, which would otherwise shadow them.
+    cfg.service(list_dead_jobs)
+        .service(retry_dead_job)
+        .service(discard_dead_job)
+        .service(list_jobs)
+        .service(job_stats)
+        .service(show_job)
+        .service(cancel_job)
+        .service(retry_job)
+        .service(run_job_now)
+        .service(enqueue_job);
 }
@@ -1,9 +1,10 @@
-use actix_web::{HttpResponse, Responder, delete, post, web};
-use serde_json::json;
+use actix_web::{HttpResponse, delete, post, web};
 
 use crate::DbPool;
+use crate::error::ApiError;
 use crate::middleware::AuthUser;
-use crate::services::likes::{LikeError, LikeOutcome, UnlikeOutcome, like_post, unlike_post};
+use crate::openapi::{NotFound, RateLimited, Unauthorized, Unavailable, Unprocessable};
+use crate::services::likes::{LikeOutcome, like_post, unlike_post};
 
 /// POST /posts/:id/like
 ///
@@ -14,38 +15,34 @@ use crate::services::likes::{LikeError, LikeOutcome, UnlikeOutcome, like_post, u
 /// - Liking the same post twice is a no-op (returns 200 with the existing
 ///   like rather than 409, so clients don't have to handle the race
 ///   between two tabs).
+#[utoipa::path(
+    tag = "posts",
+    responses(
+        (status = 201, description = "Liked"),
+        (status = 200, description = "Already liked"),
+        Unauthorized,
+        NotFound,
+        Unprocessable,
+        RateLimited,
+        Unavailable,
+    )
+)]
 #[post("/posts/{id}/like")]
 async fn like_post_route(
     pool: web::Data<DbPool>,
     auth: AuthUser,
     path: web::Path<i32>,
-) -> impl Responder {
+) -> Result<HttpResponse, ApiError> {
     let post_id = path.into_inner();
     let user_id = auth.0.user_id;
 
-    let mut conn = match pool.get() {
-        Ok(conn) => conn,
-        Err(_) => {
-            return HttpResponse::InternalServerError()
-                .json(json!({ "error": "Failed to get database connection" }));
-        }
-    };
+    let mut conn = pool.get()?;
+    let outcome = web::block(move || like_post(&mut conn, user_id, post_id)).await??;
 
-    let result = web::block(move || like_post(&mut conn, user_id, post_id)).await;
-
-    match result {
-        Ok(Ok(LikeOutcome::Created(like))) => HttpResponse::Created().json(like),
-        Ok(Ok(LikeOutcome::AlreadyLiked(like))) => HttpResponse::Ok().json(like),
-        Ok(Err(LikeError::PostNotFound)) => {
-            HttpResponse::NotFound().json(json!({ "error": "Post not found" }))
-        }
-        Ok(Err(LikeError::SelfLike)) => HttpResponse::UnprocessableEntity()
-            .json(json!({ "error": "You cannot like your own post" })),
-        Ok(Err(LikeError::Db(e))) => HttpResponse::InternalServerError()
-            .json(json!({ "error": format!("Database error: {e}") })),
-        Err(e) => HttpResponse::InternalServerError()
-            .json(json!({ "error": format!("Blocking error: {e}") })),
-    }
+    Ok(match outcome {
+        LikeOutcome::Created(like) => HttpResponse::Created().json(like),
+        LikeOutcome::AlreadyLiked(like) => HttpResponse::Ok().json(like),
+    })
 }
 
 /// DELETE /posts/:id/like
@@ -53,36 +50,29 @@ async fn like_post_route(
 /// Unlike a post. Reverses the counter updates from `like_post`. Deleting
 /// a non-existent like is a no-op (returns 204) so clients don't have to
 /// distinguish "never liked" from "already unliked."
+#[utoipa::path(
+    tag = "posts",
+    responses(
+        (status = 204, description = "Unliked, or not liked"),
+        Unauthorized,
+        NotFound,
+        RateLimited,
+        Unavailable,
+    )
+)]
 #[delete("/posts/{id}/like")]
 async fn unlike_post_route(
     pool: web::Data<DbPool>,
     auth: AuthUser,
     path: web::Path<i32>,
-) -> impl Responder {
+) -> Result<HttpResponse, ApiError> {
     let post_id = path.into_inner();
     let user_id = auth.0.user_id;
 
-    let mut conn = match pool.get() {
-        Ok(conn) => conn,
-        Err(_) => {
-            return HttpResponse::InternalServerError()
-                .json(json!({ "error": "Failed to get database connection" }));
-        }
-    };
-
-    let result = web::block(move || unlike_post(&mut conn, user_id, post_id)).await;
-
-    match result {
-        Ok(Ok(UnlikeOutcome::Removed)) | Ok(Ok(UnlikeOutcome::NothingToRemove)) => {
-            HttpResponse::NoContent().finish()
-        }
-        Ok(Err(LikeError::Db(e))) => HttpResponse::InternalServerError()
-            .json(json!({ "error": format!("Database error: {e}") })),
-        Ok(Err(_)) => HttpResponse::InternalServerError()
-            .json(json!({ "error": "Unexpected error" })),
-        Err(e) => HttpResponse::InternalServerError()
-            .json(json!({ "error": format!("Blocking error: {e}") })),
-    }
+    let mut conn = pool.get()?;
+    // Removed or nothing to remove: either way the like is gone.
+    web::block(move || unlike_post(&mut conn, user_id, post_id)).await??;
+    Ok(HttpResponse::NoContent().finish())
 }
 
 pub fn configure(cfg: &mut web::ServiceConfig) {
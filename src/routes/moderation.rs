@@ -1,461 +1,512 @@
-use actix_web::{post, web, HttpResponse, Responder};
+use actix_web::{post, web, HttpResponse};
 use diesel::prelude::*;
 use serde::Deserialize;
+use validator::Validate;
 
+use crate::error::ApiError;
 use crate::guardian::ModeratorGuard;
 use crate::moderation::{log_moderation_action, NewModerationAction, NewUserSuspension};
+use crate::openapi::{
+    BadRequest, Forbidden, NotFound, RateLimited, Unauthorized, Unavailable, Unprocessable,
+};
 use crate::schema::{posts, topics, user_suspensions};
+use crate::services::notifications;
+use crate::validation::{ValidatedJson, validate_not_blank};
 use crate::DbPool;
 
 // Topic moderation
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Validate)]
 struct TopicModerationRequest {
     topic_id: i32,
 }
 
+#[utoipa::path(
+    tag = "moderation",
+    responses(
+        (status = 200, description = "Locked"),
+        BadRequest,
+        Unauthorized,
+        Forbidden,
+        NotFound,
+        Unprocessable,
+        RateLimited,
+        Unavailable,
+    )
+)]
 #[post("/moderation/topics/lock")]
 async fn lock_topic(
     pool: web::Data<DbPool>,
     guard: ModeratorGuard,
-    req: web::Json<TopicModerationRequest>,
-) -> impl Responder {
-    let mut conn = match pool.get() {
-        Ok(c) => c,
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": format!("Database connection failed: {}", e)
-            }))
-        }
-    };
+    req: ValidatedJson<TopicModerationRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
 
-    match diesel::update(topics::table)
+    let updated = diesel::update(topics::table)
         .filter(topics::id.eq(req.topic_id))
         .set(topics::locked.eq(true))
-        .execute(&mut conn)
-    {
-        Ok(_) => {
-            let _ = log_moderation_action(
-                &pool,
-                NewModerationAction {
-                    action_type: "lock_topic".to_string(),
-                    moderator_id: guard.0.user_id,
-                    target_user_id: None,
-                    target_topic_id: Some(req.topic_id),
-                    target_post_id: None,
-                    details: None,
-                },
-            );
-
-            HttpResponse::Ok().json(serde_json::json!({
-                "message": "Topic locked successfully"
-            }))
-        }
-        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": format!("Failed to lock topic: {}", e)
-        })),
+        .execute(&mut conn)?;
+    if updated == 0 {
+        return Err(ApiError::not_found("Topic"));
     }
+
+    let _ = log_moderation_action(
+        &pool,
+        NewModerationAction {
+            action_type: "lock_topic".to_string(),
+            moderator_id: guard.0.user_id,
+            target_user_id: None,
+            target_topic_id: Some(req.topic_id),
+            target_post_id: None,
+            details: None,
+        },
+    );
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Topic locked successfully"
+    })))
 }
 
+#[utoipa::path(
+    tag = "moderation",
+    responses(
+        (status = 200, description = "Unlocked"),
+        BadRequest,
+        Unauthorized,
+        Forbidden,
+        NotFound,
+        Unprocessable,
+        RateLimited,
+        Unavailable,
+    )
+)]
 #[post("/moderation/topics/unlock")]
 async fn unlock_topic(
     pool: web::Data<DbPool>,
     guard: ModeratorGuard,
-    req: web::Json<TopicModerationRequest>,
-) -> impl Responder {
-    let mut conn = match pool.get() {
-        Ok(c) => c,
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": format!("Database connection failed: {}", e)
-            }))
-        }
-    };
+    req: ValidatedJson<TopicModerationRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
 
-    match diesel::update(topics::table)
+    let updated = diesel::update(topics::table)
         .filter(topics::id.eq(req.topic_id))
         .set(topics::locked.eq(false))
-        .execute(&mut conn)
-    {
-        Ok(_) => {
-            let _ = log_moderation_action(
-                &pool,
-                NewModerationAction {
-                    action_type: "unlock_topic".to_string(),
-                    moderator_id: guard.0.user_id,
-                    target_user_id: None,
-                    target_topic_id: Some(req.topic_id),
-                    target_post_id: None,
-                    details: None,
-                },
-            );
-
-            HttpResponse::Ok().json(serde_json::json!({
-                "message": "Topic unlocked successfully"
-            }))
-        }
-        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": format!("Failed to unlock topic: {}", e)
-        })),
+        .execute(&mut conn)?;
+    if updated == 0 {
+        return Err(ApiError::not_found("Topic"));
     }
+
+    let _ = log_moderation_action(
+        &pool,
+        NewModerationAction {
+            action_type: "unlock_topic".to_string(),
+            moderator_id: guard.0.user_id,
+            target_user_id: None,
+            target_topic_id: Some(req.topic_id),
+            target_post_id: None,
+            details: None,
+        },
+    );
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Topic unlocked successfully"
+    })))
 }
 
+#[utoipa::path(
+    tag = "moderation",
+    responses(
+        (status = 200, description = "Pinned"),
+        BadRequest,
+        Unauthorized,
+        Forbidden,
+        NotFound,
+        Unprocessable,
+        RateLimited,
+        Unavailable,
+    )
+)]
 #[post("/moderation/topics/pin")]
 async fn pin_topic(
     pool: web::Data<DbPool>,
     guard: ModeratorGuard,
-    req: web::Json<TopicModerationRequest>,
-) -> impl Responder {
-    let mut conn = match pool.get() {
-        Ok(c) => c,
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": format!("Database connection failed: {}", e)
-            }))
-        }
-    };
+    req: ValidatedJson<TopicModerationRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
 
     let now = chrono::Utc::now().naive_utc();
 
-    match diesel::update(topics::table)
+    let updated = diesel::update(topics::table)
         .filter(topics::id.eq(req.topic_id))
         .set((topics::pinned.eq(true), topics::pinned_at.eq(Some(now))))
-        .execute(&mut conn)
-    {
-        Ok(_) => {
-            let _ = log_moderation_action(
-                &pool,
-                NewModerationAction {
-                    action_type: "pin_topic".to_string(),
-                    moderator_id: guard.0.user_id,
-                    target_user_id: None,
-                    target_topic_id: Some(req.topic_id),
-                    target_post_id: None,
-                    details: None,
-                },
-            );
-
-            HttpResponse::Ok().json(serde_json::json!({
-                "message": "Topic pinned successfully"
-            }))
-        }
-        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": format!("Failed to pin topic: {}", e)
-        })),
+        .execute(&mut conn)?;
+    if updated == 0 {
+        return Err(ApiError::not_found("Topic"));
     }
+
+    let _ = log_moderation_action(
+        &pool,
+        NewModerationAction {
+            action_type: "pin_topic".to_string(),
+            moderator_id: guard.0.user_id,
+            target_user_id: None,
+            target_topic_id: Some(req.topic_id),
+            target_post_id: None,
+            details: None,
+        },
+    );
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Topic pinned successfully"
+    })))
 }
 
+#[utoipa::path(
+    tag = "moderation",
+    responses(
+        (status = 200, description = "Unpinned"),
+        BadRequest,
+        Unauthorized,
+        Forbidden,
+        NotFound,
+        Unprocessable,
+        RateLimited,
+        Unavailable,
+    )
+)]
 #[post("/moderation/topics/unpin")]
 async fn unpin_topic(
     pool: web::Data<DbPool>,
     guard: ModeratorGuard,
-    req: web::Json<TopicModerationRequest>,
-) -> impl Responder {
-    let mut conn = match pool.get() {
-        Ok(c) => c,
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": format!("Database connection failed: {}", e)
-            }))
-        }
-    };
+    req: ValidatedJson<TopicModerationRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
 
-    match diesel::update(topics::table)
+    let updated = diesel::update(topics::table)
         .filter(topics::id.eq(req.topic_id))
         .set((
             topics::pinned.eq(false),
             topics::pinned_at.eq(None::<chrono::NaiveDateTime>),
         ))
-        .execute(&mut conn)
-    {
-        Ok(_) => {
-            let _ = log_moderation_action(
-                &pool,
-                NewModerationAction {
-                    action_type: "unpin_topic".to_string(),
-                    moderator_id: guard.0.user_id,
-                    target_user_id: None,
-                    target_topic_id: Some(req.topic_id),
-                    target_post_id: None,
-                    details: None,
-                },
-            );
-
-            HttpResponse::Ok().json(serde_json::json!({
-                "message": "Topic unpinned successfully"
-            }))
-        }
-        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": format!("Failed to unpin topic: {}", e)
-        })),
+        .execute(&mut conn)?;
+    if updated == 0 {
+        return Err(ApiError::not_found("Topic"));
     }
+
+    let _ = log_moderation_action(
+        &pool,
+        NewModerationAction {
+            action_type: "unpin_topic".to_string(),
+            moderator_id: guard.0.user_id,
+            target_user_id: None,
+            target_topic_id: Some(req.topic_id),
+            target_post_id: None,
+            details: None,
+        },
+    );
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Topic unpinned successfully"
+    })))
 }
 
+#[utoipa::path(
+    tag = "moderation",
+    responses(
+        (status = 200, description = "Closed"),
+        BadRequest,
+        Unauthorized,
+        Forbidden,
+        NotFound,
+        Unprocessable,
+        RateLimited,
+        Unavailable,
+    )
+)]
 #[post("/moderation/topics/close")]
 async fn close_topic(
     pool: web::Data<DbPool>,
     guard: ModeratorGuard,
-    req: web::Json<TopicModerationRequest>,
-) -> impl Responder {
-    let mut conn = match pool.get() {
-        Ok(c) => c,
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": format!("Database connection failed: {}", e)
-            }))
-        }
-    };
+    req: ValidatedJson<TopicModerationRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
 
     let now = chrono::Utc::now().naive_utc();
 
-    match diesel::update(topics::table)
+    let updated = diesel::update(topics::table)
         .filter(topics::id.eq(req.topic_id))
         .set((topics::closed.eq(true), topics::closed_at.eq(Some(now))))
-        .execute(&mut conn)
-    {
-        Ok(_) => {
-            let _ = log_moderation_action(
-                &pool,
-                NewModerationAction {
-                    action_type: "close_topic".to_string(),
-                    moderator_id: guard.0.user_id,
-                    target_user_id: None,
-                    target_topic_id: Some(req.topic_id),
-                    target_post_id: None,
-                    details: None,
-                },
-            );
-
-            HttpResponse::Ok().json(serde_json::json!({
-                "message": "Topic closed successfully"
-            }))
-        }
-        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": format!("Failed to close topic: {}", e)
-        })),
+        .execute(&mut conn)?;
+    if updated == 0 {
+        return Err(ApiError::not_found("Topic"));
     }
+
+    let _ = log_moderation_action(
+        &pool,
+        NewModerationAction {
+            action_type: "close_topic".to_string(),
+            moderator_id: guard.0.user_id,
+            target_user_id: None,
+            target_topic_id: Some(req.topic_id),
+            target_post_id: None,
+            details: None,
+        },
+    );
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Topic closed successfully"
+    })))
 }
 
+#[utoipa::path(
+    tag = "moderation",
+    responses(
+        (status = 200, description = "Reopened"),
+        BadRequest,
+        Unauthorized,
+        Forbidden,
+        NotFound,
+        Unprocessable,
+        RateLimited,
+        Unavailable,
+    )
+)]
 #[post("/moderation/topics/open")]
 async fn open_topic(
     pool: web::Data<DbPool>,
     guard: ModeratorGuard,
-    req: web::Json<TopicModerationRequest>,
-) -> impl Responder {
-    let mut conn = match pool.get() {
-        Ok(c) => c,
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": format!("Database connection failed: {}", e)
-            }))
-        }
-    };
+    req: ValidatedJson<TopicModerationRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
 
-    match diesel::update(topics::table)
+    let updated = diesel::update(topics::table)
         .filter(topics::id.eq(req.topic_id))
         .set((
             topics::closed.eq(false),
             topics::closed_at.eq(None::<chrono::NaiveDateTime>),
         ))
-        .execute(&mut conn)
-    {
-        Ok(_) => {
-            let _ = log_moderation_action(
-                &pool,
-                NewModerationAction {
-                    action_type: "open_topic".to_string(),
-                    moderator_id: guard.0.user_id,
-                    target_user_id: None,
-                    target_topic_id: Some(req.topic_id),
-                    target_post_id: None,
-                    details: None,
-                },
-            );
-
-            HttpResponse::Ok().json(serde_json::json!({
-                "message": "Topic opened successfully"
-            }))
-        }
-        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": format!("Failed to open topic: {}", e)
-        })),
+        .execute(&mut conn)?;
+    if updated == 0 {
+        return Err(ApiError::not_found("Topic"));
+    }
+
+    let _ = log_moderation_action(
+        &pool,
+        NewModerationAction {
+            action_type: "open_topic".to_string(),
+            moderator_id: guard.0.user_id,
+            target_user_id: None,
+            target_topic_id: Some(req.topic_id),
+            target_post_id: None,
+            details: None,
+        },
+    );
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Topic opened successfully"
+    })))
+}
+
+/// Tell the user whose content (or account) a moderator acted on. Best
+/// effort, like the audit log: the action itself already happened.
+fn notify_author(conn: &mut PgConnection, user_id: i32, action: &NewModerationAction) {
+    if let Err(e) = notifications::moderator_action(conn, user_id, action) {
+        log::error!(
+            "Failed to notify user {user_id} of {}: {e}",
+            action.action_type
+        );
     }
 }
 
 // Post moderation
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Validate)]
 struct PostModerationRequest {
     post_id: i32,
 }
 
+#[utoipa::path(
+    tag = "moderation",
+    responses(
+        (status = 200, description = "Hidden"),
+        BadRequest,
+        Unauthorized,
+        Forbidden,
+        NotFound,
+        Unprocessable,
+        RateLimited,
+        Unavailable,
+    )
+)]
 #[post("/moderation/posts/hide")]
 async fn hide_post(
     pool: web::Data<DbPool>,
     guard: ModeratorGuard,
-    req: web::Json<PostModerationRequest>,
-) -> impl Responder {
-    let mut conn = match pool.get() {
-        Ok(c) => c,
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": format!("Database connection failed: {}", e)
-            }))
-        }
-    };
+    req: ValidatedJson<PostModerationRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
 
     let now = chrono::Utc::now().naive_utc();
 
-    match diesel::update(posts::table)
+    let (author_id, topic_id): (i32, i32) = diesel::update(posts::table)
         .filter(posts::id.eq(req.post_id))
         .set((
             posts::hidden.eq(true),
             posts::hidden_at.eq(Some(now)),
             posts::hidden_by_user_id.eq(Some(guard.0.user_id)),
         ))
-        .execute(&mut conn)
-    {
-        Ok(_) => {
-            let _ = log_moderation_action(
-                &pool,
-                NewModerationAction {
-                    action_type: "hide_post".to_string(),
-                    moderator_id: guard.0.user_id,
-                    target_user_id: None,
-                    target_topic_id: None,
-                    target_post_id: Some(req.post_id),
-                    details: None,
-                },
-            );
-
-            HttpResponse::Ok().json(serde_json::json!({
-                "message": "Post hidden successfully"
-            }))
-        }
-        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": format!("Failed to hide post: {}", e)
-        })),
-    }
+        .returning((posts::user_id, posts::topic_id))
+        .get_result(&mut conn)
+        .optional()?
+        .ok_or_else(|| ApiError::not_found("Post"))?;
+
+    let action = NewModerationAction {
+        action_type: "hide_post".to_string(),
+        moderator_id: guard.0.user_id,
+        target_user_id: None,
+        target_topic_id: Some(topic_id),
+        target_post_id: Some(req.post_id),
+        details: None,
+    };
+    notify_author(&mut conn, author_id, &action);
+    let _ = log_moderation_action(&pool, action);
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Post hidden successfully"
+    })))
 }
 
+#[utoipa::path(
+    tag = "moderation",
+    responses(
+        (status = 200, description = "Unhidden"),
+        BadRequest,
+        Unauthorized,
+        Forbidden,
+        NotFound,
+        Unprocessable,
+        RateLimited,
+        Unavailable,
+    )
+)]
 #[post("/moderation/posts/unhide")]
 async fn unhide_post(
     pool: web::Data<DbPool>,
     guard: ModeratorGuard,
-    req: web::Json<PostModerationRequest>,
-) -> impl Responder {
-    let mut conn = match pool.get() {
-        Ok(c) => c,
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": format!("Database connection failed: {}", e)
-            }))
-        }
-    };
+    req: ValidatedJson<PostModerationRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
 
-    match diesel::update(posts::table)
+    let updated = diesel::update(posts::table)
         .filter(posts::id.eq(req.post_id))
         .set((
             posts::hidden.eq(false),
             posts::hidden_at.eq(None::<chrono::NaiveDateTime>),
             posts::hidden_by_user_id.eq(None::<i32>),
         ))
-        .execute(&mut conn)
-    {
-        Ok(_) => {
-            let _ = log_moderation_action(
-                &pool,
-                NewModerationAction {
-                    action_type: "unhide_post".to_string(),
-                    moderator_id: guard.0.user_id,
-                    target_user_id: None,
-                    target_topic_id: None,
-                    target_post_id: Some(req.post_id),
-                    details: None,
-                },
-            );
-
-            HttpResponse::Ok().json(serde_json::json!({
-                "message": "Post unhidden successfully"
-            }))
-        }
-        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": format!("Failed to unhide post: {}", e)
-        })),
+        .execute(&mut conn)?;
+    if updated == 0 {
+        return Err(ApiError::not_found("Post"));
     }
+
+    let _ = log_moderation_action(
+        &pool,
+        NewModerationAction {
+            action_type: "unhide_post".to_string(),
+            moderator_id: guard.0.user_id,
+            target_user_id: None,
+            target_topic_id: None,
+            target_post_id: Some(req.post_id),
+            details: None,
+        },
+    );
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Post unhidden successfully"
+    })))
 }
 
+#[utoipa::path(
+    tag = "moderation",
+    responses(
+        (status = 200, description = "Deleted"),
+        BadRequest,
+        Unauthorized,
+        Forbidden,
+        NotFound,
+        Unprocessable,
+        RateLimited,
+        Unavailable,
+    )
+)]
 #[post("/moderation/posts/delete")]
 async fn delete_post(
     pool: web::Data<DbPool>,
     guard: ModeratorGuard,
-    req: web::Json<PostModerationRequest>,
-) -> impl Responder {
-    let mut conn = match pool.get() {
-        Ok(c) => c,
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": format!("Database connection failed: {}", e)
-            }))
-        }
-    };
+    req: ValidatedJson<PostModerationRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
 
     let now = chrono::Utc::now().naive_utc();
 
-    match diesel::update(posts::table)
+    let (author_id, topic_id): (i32, i32) = diesel::update(posts::table)
         .filter(posts::id.eq(req.post_id))
         .set((
             posts::deleted_at.eq(Some(now)),
             posts::deleted_by_user_id.eq(Some(guard.0.user_id)),
         ))
-        .execute(&mut conn)
-    {
-        Ok(_) => {
-            let _ = log_moderation_action(
-                &pool,
-                NewModerationAction {
-                    action_type: "delete_post".to_string(),
-                    moderator_id: guard.0.user_id,
-                    target_user_id: None,
-                    target_topic_id: None,
-                    target_post_id: Some(req.post_id),
-                    details: None,
-                },
-            );
-
-            HttpResponse::Ok().json(serde_json::json!({
-                "message": "Post deleted successfully"
-            }))
-        }
-        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": format!("Failed to delete post: {}", e)
-        })),
-    }
+        .returning((posts::user_id, posts::topic_id))
+        .get_result(&mut conn)
+        .optional()?
+        .ok_or_else(|| ApiError::not_found("Post"))?;
+
+    let action = NewModerationAction {
+        action_type: "delete_post".to_string(),
+        moderator_id: guard.0.user_id,
+        target_user_id: None,
+        target_topic_id: Some(topic_id),
+        target_post_id: Some(req.post_id),
+        details: None,
+    };
+    notify_author(&mut conn, author_id, &action);
+    let _ = log_moderation_action(&pool, action);
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Post deleted successfully"
+    })))
 }
 
 // User suspension
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Validate)]
 struct SuspendUserRequest {
     user_id: i32,
+    #[validate(length(max = 1000), custom(function = "validate_not_blank"))]
     reason: String,
+    /// Capped at 100 years; anything longer is a permanent ban in practice.
+    #[validate(range(min = 1, max = 36500))]
     duration_days: i64,
 }
 
+#[utoipa::path(
+    tag = "moderation",
+    responses(
+        (status = 200, description = "Suspended"),
+        BadRequest,
+        Unauthorized,
+        Forbidden,
+        Unprocessable,
+        RateLimited,
+        Unavailable,
+    )
+)]
 #[post("/moderation/users/suspend")]
 async fn suspend_user(
     pool: web::Data<DbPool>,
     guard: ModeratorGuard,
-    req: web::Json<SuspendUserRequest>,
-) -> impl Responder {
-    let mut conn = match pool.get() {
-        Ok(c) => c,
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": format!("Database connection failed: {}", e)
-            }))
-        }
-    };
+    req: ValidatedJson<SuspendUserRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
 
     let suspended_until =
         chrono::Utc::now().naive_utc() + chrono::Duration::days(req.duration_days);
@@ -467,35 +518,28 @@ async fn suspend_user(
         suspended_until,
     };
 
-    match diesel::insert_into(user_suspensions::table)
+    diesel::insert_into(user_suspensions::table)
         .values(&new_suspension)
-        .execute(&mut conn)
-    {
-        Ok(_) => {
-            let _ = log_moderation_action(
-                &pool,
-                NewModerationAction {
-                    action_type: "suspend_user".to_string(),
-                    moderator_id: guard.0.user_id,
-                    target_user_id: Some(req.user_id),
-                    target_topic_id: None,
-                    target_post_id: None,
-                    details: Some(serde_json::json!({
-                        "reason": req.reason,
-                        "duration_days": req.duration_days
-                    })),
-                },
-            );
-
-            HttpResponse::Ok().json(serde_json::json!({
-                "message": "User suspended successfully",
-                "suspended_until": suspended_until.to_string()
-            }))
-        }
-        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": format!("Failed to suspend user: {}", e)
+        .execute(&mut conn)?;
+
+    let action = NewModerationAction {
+        action_type: "suspend_user".to_string(),
+        moderator_id: guard.0.user_id,
+        target_user_id: Some(req.user_id),
+        target_topic_id: None,
+        target_post_id: None,
+        details: Some(serde_json::json!({
+            "reason": req.reason,
+            "duration_days": req.duration_days
         })),
-    }
+    };
+    notify_author(&mut conn, req.user_id, &action);
+    let _ = log_moderation_action(&pool, action);
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "User suspended successfully",
+        "suspended_until": suspended_until.to_string()
+    })))
 }
 
 pub fn configure(cfg: &mut web::ServiceConfig) {
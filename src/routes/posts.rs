@@ -1,29 +1,39 @@
-use actix_web::{HttpResponse, Responder, delete, get, post, put, web};
+use actix_web::{HttpResponse, delete, get, post, put, web};
 use diesel::prelude::*;
 
 use crate::DbPool;
-use crate::middleware::{AuthUser, ReadAuthUser};
-use crate::models::{CreatePostInput, Post, UpdatePostInput};
+use crate::error::ApiError;
+use crate::middleware::{AuthUser, PostingUser, ReadAuthUser};
+use crate::models::{CreatePostInput, Post, QueuedPost, UpdatePostInput};
+use crate::openapi::{
+    BadRequest, Forbidden, NotFound, RateLimited, Unauthorized, Unavailable, Unprocessable,
+};
 use crate::pagination::PaginationParams;
 use crate::schema::posts;
-
+use crate::services::queued_posts::{self, Submitted};
+use crate::validation::ValidatedJson;
+
+#[utoipa::path(
+    tag = "posts",
+    responses(
+        (status = 200, description = "Posts", body = Vec<Post>),
+        Unauthorized,
+        RateLimited,
+        Unavailable,
+    )
+)]
 #[get("/posts")]
 async fn list_posts(
     pool: web::Data<DbPool>,
     pagination: web::Query<PaginationParams>,
     _auth: ReadAuthUser,
-) -> impl Responder {
-    let mut conn = match pool.get() {
-        Ok(conn) => conn,
-        Err(_) => return HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Failed to get database connection"
-        })),
-    };
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
 
     let per_page = pagination.per_page();
     let offset = pagination.offset();
 
-    let results = web::block(move || {
+    let posts = web::block(move || {
         posts::table
             .select(Post::as_select())
             .order(posts::created_at.desc())
@@ -31,38 +41,34 @@ async fn list_posts(
             .offset(offset)
             .load(&mut conn)
     })
-    .await;
-
-    match results {
-        Ok(Ok(posts)) => HttpResponse::Ok().json(posts),
-        Ok(Err(_)) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Failed to load posts"
-        })),
-        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Blocking error"
-        })),
-    }
+    .await??;
+
+    Ok(HttpResponse::Ok().json(posts))
 }
 
+#[utoipa::path(
+    tag = "posts",
+    responses(
+        (status = 200, description = "The topic's posts", body = Vec<Post>),
+        Unauthorized,
+        RateLimited,
+        Unavailable,
+    )
+)]
 #[get("/topics/{topic_id}/posts")]
 async fn list_topic_posts(
     pool: web::Data<DbPool>,
     topic_id: web::Path<i32>,
     pagination: web::Query<PaginationParams>,
     _auth: ReadAuthUser,
-) -> impl Responder {
-    let mut conn = match pool.get() {
-        Ok(conn) => conn,
-        Err(_) => return HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Failed to get database connection"
-        })),
-    };
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
 
     let topic_id = topic_id.into_inner();
     let per_page = pagination.per_page();
     let offset = pagination.offset();
 
-    let results = web::block(move || {
+    let posts = web::block(move || {
         posts::table
             .filter(posts::topic_id.eq(topic_id))
             .select(Post::as_select())
@@ -71,145 +77,152 @@ async fn list_topic_posts(
             .offset(offset)
             .load(&mut conn)
     })
-    .await;
-
-    match results {
-        Ok(Ok(posts)) => HttpResponse::Ok().json(posts),
-        Ok(Err(_)) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Failed to load posts"
-        })),
-        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Blocking error"
-        })),
-    }
+    .await??;
+
+    Ok(HttpResponse::Ok().json(posts))
 }
 
+/// POST /posts
+///
+/// 201 with the post, or 202 with the queued post if it has to wait for a
+/// moderator (see `services::queued_posts`). Users can only post as
+/// themselves: whether a post is queued depends on who wrote it.
+#[utoipa::path(
+    tag = "posts",
+    request_body = CreatePostInput,
+    responses(
+        (status = 201, description = "Posted", body = Post),
+        (status = 202, description = "Queued for approval", body = QueuedPost),
+        BadRequest,
+        Unauthorized,
+        Forbidden,
+        Unprocessable,
+        RateLimited,
+        Unavailable,
+    )
+)]
 #[post("/posts")]
 async fn create_post(
     pool: web::Data<DbPool>,
     job_queue: Option<web::Data<crate::jobs::JobQueue>>,
-    _auth: AuthUser,
-    input: web::Json<CreatePostInput>,
-) -> impl Responder {
-    let mut conn = match pool.get() {
-        Ok(conn) => conn,
-        Err(_) => return HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Failed to get database connection"
-        })),
-    };
-
-    // Convert input to NewPost, rendering markdown in the process
-    let new_post = input.into_inner().into_new_post();
-
-    let result = web::block(move || {
-        conn.transaction::<Post, diesel::result::Error, _>(|conn| {
-            let post: Post = diesel::insert_into(posts::table)
-                .values(&new_post)
-                .returning(Post::as_returning())
-                .get_result(conn)?;
-            crate::services::user_stats::incr_post_count(conn, post.user_id)?;
-            Ok(post)
-        })
-    })
-    .await;
-
-    match result {
-        Ok(Ok(post)) => {
-            // Enqueue after the tx commits so the worker sees the bumped
-            // counter. Best effort — a failed enqueue logs but doesn't fail
-            // the request, since the user's post did succeed.
-            if let Some(jq) = job_queue {
-                if let Err(e) = jq.enqueue(crate::jobs::CheckTrustLevelPromotionJob {
-                    user_id: post.user_id,
-                }) {
-                    log::error!("Failed to enqueue trust-level check: {e}");
-                }
-            }
-            HttpResponse::Created().json(post)
+    auth: PostingUser,
+    input: ValidatedJson<CreatePostInput>,
+) -> Result<HttpResponse, ApiError> {
+    let input = input.into_inner();
+    if input.user_id != auth.0.user_id {
+        return Err(ApiError::Forbidden(
+            "You can only post as yourself".to_string(),
+        ));
+    }
+
+    let mut conn = pool.get()?;
+    let submitted = web::block(move || queued_posts::submit(&mut conn, input)).await??;
+
+    match submitted {
+        Submitted::Created(post) => {
+            enqueue_created_post_jobs(job_queue.as_ref(), &post);
+            Ok(HttpResponse::Created().json(post))
         }
-        Ok(Err(_)) => HttpResponse::BadRequest().json(serde_json::json!({
-            "error": "Failed to create post"
-        })),
-        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Blocking error"
-        })),
+        Submitted::Queued(queued) => Ok(HttpResponse::Accepted().json(queued)),
     }
 }
 
+/// Jobs that follow a new post going live, whether posted directly or
+/// approved from the queue.
+pub(crate) fn enqueue_created_post_jobs(
+    job_queue: Option<&web::Data<crate::jobs::JobQueue>>,
+    post: &Post,
+) {
+    // Enqueue after the tx commits so the worker sees the bumped
+    // counter. Best effort — a failed enqueue logs but doesn't fail
+    // the request, since the user's post did succeed.
+    if let Some(jq) = job_queue
+        && let Err(e) = jq.enqueue(crate::jobs::CheckTrustLevelPromotionJob {
+            user_id: post.user_id,
+        })
+    {
+        log::error!("Failed to enqueue trust-level check: {e}");
+    }
+    enqueue_link_previews(job_queue, post);
+}
+
+#[utoipa::path(
+    tag = "posts",
+    request_body = UpdatePostInput,
+    responses(
+        (status = 200, description = "Updated", body = Post),
+        BadRequest,
+        Unauthorized,
+        Forbidden,
+        NotFound,
+        Unprocessable,
+        RateLimited,
+        Unavailable,
+    )
+)]
 #[put("/posts/{id}")]
 async fn update_post(
     pool: web::Data<DbPool>,
-    _auth: AuthUser,
+    job_queue: Option<web::Data<crate::jobs::JobQueue>>,
+    auth: PostingUser,
     post_id: web::Path<i32>,
-    input: web::Json<UpdatePostInput>,
-) -> impl Responder {
-    let mut conn = match pool.get() {
-        Ok(conn) => conn,
-        Err(_) => return HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Failed to get database connection"
-        })),
-    };
+    input: ValidatedJson<UpdatePostInput>,
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
 
     let post_id = post_id.into_inner();
-    // Convert input to UpdatePost, rendering markdown if raw was provided
-    let update_post = input.into_inner().into_update_post();
-
-    let result = web::block(move || {
-        diesel::update(posts::table.find(post_id))
-            .set(&update_post)
-            .returning(Post::as_returning())
-            .get_result(&mut conn)
+    let editor_id = auth.0.user_id;
+    let input = input.into_inner();
+    let post = web::block(move || {
+        crate::services::posts::update(&mut conn, post_id, editor_id, input)
     })
-    .await;
-
-    match result {
-        Ok(Ok(post)) => HttpResponse::Ok().json(post),
-        Ok(Err(diesel::NotFound)) => HttpResponse::NotFound().json(serde_json::json!({
-            "error": "Post not found"
-        })),
-        Ok(Err(_)) => HttpResponse::BadRequest().json(serde_json::json!({
-            "error": "Failed to update post"
-        })),
-        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Blocking error"
-        })),
+    .await??
+        .ok_or_else(|| ApiError::not_found("Post"))?;
+
+    enqueue_link_previews(job_queue.as_ref(), &post);
+    Ok(HttpResponse::Ok().json(post))
+}
+
+/// Queue a preview lookup if the post has links on lines of their own. The
+/// job skips URLs it already has, so this doesn't check the cache first.
+/// Best effort, like the other post-save jobs.
+fn enqueue_link_previews(job_queue: Option<&web::Data<crate::jobs::JobQueue>>, post: &Post) {
+    if crate::markdown::extract_onebox_urls(&post.raw).is_empty() {
+        return;
+    }
+    if let Some(jq) = job_queue
+        && let Err(e) = jq.enqueue(crate::jobs::FetchLinkPreviewsJob { post_id: post.id })
+    {
+        log::error!("Failed to enqueue link preview fetch: {e}");
     }
 }
 
+#[utoipa::path(
+    tag = "posts",
+    responses(
+        (status = 204, description = "Deleted"),
+        Unauthorized,
+        NotFound,
+        RateLimited,
+        Unavailable,
+    )
+)]
 #[delete("/posts/{id}")]
 async fn delete_post(
     pool: web::Data<DbPool>,
     post_id: web::Path<i32>,
     _auth: AuthUser,
-) -> impl Responder {
-    let mut conn = match pool.get() {
-        Ok(conn) => conn,
-        Err(_) => {
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to get database connection"
-            }))
-        }
-    };
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
 
     let post_id = post_id.into_inner();
 
-    let result =
-        web::block(move || diesel::delete(posts::table.find(post_id)).execute(&mut conn)).await;
-
-    match result {
-        Ok(Ok(1)) => HttpResponse::NoContent().finish(),
-        Ok(Ok(0)) => HttpResponse::NotFound().json(serde_json::json!({
-            "error": "Post not found"
-        })),
-        Ok(Ok(_)) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Multiple posts deleted"
-        })),
-        Ok(Err(_)) => HttpResponse::BadRequest().json(serde_json::json!({
-            "error": "Failed to delete post"
-        })),
-        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Blocking error"
-        })),
+    let deleted =
+        web::block(move || diesel::delete(posts::table.find(post_id)).execute(&mut conn)).await??;
+
+    match deleted {
+        0 => Err(ApiError::not_found("Post")),
+        _ => Ok(HttpResponse::NoContent().finish()),
     }
 }
 
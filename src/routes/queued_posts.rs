@@ -0,0 +1,130 @@
+use actix_web::{HttpResponse, get, post, web};
+use serde::Deserialize;
+
+use crate::DbPool;
+use crate::error::ApiError;
+use crate::guardian::StaffGuard;
+use crate::models::queued_post::STATUS_PENDING;
+use crate::models::{Post, QueuedPost, RejectQueuedPostInput};
+use crate::openapi::{
+    BadRequest, Conflict, Forbidden, NotFound, RateLimited, Unauthorized, Unavailable,
+    Unprocessable,
+};
+use crate::pagination::PaginationParams;
+use crate::services::queued_posts;
+use crate::validation::ValidatedJson;
+
+#[derive(Debug, Deserialize)]
+struct QueuedPostsQuery {
+    /// `pending` (the default), `approved` or `rejected`.
+    status: Option<String>,
+}
+
+/// GET /queued_posts
+///
+/// The approval queue, oldest first (staff only).
+#[utoipa::path(
+    tag = "moderation",
+    responses(
+        (status = 200, description = "Queued posts, oldest first", body = Vec<QueuedPost>),
+        Unauthorized,
+        Forbidden,
+        RateLimited,
+        Unavailable,
+    )
+)]
+#[get("/queued_posts")]
+async fn list_queued_posts(
+    pool: web::Data<DbPool>,
+    _guard: StaffGuard,
+    query: web::Query<QueuedPostsQuery>,
+    pagination: web::Query<PaginationParams>,
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
+
+    let status = query
+        .into_inner()
+        .status
+        .unwrap_or_else(|| STATUS_PENDING.to_string());
+    let per_page = pagination.per_page();
+    let offset = pagination.offset();
+    let queued =
+        web::block(move || queued_posts::list(&mut conn, &status, per_page, offset)).await??;
+    Ok(HttpResponse::Ok().json(queued))
+}
+
+/// POST /queued_posts/{id}/approve
+///
+/// Publish the post and notify its author. 201 with the new post; 409 if
+/// it was already reviewed.
+#[utoipa::path(
+    tag = "moderation",
+    responses(
+        (status = 201, description = "Published", body = Post),
+        Unauthorized,
+        Forbidden,
+        NotFound,
+        Conflict,
+        Unprocessable,
+        RateLimited,
+        Unavailable,
+    )
+)]
+#[post("/queued_posts/{id}/approve")]
+async fn approve_queued_post(
+    pool: web::Data<DbPool>,
+    job_queue: Option<web::Data<crate::jobs::JobQueue>>,
+    guard: StaffGuard,
+    id: web::Path<i32>,
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
+
+    let id = id.into_inner();
+    let reviewer_id = guard.0.user_id;
+    let post = web::block(move || queued_posts::approve(&mut conn, id, reviewer_id)).await??;
+
+    super::posts::enqueue_created_post_jobs(job_queue.as_ref(), &post);
+    Ok(HttpResponse::Created().json(post))
+}
+
+/// POST /queued_posts/{id}/reject
+///
+/// Turn the post down and notify its author, with the optional `reason`.
+/// 409 if it was already reviewed.
+#[utoipa::path(
+    tag = "moderation",
+    request_body = RejectQueuedPostInput,
+    responses(
+        (status = 200, description = "Rejected", body = QueuedPost),
+        BadRequest,
+        Unauthorized,
+        Forbidden,
+        NotFound,
+        Conflict,
+        Unprocessable,
+        RateLimited,
+        Unavailable,
+    )
+)]
+#[post("/queued_posts/{id}/reject")]
+async fn reject_queued_post(
+    pool: web::Data<DbPool>,
+    guard: StaffGuard,
+    id: web::Path<i32>,
+    input: ValidatedJson<RejectQueuedPostInput>,
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
+
+    let id = id.into_inner();
+    let reviewer_id = guard.0.user_id;
+    let reason = input.into_inner().reason;
+    let queued =
+        web::block(move || queued_posts::reject(&mut conn, id, reviewer_id, reason)).await??;
+    Ok(HttpResponse::Ok().json(queued))
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(list_queued_posts)
+        .service(approve_queued_post)
+        .service(reject_queued_post);
+}
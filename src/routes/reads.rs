@@ -1,17 +1,21 @@
-use actix_web::{HttpResponse, Responder, post, web};
+use actix_web::{HttpResponse, post, web};
 use serde::Deserialize;
-use serde_json::json;
+use validator::Validate;
 
 use crate::DbPool;
+use crate::error::ApiError;
 use crate::middleware::AuthUser;
-use crate::services::reads::{ReadError, record_topic_view};
+use crate::openapi::{BadRequest, NotFound, RateLimited, Unauthorized, Unavailable, Unprocessable};
+use crate::services::reads::record_topic_view;
+use crate::validation::ValidatedJson;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Validate)]
 pub struct TopicReadInput {
     pub topic_id: i32,
     /// Seconds spent on the topic since the last call. Server caps this
     /// (see `services::reads::MAX_SECONDS_PER_CALL`), so a client that
     /// reports 9999 won't inflate the counter.
+    #[validate(range(min = 0))]
     pub seconds: i32,
 }
 
@@ -24,35 +28,30 @@ pub struct TopicReadInput {
 /// - 204 No Content on success (first view or revisit, no distinction
 ///   exposed to the client)
 /// - 404 if the topic doesn't exist
+#[utoipa::path(
+    tag = "topics",
+    responses(
+        (status = 204, description = "Recorded"),
+        BadRequest,
+        Unauthorized,
+        NotFound,
+        Unprocessable,
+        RateLimited,
+        Unavailable,
+    )
+)]
 #[post("/read/topic")]
 async fn record_topic_read(
     pool: web::Data<DbPool>,
     auth: AuthUser,
-    input: web::Json<TopicReadInput>,
-) -> impl Responder {
+    input: ValidatedJson<TopicReadInput>,
+) -> Result<HttpResponse, ApiError> {
     let user_id = auth.0.user_id;
     let TopicReadInput { topic_id, seconds } = input.into_inner();
 
-    let mut conn = match pool.get() {
-        Ok(c) => c,
-        Err(_) => {
-            return HttpResponse::InternalServerError()
-                .json(json!({ "error": "Failed to get database connection" }));
-        }
-    };
-
-    let result = web::block(move || record_topic_view(&mut conn, user_id, topic_id, seconds)).await;
-
-    match result {
-        Ok(Ok(_)) => HttpResponse::NoContent().finish(),
-        Ok(Err(ReadError::TopicNotFound)) => {
-            HttpResponse::NotFound().json(json!({ "error": "Topic not found" }))
-        }
-        Ok(Err(ReadError::Db(e))) => HttpResponse::InternalServerError()
-            .json(json!({ "error": format!("Database error: {e}") })),
-        Err(e) => HttpResponse::InternalServerError()
-            .json(json!({ "error": format!("Blocking error: {e}") })),
-    }
+    let mut conn = pool.get()?;
+    web::block(move || record_topic_view(&mut conn, user_id, topic_id, seconds)).await??;
+    Ok(HttpResponse::NoContent().finish())
 }
 
 pub fn configure(cfg: &mut web::ServiceConfig) {
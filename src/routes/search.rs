@@ -1,9 +1,11 @@
-use actix_web::{get, web, HttpResponse, Responder};
+use actix_web::{get, web, HttpResponse};
 use diesel::prelude::*;
 use diesel::sql_types::{Integer, Text, Timestamp};
 use serde::{Deserialize, Serialize};
 
 use crate::DbPool;
+use crate::error::ApiError;
+use crate::openapi::{BadRequest, RateLimited, Unavailable};
 
 #[derive(Deserialize)]
 pub struct SearchQuery {
@@ -61,25 +63,30 @@ pub struct SearchResults {
     pub query: String,
 }
 
+#[utoipa::path(
+    tag = "search",
+    responses(
+        (status = 200, description = "Matching topics, posts and users"),
+        BadRequest,
+        RateLimited,
+        Unavailable,
+    )
+)]
 #[get("/search")]
-async fn search(pool: web::Data<DbPool>, query: web::Query<SearchQuery>) -> impl Responder {
-    let mut conn = match pool.get() {
-        Ok(c) => c,
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": format!("Database connection failed: {}", e)
-            }))
-        }
-    };
+async fn search(
+    pool: web::Data<DbPool>,
+    query: web::Query<SearchQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
 
     let search_term = query.q.trim();
     if search_term.is_empty() {
-        return HttpResponse::BadRequest().json(serde_json::json!({
-            "error": "Search query cannot be empty"
-        }));
+        return Err(ApiError::BadRequest(
+            "Search query cannot be empty".to_string(),
+        ));
     }
 
-    let limit = query.limit.min(100).max(1);
+    let limit = query.limit.clamp(1, 100);
 
     // Search topics by title using PostgreSQL full-text search
     let topics: Vec<TopicSearchResult> = match diesel::sql_query(
@@ -125,11 +132,11 @@ async fn search(pool: web::Data<DbPool>, query: web::Query<SearchQuery>) -> impl
         }
     };
 
-    HttpResponse::Ok().json(SearchResults {
+    Ok(HttpResponse::Ok().json(SearchResults {
         topics,
         posts,
         query: search_term.to_string(),
-    })
+    }))
 }
 
 pub fn configure(cfg: &mut web::ServiceConfig) {
@@ -1,14 +1,19 @@
-use actix_web::{HttpResponse, Responder, get, put, web};
+use actix_web::{HttpResponse, get, put, web};
 use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
+use validator::Validate;
 
 use crate::DbPool;
+use crate::error::ApiError;
 use crate::middleware::{AuthUser, ReadAuthUser};
 use crate::models::{SiteSetting, UpdateSiteSetting};
+use crate::openapi::{BadRequest, NotFound, RateLimited, Unauthorized, Unavailable, Unprocessable};
 use crate::schema::site_settings;
+use crate::validation::ValidatedJson;
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Validate)]
 struct UpdateSettingRequest {
+    #[validate(length(max = 10000))]
     value: String,
 }
 
@@ -17,115 +22,102 @@ struct SettingsResponse {
     settings: Vec<SiteSetting>,
 }
 
+#[utoipa::path(
+    tag = "settings",
+    responses(
+        (status = 200, description = "Site settings"),
+        Unauthorized,
+        RateLimited,
+        Unavailable,
+    )
+)]
 #[get("/settings")]
-async fn list_settings(pool: web::Data<DbPool>, _auth: ReadAuthUser) -> impl Responder {
-    let mut conn = match pool.get() {
-        Ok(conn) => conn,
-        Err(_) => {
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to get database connection"
-            }))
-        }
-    };
+async fn list_settings(
+    pool: web::Data<DbPool>,
+    _auth: ReadAuthUser,
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
 
-    let results = web::block(move || {
+    let settings = web::block(move || {
         site_settings::table
             .select(SiteSetting::as_select())
             .load(&mut conn)
     })
-    .await;
+    .await??;
 
-    match results {
-        Ok(Ok(settings)) => HttpResponse::Ok().json(SettingsResponse { settings }),
-        Ok(Err(_)) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Failed to load settings"
-        })),
-        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Blocking error"
-        })),
-    }
+    Ok(HttpResponse::Ok().json(SettingsResponse { settings }))
 }
 
+#[utoipa::path(
+    tag = "settings",
+    responses(
+        (status = 200, description = "The setting"),
+        Unauthorized,
+        NotFound,
+        RateLimited,
+        Unavailable,
+    )
+)]
 #[get("/settings/{key}")]
 async fn get_setting(
     pool: web::Data<DbPool>,
     key: web::Path<String>,
     _auth: ReadAuthUser,
-) -> impl Responder {
-    let mut conn = match pool.get() {
-        Ok(conn) => conn,
-        Err(_) => {
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to get database connection"
-            }))
-        }
-    };
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
 
     let key = key.into_inner();
 
-    let result = web::block(move || {
+    let setting = web::block(move || {
         site_settings::table
             .find(&key)
             .select(SiteSetting::as_select())
             .first(&mut conn)
+            .optional()
     })
-    .await;
+    .await??
+    .ok_or_else(|| ApiError::not_found("Setting"))?;
 
-    match result {
-        Ok(Ok(setting)) => HttpResponse::Ok().json(setting),
-        Ok(Err(diesel::NotFound)) => HttpResponse::NotFound().json(serde_json::json!({
-            "error": "Setting not found"
-        })),
-        Ok(Err(_)) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Failed to load setting"
-        })),
-        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Blocking error"
-        })),
-    }
+    Ok(HttpResponse::Ok().json(setting))
 }
 
+#[utoipa::path(
+    tag = "settings",
+    responses(
+        (status = 200, description = "Updated"),
+        BadRequest,
+        Unauthorized,
+        NotFound,
+        Unprocessable,
+        RateLimited,
+        Unavailable,
+    )
+)]
 #[put("/settings/{key}")]
 async fn update_setting(
     pool: web::Data<DbPool>,
     _auth: AuthUser,
     key: web::Path<String>,
-    update_request: web::Json<UpdateSettingRequest>,
-) -> impl Responder {
-    let mut conn = match pool.get() {
-        Ok(conn) => conn,
-        Err(_) => {
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to get database connection"
-            }))
-        }
-    };
+    update_request: ValidatedJson<UpdateSettingRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
 
     let key = key.into_inner();
     let update = UpdateSiteSetting {
         value: update_request.value.clone(),
     };
 
-    let result = web::block(move || {
+    let setting = web::block(move || {
         diesel::update(site_settings::table.find(&key))
             .set(&update)
             .returning(SiteSetting::as_returning())
             .get_result(&mut conn)
+            .optional()
     })
-    .await;
+    .await??
+    .ok_or_else(|| ApiError::not_found("Setting"))?;
 
-    match result {
-        Ok(Ok(setting)) => HttpResponse::Ok().json(setting),
-        Ok(Err(diesel::NotFound)) => HttpResponse::NotFound().json(serde_json::json!({
-            "error": "Setting not found"
-        })),
-        Ok(Err(_)) => HttpResponse::BadRequest().json(serde_json::json!({
-            "error": "Failed to update setting"
-        })),
-        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Blocking error"
-        })),
-    }
+    Ok(HttpResponse::Ok().json(setting))
 }
 
 pub fn configure(cfg: &mut web::ServiceConfig) {
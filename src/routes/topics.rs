@@ -1,29 +1,41 @@
-use actix_web::{HttpResponse, Responder, delete, get, post, put, web};
+use actix_web::{HttpResponse, delete, get, post, put, web};
 use diesel::prelude::*;
 
 use crate::DbPool;
-use crate::middleware::{AuthUser, ReadAuthUser};
+use crate::error::ApiError;
+use crate::middleware::{AuthUser, PostingUser, ReadAuthUser};
 use crate::models::{NewTopic, Topic, UpdateTopic};
+use crate::openapi::{
+    BadRequest, Forbidden, NotFound, RateLimited, Unauthorized, Unavailable, Unprocessable,
+};
 use crate::pagination::PaginationParams;
 use crate::schema::topics;
+use crate::services::notifications;
+use crate::services::posts as post_service;
+use crate::services::watched_words::{self, ContentError, Matches};
+use crate::validation::ValidatedJson;
 
+#[utoipa::path(
+    tag = "topics",
+    responses(
+        (status = 200, description = "Topics", body = Vec<Topic>),
+        Unauthorized,
+        RateLimited,
+        Unavailable,
+    )
+)]
 #[get("/topics")]
 async fn list_topics(
     pool: web::Data<DbPool>,
     pagination: web::Query<PaginationParams>,
     _auth: ReadAuthUser,
-) -> impl Responder {
-    let mut conn = match pool.get() {
-        Ok(conn) => conn,
-        Err(_) => return HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Failed to get database connection"
-        })),
-    };
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
 
     let per_page = pagination.per_page();
     let offset = pagination.offset();
 
-    let results = web::block(move || {
+    let topics = web::block(move || {
         topics::table
             .select(Topic::as_select())
             .order(topics::created_at.desc())
@@ -31,167 +43,254 @@ async fn list_topics(
             .offset(offset)
             .load(&mut conn)
     })
-    .await;
-
-    match results {
-        Ok(Ok(topics)) => HttpResponse::Ok().json(topics),
-        Ok(Err(_)) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Failed to load topics"
-        })),
-        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Blocking error"
-        })),
-    }
+    .await??;
+
+    Ok(HttpResponse::Ok().json(topics))
 }
 
+#[utoipa::path(
+    tag = "topics",
+    responses(
+        (status = 200, description = "The topic", body = Topic),
+        Unauthorized,
+        NotFound,
+        RateLimited,
+        Unavailable,
+    )
+)]
 #[get("/topics/{id}")]
 async fn get_topic(
     pool: web::Data<DbPool>,
     topic_id: web::Path<i32>,
     _auth: ReadAuthUser,
-) -> impl Responder {
-    let mut conn = match pool.get() {
-        Ok(conn) => conn,
-        Err(_) => return HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Failed to get database connection"
-        })),
-    };
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
 
     let topic_id = topic_id.into_inner();
 
-    let result = web::block(move || {
+    let topic = web::block(move || {
         topics::table
             .find(topic_id)
             .select(Topic::as_select())
             .first(&mut conn)
+            .optional()
     })
-    .await;
-
-    match result {
-        Ok(Ok(topic)) => HttpResponse::Ok().json(topic),
-        Ok(Err(diesel::NotFound)) => HttpResponse::NotFound().json(serde_json::json!({
-            "error": "Topic not found"
-        })),
-        Ok(Err(_)) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Failed to load topic"
-        })),
-        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Blocking error"
-        })),
-    }
+    .await??
+    .ok_or_else(|| ApiError::not_found("Topic"))?;
+
+    Ok(HttpResponse::Ok().json(topic))
 }
 
+#[utoipa::path(
+    tag = "topics",
+    request_body = NewTopic,
+    responses(
+        (status = 201, description = "Created", body = Topic),
+        BadRequest,
+        Unauthorized,
+        Forbidden,
+        Unprocessable,
+        RateLimited,
+        Unavailable,
+    )
+)]
 #[post("/topics")]
 async fn create_topic(
     pool: web::Data<DbPool>,
-    _auth: AuthUser,
-    new_topic: web::Json<NewTopic>,
-) -> impl Responder {
-    let mut conn = match pool.get() {
-        Ok(conn) => conn,
-        Err(_) => return HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Failed to get database connection"
-        })),
-    };
-
-    let new_topic = new_topic.into_inner();
-
-    let result = web::block(move || {
-        conn.transaction::<Topic, diesel::result::Error, _>(|conn| {
+    auth: PostingUser,
+    new_topic: ValidatedJson<NewTopic>,
+) -> Result<HttpResponse, ApiError> {
+    let mut new_topic = new_topic.into_inner();
+    if new_topic.user_id != auth.0.user_id {
+        return Err(ApiError::Forbidden(
+            "You can only open topics as yourself".to_string(),
+        ));
+    }
+
+    let mut conn = pool.get()?;
+
+    let topic = web::block(move || {
+        conn.transaction::<Topic, ContentError, _>(|conn| {
+            let (title, matches) = watched_words::check_title(conn, &new_topic.title)?;
+            new_topic.title = title;
             let topic: Topic = diesel::insert_into(topics::table)
                 .values(&new_topic)
                 .returning(Topic::as_returning())
                 .get_result(conn)?;
             crate::services::user_stats::incr_topic_count(conn, topic.user_id)?;
+            // The first post picks up the title's other words when it's created.
+            watched_words::add_tags(conn, topic.id, &matches.tags)?;
+            notifications::watch(conn, topic.id, topic.user_id)?;
             Ok(topic)
         })
     })
-    .await;
-
-    match result {
-        Ok(Ok(topic)) => HttpResponse::Created().json(topic),
-        Ok(Err(_)) => HttpResponse::BadRequest().json(serde_json::json!({
-            "error": "Failed to create topic"
-        })),
-        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Blocking error"
-        })),
-    }
+    .await??;
+
+    Ok(HttpResponse::Created().json(topic))
 }
 
+#[utoipa::path(
+    tag = "topics",
+    request_body = UpdateTopic,
+    responses(
+        (status = 200, description = "Updated", body = Topic),
+        BadRequest,
+        Unauthorized,
+        Forbidden,
+        NotFound,
+        Unprocessable,
+        RateLimited,
+        Unavailable,
+    )
+)]
 #[put("/topics/{id}")]
 async fn update_topic(
     pool: web::Data<DbPool>,
-    _auth: AuthUser,
+    auth: PostingUser,
     topic_id: web::Path<i32>,
-    update_topic: web::Json<UpdateTopic>,
-) -> impl Responder {
-    let mut conn = match pool.get() {
-        Ok(conn) => conn,
-        Err(_) => return HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Failed to get database connection"
-        })),
-    };
+    update_topic: ValidatedJson<UpdateTopic>,
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
 
     let topic_id = topic_id.into_inner();
-    let update_topic = update_topic.into_inner();
+    let editor_id = auth.0.user_id;
+    let mut update_topic = update_topic.into_inner();
 
-    let result = web::block(move || {
-        diesel::update(topics::table.find(topic_id))
-            .set(&update_topic)
-            .returning(Topic::as_returning())
-            .get_result(&mut conn)
+    let topic = web::block(move || {
+        conn.transaction::<Option<Topic>, ContentError, _>(|conn| {
+            let matches = match &update_topic.title {
+                Some(title) => {
+                    let (title, mut matches) = watched_words::check_title(conn, title)?;
+                    update_topic.title = Some(title);
+                    // As with post edits, staff aren't held to `require_approval`.
+                    if post_service::is_staff(conn, editor_id)? {
+                        matches.require_approval.clear();
+                    }
+                    matches
+                }
+                None => Matches::default(),
+            };
+            let topic = diesel::update(topics::table.find(topic_id))
+                .set(&update_topic)
+                .returning(Topic::as_returning())
+                .get_result(conn)
+                .optional()?;
+            if let Some(topic) = &topic {
+                watched_words::apply_to_title(conn, topic.id, &matches)?;
+            }
+            Ok(topic)
+        })
     })
-    .await;
-
-    match result {
-        Ok(Ok(topic)) => HttpResponse::Ok().json(topic),
-        Ok(Err(diesel::NotFound)) => HttpResponse::NotFound().json(serde_json::json!({
-            "error": "Topic not found"
-        })),
-        Ok(Err(_)) => HttpResponse::BadRequest().json(serde_json::json!({
-            "error": "Failed to update topic"
-        })),
-        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Blocking error"
-        })),
-    }
+    .await??
+    .ok_or_else(|| ApiError::not_found("Topic"))?;
+
+    Ok(HttpResponse::Ok().json(topic))
+}
+
+/// GET /topics/{id}/tags
+///
+/// The topic's tag names, alphabetically.
+#[utoipa::path(
+    tag = "topics",
+    responses(
+        (status = 200, description = "Tag names, alphabetically", body = Vec<String>),
+        Unauthorized,
+        RateLimited,
+        Unavailable,
+    )
+)]
+#[get("/topics/{id}/tags")]
+async fn list_topic_tags(
+    pool: web::Data<DbPool>,
+    topic_id: web::Path<i32>,
+    _auth: ReadAuthUser,
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
+
+    let topic_id = topic_id.into_inner();
+    let tags = web::block(move || watched_words::topic_tags(&mut conn, topic_id)).await??;
+    Ok(HttpResponse::Ok().json(tags))
+}
+
+/// PUT /topics/{id}/watch
+///
+/// Get a `posted` notification for every new post in the topic. Topic
+/// creators watch their topics already.
+#[utoipa::path(
+    tag = "topics",
+    responses(
+        (status = 204, description = "Watching"),
+        Unauthorized,
+        NotFound,
+        RateLimited,
+        Unavailable,
+    )
+)]
+#[put("/topics/{id}/watch")]
+async fn watch_topic(
+    pool: web::Data<DbPool>,
+    auth: AuthUser,
+    topic_id: web::Path<i32>,
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
+
+    let topic_id = topic_id.into_inner();
+    let user_id = auth.0.user_id;
+    web::block(move || notifications::watch(&mut conn, topic_id, user_id)).await??;
+    Ok(HttpResponse::NoContent().finish())
 }
 
+/// DELETE /topics/{id}/watch
+#[utoipa::path(
+    tag = "topics",
+    responses(
+        (status = 204, description = "Not watching"),
+        Unauthorized,
+        RateLimited,
+        Unavailable,
+    )
+)]
+#[delete("/topics/{id}/watch")]
+async fn unwatch_topic(
+    pool: web::Data<DbPool>,
+    auth: AuthUser,
+    topic_id: web::Path<i32>,
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
+
+    let topic_id = topic_id.into_inner();
+    let user_id = auth.0.user_id;
+    web::block(move || notifications::unwatch(&mut conn, topic_id, user_id)).await??;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[utoipa::path(
+    tag = "topics",
+    responses(
+        (status = 204, description = "Deleted"),
+        Unauthorized,
+        NotFound,
+        RateLimited,
+        Unavailable,
+    )
+)]
 #[delete("/topics/{id}")]
 async fn delete_topic(
     pool: web::Data<DbPool>,
     topic_id: web::Path<i32>,
     _auth: AuthUser,
-) -> impl Responder {
-    let mut conn = match pool.get() {
-        Ok(conn) => conn,
-        Err(_) => {
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to get database connection"
-            }))
-        }
-    };
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
 
     let topic_id = topic_id.into_inner();
 
-    let result =
-        web::block(move || diesel::delete(topics::table.find(topic_id)).execute(&mut conn)).await;
-
-    match result {
-        Ok(Ok(1)) => HttpResponse::NoContent().finish(),
-        Ok(Ok(0)) => HttpResponse::NotFound().json(serde_json::json!({
-            "error": "Topic not found"
-        })),
-        Ok(Ok(_)) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Multiple topics deleted"
-        })),
-        Ok(Err(_)) => HttpResponse::BadRequest().json(serde_json::json!({
-            "error": "Failed to delete topic"
-        })),
-        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Blocking error"
-        })),
+    let deleted =
+        web::block(move || diesel::delete(topics::table.find(topic_id)).execute(&mut conn))
+            .await??;
+
+    match deleted {
+        0 => Err(ApiError::not_found("Topic")),
+        _ => Ok(HttpResponse::NoContent().finish()),
     }
 }
 
@@ -200,5 +299,8 @@ pub fn configure(cfg: &mut web::ServiceConfig) {
         .service(get_topic)
         .service(create_topic)
         .service(update_topic)
+        .service(list_topic_tags)
+        .service(watch_topic)
+        .service(unwatch_topic)
         .service(delete_topic);
 }
@@ -1,103 +1,133 @@
-use actix_web::{HttpResponse, Responder, delete, get, post, put, web};
+use actix_web::{HttpResponse, delete, get, post, put, web};
 use diesel::prelude::*;
 
 use crate::middleware::{AuthUser, ReadAuthUser};
 
+use crate::auth::generate_token;
+use crate::error::ApiError;
 use crate::jobs::{JobQueue, PropagateUsernameJob};
 use crate::models::{NewUser, UpdateUser, User};
+use crate::openapi::{
+    BadRequest, Conflict, Forbidden, NotFound, RateLimited, Unauthorized, Unavailable,
+    Unprocessable,
+};
 use crate::pagination::PaginationParams;
+use crate::routes::auth::AuthResponse;
 use crate::schema::users;
+use crate::services::{passwords, two_factor};
+use crate::validation::{ValidatedJson, validate_password};
 use crate::DbPool;
+use validator::Validate;
 
+#[derive(Debug, serde::Deserialize, Validate)]
+pub struct ChangePasswordRequest {
+    #[validate(length(min = 1))]
+    pub current_password: String,
+    #[validate(custom(function = "validate_password"))]
+    pub new_password: String,
+}
+
+#[derive(Debug, serde::Deserialize, Validate)]
+pub struct TwoFactorCodeRequest {
+    #[validate(length(min = 1, max = 16))]
+    pub code: String,
+}
+
+#[derive(Debug, serde::Deserialize, Validate)]
+pub struct DisableTwoFactorRequest {
+    #[validate(length(min = 1))]
+    pub password: String,
+}
+
+#[utoipa::path(
+    tag = "users",
+    responses(
+        (status = 200, description = "Users", body = Vec<User>),
+        Unauthorized,
+        RateLimited,
+        Unavailable,
+    )
+)]
 #[get("/users")]
 async fn list_users(
     pool: web::Data<DbPool>,
     pagination: web::Query<PaginationParams>,
     _auth: ReadAuthUser,
-) -> impl Responder {
-    let mut conn = match pool.get() {
-        Ok(conn) => conn,
-        Err(_) => return HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Failed to get database connection"
-        })),
-    };
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
 
     let per_page = pagination.per_page();
     let offset = pagination.offset();
 
-    let results = web::block(move || {
+    let users = web::block(move || {
         users::table
             .select(User::as_select())
             .limit(per_page)
             .offset(offset)
             .load(&mut conn)
     })
-    .await;
-
-    match results {
-        Ok(Ok(users)) => HttpResponse::Ok().json(users),
-        Ok(Err(_)) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Failed to load users"
-        })),
-        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Blocking error"
-        })),
-    }
+    .await??;
+
+    Ok(HttpResponse::Ok().json(users))
 }
 
+#[utoipa::path(
+    tag = "users",
+    responses(
+        (status = 200, description = "The user", body = User),
+        Unauthorized,
+        NotFound,
+        RateLimited,
+        Unavailable,
+    )
+)]
 #[get("/users/{id}")]
 async fn get_user(
     pool: web::Data<DbPool>,
     user_id: web::Path<i32>,
     _auth: ReadAuthUser,
-) -> impl Responder {
-    let mut conn = match pool.get() {
-        Ok(conn) => conn,
-        Err(_) => return HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Failed to get database connection"
-        })),
-    };
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
 
     let user_id = user_id.into_inner();
 
-    let result = web::block(move || {
+    let user = web::block(move || {
         users::table
             .find(user_id)
             .select(User::as_select())
             .first(&mut conn)
+            .optional()
     })
-    .await;
-
-    match result {
-        Ok(Ok(user)) => HttpResponse::Ok().json(user),
-        Ok(Err(diesel::NotFound)) => HttpResponse::NotFound().json(serde_json::json!({
-            "error": "User not found"
-        })),
-        Ok(Err(_)) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Failed to load user"
-        })),
-        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Blocking error"
-        })),
-    }
+    .await??
+    .ok_or_else(|| ApiError::not_found("User"))?;
+
+    Ok(HttpResponse::Ok().json(user))
 }
 
+#[utoipa::path(
+    tag = "users",
+    request_body = NewUser,
+    responses(
+        (status = 201, description = "Created", body = User),
+        BadRequest,
+        Unauthorized,
+        Conflict,
+        Unprocessable,
+        RateLimited,
+        Unavailable,
+    )
+)]
 #[post("/users")]
 async fn create_user(
     pool: web::Data<DbPool>,
     _auth: AuthUser,
-    new_user: web::Json<NewUser>,
-) -> impl Responder {
-    let mut conn = match pool.get() {
-        Ok(conn) => conn,
-        Err(_) => return HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Failed to get database connection"
-        })),
-    };
+    new_user: ValidatedJson<NewUser>,
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
 
     let new_user = new_user.into_inner();
 
-    let result = web::block(move || {
+    let user = web::block(move || {
         conn.transaction::<User, diesel::result::Error, _>(|conn| {
             let user: User = diesel::insert_into(users::table)
                 .values(&new_user)
@@ -107,33 +137,34 @@ async fn create_user(
             Ok(user)
         })
     })
-    .await;
-
-    match result {
-        Ok(Ok(user)) => HttpResponse::Created().json(user),
-        Ok(Err(_)) => HttpResponse::BadRequest().json(serde_json::json!({
-            "error": "Failed to create user"
-        })),
-        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Blocking error"
-        })),
-    }
+    .await??;
+
+    Ok(HttpResponse::Created().json(user))
 }
 
+#[utoipa::path(
+    tag = "users",
+    request_body = UpdateUser,
+    responses(
+        (status = 200, description = "Updated", body = User),
+        BadRequest,
+        Unauthorized,
+        NotFound,
+        Conflict,
+        Unprocessable,
+        RateLimited,
+        Unavailable,
+    )
+)]
 #[put("/users/{id}")]
 async fn update_user(
     pool: web::Data<DbPool>,
     job_queue: web::Data<JobQueue>,
     _auth: AuthUser,
     user_id: web::Path<i32>,
-    update_user: web::Json<UpdateUser>,
-) -> impl Responder {
-    let mut conn = match pool.get() {
-        Ok(conn) => conn,
-        Err(_) => return HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Failed to get database connection"
-        })),
-    };
+    update_user: ValidatedJson<UpdateUser>,
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
 
     let user_id_val = user_id.into_inner();
     let update_data = update_user.into_inner();
@@ -144,91 +175,258 @@ async fn update_user(
             .find(user_id_val)
             .select(users::username)
             .first(&mut conn)
-            .ok()
+            .optional()?
     } else {
         None
     };
 
     let new_username = update_data.username.clone();
 
-    let result = web::block(move || {
+    let user = web::block(move || {
         diesel::update(users::table.find(user_id_val))
             .set(&update_data)
             .returning(User::as_returning())
             .get_result(&mut conn)
+            .optional()
     })
-    .await;
-
-    match result {
-        Ok(Ok(user)) => {
-            // If username changed, enqueue propagation job
-            if let (Some(old), Some(new)) = (old_username, new_username) {
-                if old != new {
-                    let job = PropagateUsernameJob {
-                        user_id: user_id_val,
-                        old_username: old,
-                        new_username: new,
-                    };
-                    if let Err(e) = job_queue.enqueue(job) {
-                        log::error!("Failed to enqueue username propagation job: {}", e);
-                    }
-                }
-            }
-            HttpResponse::Ok().json(user)
+    .await??
+    .ok_or_else(|| ApiError::not_found("User"))?;
+
+    // If username changed, enqueue propagation job
+    if let (Some(old), Some(new)) = (old_username, new_username)
+        && old != new
+    {
+        let job = PropagateUsernameJob {
+            user_id: user_id_val,
+            old_username: old,
+            new_username: new,
+        };
+        if let Err(e) = job_queue.enqueue(job) {
+            log::error!("Failed to enqueue username propagation job: {}", e);
         }
-        Ok(Err(diesel::NotFound)) => HttpResponse::NotFound().json(serde_json::json!({
-            "error": "User not found"
-        })),
-        Ok(Err(_)) => HttpResponse::BadRequest().json(serde_json::json!({
-            "error": "Failed to update user"
-        })),
-        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Blocking error"
-        })),
     }
+    Ok(HttpResponse::Ok().json(user))
 }
 
+#[utoipa::path(
+    tag = "users",
+    responses(
+        (status = 204, description = "Deleted"),
+        Unauthorized,
+        NotFound,
+        RateLimited,
+        Unavailable,
+    )
+)]
 #[delete("/users/{id}")]
 async fn delete_user(
     pool: web::Data<DbPool>,
     user_id: web::Path<i32>,
     _auth: AuthUser,
-) -> impl Responder {
-    let mut conn = match pool.get() {
-        Ok(conn) => conn,
-        Err(_) => {
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to get database connection"
-            }))
-        }
-    };
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
 
     let user_id = user_id.into_inner();
 
-    let result = web::block(move || diesel::delete(users::table.find(user_id)).execute(&mut conn))
-        .await;
-
-    match result {
-        Ok(Ok(1)) => HttpResponse::NoContent().finish(),
-        Ok(Ok(0)) => HttpResponse::NotFound().json(serde_json::json!({
-            "error": "User not found"
-        })),
-        Ok(Ok(_)) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Multiple users deleted"
-        })),
-        Ok(Err(_)) => HttpResponse::BadRequest().json(serde_json::json!({
-            "error": "Failed to delete user"
-        })),
-        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Blocking error"
-        })),
+    let deleted =
+        web::block(move || diesel::delete(users::table.find(user_id)).execute(&mut conn)).await??;
+
+    match deleted {
+        0 => Err(ApiError::not_found("User")),
+        _ => Ok(HttpResponse::NoContent().finish()),
     }
 }
 
+/// Change the caller's password. Every other session is logged out; the
+/// response carries a fresh token for this one.
+#[utoipa::path(
+    tag = "users",
+    responses(
+        (status = 200, description = "A fresh session token"),
+        BadRequest,
+        Unauthorized,
+        Forbidden,
+        Unprocessable,
+        RateLimited,
+        Unavailable,
+    )
+)]
+#[put("/users/me/password")]
+async fn change_password(
+    pool: web::Data<DbPool>,
+    auth: AuthUser,
+    req: ValidatedJson<ChangePasswordRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
+
+    let user_id = auth.0.user_id;
+    let req = req.into_inner();
+    let user = web::block(move || {
+        passwords::change(&mut conn, user_id, &req.current_password, &req.new_password)
+    })
+    .await??;
+
+    let token = generate_token(user.id, user.username.clone(), user.token_version)?;
+    Ok(HttpResponse::Ok().json(AuthResponse {
+        token: Some(token),
+        user: user.into(),
+    }))
+}
+
+/// Whether the caller has 2FA on, and how many backup codes are left.
+#[utoipa::path(
+    tag = "users",
+    responses(
+        (status = 200, description = "Two-factor status"),
+        Unauthorized,
+        RateLimited,
+        Unavailable,
+    )
+)]
+#[get("/users/me/2fa")]
+async fn two_factor_status(
+    pool: web::Data<DbPool>,
+    auth: AuthUser,
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
+
+    let user_id = auth.0.user_id;
+    let (enabled, remaining) = web::block(move || {
+        let enabled: bool = users::table
+            .find(user_id)
+            .select(users::totp_enabled)
+            .first(&mut conn)?;
+        let remaining = two_factor::backup_codes_remaining(&mut conn, user_id)?;
+        Ok::<_, diesel::result::Error>((enabled, remaining))
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "enabled": enabled,
+        "backup_codes_remaining": remaining,
+    })))
+}
+
+/// Start TOTP enrollment: returns a new secret and its `otpauth://` URI.
+/// Nothing changes at login until the enrollment is confirmed.
+#[utoipa::path(
+    tag = "users",
+    responses(
+        (status = 200, description = "A new TOTP secret and its URI"),
+        Unauthorized,
+        Conflict,
+        RateLimited,
+        Unavailable,
+    )
+)]
+#[post("/users/me/2fa/totp")]
+async fn enroll_totp(pool: web::Data<DbPool>, auth: AuthUser) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
+
+    let user_id = auth.0.user_id;
+    let enrollment =
+        web::block(move || two_factor::begin_enrollment(&mut conn, user_id)).await??;
+
+    Ok(HttpResponse::Ok().json(enrollment))
+}
+
+/// Confirm enrollment with a code from the authenticator app, turning 2FA
+/// on. The response holds the backup codes, shown only this once.
+#[utoipa::path(
+    tag = "users",
+    responses(
+        (status = 200, description = "Enabled; the backup codes"),
+        BadRequest,
+        Unauthorized,
+        Conflict,
+        Unprocessable,
+        RateLimited,
+        Unavailable,
+    )
+)]
+#[post("/users/me/2fa/totp/enable")]
+async fn enable_totp(
+    pool: web::Data<DbPool>,
+    auth: AuthUser,
+    req: ValidatedJson<TwoFactorCodeRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
+
+    let user_id = auth.0.user_id;
+    let code = req.into_inner().code;
+    let backup_codes =
+        web::block(move || two_factor::enable(&mut conn, user_id, &code)).await??;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "backup_codes": backup_codes })))
+}
+
+/// Turn 2FA off. Needs the account password.
+#[utoipa::path(
+    tag = "users",
+    responses(
+        (status = 204, description = "Disabled"),
+        BadRequest,
+        Unauthorized,
+        Forbidden,
+        Unprocessable,
+        RateLimited,
+        Unavailable,
+    )
+)]
+#[post("/users/me/2fa/disable")]
+async fn disable_two_factor(
+    pool: web::Data<DbPool>,
+    auth: AuthUser,
+    req: ValidatedJson<DisableTwoFactorRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
+
+    let user_id = auth.0.user_id;
+    let password = req.into_inner().password;
+    web::block(move || two_factor::disable(&mut conn, user_id, &password)).await??;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Replace the caller's backup codes. Needs a current TOTP code.
+#[utoipa::path(
+    tag = "users",
+    responses(
+        (status = 200, description = "New backup codes"),
+        BadRequest,
+        Unauthorized,
+        Unprocessable,
+        RateLimited,
+        Unavailable,
+    )
+)]
+#[post("/users/me/2fa/backup_codes")]
+async fn regenerate_backup_codes(
+    pool: web::Data<DbPool>,
+    auth: AuthUser,
+    req: ValidatedJson<TwoFactorCodeRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
+
+    let user_id = auth.0.user_id;
+    let code = req.into_inner().code;
+    let backup_codes =
+        web::block(move || two_factor::regenerate_backup_codes(&mut conn, user_id, &code))
+            .await??;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "backup_codes": backup_codes })))
+}
+
 pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(list_users)
         .service(get_user)
         .service(create_user)
         .service(update_user)
-        .service(delete_user);
+        .service(delete_user)
+        .service(change_password)
+        .service(two_factor_status)
+        .service(enroll_totp)
+        .service(enable_totp)
+        .service(disable_two_factor)
+        .service(regenerate_backup_codes);
 }
@@ -0,0 +1,175 @@
+use actix_web::{HttpResponse, delete, get, post, put, web};
+use serde::Deserialize;
+use validator::Validate;
+
+use crate::DbPool;
+use crate::error::ApiError;
+use crate::guardian::AdminGuard;
+use crate::models::{WatchedWord, WatchedWordInput};
+use crate::openapi::{
+    BadRequest, Conflict, Forbidden, NotFound, RateLimited, Unauthorized, Unavailable,
+    Unprocessable,
+};
+use crate::services::watched_words::{self as watched_words_service, Matches};
+use crate::validation::ValidatedJson;
+
+/// GET /admin/watched_words
+#[utoipa::path(
+    tag = "admin",
+    responses(
+        (status = 200, description = "Watched words", body = Vec<WatchedWord>),
+        Unauthorized,
+        Forbidden,
+        RateLimited,
+        Unavailable,
+    )
+)]
+#[get("/admin/watched_words")]
+async fn list_watched_words(
+    pool: web::Data<DbPool>,
+    _guard: AdminGuard,
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
+
+    let words = web::block(move || watched_words_service::list(&mut conn)).await??;
+    Ok(HttpResponse::Ok().json(words))
+}
+
+/// POST /admin/watched_words
+///
+/// Watch a word. 409 if it's already watched with the same action.
+#[utoipa::path(
+    tag = "admin",
+    request_body = WatchedWordInput,
+    responses(
+        (status = 201, description = "Created", body = WatchedWord),
+        BadRequest,
+        Unauthorized,
+        Forbidden,
+        Conflict,
+        Unprocessable,
+        RateLimited,
+        Unavailable,
+    )
+)]
+#[post("/admin/watched_words")]
+async fn create_watched_word(
+    pool: web::Data<DbPool>,
+    _guard: AdminGuard,
+    input: ValidatedJson<WatchedWordInput>,
+) -> Result<HttpResponse, ApiError> {
+    let input = input.into_inner();
+    input.check().map_err(ApiError::Unprocessable)?;
+    let mut conn = pool.get()?;
+
+    let new = input.into_new_watched_word();
+    let word = web::block(move || watched_words_service::create(&mut conn, new)).await??;
+    Ok(HttpResponse::Created().json(word))
+}
+
+/// PUT /admin/watched_words/{id}
+#[utoipa::path(
+    tag = "admin",
+    request_body = WatchedWordInput,
+    responses(
+        (status = 200, description = "Updated", body = WatchedWord),
+        BadRequest,
+        Unauthorized,
+        Forbidden,
+        NotFound,
+        Conflict,
+        Unprocessable,
+        RateLimited,
+        Unavailable,
+    )
+)]
+#[put("/admin/watched_words/{id}")]
+async fn update_watched_word(
+    pool: web::Data<DbPool>,
+    _guard: AdminGuard,
+    id: web::Path<i32>,
+    input: ValidatedJson<WatchedWordInput>,
+) -> Result<HttpResponse, ApiError> {
+    let input = input.into_inner();
+    input.check().map_err(ApiError::Unprocessable)?;
+    let mut conn = pool.get()?;
+
+    let id = id.into_inner();
+    let new = input.into_new_watched_word();
+    let word = web::block(move || watched_words_service::update(&mut conn, id, new))
+        .await??
+        .ok_or_else(|| ApiError::not_found("Watched word"))?;
+    Ok(HttpResponse::Ok().json(word))
+}
+
+/// DELETE /admin/watched_words/{id}
+#[utoipa::path(
+    tag = "admin",
+    responses(
+        (status = 204, description = "Deleted"),
+        Unauthorized,
+        Forbidden,
+        NotFound,
+        RateLimited,
+        Unavailable,
+    )
+)]
+#[delete("/admin/watched_words/{id}")]
+async fn delete_watched_word(
+    pool: web::Data<DbPool>,
+    _guard: AdminGuard,
+    id: web::Path<i32>,
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
+
+    let id = id.into_inner();
+    if !web::block(move || watched_words_service::delete(&mut conn, id)).await?? {
+        return Err(ApiError::not_found("Watched word"));
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[derive(Debug, Deserialize, Validate)]
+struct TestWatchedWordsRequest {
+    #[validate(length(max = 100_000))]
+    text: String,
+}
+
+/// POST /admin/watched_words/test
+///
+/// What the current watched words would do to `text`: the words matched
+/// per action and the tags it would add. Nothing is saved.
+#[utoipa::path(
+    tag = "admin",
+    responses(
+        (status = 200, description = "What the watched words would do", body = Matches),
+        BadRequest,
+        Unauthorized,
+        Forbidden,
+        Unprocessable,
+        RateLimited,
+        Unavailable,
+    )
+)]
+#[post("/admin/watched_words/test")]
+async fn test_watched_words(
+    pool: web::Data<DbPool>,
+    _guard: AdminGuard,
+    req: ValidatedJson<TestWatchedWordsRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = pool.get()?;
+
+    let text = req.into_inner().text;
+    let matches =
+        web::block(move || watched_words_service::current(&mut conn).map(|set| set.matches(&text)))
+            .await??;
+    Ok(HttpResponse::Ok().json(matches))
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(test_watched_words)
+        .service(list_watched_words)
+        .service(create_watched_word)
+        .service(update_watched_word)
+        .service(delete_watched_word);
+}
@@ -14,6 +14,9 @@ diesel::table! {
         running_at -> Nullable<Timestamptz>,
         done_at -> Nullable<Timestamptz>,
         error -> Nullable<Text>,
+        dead_at -> Nullable<Timestamptz>,
+        queue_name -> Text,
+        priority -> Int4,
     }
 }
 
@@ -33,6 +36,64 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    custom_emojis (id) {
+        id -> Int4,
+        #[max_length = 50]
+        name -> Varchar,
+        url -> Text,
+        #[max_length = 50]
+        group_name -> Varchar,
+        user_id -> Nullable<Int4>,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    email_logs (id) {
+        id -> Int8,
+        user_id -> Nullable<Int4>,
+        to_address -> Text,
+        #[max_length = 50]
+        email_type -> Varchar,
+        subject -> Text,
+        message_id -> Text,
+        #[max_length = 20]
+        status -> Varchar,
+        error -> Nullable<Text>,
+        created_at -> Timestamptz,
+        bounced_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    email_tokens (id) {
+        id -> Int8,
+        user_id -> Int4,
+        token_hash -> Text,
+        #[max_length = 30]
+        purpose -> Varchar,
+        #[max_length = 254]
+        email -> Varchar,
+        expires_at -> Timestamptz,
+        consumed_at -> Nullable<Timestamptz>,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    link_previews (id) {
+        id -> Int4,
+        url -> Text,
+        title -> Nullable<Text>,
+        description -> Nullable<Text>,
+        image_url -> Nullable<Text>,
+        site_name -> Nullable<Text>,
+        error -> Nullable<Text>,
+        fetched_at -> Timestamptz,
+    }
+}
+
 diesel::table! {
     moderation_actions (id) {
         id -> Int8,
@@ -62,6 +123,18 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    post_flags (id) {
+        id -> Int4,
+        post_id -> Int4,
+        user_id -> Nullable<Int4>,
+        #[max_length = 50]
+        reason -> Varchar,
+        message -> Nullable<Text>,
+        created_at -> Timestamptz,
+    }
+}
+
 diesel::table! {
     post_likes (id) {
         id -> Int4,
@@ -71,6 +144,14 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    post_mentions (post_id, user_id) {
+        post_id -> Int4,
+        user_id -> Int4,
+        created_at -> Timestamptz,
+    }
+}
+
 diesel::table! {
     posts (id) {
         id -> Int4,
@@ -88,6 +169,26 @@ diesel::table! {
         hidden_by_user_id -> Nullable<Int4>,
         deleted_by_user_id -> Nullable<Int4>,
         like_count -> Int4,
+        cook_version -> Int4,
+    }
+}
+
+diesel::table! {
+    queued_posts (id) {
+        id -> Int4,
+        topic_id -> Int4,
+        user_id -> Int4,
+        raw -> Text,
+        reply_to_post_number -> Nullable<Int4>,
+        #[max_length = 20]
+        reason -> Varchar,
+        #[max_length = 20]
+        status -> Varchar,
+        post_id -> Nullable<Int4>,
+        reviewed_by_user_id -> Nullable<Int4>,
+        reviewed_at -> Nullable<Timestamptz>,
+        reject_reason -> Nullable<Text>,
+        created_at -> Timestamptz,
     }
 }
 
@@ -100,6 +201,15 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    topic_tags (topic_id, name) {
+        topic_id -> Int4,
+        #[max_length = 100]
+        name -> Varchar,
+        created_at -> Timestamptz,
+    }
+}
+
 diesel::table! {
     topic_views (id) {
         id -> Int4,
@@ -110,6 +220,14 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    topic_watches (topic_id, user_id) {
+        topic_id -> Int4,
+        user_id -> Int4,
+        created_at -> Timestamptz,
+    }
+}
+
 diesel::table! {
     topics (id) {
         id -> Int4,
@@ -131,6 +249,16 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    user_backup_codes (id) {
+        id -> Int8,
+        user_id -> Int4,
+        code_hash -> Text,
+        used_at -> Nullable<Timestamptz>,
+        created_at -> Timestamptz,
+    }
+}
+
 diesel::table! {
     user_stats (user_id) {
         user_id -> Int4,
@@ -173,33 +301,81 @@ diesel::table! {
         updated_at -> Timestamp,
         likes_given -> Int4,
         likes_received -> Int4,
+        email_confirmed -> Bool,
+        email_confirmed_at -> Nullable<Timestamptz>,
+        token_version -> Int4,
+        password_changed_at -> Nullable<Timestamptz>,
+        totp_secret -> Nullable<Text>,
+        totp_enabled -> Bool,
+        totp_last_used_step -> Nullable<Int8>,
+        totp_failed_attempts -> Int4,
+        totp_locked_until -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    watched_words (id) {
+        id -> Int4,
+        #[max_length = 100]
+        word -> Varchar,
+        #[max_length = 20]
+        action -> Varchar,
+        #[max_length = 100]
+        replacement -> Nullable<Varchar>,
+        regex -> Bool,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
     }
 }
 
+diesel::joinable!(custom_emojis -> users (user_id));
+diesel::joinable!(email_logs -> users (user_id));
+diesel::joinable!(email_tokens -> users (user_id));
 diesel::joinable!(moderation_actions -> posts (target_post_id));
 diesel::joinable!(moderation_actions -> topics (target_topic_id));
 diesel::joinable!(notifications -> posts (post_id));
 diesel::joinable!(notifications -> topics (topic_id));
+diesel::joinable!(post_flags -> posts (post_id));
+diesel::joinable!(post_flags -> users (user_id));
 diesel::joinable!(post_likes -> posts (post_id));
 diesel::joinable!(post_likes -> users (user_id));
+diesel::joinable!(post_mentions -> posts (post_id));
+diesel::joinable!(post_mentions -> users (user_id));
 diesel::joinable!(posts -> topics (topic_id));
+diesel::joinable!(queued_posts -> posts (post_id));
+diesel::joinable!(queued_posts -> topics (topic_id));
+diesel::joinable!(topic_tags -> topics (topic_id));
 diesel::joinable!(topic_views -> topics (topic_id));
 diesel::joinable!(topic_views -> users (user_id));
+diesel::joinable!(topic_watches -> topics (topic_id));
+diesel::joinable!(topic_watches -> users (user_id));
 diesel::joinable!(topics -> categories (category_id));
 diesel::joinable!(topics -> users (user_id));
+diesel::joinable!(user_backup_codes -> users (user_id));
 diesel::joinable!(user_stats -> users (user_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
     backie_tasks,
     categories,
+    custom_emojis,
+    email_logs,
+    email_tokens,
+    link_previews,
     moderation_actions,
     notifications,
+    post_flags,
     post_likes,
+    post_mentions,
     posts,
+    queued_posts,
     site_settings,
+    topic_tags,
     topic_views,
+    topic_watches,
     topics,
+    user_backup_codes,
     user_stats,
     user_suspensions,
     users,
+    watched_words,
 );
@@ -0,0 +1,221 @@
+//! Email confirmation for new accounts. Registration issues a token and
+//! the confirmation email carries it; presenting it marks the address
+//! confirmed. Until then, the `unconfirmed_user_access` site setting
+//! decides what the account can do.
+
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+
+use crate::models::User;
+use crate::schema::{site_settings, users};
+use crate::services::email_tokens::{self, CONFIRM_EMAIL};
+
+/// How long a confirmation link works.
+pub const CONFIRM_TOKEN_TTL_HOURS: i64 = 48;
+
+/// Minimum gap between confirmation emails to one account.
+pub const RESEND_INTERVAL_SECS: i64 = 60;
+
+/// Most confirmation emails one account can be sent in 24 hours,
+/// including the one sent at registration.
+pub const MAX_CONFIRMATIONS_PER_DAY: usize = 5;
+
+/// The `unconfirmed_user_access` site setting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnconfirmedAccess {
+    /// Unconfirmed accounts can't log in.
+    None,
+    /// They can log in and read, but not create topics or posts.
+    Read,
+    /// Confirmation is optional.
+    Full,
+}
+
+impl UnconfirmedAccess {
+    /// Unknown values fall back to `Read`, the default.
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "none" => UnconfirmedAccess::None,
+            "full" => UnconfirmedAccess::Full,
+            _ => UnconfirmedAccess::Read,
+        }
+    }
+
+    pub fn current(conn: &mut PgConnection) -> Result<Self, DieselError> {
+        let value = site_settings::table
+            .find("unconfirmed_user_access")
+            .select(site_settings::value)
+            .first::<String>(conn)
+            .optional()?;
+        Ok(value.map_or(UnconfirmedAccess::Read, |v| Self::parse(&v)))
+    }
+
+    pub fn can_log_in(self) -> bool {
+        self != UnconfirmedAccess::None
+    }
+
+    pub fn can_post(self) -> bool {
+        self == UnconfirmedAccess::Full
+    }
+}
+
+/// Whether `user_id` may use the API signed in right now. Checked on every
+/// request, so switching the setting to `none` also shuts out unconfirmed
+/// accounts that logged in before.
+pub fn can_log_in(conn: &mut PgConnection, user_id: i32) -> Result<bool, DieselError> {
+    let confirmed: bool = users::table
+        .find(user_id)
+        .select(users::email_confirmed)
+        .first(conn)?;
+    Ok(confirmed || UnconfirmedAccess::current(conn)?.can_log_in())
+}
+
+/// Whether `user_id` may create topics and posts right now.
+pub fn can_post(conn: &mut PgConnection, user_id: i32) -> Result<bool, DieselError> {
+    let confirmed: bool = users::table
+        .find(user_id)
+        .select(users::email_confirmed)
+        .first(conn)?;
+    Ok(confirmed || UnconfirmedAccess::current(conn)?.can_post())
+}
+
+/// Issue a confirmation token for `user`'s current address. The caller
+/// emails it.
+pub fn issue_token(conn: &mut PgConnection, user: &User) -> Result<String, DieselError> {
+    email_tokens::issue(
+        conn,
+        user.id,
+        &user.email,
+        CONFIRM_EMAIL,
+        Duration::hours(CONFIRM_TOKEN_TTL_HOURS),
+    )
+}
+
+#[derive(Debug)]
+pub enum ConfirmError {
+    /// Unknown, expired or already used, or issued for an address the
+    /// account no longer has.
+    InvalidToken,
+    Db(DieselError),
+}
+
+impl From<DieselError> for ConfirmError {
+    fn from(e: DieselError) -> Self {
+        ConfirmError::Db(e)
+    }
+}
+
+#[derive(Debug)]
+pub struct Confirmation {
+    pub user: User,
+    /// False if the account was already confirmed (e.g. by an earlier
+    /// link), so callers only act on the first confirmation.
+    pub newly_confirmed: bool,
+}
+
+/// Confirm the address a token was sent to. Uses the token up even if
+/// the account has since been confirmed another way.
+pub fn confirm(conn: &mut PgConnection, token: &str) -> Result<Confirmation, ConfirmError> {
+    conn.transaction(|conn| {
+        let token =
+            email_tokens::consume(conn, token, CONFIRM_EMAIL)?.ok_or(ConfirmError::InvalidToken)?;
+
+        let user: User = users::table
+            .find(token.user_id)
+            .select(User::as_select())
+            .for_update()
+            .first(conn)?;
+        if user.email != token.email {
+            return Err(ConfirmError::InvalidToken);
+        }
+        if user.email_confirmed {
+            return Ok(Confirmation {
+                user,
+                newly_confirmed: false,
+            });
+        }
+
+        let user = diesel::update(users::table.find(user.id))
+            .set((
+                users::email_confirmed.eq(true),
+                users::email_confirmed_at.eq(Some(Utc::now())),
+            ))
+            .returning(User::as_returning())
+            .get_result(conn)?;
+        Ok(Confirmation {
+            user,
+            newly_confirmed: true,
+        })
+    })
+}
+
+#[derive(Debug)]
+pub enum ResendOutcome {
+    /// A new token was issued; email it.
+    Issued { user: User, token: String },
+    /// No unconfirmed account has that address. Nothing to send.
+    NotNeeded,
+    /// Too many recent emails; try again in this many seconds.
+    RateLimited { retry_after_secs: i64 },
+}
+
+/// Issue a fresh confirmation token for the unconfirmed account with this
+/// email, unless one was sent too recently.
+pub fn resend(conn: &mut PgConnection, email: &str) -> Result<ResendOutcome, DieselError> {
+    conn.transaction(|conn| {
+        // Locking the user serializes concurrent resends, so both can't
+        // pass the rate limit.
+        let Some(user) = users::table
+            .filter(users::email.eq(email))
+            .filter(users::email_confirmed.eq(false))
+            .select(User::as_select())
+            .for_update()
+            .first(conn)
+            .optional()?
+        else {
+            return Ok(ResendOutcome::NotNeeded);
+        };
+
+        let now = Utc::now();
+        let recent =
+            email_tokens::issued_since(conn, user.id, CONFIRM_EMAIL, now - Duration::days(1))?;
+
+        if let Some(latest) = recent.first() {
+            let wait = (*latest + Duration::seconds(RESEND_INTERVAL_SECS) - now).num_seconds();
+            if wait > 0 {
+                return Ok(ResendOutcome::RateLimited {
+                    retry_after_secs: wait,
+                });
+            }
+        }
+        if recent.len() >= MAX_CONFIRMATIONS_PER_DAY {
+            // The oldest of the day's emails ages out first.
+            let oldest = recent[MAX_CONFIRMATIONS_PER_DAY - 1];
+            return Ok(ResendOutcome::RateLimited {
+                retry_after_secs: (oldest + Duration::days(1) - now).num_seconds().max(1),
+            });
+        }
+
+        let token = issue_token(conn, &user)?;
+        Ok(ResendOutcome::Issued { user, token })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn access_setting_parses_with_read_default() {
+        assert_eq!(UnconfirmedAccess::parse("none"), UnconfirmedAccess::None);
+        assert_eq!(UnconfirmedAccess::parse("read"), UnconfirmedAccess::Read);
+        assert_eq!(UnconfirmedAccess::parse("full"), UnconfirmedAccess::Full);
+        assert_eq!(UnconfirmedAccess::parse("bogus"), UnconfirmedAccess::Read);
+
+        assert!(!UnconfirmedAccess::None.can_log_in());
+        assert!(UnconfirmedAccess::Read.can_log_in());
+        assert!(!UnconfirmedAccess::Read.can_post());
+        assert!(UnconfirmedAccess::Full.can_post());
+    }
+}
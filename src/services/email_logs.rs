@@ -0,0 +1,80 @@
+//! Bookkeeping for `email_logs`: one row per delivery attempt, written by
+//! [`crate::mailer::deliver`], plus bounce reports matched back to the
+//! message they're about.
+
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+
+use crate::models::{EmailLog, NewEmailLog};
+use crate::schema::email_logs;
+
+pub fn record(conn: &mut PgConnection, log: &NewEmailLog) -> Result<EmailLog, DieselError> {
+    diesel::insert_into(email_logs::table)
+        .values(log)
+        .returning(EmailLog::as_returning())
+        .get_result(conn)
+}
+
+/// Mark the message with this `Message-ID` as bounced. Angle brackets
+/// are optional, since bounce reports usually quote the header verbatim.
+/// Returns `None` if we never sent that message. Recording the same
+/// bounce twice keeps the first reason.
+pub fn record_bounce(
+    conn: &mut PgConnection,
+    message_id: &str,
+    reason: &str,
+) -> Result<Option<EmailLog>, DieselError> {
+    let message_id = message_id
+        .trim()
+        .trim_start_matches('<')
+        .trim_end_matches('>');
+
+    conn.transaction(|conn| {
+        let Some(log) = email_logs::table
+            .filter(email_logs::message_id.eq(message_id))
+            .select(EmailLog::as_select())
+            .for_update()
+            .first(conn)
+            .optional()?
+        else {
+            return Ok(None);
+        };
+        if log.status == "bounced" {
+            return Ok(Some(log));
+        }
+
+        diesel::update(email_logs::table.find(log.id))
+            .set((
+                email_logs::status.eq("bounced"),
+                email_logs::error.eq(Some(reason)),
+                email_logs::bounced_at.eq(Some(chrono::Utc::now())),
+            ))
+            .returning(EmailLog::as_returning())
+            .get_result(conn)
+            .map(Some)
+    })
+}
+
+/// Newest first, optionally only one status (`sent`, `failed`, `bounced`)
+/// and/or one user.
+pub fn list(
+    conn: &mut PgConnection,
+    status: Option<&str>,
+    user_id: Option<i32>,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<EmailLog>, DieselError> {
+    let mut query = email_logs::table.into_boxed();
+    if let Some(status) = status {
+        query = query.filter(email_logs::status.eq(status.to_string()));
+    }
+    if let Some(user_id) = user_id {
+        query = query.filter(email_logs::user_id.eq(user_id));
+    }
+    query
+        .order((email_logs::created_at.desc(), email_logs::id.desc()))
+        .limit(limit)
+        .offset(offset)
+        .select(EmailLog::as_select())
+        .load(conn)
+}
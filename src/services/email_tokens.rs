@@ -0,0 +1,123 @@
+//! Single-use tokens delivered by email. The raw token goes in the link
+//! we send; only its SHA-256 is stored, and using a token marks it
+//! consumed so a link works once.
+
+use chrono::{DateTime, Duration, Utc};
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use rand::Rng;
+use sha2::{Digest, Sha256};
+
+use crate::models::{EmailToken, NewEmailToken};
+use crate::schema::email_tokens;
+
+/// `purpose` for email confirmation links.
+pub const CONFIRM_EMAIL: &str = "confirm_email";
+
+/// `purpose` for password reset links.
+pub const PASSWORD_RESET: &str = "password_reset";
+
+/// 32 random bytes, hex-encoded.
+pub fn generate() -> String {
+    let bytes: [u8; 32] = rand::rng().random();
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+pub fn hash(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// Store a new token for `user_id` and return the raw token to email.
+pub fn issue(
+    conn: &mut PgConnection,
+    user_id: i32,
+    email: &str,
+    purpose: &str,
+    ttl: Duration,
+) -> Result<String, DieselError> {
+    let token = generate();
+    diesel::insert_into(email_tokens::table)
+        .values(&NewEmailToken {
+            user_id,
+            token_hash: hash(&token),
+            purpose: purpose.to_string(),
+            email: email.to_string(),
+            expires_at: Utc::now() + ttl,
+        })
+        .execute(conn)?;
+    Ok(token)
+}
+
+/// Use up a token. `None` if it doesn't exist, is for another purpose,
+/// has expired or was already used. A single UPDATE, so two concurrent
+/// requests can't both consume the same token.
+pub fn consume(
+    conn: &mut PgConnection,
+    token: &str,
+    purpose: &str,
+) -> Result<Option<EmailToken>, DieselError> {
+    let now = Utc::now();
+    diesel::update(
+        email_tokens::table
+            .filter(email_tokens::token_hash.eq(hash(token)))
+            .filter(email_tokens::purpose.eq(purpose))
+            .filter(email_tokens::consumed_at.is_null())
+            .filter(email_tokens::expires_at.gt(now)),
+    )
+    .set(email_tokens::consumed_at.eq(Some(now)))
+    .returning(EmailToken::as_returning())
+    .get_result(conn)
+    .optional()
+}
+
+/// Use up every outstanding token `user_id` has for `purpose`, so older
+/// links stop working.
+pub fn revoke(conn: &mut PgConnection, user_id: i32, purpose: &str) -> Result<usize, DieselError> {
+    diesel::update(
+        email_tokens::table
+            .filter(email_tokens::user_id.eq(user_id))
+            .filter(email_tokens::purpose.eq(purpose))
+            .filter(email_tokens::consumed_at.is_null()),
+    )
+    .set(email_tokens::consumed_at.eq(Some(Utc::now())))
+    .execute(conn)
+}
+
+/// Creation times of `user_id`'s tokens for `purpose` since `since`,
+/// newest first. Used for rate limiting.
+pub fn issued_since(
+    conn: &mut PgConnection,
+    user_id: i32,
+    purpose: &str,
+    since: DateTime<Utc>,
+) -> Result<Vec<DateTime<Utc>>, DieselError> {
+    email_tokens::table
+        .filter(email_tokens::user_id.eq(user_id))
+        .filter(email_tokens::purpose.eq(purpose))
+        .filter(email_tokens::created_at.gt(since))
+        .order(email_tokens::created_at.desc())
+        .select(email_tokens::created_at)
+        .load(conn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_are_random_hex() {
+        let a = generate();
+        let b = generate();
+        assert_eq!(a.len(), 64);
+        assert!(a.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn hash_is_sha256_hex() {
+        assert_eq!(
+            hash("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
@@ -0,0 +1,101 @@
+//! Emoji for posts and pickers: the built-in set from [`crate::emoji`]
+//! plus custom emoji admins add to `custom_emojis`.
+
+use std::collections::HashMap;
+
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::emoji::{BUILTIN, GROUPS};
+use crate::models::{CustomEmoji, NewCustomEmoji};
+use crate::schema::custom_emojis;
+
+/// One emoji as a picker shows it. Built-in emoji have `unicode`, custom
+/// ones `url`. `name` is the stable key to store, e.g. for reactions.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct EmojiView {
+    pub name: String,
+    pub group: String,
+    pub aliases: Vec<String>,
+    pub unicode: Option<String>,
+    pub url: Option<String>,
+    pub custom: bool,
+}
+
+/// Everything a picker needs: group names in display order, then every
+/// emoji in picker order.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct EmojiList {
+    pub groups: Vec<String>,
+    pub emojis: Vec<EmojiView>,
+}
+
+/// The built-in set followed by custom emoji, oldest first. Groups are the
+/// built-in ones plus any extra groups custom emoji were filed under.
+pub fn list(conn: &mut PgConnection) -> Result<EmojiList, DieselError> {
+    let custom: Vec<CustomEmoji> = custom_emojis::table
+        .order(custom_emojis::id)
+        .select(CustomEmoji::as_select())
+        .load(conn)?;
+
+    let mut groups: Vec<String> = GROUPS.iter().map(|g| g.to_string()).collect();
+    for emoji in &custom {
+        if !groups.contains(&emoji.group_name) {
+            groups.push(emoji.group_name.clone());
+        }
+    }
+
+    let builtin = BUILTIN.iter().map(|e| EmojiView {
+        name: e.name.to_string(),
+        group: e.group.to_string(),
+        aliases: e.aliases.iter().map(|a| a.to_string()).collect(),
+        unicode: Some(e.unicode.to_string()),
+        url: None,
+        custom: false,
+    });
+    let custom = custom.into_iter().map(|e| EmojiView {
+        name: e.name,
+        group: e.group_name,
+        aliases: Vec::new(),
+        unicode: None,
+        url: Some(e.url),
+        custom: true,
+    });
+    Ok(EmojiList {
+        groups,
+        emojis: builtin.chain(custom).collect(),
+    })
+}
+
+/// The `RenderContext::custom_emoji` map for whichever of `names` (as
+/// returned by `markdown::extract_custom_emoji`) exist.
+pub fn render_map(
+    conn: &mut PgConnection,
+    names: &[String],
+) -> Result<HashMap<String, String>, DieselError> {
+    if names.is_empty() {
+        return Ok(HashMap::new());
+    }
+    let rows: Vec<(String, String)> = custom_emojis::table
+        .filter(custom_emojis::name.eq_any(names))
+        .select((custom_emojis::name, custom_emojis::url))
+        .load(conn)?;
+    Ok(rows.into_iter().collect())
+}
+
+pub fn create(conn: &mut PgConnection, new: NewCustomEmoji) -> Result<CustomEmoji, DieselError> {
+    diesel::insert_into(custom_emojis::table)
+        .values(&new)
+        .returning(CustomEmoji::as_returning())
+        .get_result(conn)
+}
+
+/// Remove the custom emoji called `name`. Posts already cooked with it
+/// keep the image until they're next cooked. Returns whether it existed.
+pub fn delete(conn: &mut PgConnection, name: &str) -> Result<bool, DieselError> {
+    let deleted =
+        diesel::delete(custom_emojis::table.filter(custom_emojis::name.eq(name))).execute(conn)?;
+    Ok(deleted > 0)
+}
@@ -0,0 +1,59 @@
+//! `#slug` hashtags: resolving them against categories and topic tags
+//! while a post is cooked. A tag matches when its name, lowercased, is the
+//! slug; when a category and a tag share a slug, the category wins.
+
+use std::collections::HashMap;
+
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+
+use crate::markdown::{Hashtag, HashtagKind};
+use crate::schema::{categories, topic_tags};
+use crate::services::mentions::lower;
+
+/// The `RenderContext::hashtags` map for whichever of `slugs` (already
+/// lowercased, as returned by `markdown::extract_hashtags`) name a
+/// category or a tag.
+pub fn render_map(
+    conn: &mut PgConnection,
+    slugs: &[String],
+) -> Result<HashMap<String, Hashtag>, DieselError> {
+    if slugs.is_empty() {
+        return Ok(HashMap::new());
+    }
+    let mut map = HashMap::new();
+
+    let tags: Vec<String> = topic_tags::table
+        .filter(lower(topic_tags::name).eq_any(slugs))
+        .select(topic_tags::name)
+        .distinct()
+        .order(topic_tags::name)
+        .load(conn)?;
+    for name in tags {
+        let slug = name.to_ascii_lowercase();
+        map.entry(slug.clone()).or_insert(Hashtag {
+            kind: HashtagKind::Tag,
+            slug,
+            name,
+            color: None,
+        });
+    }
+
+    // Inserted last, so categories replace tags with the same slug.
+    let categories: Vec<(String, String, String)> = categories::table
+        .filter(categories::slug.eq_any(slugs))
+        .select((categories::slug, categories::name, categories::color))
+        .load(conn)?;
+    for (slug, name, color) in categories {
+        map.insert(
+            slug.clone(),
+            Hashtag {
+                kind: HashtagKind::Category,
+                slug,
+                name,
+                color: Some(color),
+            },
+        );
+    }
+    Ok(map)
+}
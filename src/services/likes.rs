@@ -4,10 +4,10 @@
 
 use diesel::prelude::*;
 use diesel::result::Error as DieselError;
-use serde_json::json;
 
-use crate::models::{NewNotification, NewPostLike, PostLike};
-use crate::schema::{notifications, post_likes, posts, users};
+use crate::models::{NewPostLike, PostLike};
+use crate::schema::{post_likes, posts, users};
+use crate::services::notifications;
 
 #[derive(Debug)]
 pub enum LikeError {
@@ -60,12 +60,13 @@ pub fn like_post(
     conn.transaction::<LikeOutcome, LikeError, _>(|conn| {
         // Find the post and its author. Filter out hidden posts here so we
         // never create likes on invisible content.
-        let (author_id, deleted): (i32, Option<chrono::NaiveDateTime>) = posts::table
-            .filter(posts::id.eq(post_id))
-            .filter(posts::hidden.eq(false))
-            .select((posts::user_id, posts::deleted_at))
-            .first(conn)
-            .map_err(LikeError::from)?;
+        let (author_id, topic_id, deleted): (i32, i32, Option<chrono::NaiveDateTime>) =
+            posts::table
+                .filter(posts::id.eq(post_id))
+                .filter(posts::hidden.eq(false))
+                .select((posts::user_id, posts::topic_id, posts::deleted_at))
+                .first(conn)
+                .map_err(LikeError::from)?;
 
         if deleted.is_some() {
             return Err(LikeError::PostNotFound);
@@ -112,19 +113,9 @@ pub fn like_post(
             .execute(conn)
             .map_err(LikeError::from)?;
 
-        // Notify the post author. No consolidation in v1 — N likes produce
-        // N notifications. Consolidation can come later.
-        let notif = NewNotification {
-            user_id: author_id,
-            notification_type: "post_liked".to_string(),
-            data: json!({}),
-            topic_id: None,
-            post_id: Some(post_id),
-            acting_user_id: Some(user_id),
-        };
-        diesel::insert_into(notifications::table)
-            .values(&notif)
-            .execute(conn)
+        // Notify the post author, folding this into their unread like
+        // notification for the post if there's a recent one.
+        notifications::liked(conn, author_id, user_id, post_id, topic_id)
             .map_err(LikeError::from)?;
 
         Ok(LikeOutcome::Created(like))
@@ -165,10 +156,12 @@ pub fn unlike_post(
             return Ok(UnlikeOutcome::NothingToRemove);
         }
 
-        diesel::sql_query("UPDATE posts SET like_count = GREATEST(like_count - 1, 0) WHERE id = $1")
-            .bind::<diesel::sql_types::Integer, _>(post_id)
-            .execute(conn)
-            .map_err(LikeError::from)?;
+        diesel::sql_query(
+            "UPDATE posts SET like_count = GREATEST(like_count - 1, 0) WHERE id = $1",
+        )
+        .bind::<diesel::sql_types::Integer, _>(post_id)
+        .execute(conn)
+        .map_err(LikeError::from)?;
 
         diesel::sql_query(
             "UPDATE users SET likes_given = GREATEST(likes_given - 1, 0) WHERE id = $1",
@@ -184,6 +177,8 @@ pub fn unlike_post(
         .execute(conn)
         .map_err(LikeError::from)?;
 
+        notifications::unliked(conn, author_id, user_id, post_id).map_err(LikeError::from)?;
+
         Ok(UnlikeOutcome::Removed)
     })
 }
@@ -0,0 +1,82 @@
+//! The `link_previews` cache: what cooking reads to render oneboxes, and
+//! what `FetchLinkPreviewsJob` writes after a lookup.
+
+use std::collections::HashMap;
+
+use chrono::Utc;
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+
+use crate::markdown::Onebox;
+use crate::models::{LinkPreview, NewLinkPreview};
+use crate::onebox::FetchError;
+use crate::schema::link_previews;
+
+/// Cached previews (and failures) older than this are fetched again.
+pub const LINK_PREVIEW_TTL_DAYS: i64 = 7;
+
+/// Previews to render for `urls`: every successful lookup we have, however
+/// old, so a post never loses its cards while a refresh is pending.
+pub fn oneboxes(
+    conn: &mut PgConnection,
+    urls: &[String],
+) -> Result<HashMap<String, Onebox>, DieselError> {
+    if urls.is_empty() {
+        return Ok(HashMap::new());
+    }
+    let rows: Vec<LinkPreview> = link_previews::table
+        .filter(link_previews::url.eq_any(urls))
+        .filter(link_previews::error.is_null())
+        .select(LinkPreview::as_select())
+        .load(conn)?;
+    Ok(rows
+        .into_iter()
+        .filter_map(|row| row.onebox().map(|onebox| (row.url, onebox)))
+        .collect())
+}
+
+/// The subset of `urls` with no lookup inside the refresh window.
+pub fn stale(conn: &mut PgConnection, urls: &[String]) -> Result<Vec<String>, DieselError> {
+    if urls.is_empty() {
+        return Ok(Vec::new());
+    }
+    let cutoff = Utc::now() - chrono::Duration::days(LINK_PREVIEW_TTL_DAYS);
+    let fresh: Vec<String> = link_previews::table
+        .filter(link_previews::url.eq_any(urls))
+        .filter(link_previews::fetched_at.gt(cutoff))
+        .select(link_previews::url)
+        .load(conn)?;
+    Ok(urls
+        .iter()
+        .filter(|url| !fresh.contains(url))
+        .cloned()
+        .collect())
+}
+
+/// Store the outcome of looking up `url`, replacing any earlier one.
+pub fn save(
+    conn: &mut PgConnection,
+    url: &str,
+    outcome: &Result<Onebox, FetchError>,
+) -> Result<LinkPreview, DieselError> {
+    let (onebox, error) = match outcome {
+        Ok(onebox) => (onebox.clone(), None),
+        Err(e) => (Onebox::default(), Some(e.to_string())),
+    };
+    let row = NewLinkPreview {
+        url: url.to_string(),
+        title: onebox.title,
+        description: onebox.description,
+        image_url: onebox.image_url,
+        site_name: onebox.site_name,
+        error,
+        fetched_at: Utc::now(),
+    };
+    diesel::insert_into(link_previews::table)
+        .values(&row)
+        .on_conflict(link_previews::url)
+        .do_update()
+        .set(&row)
+        .returning(LinkPreview::as_returning())
+        .get_result(conn)
+}
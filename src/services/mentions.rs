@@ -0,0 +1,134 @@
+//! `@username` mentions: resolving them while a post is cooked, and
+//! recording who each post mentions so only new mentions notify.
+
+use std::collections::{HashMap, HashSet};
+
+use chrono::Utc;
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use diesel::sql_types::Text;
+
+use crate::markdown;
+use crate::models::{NewPostMention, Post};
+use crate::schema::{post_mentions, posts, users};
+use crate::services::posts as posts_service;
+
+sql_function!(fn lower(x: Text) -> Text);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MentionedUser {
+    pub id: i32,
+    pub username: String,
+}
+
+/// Look up the users named by `names` (already lowercased, as returned by
+/// `markdown::extract_mentions`). Names that match nobody are dropped.
+pub fn resolve(
+    conn: &mut PgConnection,
+    names: &[String],
+) -> Result<Vec<MentionedUser>, DieselError> {
+    if names.is_empty() {
+        return Ok(Vec::new());
+    }
+    let rows: Vec<(i32, String)> = users::table
+        .filter(lower(users::username).eq_any(names))
+        .select((users::id, users::username))
+        .load(conn)?;
+    Ok(rows
+        .into_iter()
+        .map(|(id, username)| MentionedUser { id, username })
+        .collect())
+}
+
+/// The `RenderContext::mentions` map for these users.
+pub fn render_map(mentioned: &[MentionedUser]) -> HashMap<String, String> {
+    mentioned
+        .iter()
+        .map(|u| (u.username.to_ascii_lowercase(), u.username.clone()))
+        .collect()
+}
+
+/// Make `post_mentions` match `mentioned` for this post. Returns the ids
+/// of users it didn't mention before, author aside: the ones to notify,
+/// so editing a post doesn't re-notify.
+pub fn record(
+    conn: &mut PgConnection,
+    post: &Post,
+    mentioned: &[MentionedUser],
+) -> Result<Vec<i32>, DieselError> {
+    let previous: HashSet<i32> = post_mentions::table
+        .filter(post_mentions::post_id.eq(post.id))
+        .select(post_mentions::user_id)
+        .load::<i32>(conn)?
+        .into_iter()
+        .collect();
+    let current: HashSet<i32> = mentioned.iter().map(|u| u.id).collect();
+
+    let removed: Vec<i32> = previous.difference(&current).copied().collect();
+    if !removed.is_empty() {
+        diesel::delete(
+            post_mentions::table
+                .filter(post_mentions::post_id.eq(post.id))
+                .filter(post_mentions::user_id.eq_any(&removed)),
+        )
+        .execute(conn)?;
+    }
+
+    let added: Vec<i32> = mentioned
+        .iter()
+        .map(|u| u.id)
+        .filter(|id| !previous.contains(id))
+        .collect();
+    if added.is_empty() {
+        return Ok(Vec::new());
+    }
+    let rows: Vec<NewPostMention> = added
+        .iter()
+        .map(|&user_id| NewPostMention {
+            post_id: post.id,
+            user_id,
+        })
+        .collect();
+    diesel::insert_into(post_mentions::table)
+        .values(&rows)
+        .on_conflict_do_nothing()
+        .execute(conn)?;
+
+    Ok(added.into_iter().filter(|&id| id != post.user_id).collect())
+}
+
+/// Rewrite `@old` to `@new` in every post that mentions `user_id`, and
+/// re-cook them. Only posts recorded in `post_mentions` are touched, and
+/// only exact mention tokens within them, so `@bobby` survives `bob` being
+/// renamed. Returns the number of posts changed.
+pub fn propagate_rename(
+    conn: &mut PgConnection,
+    user_id: i32,
+    old_username: &str,
+    new_username: &str,
+) -> Result<usize, DieselError> {
+    let mentioning: Vec<(i32, String, i32)> = posts::table
+        .inner_join(post_mentions::table)
+        .filter(post_mentions::user_id.eq(user_id))
+        .select((posts::id, posts::raw, posts::user_id))
+        .load(conn)?;
+
+    let mut changed = 0;
+    for (post_id, raw, author_id) in mentioning {
+        let new_raw = markdown::replace_mention(&raw, old_username, new_username);
+        if new_raw == raw {
+            continue;
+        }
+        let cooked = posts_service::cook(conn, &new_raw, author_id)?.html;
+        diesel::update(posts::table.find(post_id))
+            .set((
+                posts::raw.eq(&new_raw),
+                posts::cooked.eq(&cooked),
+                posts::cook_version.eq(markdown::COOK_VERSION),
+                posts::updated_at.eq(Utc::now().naive_utc()),
+            ))
+            .execute(conn)?;
+        changed += 1;
+    }
+    Ok(changed)
+}
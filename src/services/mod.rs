@@ -6,7 +6,21 @@
 //! Why split this out: services are easy to test against a real DB without
 //! spinning up actix, and the same logic is reusable from background jobs.
 
+pub mod email_confirmation;
+pub mod email_logs;
+pub mod email_tokens;
+pub mod emoji;
+pub mod hashtags;
 pub mod likes;
+pub mod link_previews;
+pub mod mentions;
+pub mod notifications;
+pub mod passwords;
+pub mod posts;
+pub mod queued_posts;
+pub mod quotes;
 pub mod reads;
 pub mod trust_levels;
+pub mod two_factor;
 pub mod user_stats;
+pub mod watched_words;
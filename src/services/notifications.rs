@@ -0,0 +1,326 @@
+//! Creating notifications. Everything that tells a user something goes
+//! through here, so each event has one notification type and one data
+//! shape.
+//!
+//! When a post is saved, each user gets at most one notification for it,
+//! the first that applies of `mentioned`, `quoted`, `replied` and
+//! `posted`. Likes are consolidated: another like on a post whose author
+//! hasn't read the last like notification yet updates that row ("alice
+//! and 5 others liked your post") instead of adding one, and unliking
+//! takes the liker back out.
+
+use std::collections::HashSet;
+
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use serde_json::json;
+
+use crate::models::{NewNotification, Post};
+use crate::moderation::NewModerationAction;
+use crate::schema::{notifications, posts, topic_watches, topics, users};
+use crate::services::quotes::QuotedPost;
+
+pub const MENTIONED: &str = "mentioned";
+pub const QUOTED: &str = "quoted";
+pub const REPLIED: &str = "replied";
+pub const POSTED: &str = "posted";
+pub const LIKED: &str = "post_liked";
+pub const GRANTED_BADGE: &str = "granted_badge";
+pub const MODERATOR_ACTION: &str = "moderator_action";
+pub const QUEUED_POST_APPROVED: &str = "queued_post_approved";
+pub const QUEUED_POST_REJECTED: &str = "queued_post_rejected";
+
+/// How long an unread like notification keeps absorbing new likes on the
+/// same post.
+pub const LIKE_CONSOLIDATION_WINDOW_HOURS: i64 = 6;
+
+pub fn create(conn: &mut PgConnection, notification: &NewNotification) -> Result<(), DieselError> {
+    diesel::insert_into(notifications::table)
+        .values(notification)
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Notify everyone a just-saved post concerns:
+///
+/// - `mentioned`: users in `newly_mentioned` (as returned by
+///   `mentions::record`)
+/// - `quoted`: authors of `quoted` posts, once per post even across edits
+/// - `replied`: for new posts, the author of the post replied to, or the
+///   topic's creator if it isn't a reply to a particular post
+/// - `posted`: for new posts, everyone watching the topic
+///
+/// Authors aren't notified about their own posts. Returns the ids of the
+/// users notified.
+pub fn post_saved(
+    conn: &mut PgConnection,
+    post: &Post,
+    newly_mentioned: &[i32],
+    quoted: &[QuotedPost],
+    is_new: bool,
+) -> Result<Vec<i32>, DieselError> {
+    let mut notified: HashSet<i32> = HashSet::from([post.user_id]);
+    let mut rows: Vec<NewNotification> = Vec::new();
+    let mut add = |user_id: i32, kind: &str, data: serde_json::Value| {
+        if notified.insert(user_id) {
+            rows.push(NewNotification {
+                user_id,
+                notification_type: kind.to_string(),
+                data,
+                topic_id: Some(post.topic_id),
+                post_id: Some(post.id),
+                acting_user_id: Some(post.user_id),
+            });
+        }
+    };
+
+    for &user_id in newly_mentioned {
+        add(
+            user_id,
+            MENTIONED,
+            json!({ "post_number": post.post_number }),
+        );
+    }
+
+    let already_quoted: HashSet<i32> = notifications::table
+        .filter(notifications::notification_type.eq(QUOTED))
+        .filter(notifications::post_id.eq(post.id))
+        .select(notifications::user_id)
+        .load::<i32>(conn)?
+        .into_iter()
+        .collect();
+    for q in quoted
+        .iter()
+        .filter(|q| !already_quoted.contains(&q.user_id))
+    {
+        add(
+            q.user_id,
+            QUOTED,
+            json!({ "post_number": post.post_number, "quoted_post_id": q.post_id }),
+        );
+    }
+
+    if is_new {
+        if let Some(user_id) = replied_to(conn, post)? {
+            add(
+                user_id,
+                REPLIED,
+                json!({
+                    "post_number": post.post_number,
+                    "reply_to_post_number": post.reply_to_post_number,
+                }),
+            );
+        }
+        let watchers: Vec<i32> = topic_watches::table
+            .filter(topic_watches::topic_id.eq(post.topic_id))
+            .order(topic_watches::user_id)
+            .select(topic_watches::user_id)
+            .load(conn)?;
+        for user_id in watchers {
+            add(user_id, POSTED, json!({ "post_number": post.post_number }));
+        }
+    }
+
+    let ids = rows.iter().map(|n| n.user_id).collect();
+    if !rows.is_empty() {
+        diesel::insert_into(notifications::table)
+            .values(&rows)
+            .execute(conn)?;
+    }
+    Ok(ids)
+}
+
+/// Whose post `post` answers: the author of the post it replies to, or the
+/// topic's creator for any post after the first.
+fn replied_to(conn: &mut PgConnection, post: &Post) -> Result<Option<i32>, DieselError> {
+    match post.reply_to_post_number {
+        Some(number) => posts::table
+            .filter(posts::topic_id.eq(post.topic_id))
+            .filter(posts::post_number.eq(number))
+            .filter(posts::deleted_at.is_null())
+            .select(posts::user_id)
+            .first(conn)
+            .optional(),
+        None if post.post_number > 1 => topics::table
+            .find(post.topic_id)
+            .select(topics::user_id)
+            .first(conn)
+            .optional(),
+        None => Ok(None),
+    }
+}
+
+/// Tell `author_id` that `liker_id` liked their post. While the last like
+/// notification for the post is unread and recent, it's updated to add the
+/// liker and name them as the latest; otherwise a new one starts with just
+/// them. The data keeps the likers' ids, so liking again after unliking
+/// doesn't count twice.
+pub fn liked(
+    conn: &mut PgConnection,
+    author_id: i32,
+    liker_id: i32,
+    post_id: i32,
+    topic_id: i32,
+) -> Result<(), DieselError> {
+    match unread_like(conn, author_id, post_id)? {
+        Some((id, mut likers)) => {
+            likers.retain(|&user_id| user_id != liker_id);
+            likers.push(liker_id);
+            let data = like_data(conn, &likers)?;
+            diesel::update(notifications::table.find(id))
+                .set((
+                    notifications::data.eq(data),
+                    notifications::acting_user_id.eq(liker_id),
+                    notifications::created_at.eq(Utc::now()),
+                ))
+                .execute(conn)?;
+            Ok(())
+        }
+        None => {
+            let data = like_data(conn, &[liker_id])?;
+            create(
+                conn,
+                &NewNotification {
+                    user_id: author_id,
+                    notification_type: LIKED.to_string(),
+                    data,
+                    topic_id: Some(topic_id),
+                    post_id: Some(post_id),
+                    acting_user_id: Some(liker_id),
+                },
+            )
+        }
+    }
+}
+
+/// Take `liker_id` back out of the unread like notification for the post,
+/// if they're in it. The notification goes away with its last liker.
+pub fn unliked(
+    conn: &mut PgConnection,
+    author_id: i32,
+    liker_id: i32,
+    post_id: i32,
+) -> Result<(), DieselError> {
+    let Some((id, mut likers)) = unread_like(conn, author_id, post_id)? else {
+        return Ok(());
+    };
+    if !likers.contains(&liker_id) {
+        return Ok(());
+    }
+    likers.retain(|&user_id| user_id != liker_id);
+    if likers.is_empty() {
+        diesel::delete(notifications::table.find(id)).execute(conn)?;
+        return Ok(());
+    }
+    let data = like_data(conn, &likers)?;
+    diesel::update(notifications::table.find(id))
+        .set((
+            notifications::data.eq(data),
+            notifications::acting_user_id.eq(likers.last().copied()),
+        ))
+        .execute(conn)?;
+    Ok(())
+}
+
+/// The id and likers (oldest first) of the author's unread, recent like
+/// notification for a post, locked for update.
+fn unread_like(
+    conn: &mut PgConnection,
+    author_id: i32,
+    post_id: i32,
+) -> Result<Option<(i64, Vec<i32>)>, DieselError> {
+    let since = Utc::now() - Duration::hours(LIKE_CONSOLIDATION_WINDOW_HOURS);
+    let unread: Option<(i64, serde_json::Value)> = notifications::table
+        .filter(notifications::user_id.eq(author_id))
+        .filter(notifications::notification_type.eq(LIKED))
+        .filter(notifications::post_id.eq(post_id))
+        .filter(notifications::read.eq(false))
+        .filter(notifications::created_at.gt(since))
+        .order(notifications::id.desc())
+        .select((notifications::id, notifications::data))
+        .for_update()
+        .first(conn)
+        .optional()?;
+    Ok(unread.map(|(id, data)| {
+        let likers = data["user_ids"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|id| id.as_i64()?.try_into().ok())
+            .collect();
+        (id, likers)
+    }))
+}
+
+/// `{"username": <latest liker>, "count": <likers>, "user_ids": [...]}`.
+/// `likers` is never empty.
+fn like_data(conn: &mut PgConnection, likers: &[i32]) -> Result<serde_json::Value, DieselError> {
+    let latest = likers.last().copied().unwrap_or_default();
+    let username: String = users::table
+        .find(latest)
+        .select(users::username)
+        .first(conn)?;
+    Ok(json!({ "username": username, "count": likers.len(), "user_ids": likers }))
+}
+
+/// Tell `user_id` they were granted the badge `badge`.
+pub fn granted_badge(
+    conn: &mut PgConnection,
+    user_id: i32,
+    badge: &str,
+) -> Result<(), DieselError> {
+    create(
+        conn,
+        &NewNotification {
+            user_id,
+            notification_type: GRANTED_BADGE.to_string(),
+            data: json!({ "badge_name": badge }),
+            topic_id: None,
+            post_id: None,
+            acting_user_id: None,
+        },
+    )
+}
+
+/// Tell `user_id` a moderator acted on them or their content.
+pub fn moderator_action(
+    conn: &mut PgConnection,
+    user_id: i32,
+    action: &NewModerationAction,
+) -> Result<(), DieselError> {
+    create(
+        conn,
+        &NewNotification {
+            user_id,
+            notification_type: MODERATOR_ACTION.to_string(),
+            data: json!({ "action": action.action_type, "details": action.details }),
+            topic_id: action.target_topic_id,
+            post_id: action.target_post_id,
+            acting_user_id: Some(action.moderator_id),
+        },
+    )
+}
+
+/// Start watching a topic. Watching it already is fine.
+pub fn watch(conn: &mut PgConnection, topic_id: i32, user_id: i32) -> Result<(), DieselError> {
+    diesel::insert_into(topic_watches::table)
+        .values((
+            topic_watches::topic_id.eq(topic_id),
+            topic_watches::user_id.eq(user_id),
+        ))
+        .on_conflict_do_nothing()
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Stop watching a topic. Returns whether the user was watching it.
+pub fn unwatch(conn: &mut PgConnection, topic_id: i32, user_id: i32) -> Result<bool, DieselError> {
+    let deleted = diesel::delete(
+        topic_watches::table
+            .filter(topic_watches::topic_id.eq(topic_id))
+            .filter(topic_watches::user_id.eq(user_id)),
+    )
+    .execute(conn)?;
+    Ok(deleted > 0)
+}
@@ -0,0 +1,163 @@
+//! Password reset and change. A reset is requested by username or email
+//! and completed with the single-use token from the reset email; a change
+//! needs the current password. Either way the user's `token_version` is
+//! bumped, which invalidates every JWT issued before.
+
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+
+use crate::auth::{Claims, hash_password, verify_password};
+use crate::models::User;
+use crate::schema::users;
+use crate::services::email_tokens::{self, PASSWORD_RESET};
+
+/// How long a reset link works.
+pub const RESET_TOKEN_TTL_MINUTES: i64 = 60;
+
+/// Minimum gap between reset emails to one account.
+pub const RESET_INTERVAL_SECS: i64 = 60;
+
+/// Most reset emails one account can be sent in 24 hours.
+pub const MAX_RESETS_PER_DAY: usize = 5;
+
+#[derive(Debug)]
+pub enum PasswordError {
+    /// Unknown, expired or already used reset token.
+    InvalidToken,
+    /// The current password given for a change is wrong.
+    WrongPassword,
+    /// bcrypt failed.
+    Hash(bcrypt::BcryptError),
+    Db(DieselError),
+}
+
+impl From<DieselError> for PasswordError {
+    fn from(e: DieselError) -> Self {
+        PasswordError::Db(e)
+    }
+}
+
+impl From<bcrypt::BcryptError> for PasswordError {
+    fn from(e: bcrypt::BcryptError) -> Self {
+        PasswordError::Hash(e)
+    }
+}
+
+/// Whether `claims` were minted under the user's current `token_version`.
+/// False for deleted users too.
+pub fn token_is_current(conn: &mut PgConnection, claims: &Claims) -> Result<bool, DieselError> {
+    let version: Option<i32> = users::table
+        .find(claims.user_id)
+        .select(users::token_version)
+        .first(conn)
+        .optional()?;
+    Ok(version == Some(claims.token_version))
+}
+
+/// Issue a reset token for the account whose username or email is
+/// `login`. `None` when there's no such account or it was sent a reset
+/// email too recently; the caller answers the same either way, so the
+/// endpoint doesn't reveal which accounts exist.
+pub fn request_reset(
+    conn: &mut PgConnection,
+    login: &str,
+) -> Result<Option<(User, String)>, DieselError> {
+    conn.transaction(|conn| {
+        // Locking the user serializes concurrent requests, so both can't
+        // pass the rate limit.
+        let Some(user) = users::table
+            .filter(users::username.eq(login).or(users::email.eq(login)))
+            .select(User::as_select())
+            .for_update()
+            .first(conn)
+            .optional()?
+        else {
+            return Ok(None);
+        };
+
+        let now = Utc::now();
+        let recent =
+            email_tokens::issued_since(conn, user.id, PASSWORD_RESET, now - Duration::days(1))?;
+        let too_soon = recent
+            .first()
+            .is_some_and(|latest| *latest + Duration::seconds(RESET_INTERVAL_SECS) > now);
+        if too_soon || recent.len() >= MAX_RESETS_PER_DAY {
+            return Ok(None);
+        }
+
+        let token = email_tokens::issue(
+            conn,
+            user.id,
+            &user.email,
+            PASSWORD_RESET,
+            Duration::minutes(RESET_TOKEN_TTL_MINUTES),
+        )?;
+        Ok(Some((user, token)))
+    })
+}
+
+/// Set a new password with a reset token. The token is used up, along
+/// with any other outstanding reset links for the account. Following a
+/// link sent to the account's current address proves the user owns it,
+/// so this also confirms the email.
+pub fn reset(
+    conn: &mut PgConnection,
+    token: &str,
+    new_password: &str,
+) -> Result<User, PasswordError> {
+    let password_hash = hash_password(new_password)?;
+    conn.transaction(|conn| {
+        let token = email_tokens::consume(conn, token, PASSWORD_RESET)?
+            .ok_or(PasswordError::InvalidToken)?;
+
+        email_tokens::revoke(conn, token.user_id, PASSWORD_RESET)?;
+
+        let user = set_password(conn, token.user_id, &password_hash)?;
+        if user.email == token.email && !user.email_confirmed {
+            return Ok(diesel::update(users::table.find(user.id))
+                .set((
+                    users::email_confirmed.eq(true),
+                    users::email_confirmed_at.eq(Some(Utc::now())),
+                ))
+                .returning(User::as_returning())
+                .get_result(conn)?);
+        }
+        Ok(user)
+    })
+}
+
+/// Change `user_id`'s password after checking `current_password`.
+pub fn change(
+    conn: &mut PgConnection,
+    user_id: i32,
+    current_password: &str,
+    new_password: &str,
+) -> Result<User, PasswordError> {
+    let current_hash: String = users::table
+        .find(user_id)
+        .select(users::password_hash)
+        .first(conn)?;
+    if !verify_password(current_password, &current_hash)? {
+        return Err(PasswordError::WrongPassword);
+    }
+    let password_hash = hash_password(new_password)?;
+    Ok(set_password(conn, user_id, &password_hash)?)
+}
+
+/// Store the new hash and bump `token_version`, logging out every session.
+fn set_password(
+    conn: &mut PgConnection,
+    user_id: i32,
+    password_hash: &str,
+) -> Result<User, DieselError> {
+    diesel::update(users::table.find(user_id))
+        .set((
+            users::password_hash.eq(password_hash),
+            users::token_version.eq(users::token_version + 1),
+            users::password_changed_at.eq(Some(Utc::now())),
+            users::updated_at.eq(Utc::now().naive_utc()),
+        ))
+        .returning(User::as_returning())
+        .get_result(conn)
+}
@@ -0,0 +1,229 @@
+//! Creating and editing posts. Route handlers (and anything else that
+//! writes posts on a user's behalf) go through here so cooking, counters
+//! and mention bookkeeping happen the same way every time.
+
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+
+use crate::guardian::TRUST_LEVEL_REGULAR;
+use crate::mailer;
+use crate::markdown::{self, COOK_VERSION, Cooker, RenderContext};
+use crate::models::{CreatePostInput, Post, UpdatePostInput};
+use crate::schema::{posts, users};
+use crate::services::mentions::{self, MentionedUser};
+use crate::services::quotes::{self, QuotedPost};
+use crate::services::watched_words::{self, ContentError};
+use crate::services::{emoji, hashtags, link_previews, notifications, user_stats};
+
+/// A post's cooked HTML plus what cooking resolved, for the bookkeeping
+/// that follows a save.
+#[derive(Debug)]
+pub struct Cooked {
+    pub html: String,
+    pub mentioned: Vec<MentionedUser>,
+    pub quoted: Vec<QuotedPost>,
+}
+
+/// Below this trust level (staff aside) an author's external links are
+/// marked `rel="nofollow ugc"`.
+pub const FOLLOW_LINKS_TRUST_LEVEL: i32 = TRUST_LEVEL_REGULAR;
+
+/// Render `raw`, written by `user_id`, through the standard [`Cooker`],
+/// resolving its mentions, hashtags, custom emoji, quotes, link previews
+/// and watched words against the database.
+pub fn cook(conn: &mut PgConnection, raw: &str, user_id: i32) -> Result<Cooked, DieselError> {
+    let author: Option<(i32, bool, bool)> = users::table
+        .find(user_id)
+        .select((users::trust_level, users::admin, users::moderator))
+        .first(conn)
+        .optional()?;
+    let trusted = author.is_some_and(|(trust_level, admin, moderator)| {
+        admin || moderator || trust_level >= FOLLOW_LINKS_TRUST_LEVEL
+    });
+    let mentioned = mentions::resolve(conn, &markdown::extract_mentions(raw))?;
+    let quoted = quotes::resolve(conn, &markdown::extract_quotes(raw))?;
+    let watched = watched_words::current(conn)?;
+    let ctx = RenderContext {
+        mentions: mentions::render_map(&mentioned),
+        hashtags: hashtags::render_map(conn, &markdown::extract_hashtags(raw))?,
+        custom_emoji: emoji::render_map(conn, &markdown::extract_custom_emoji(raw))?,
+        quotes: quoted.iter().map(|q| (q.topic_id, q.post_number)).collect(),
+        oneboxes: link_previews::oneboxes(conn, &markdown::extract_onebox_urls(raw))?,
+        censor: watched.censor.clone(),
+        replace: watched.replace.clone(),
+        nofollow: !trusted,
+        base_url: Some(mailer::base_url()),
+    };
+    Ok(Cooked {
+        html: Cooker::standard().cook(raw, &ctx),
+        mentioned,
+        quoted,
+    })
+}
+
+/// Whether `user_id` is an admin or moderator. `false` for unknown users;
+/// the insert fails on the foreign key as usual.
+pub fn is_staff(conn: &mut PgConnection, user_id: i32) -> Result<bool, DieselError> {
+    let staff: Option<(bool, bool)> = users::table
+        .find(user_id)
+        .select((users::admin, users::moderator))
+        .first(conn)
+        .optional()?;
+    Ok(staff.is_some_and(|(admin, moderator)| admin || moderator))
+}
+
+/// Everything that follows from a post's content once it's saved.
+fn after_save(
+    conn: &mut PgConnection,
+    post: &Post,
+    cooked: &Cooked,
+    is_new: bool,
+) -> Result<(), DieselError> {
+    let newly_mentioned = mentions::record(conn, post, &cooked.mentioned)?;
+    notifications::post_saved(conn, post, &newly_mentioned, &cooked.quoted, is_new)?;
+    Ok(())
+}
+
+/// Cook and insert a post, bump the author's post count, record its
+/// mentions, send its notifications and act on its watched words, in one
+/// transaction. Posts with `block` words are rejected.
+///
+/// This publishes the post directly; the `POST /posts` route goes through
+/// `queued_posts::submit`, which may hold it for review instead.
+pub fn create(conn: &mut PgConnection, input: CreatePostInput) -> Result<Post, ContentError> {
+    insert(conn, input, false)
+}
+
+/// [`create`], for a post a moderator approved from the queue:
+/// `require_approval` words don't hold it back a second time.
+///
+/// Staff posts skip `require_approval` words either way, as they skip the
+/// queue.
+pub fn create_approved(
+    conn: &mut PgConnection,
+    input: CreatePostInput,
+) -> Result<Post, ContentError> {
+    insert(conn, input, true)
+}
+
+fn insert(
+    conn: &mut PgConnection,
+    input: CreatePostInput,
+    approved: bool,
+) -> Result<Post, ContentError> {
+    conn.transaction(|conn| {
+        let mut matches =
+            watched_words::check_post(conn, input.topic_id, input.post_number, &input.raw)?;
+        if approved || is_staff(conn, input.user_id)? {
+            matches.require_approval.clear();
+        }
+        let cooked = cook(conn, &input.raw, input.user_id)?;
+        let post: Post = diesel::insert_into(posts::table)
+            .values(&input.into_new_post(cooked.html.clone()))
+            .returning(Post::as_returning())
+            .get_result(conn)?;
+        user_stats::incr_post_count(conn, post.user_id)?;
+        after_save(conn, &post, &cooked, true)?;
+        watched_words::apply(conn, &post, &matches)?;
+        Ok(post)
+    })
+}
+
+/// Apply an edit made by `editor_id`. When `raw` changes the post is
+/// re-cooked, users newly mentioned or quoted are notified and its watched
+/// words are acted on again; edits that add `block` words are rejected.
+/// `Ok(None)` if the post doesn't exist.
+///
+/// Edits never go to the approval queue: the post is already public, so
+/// one that adds a `require_approval` word hides the post and flags it for
+/// a moderator instead. Edits by staff are exempt, as in [`create`],
+/// whoever wrote the post.
+pub fn update(
+    conn: &mut PgConnection,
+    post_id: i32,
+    editor_id: i32,
+    input: UpdatePostInput,
+) -> Result<Option<Post>, ContentError> {
+    conn.transaction(|conn| {
+        let Some(user_id) = posts::table
+            .find(post_id)
+            .select(posts::user_id)
+            .first(conn)
+            .optional()?
+        else {
+            return Ok(None);
+        };
+        let mut matches = input
+            .raw
+            .as_deref()
+            .map(|raw| watched_words::check(conn, raw))
+            .transpose()?;
+        if let Some(matches) = &mut matches
+            && is_staff(conn, editor_id)?
+        {
+            matches.require_approval.clear();
+        }
+        let cooked = input
+            .raw
+            .as_deref()
+            .map(|raw| cook(conn, raw, user_id))
+            .transpose()?;
+        let Some(post) = diesel::update(posts::table.find(post_id))
+            .set(&input.into_update_post(cooked.as_ref().map(|c| c.html.clone())))
+            .returning(Post::as_returning())
+            .get_result(conn)
+            .optional()?
+        else {
+            return Ok(None);
+        };
+        if let Some(cooked) = &cooked {
+            after_save(conn, &post, cooked, false)?;
+        }
+        if let Some(matches) = &matches {
+            watched_words::apply(conn, &post, matches)?;
+        }
+        Ok(Some(post))
+    })
+}
+
+/// Re-render a post from its current raw, e.g. once link previews arrive
+/// or the pipeline changes. Notifications aren't re-sent and `updated_at`
+/// is left alone, since the author didn't change anything. The post is
+/// stamped with the current [`COOK_VERSION`] either way. Returns whether
+/// the cooked HTML changed.
+pub fn recook(conn: &mut PgConnection, post_id: i32) -> Result<bool, DieselError> {
+    conn.transaction(|conn| {
+        let (raw, old, user_id): (String, String, i32) = posts::table
+            .find(post_id)
+            .select((posts::raw, posts::cooked, posts::user_id))
+            .for_update()
+            .first(conn)?;
+        let html = cook(conn, &raw, user_id)?.html;
+        let changed = html != old;
+        diesel::update(posts::table.find(post_id))
+            .set((posts::cooked.eq(html), posts::cook_version.eq(COOK_VERSION)))
+            .execute(conn)?;
+        Ok(changed)
+    })
+}
+
+/// Re-cook up to `limit` posts cooked by an older pipeline, in id order
+/// after `after_id`. Returns the last id handled, or `None` once nothing
+/// stale is left past `after_id`.
+pub fn rebake_batch(
+    conn: &mut PgConnection,
+    after_id: i32,
+    limit: i64,
+) -> Result<Option<i32>, DieselError> {
+    let ids: Vec<i32> = posts::table
+        .filter(posts::id.gt(after_id))
+        .filter(posts::cook_version.lt(COOK_VERSION))
+        .order(posts::id)
+        .limit(limit)
+        .select(posts::id)
+        .load(conn)?;
+    for &id in &ids {
+        recook(conn, id)?;
+    }
+    Ok(ids.last().copied())
+}
@@ -0,0 +1,248 @@
+//! The approval queue: posts held for a moderator before they go live.
+//!
+//! New posts from the API go through [`submit`], which queues them when the
+//! author is below the `approve_unless_trust_level` setting, the post
+//! matches a `require_approval` watched word, or its topic is in one of the
+//! `approve_post_categories`. Staff posts are never queued. Approving a
+//! queued post creates it through `services::posts`, same as any other;
+//! the author is notified either way.
+
+use chrono::Utc;
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use serde_json::json;
+
+use crate::models::queued_post::{
+    REASON_CATEGORY, REASON_TRUST_LEVEL, REASON_WATCHED_WORD, STATUS_APPROVED, STATUS_PENDING,
+    STATUS_REJECTED,
+};
+use crate::models::{CreatePostInput, NewNotification, NewQueuedPost, Post, QueuedPost};
+use crate::schema::{posts, queued_posts, site_settings, topics, users};
+use crate::services::notifications;
+use crate::services::posts as post_service;
+use crate::services::watched_words::{self, ContentError, Matches};
+
+#[derive(Debug)]
+pub enum QueueError {
+    NotFound,
+    AlreadyReviewed,
+    /// A `block` word was added since the post was queued.
+    Blocked(Vec<String>),
+    Db(DieselError),
+}
+
+impl From<DieselError> for QueueError {
+    fn from(e: DieselError) -> Self {
+        QueueError::Db(e)
+    }
+}
+
+impl From<ContentError> for QueueError {
+    fn from(e: ContentError) -> Self {
+        match e {
+            ContentError::Blocked(words) => QueueError::Blocked(words),
+            ContentError::Db(e) => QueueError::Db(e),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Submitted {
+    /// The post went live.
+    Created(Post),
+    /// The post waits for a moderator.
+    Queued(QueuedPost),
+}
+
+/// Create a post, or queue it for review if it needs approval. Posts with
+/// `block` words are rejected either way. `input.user_id` decides whether
+/// the post is queued, so callers must have checked it's the author.
+pub fn submit(conn: &mut PgConnection, input: CreatePostInput) -> Result<Submitted, ContentError> {
+    conn.transaction(|conn| {
+        let matches =
+            watched_words::check_post(conn, input.topic_id, input.post_number, &input.raw)?;
+        let Some(reason) = queue_reason(conn, &input, &matches)? else {
+            return post_service::create(conn, input).map(Submitted::Created);
+        };
+        let queued = diesel::insert_into(queued_posts::table)
+            .values(&NewQueuedPost {
+                topic_id: input.topic_id,
+                user_id: input.user_id,
+                raw: input.raw,
+                reply_to_post_number: input.reply_to_post_number,
+                reason: reason.to_string(),
+            })
+            .returning(QueuedPost::as_returning())
+            .get_result(conn)?;
+        Ok(Submitted::Queued(queued))
+    })
+}
+
+/// Why `input` has to wait for a moderator, if it does.
+fn queue_reason(
+    conn: &mut PgConnection,
+    input: &CreatePostInput,
+    matches: &Matches,
+) -> Result<Option<&'static str>, DieselError> {
+    let Some((trust_level, admin, moderator)) = users::table
+        .find(input.user_id)
+        .select((users::trust_level, users::admin, users::moderator))
+        .first::<(i32, bool, bool)>(conn)
+        .optional()?
+    else {
+        // Let the insert fail on the foreign key as usual.
+        return Ok(None);
+    };
+    if admin || moderator {
+        return Ok(None);
+    }
+    if !matches.require_approval.is_empty() {
+        return Ok(Some(REASON_WATCHED_WORD));
+    }
+    let category_id: Option<i32> = topics::table
+        .find(input.topic_id)
+        .select(topics::category_id)
+        .first(conn)
+        .optional()?
+        .flatten();
+    if let Some(category_id) = category_id {
+        let ids = setting(conn, "approve_post_categories")?;
+        if ids
+            .split('|')
+            .any(|id| id.trim().parse() == Ok(category_id))
+        {
+            return Ok(Some(REASON_CATEGORY));
+        }
+    }
+    let min_trust_level = setting(conn, "approve_unless_trust_level")?
+        .trim()
+        .parse::<i32>()
+        .unwrap_or(0);
+    if trust_level < min_trust_level {
+        return Ok(Some(REASON_TRUST_LEVEL));
+    }
+    Ok(None)
+}
+
+/// A setting's value, or `""` if it's missing.
+fn setting(conn: &mut PgConnection, key: &str) -> Result<String, DieselError> {
+    let value = site_settings::table
+        .find(key)
+        .select(site_settings::value)
+        .first::<String>(conn)
+        .optional()?;
+    Ok(value.unwrap_or_default())
+}
+
+/// Queued posts with this status, oldest first.
+pub fn list(
+    conn: &mut PgConnection,
+    status: &str,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<QueuedPost>, DieselError> {
+    queued_posts::table
+        .filter(queued_posts::status.eq(status))
+        .order(queued_posts::id)
+        .limit(limit)
+        .offset(offset)
+        .select(QueuedPost::as_select())
+        .load(conn)
+}
+
+/// The pending queued post `id`, locked for review.
+fn pending(conn: &mut PgConnection, id: i32) -> Result<QueuedPost, QueueError> {
+    let queued = queued_posts::table
+        .find(id)
+        .select(QueuedPost::as_select())
+        .for_update()
+        .first(conn)
+        .optional()?
+        .ok_or(QueueError::NotFound)?;
+    if queued.status != STATUS_PENDING {
+        return Err(QueueError::AlreadyReviewed);
+    }
+    Ok(queued)
+}
+
+/// Publish a queued post as the next post in its topic and tell the
+/// author.
+pub fn approve(conn: &mut PgConnection, id: i32, reviewer_id: i32) -> Result<Post, QueueError> {
+    conn.transaction(|conn| {
+        let queued = pending(conn, id)?;
+        // Locking the topic serializes approvals into it, so two can't both
+        // take the next post number.
+        topics::table
+            .find(queued.topic_id)
+            .select(topics::id)
+            .for_update()
+            .first::<i32>(conn)?;
+        let last: Option<i32> = posts::table
+            .filter(posts::topic_id.eq(queued.topic_id))
+            .select(diesel::dsl::max(posts::post_number))
+            .first(conn)?;
+        let post = post_service::create_approved(
+            conn,
+            CreatePostInput {
+                topic_id: queued.topic_id,
+                user_id: queued.user_id,
+                post_number: last.unwrap_or(0) + 1,
+                raw: queued.raw,
+                reply_to_post_number: queued.reply_to_post_number,
+            },
+        )?;
+        diesel::update(queued_posts::table.find(id))
+            .set((
+                queued_posts::status.eq(STATUS_APPROVED),
+                queued_posts::post_id.eq(post.id),
+                queued_posts::reviewed_by_user_id.eq(reviewer_id),
+                queued_posts::reviewed_at.eq(Utc::now()),
+            ))
+            .execute(conn)?;
+        notifications::create(
+            conn,
+            &NewNotification {
+                user_id: post.user_id,
+                notification_type: notifications::QUEUED_POST_APPROVED.to_string(),
+                data: json!({ "queued_post_id": id, "post_number": post.post_number }),
+                topic_id: Some(post.topic_id),
+                post_id: Some(post.id),
+                acting_user_id: Some(reviewer_id),
+            },
+        )?;
+        Ok(post)
+    })
+}
+
+/// Turn a queued post down and tell the author, with `reason` if given.
+pub fn reject(
+    conn: &mut PgConnection,
+    id: i32,
+    reviewer_id: i32,
+    reason: Option<String>,
+) -> Result<QueuedPost, QueueError> {
+    conn.transaction(|conn| {
+        let queued = pending(conn, id)?;
+        let rejected = diesel::update(queued_posts::table.find(id))
+            .set((
+                queued_posts::status.eq(STATUS_REJECTED),
+                queued_posts::reviewed_by_user_id.eq(reviewer_id),
+                queued_posts::reviewed_at.eq(Utc::now()),
+                queued_posts::reject_reason.eq(&reason),
+            ))
+            .returning(QueuedPost::as_returning())
+            .get_result(conn)?;
+        notifications::create(
+            conn,
+            &NewNotification {
+                user_id: queued.user_id,
+                notification_type: notifications::QUEUED_POST_REJECTED.to_string(),
+                data: json!({ "queued_post_id": id, "reason": reason }),
+                topic_id: Some(queued.topic_id),
+                post_id: None,
+                acting_user_id: Some(reviewer_id),
+            },
+        )?;
+        Ok(rejected)
+    })
+}
@@ -0,0 +1,53 @@
+//! `[quote="user, post:N, topic:N"]` blocks: checking the quoted post is
+//! one the quote may link to. Quoted authors are notified by
+//! `services::notifications`.
+
+use std::collections::HashSet;
+
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+
+use crate::markdown::QuoteRef;
+use crate::schema::posts;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuotedPost {
+    pub post_id: i32,
+    pub topic_id: i32,
+    pub post_number: i32,
+    pub user_id: i32,
+}
+
+/// The quoted posts that exist and are visible: not deleted and not hidden
+/// by moderation. Quotes of anything else render unlinked and notify no one.
+pub fn resolve(conn: &mut PgConnection, refs: &[QuoteRef]) -> Result<Vec<QuotedPost>, DieselError> {
+    if refs.is_empty() {
+        return Ok(Vec::new());
+    }
+    let wanted: HashSet<(i32, i32)> = refs.iter().map(|q| (q.topic_id, q.post_number)).collect();
+    let topic_ids: Vec<i32> = refs.iter().map(|q| q.topic_id).collect();
+    let post_numbers: Vec<i32> = refs.iter().map(|q| q.post_number).collect();
+
+    let rows: Vec<(i32, i32, i32, i32)> = posts::table
+        .filter(posts::topic_id.eq_any(&topic_ids))
+        .filter(posts::post_number.eq_any(&post_numbers))
+        .filter(posts::hidden.eq(false))
+        .filter(posts::deleted_at.is_null())
+        .select((
+            posts::id,
+            posts::topic_id,
+            posts::post_number,
+            posts::user_id,
+        ))
+        .load(conn)?;
+    Ok(rows
+        .into_iter()
+        .filter(|(_, topic_id, post_number, _)| wanted.contains(&(*topic_id, *post_number)))
+        .map(|(post_id, topic_id, post_number, user_id)| QuotedPost {
+            post_id,
+            topic_id,
+            post_number,
+            user_id,
+        })
+        .collect())
+}
@@ -11,15 +11,20 @@
 //! - TL1 -> TL2: not implemented yet (waiting on time_read / days_visited
 //!   instrumentation in PR3+).
 //!
+//! Each promotion grants the new level's badge (see [`badge_name`]), which
+//! the user is notified of.
+//!
 //! v1 does NOT demote. Once promoted, the user stays promoted. Manual
 //! demotion via the existing admin update_user route still works.
 
 use diesel::prelude::*;
 use diesel::result::Error as DieselError;
 
-use crate::guardian::TRUST_LEVEL_BASIC;
+use crate::guardian::{
+    TRUST_LEVEL_BASIC, TRUST_LEVEL_LEADER, TRUST_LEVEL_MEMBER, TRUST_LEVEL_REGULAR,
+};
 use crate::schema::users;
-use crate::services::user_stats;
+use crate::services::{notifications, user_stats};
 
 /// Posts required to promote from TL0 to TL1. Discourse default is 3.
 pub const TL1_MIN_POSTS: i32 = 3;
@@ -58,6 +63,9 @@ pub fn evaluate(conn: &mut PgConnection, user_id: i32) -> Result<PromotionOutcom
     diesel::update(users::table.find(user_id))
         .set(users::trust_level.eq(target))
         .execute(conn)?;
+    if let Some(badge) = badge_name(target) {
+        notifications::granted_badge(conn, user_id, badge)?;
+    }
 
     Ok(PromotionOutcome {
         previous: current_tl,
@@ -65,6 +73,18 @@ pub fn evaluate(conn: &mut PgConnection, user_id: i32) -> Result<PromotionOutcom
     })
 }
 
+/// The badge that comes with reaching `trust_level`, as Discourse names
+/// them. Level 0 has none.
+pub fn badge_name(trust_level: i32) -> Option<&'static str> {
+    match trust_level {
+        TRUST_LEVEL_BASIC => Some("Basic User"),
+        TRUST_LEVEL_MEMBER => Some("Member"),
+        TRUST_LEVEL_REGULAR => Some("Regular"),
+        TRUST_LEVEL_LEADER => Some("Leader"),
+        _ => None,
+    }
+}
+
 /// Pure decision function: given a user's current TL and stats, return
 /// the level they should be at. Never returns a level *below* the
 /// current one (no demotion in v1).
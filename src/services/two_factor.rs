@@ -0,0 +1,440 @@
+//! Two-factor authentication: TOTP codes from an authenticator app
+//! (RFC 6238, SHA-1, 6 digits, 30-second steps), with one-time backup
+//! codes for when the app is lost.
+//!
+//! Enrollment is two steps so a typo'd secret can't lock anyone out:
+//! [`begin_enrollment`] stores a fresh secret, and [`enable`] turns 2FA on
+//! once the user proves their app has it. From then on login needs a code
+//! as well as the password; see [`verify`].
+
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+use crate::auth::verify_password;
+use crate::models::{NewBackupCode, User};
+use crate::schema::{site_settings, user_backup_codes, users};
+
+/// Seconds each TOTP code is valid for.
+pub const TOTP_STEP_SECS: i64 = 30;
+
+pub const TOTP_DIGITS: u32 = 6;
+
+/// Codes for this many steps either side of now are accepted, to allow
+/// for clocks that drift.
+pub const TOTP_SKEW_STEPS: i64 = 1;
+
+/// How many backup codes a user gets at a time.
+pub const BACKUP_CODE_COUNT: usize = 10;
+
+/// Wrong codes allowed at login before the second step is locked for
+/// [`LOCKOUT_SECS`]. Each guess can hit a few TOTP steps and any unused
+/// backup code, so this stays small.
+pub const MAX_FAILED_ATTEMPTS: i32 = 5;
+
+pub const LOCKOUT_SECS: i64 = 15 * 60;
+
+/// Shown as the account's issuer in authenticator apps.
+pub const ISSUER: &str = "Discourse-rs";
+
+#[derive(Debug)]
+pub enum TwoFactorError {
+    /// Enrolling again while 2FA is on would silently swap the secret.
+    AlreadyEnabled,
+    /// Enabling before [`begin_enrollment`], or using 2FA that isn't on.
+    NotEnrolled,
+    /// Wrong, expired or replayed code.
+    InvalidCode,
+    /// The password given to disable 2FA is wrong.
+    WrongPassword,
+    /// bcrypt failed.
+    Hash(bcrypt::BcryptError),
+    Db(DieselError),
+}
+
+impl From<DieselError> for TwoFactorError {
+    fn from(e: DieselError) -> Self {
+        TwoFactorError::Db(e)
+    }
+}
+
+impl From<bcrypt::BcryptError> for TwoFactorError {
+    fn from(e: bcrypt::BcryptError) -> Self {
+        TwoFactorError::Hash(e)
+    }
+}
+
+/// The outcome of [`verify_login`].
+#[derive(Debug, PartialEq, Eq)]
+pub enum LoginCheck {
+    Verified,
+    Invalid,
+    /// Too many wrong codes; no code is checked until the lockout ends.
+    LockedOut {
+        retry_after_secs: i64,
+    },
+}
+
+/// What the user needs to add the account to their authenticator app.
+#[derive(Debug, serde::Serialize)]
+pub struct Enrollment {
+    /// Base32, for typing in by hand.
+    pub secret: String,
+    /// `otpauth://` URI, for rendering as a QR code.
+    pub otpauth_uri: String,
+}
+
+/// 160 random bits, base32-encoded without padding as authenticator apps
+/// expect.
+pub fn generate_secret() -> String {
+    let bytes: [u8; 20] = rand::rng().random();
+    base32::encode(base32::Alphabet::Rfc4648 { padding: false }, &bytes)
+}
+
+/// The code for `secret` at `time`, or `None` if the secret isn't valid
+/// base32.
+pub fn code_at(secret: &str, time: DateTime<Utc>) -> Option<String> {
+    let key = decode_secret(secret)?;
+    Some(code_for_step(
+        &key,
+        time.timestamp().div_euclid(TOTP_STEP_SECS),
+    ))
+}
+
+/// The URI authenticator apps read from a QR code. Usernames are limited
+/// to URI-safe characters, so neither part needs escaping.
+pub fn otpauth_uri(secret: &str, username: &str) -> String {
+    format!(
+        "otpauth://totp/{ISSUER}:{username}?secret={secret}&issuer={ISSUER}\
+         &algorithm=SHA1&digits={TOTP_DIGITS}&period={TOTP_STEP_SECS}"
+    )
+}
+
+fn decode_secret(secret: &str) -> Option<Vec<u8>> {
+    base32::decode(base32::Alphabet::Rfc4648 { padding: false }, secret)
+}
+
+/// RFC 4226 HOTP over the step counter, truncated to [`TOTP_DIGITS`].
+fn code_for_step(key: &[u8], step: i64) -> String {
+    let mut mac = Hmac::<Sha1>::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(&step.to_be_bytes());
+    let digest = mac.finalize().into_bytes();
+    let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        digest[offset] & 0x7f,
+        digest[offset + 1],
+        digest[offset + 2],
+        digest[offset + 3],
+    ]);
+    format!(
+        "{:0width$}",
+        binary % 10u32.pow(TOTP_DIGITS),
+        width = TOTP_DIGITS as usize
+    )
+}
+
+/// The step `code` is valid for, within the allowed skew of `now`.
+fn matching_step(secret: &str, code: &str, now: DateTime<Utc>) -> Option<i64> {
+    let key = decode_secret(secret)?;
+    let current = now.timestamp().div_euclid(TOTP_STEP_SECS);
+    (current - TOTP_SKEW_STEPS..=current + TOTP_SKEW_STEPS)
+        .find(|step| code_for_step(&key, *step) == code)
+}
+
+fn is_totp_code(code: &str) -> bool {
+    code.len() == TOTP_DIGITS as usize && code.chars().all(|c| c.is_ascii_digit())
+}
+
+fn hash_backup_code(code: &str) -> String {
+    format!("{:x}", Sha256::digest(code.as_bytes()))
+}
+
+/// Store a fresh secret for `user_id`, replacing any unfinished
+/// enrollment. 2FA stays off until [`enable`].
+pub fn begin_enrollment(
+    conn: &mut PgConnection,
+    user_id: i32,
+) -> Result<Enrollment, TwoFactorError> {
+    let user: User = users::table
+        .find(user_id)
+        .select(User::as_select())
+        .first(conn)?;
+    if user.totp_enabled {
+        return Err(TwoFactorError::AlreadyEnabled);
+    }
+
+    let secret = generate_secret();
+    diesel::update(users::table.find(user_id))
+        .set(users::totp_secret.eq(Some(&secret)))
+        .execute(conn)?;
+    Ok(Enrollment {
+        otpauth_uri: otpauth_uri(&secret, &user.username),
+        secret,
+    })
+}
+
+/// Turn 2FA on with a code from the enrolled secret. Returns the user's
+/// backup codes, which are never shown again.
+pub fn enable(
+    conn: &mut PgConnection,
+    user_id: i32,
+    code: &str,
+) -> Result<Vec<String>, TwoFactorError> {
+    conn.transaction(|conn| {
+        let user: User = users::table
+            .find(user_id)
+            .select(User::as_select())
+            .for_update()
+            .first(conn)?;
+        if user.totp_enabled {
+            return Err(TwoFactorError::AlreadyEnabled);
+        }
+        let secret = user.totp_secret.ok_or(TwoFactorError::NotEnrolled)?;
+        let step =
+            matching_step(&secret, code.trim(), Utc::now()).ok_or(TwoFactorError::InvalidCode)?;
+
+        diesel::update(users::table.find(user_id))
+            .set((
+                users::totp_enabled.eq(true),
+                users::totp_last_used_step.eq(Some(step)),
+            ))
+            .execute(conn)?;
+        Ok(replace_backup_codes(conn, user_id)?)
+    })
+}
+
+/// Turn 2FA off, after checking the user's password. The secret and
+/// backup codes are discarded.
+pub fn disable(
+    conn: &mut PgConnection,
+    user_id: i32,
+    password: &str,
+) -> Result<(), TwoFactorError> {
+    let password_hash: String = users::table
+        .find(user_id)
+        .select(users::password_hash)
+        .first(conn)?;
+    if !verify_password(password, &password_hash)? {
+        return Err(TwoFactorError::WrongPassword);
+    }
+    conn.transaction(|conn| {
+        diesel::update(users::table.find(user_id))
+            .set((
+                users::totp_secret.eq(None::<String>),
+                users::totp_enabled.eq(false),
+                users::totp_last_used_step.eq(None::<i64>),
+            ))
+            .execute(conn)?;
+        diesel::delete(user_backup_codes::table.filter(user_backup_codes::user_id.eq(user_id)))
+            .execute(conn)?;
+        Ok(())
+    })
+}
+
+/// Replace the user's backup codes with a new set, after checking a
+/// current TOTP code. Old codes stop working.
+pub fn regenerate_backup_codes(
+    conn: &mut PgConnection,
+    user_id: i32,
+    code: &str,
+) -> Result<Vec<String>, TwoFactorError> {
+    conn.transaction(|conn| {
+        if !verify_totp(conn, user_id, code)? {
+            return Err(TwoFactorError::InvalidCode);
+        }
+        Ok(replace_backup_codes(conn, user_id)?)
+    })
+}
+
+/// Check a second factor at login: a TOTP code, or an unused backup code,
+/// which this uses up. False for users without 2FA.
+pub fn verify(conn: &mut PgConnection, user_id: i32, code: &str) -> Result<bool, DieselError> {
+    let code = code.trim();
+    if is_totp_code(code) {
+        return conn.transaction(|conn| verify_totp(conn, user_id, code));
+    }
+
+    let enabled: bool = users::table
+        .find(user_id)
+        .select(users::totp_enabled)
+        .first(conn)?;
+    if !enabled {
+        return Ok(false);
+    }
+    // A single UPDATE, so two concurrent logins can't share one code.
+    let used = diesel::update(
+        user_backup_codes::table
+            .filter(user_backup_codes::user_id.eq(user_id))
+            .filter(user_backup_codes::code_hash.eq(hash_backup_code(&code.to_lowercase())))
+            .filter(user_backup_codes::used_at.is_null()),
+    )
+    .set(user_backup_codes::used_at.eq(Some(Utc::now())))
+    .execute(conn)?;
+    Ok(used > 0)
+}
+
+/// [`verify`] for the second step of logging in, counting wrong codes per
+/// account. The [`MAX_FAILED_ATTEMPTS`]th in a row locks the second step
+/// for [`LOCKOUT_SECS`], during which even a right code is refused; a
+/// right code resets the count.
+pub fn verify_login(
+    conn: &mut PgConnection,
+    user_id: i32,
+    code: &str,
+) -> Result<LoginCheck, DieselError> {
+    conn.transaction(|conn| {
+        let (failed, locked_until): (i32, Option<DateTime<Utc>>) = users::table
+            .find(user_id)
+            .select((users::totp_failed_attempts, users::totp_locked_until))
+            .for_update()
+            .first(conn)?;
+        let now = Utc::now();
+        if let Some(until) = locked_until.filter(|until| *until > now) {
+            return Ok(LoginCheck::LockedOut {
+                retry_after_secs: (until - now).num_seconds().max(1),
+            });
+        }
+
+        if verify(conn, user_id, code)? {
+            diesel::update(users::table.find(user_id))
+                .set((
+                    users::totp_failed_attempts.eq(0),
+                    users::totp_locked_until.eq(None::<DateTime<Utc>>),
+                ))
+                .execute(conn)?;
+            return Ok(LoginCheck::Verified);
+        }
+        let failed = failed + 1;
+        let (failed, locked_until) = if failed >= MAX_FAILED_ATTEMPTS {
+            (0, Some(now + chrono::Duration::seconds(LOCKOUT_SECS)))
+        } else {
+            (failed, None)
+        };
+        diesel::update(users::table.find(user_id))
+            .set((
+                users::totp_failed_attempts.eq(failed),
+                users::totp_locked_until.eq(locked_until),
+            ))
+            .execute(conn)?;
+        Ok(LoginCheck::Invalid)
+    })
+}
+
+/// How many unused backup codes the user has left.
+pub fn backup_codes_remaining(conn: &mut PgConnection, user_id: i32) -> Result<i64, DieselError> {
+    user_backup_codes::table
+        .filter(user_backup_codes::user_id.eq(user_id))
+        .filter(user_backup_codes::used_at.is_null())
+        .count()
+        .get_result(conn)
+}
+
+/// Whether the `enforce_2fa_for_staff` setting is on.
+pub fn enforced_for_staff(conn: &mut PgConnection) -> Result<bool, DieselError> {
+    let value = site_settings::table
+        .find("enforce_2fa_for_staff")
+        .select(site_settings::value)
+        .first::<String>(conn)
+        .optional()?;
+    Ok(value.as_deref() == Some("true"))
+}
+
+/// Check a TOTP code and record its step, refusing replays. Must run in a
+/// transaction: the user row is locked so concurrent checks serialize.
+fn verify_totp(conn: &mut PgConnection, user_id: i32, code: &str) -> Result<bool, DieselError> {
+    let user: User = users::table
+        .find(user_id)
+        .select(User::as_select())
+        .for_update()
+        .first(conn)?;
+    let Some(secret) = user.totp_secret.filter(|_| user.totp_enabled) else {
+        return Ok(false);
+    };
+    let Some(step) = matching_step(&secret, code.trim(), Utc::now()) else {
+        return Ok(false);
+    };
+    if user.totp_last_used_step.is_some_and(|last| step <= last) {
+        return Ok(false);
+    }
+    diesel::update(users::table.find(user_id))
+        .set(users::totp_last_used_step.eq(Some(step)))
+        .execute(conn)?;
+    Ok(true)
+}
+
+/// Delete the user's backup codes and store [`BACKUP_CODE_COUNT`] new
+/// ones, returning them in the clear.
+fn replace_backup_codes(conn: &mut PgConnection, user_id: i32) -> Result<Vec<String>, DieselError> {
+    diesel::delete(user_backup_codes::table.filter(user_backup_codes::user_id.eq(user_id)))
+        .execute(conn)?;
+
+    // 16 hex digits: 64 bits, plenty for a single-use code behind SHA-256.
+    let codes: Vec<String> = (0..BACKUP_CODE_COUNT)
+        .map(|_| {
+            let bytes: [u8; 8] = rand::rng().random();
+            bytes.iter().map(|b| format!("{b:02x}")).collect()
+        })
+        .collect();
+    let rows: Vec<NewBackupCode> = codes
+        .iter()
+        .map(|code| NewBackupCode {
+            user_id,
+            code_hash: hash_backup_code(code),
+        })
+        .collect();
+    diesel::insert_into(user_backup_codes::table)
+        .values(&rows)
+        .execute(conn)?;
+    Ok(codes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    /// The SHA-1 vectors from RFC 6238 appendix B, truncated to 6 digits.
+    #[test]
+    fn matches_rfc_6238_vectors() {
+        let secret = base32::encode(
+            base32::Alphabet::Rfc4648 { padding: false },
+            b"12345678901234567890",
+        );
+        for (time, expected) in [
+            (59, "287082"),
+            (1111111109, "081804"),
+            (1111111111, "050471"),
+            (1234567890, "005924"),
+            (2000000000, "279037"),
+        ] {
+            let at = Utc.timestamp_opt(time, 0).unwrap();
+            assert_eq!(code_at(&secret, at).unwrap(), expected, "at {time}");
+        }
+    }
+
+    #[test]
+    fn accepts_codes_one_step_either_side() {
+        let secret = generate_secret();
+        let now = Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+        let step = now.timestamp() / TOTP_STEP_SECS;
+        for offset in [-30, 0, 30] {
+            let code = code_at(&secret, now + chrono::Duration::seconds(offset)).unwrap();
+            assert!(matching_step(&secret, &code, now).is_some_and(|s| (s - step).abs() <= 1));
+        }
+        let stale = code_at(&secret, now - chrono::Duration::seconds(90)).unwrap();
+        assert_eq!(matching_step(&secret, &stale, now), None);
+    }
+
+    #[test]
+    fn otpauth_uri_names_issuer_and_account() {
+        assert_eq!(
+            otpauth_uri("ABC", "alice"),
+            "otpauth://totp/Discourse-rs:alice?secret=ABC&issuer=Discourse-rs\
+             &algorithm=SHA1&digits=6&period=30"
+        );
+    }
+}
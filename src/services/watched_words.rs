@@ -0,0 +1,374 @@
+//! Watched words: admin-managed words and patterns that block, censor,
+//! replace, hold for approval, flag or tag the posts and topic titles they
+//! turn up in.
+//!
+//! The table is compiled into one [`WatchedWordSet`] of regexes per action
+//! and cached for the process. Every lookup checks a cheap fingerprint of
+//! the table first, so edits (from this process or another) take effect
+//! on the next post.
+
+use std::sync::{Arc, RwLock};
+
+use chrono::{DateTime, Utc};
+use diesel::dsl::count_star;
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use regex::{Regex, RegexBuilder};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::markdown;
+use crate::models::watched_word::{
+    ACTION_BLOCK, ACTION_CENSOR, ACTION_FLAG, ACTION_REPLACE, ACTION_REQUIRE_APPROVAL, ACTION_TAG,
+};
+use crate::models::{NewPostFlag, NewWatchedWord, Post, WatchedWord};
+use crate::schema::{post_flags, posts, topic_tags, topics, watched_words};
+
+/// Flag reason for posts that matched a `flag` word.
+pub const FLAG_REASON: &str = "watched_word";
+/// Flag reason for posts hidden until a moderator approves them.
+pub const APPROVAL_FLAG_REASON: &str = "watched_word_approval";
+
+/// A post or title was rejected because it contains `block` words.
+#[derive(Debug)]
+pub enum ContentError {
+    /// The blocked words found, as written.
+    Blocked(Vec<String>),
+    Db(DieselError),
+}
+
+impl From<DieselError> for ContentError {
+    fn from(e: DieselError) -> Self {
+        ContentError::Db(e)
+    }
+}
+
+/// The watched words, compiled. Words for the same action share one
+/// pattern; `replace` and `tag` words keep theirs apart since each has its
+/// own replacement or tag.
+#[derive(Debug, Default)]
+pub struct WatchedWordSet {
+    pub block: Option<Regex>,
+    pub censor: Option<Regex>,
+    pub require_approval: Option<Regex>,
+    pub flag: Option<Regex>,
+    pub replace: Vec<(Regex, String)>,
+    pub tag: Vec<(Regex, String)>,
+}
+
+/// What a piece of text matched, per action, as the text was written.
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct Matches {
+    pub block: Vec<String>,
+    pub censor: Vec<String>,
+    pub replace: Vec<String>,
+    pub require_approval: Vec<String>,
+    pub flag: Vec<String>,
+    /// Tags the text would add to its topic.
+    pub tags: Vec<String>,
+}
+
+impl WatchedWordSet {
+    pub fn compile(words: &[WatchedWord]) -> Self {
+        let of = |action: &str| -> Vec<&WatchedWord> {
+            words.iter().filter(|w| w.action == action).collect()
+        };
+        let each = |action: &str| {
+            of(action)
+                .into_iter()
+                .filter_map(|w| Some((pattern(&[w])?, w.replacement.clone()?)))
+                .collect()
+        };
+        Self {
+            block: pattern(&of(ACTION_BLOCK)),
+            censor: pattern(&of(ACTION_CENSOR)),
+            require_approval: pattern(&of(ACTION_REQUIRE_APPROVAL)),
+            flag: pattern(&of(ACTION_FLAG)),
+            replace: each(ACTION_REPLACE),
+            tag: each(ACTION_TAG),
+        }
+    }
+
+    pub fn matches(&self, text: &str) -> Matches {
+        let found = |re: Option<&Regex>| -> Vec<String> {
+            let mut found: Vec<String> = Vec::new();
+            for m in re.into_iter().flat_map(|re| re.find_iter(text)) {
+                if !m.as_str().is_empty()
+                    && !found.iter().any(|f| f.eq_ignore_ascii_case(m.as_str()))
+                {
+                    found.push(m.as_str().to_string());
+                }
+            }
+            found
+        };
+        let replace = self
+            .replace
+            .iter()
+            .flat_map(|(re, _)| found(Some(re)))
+            .collect();
+        let mut tags: Vec<String> = Vec::new();
+        for (re, tag) in &self.tag {
+            if re.is_match(text) && !tags.contains(tag) {
+                tags.push(tag.clone());
+            }
+        }
+        Matches {
+            block: found(self.block.as_ref()),
+            censor: found(self.censor.as_ref()),
+            replace,
+            require_approval: found(self.require_approval.as_ref()),
+            flag: found(self.flag.as_ref()),
+            tags,
+        }
+    }
+}
+
+/// One case-insensitive pattern matching any of `words`. Literal words
+/// match whole words only, longest first so the longer of two overlapping
+/// words wins. Regex words are used as written (an inline `(?-i)` makes
+/// one case-sensitive). Anything that won't compile is logged and left
+/// out. `None` if nothing is left.
+fn pattern(words: &[&WatchedWord]) -> Option<Regex> {
+    let mut literal: Vec<&WatchedWord> = words.iter().copied().filter(|w| !w.regex).collect();
+    literal.sort_by_key(|w| std::cmp::Reverse(w.word.len()));
+    let is_word_char = |c: char| c.is_alphanumeric() || c == '_';
+    let mut parts: Vec<String> = Vec::new();
+    for word in literal {
+        let start = if word.word.starts_with(is_word_char) {
+            r"\b"
+        } else {
+            ""
+        };
+        let end = if word.word.ends_with(is_word_char) {
+            r"\b"
+        } else {
+            ""
+        };
+        parts.push(format!("{start}{}{end}", regex::escape(&word.word)));
+    }
+    for word in words.iter().filter(|w| w.regex) {
+        match Regex::new(&word.word) {
+            Ok(_) => parts.push(format!("(?:{})", word.word)),
+            Err(e) => log::warn!("Skipping watched word {}: {e}", word.id),
+        }
+    }
+    if parts.is_empty() {
+        return None;
+    }
+    RegexBuilder::new(&parts.join("|"))
+        .case_insensitive(true)
+        .build()
+        .inspect_err(|e| log::warn!("Watched words didn't compile: {e}"))
+        .ok()
+}
+
+/// Row count, highest id and latest `updated_at`: changes whenever a word
+/// is added, edited or deleted.
+type Fingerprint = (i64, Option<i32>, Option<DateTime<Utc>>);
+
+static CACHE: RwLock<Option<(Fingerprint, Arc<WatchedWordSet>)>> = RwLock::new(None);
+
+/// The compiled watched words, from the cache unless the table changed.
+pub fn current(conn: &mut PgConnection) -> Result<Arc<WatchedWordSet>, DieselError> {
+    let fingerprint: Fingerprint = watched_words::table
+        .select((
+            count_star(),
+            diesel::dsl::max(watched_words::id),
+            diesel::dsl::max(watched_words::updated_at),
+        ))
+        .first(conn)?;
+    if let Some((cached, set)) = &*CACHE.read().unwrap_or_else(|e| e.into_inner())
+        && *cached == fingerprint
+    {
+        return Ok(set.clone());
+    }
+    let set = Arc::new(WatchedWordSet::compile(&list(conn)?));
+    *CACHE.write().unwrap_or_else(|e| e.into_inner()) = Some((fingerprint, set.clone()));
+    Ok(set)
+}
+
+/// Match `text` against the current watched words, rejecting it if it
+/// contains any `block` words.
+pub fn check(conn: &mut PgConnection, text: &str) -> Result<Matches, ContentError> {
+    unblocked(current(conn)?.matches(text))
+}
+
+/// [`check`] for a new post. A topic's first post also takes on the
+/// `require_approval` and `flag` words in its title: topics have no queue
+/// or flags of their own, so the title is held back or flagged along with
+/// the post that opens the topic.
+pub fn check_post(
+    conn: &mut PgConnection,
+    topic_id: i32,
+    post_number: i32,
+    raw: &str,
+) -> Result<Matches, ContentError> {
+    let mut matches = check(conn, raw)?;
+    if post_number != 1 {
+        return Ok(matches);
+    }
+    let title: Option<String> = topics::table
+        .find(topic_id)
+        .select(topics::title)
+        .first(conn)
+        .optional()?;
+    if let Some(title) = title {
+        let in_title = current(conn)?.matches(&title);
+        merge(&mut matches.require_approval, in_title.require_approval);
+        merge(&mut matches.flag, in_title.flag);
+    }
+    Ok(matches)
+}
+
+fn merge(words: &mut Vec<String>, more: Vec<String>) {
+    for word in more {
+        if !words.iter().any(|w| w.eq_ignore_ascii_case(&word)) {
+            words.push(word);
+        }
+    }
+}
+
+fn unblocked(matches: Matches) -> Result<Matches, ContentError> {
+    if !matches.block.is_empty() {
+        return Err(ContentError::Blocked(matches.block));
+    }
+    Ok(matches)
+}
+
+/// `title` with `censor` words blacked out and `replace` words swapped
+/// out, after rejecting it if it contains `block` words. Also returns what
+/// it matched, for [`apply_to_title`].
+pub fn check_title(
+    conn: &mut PgConnection,
+    title: &str,
+) -> Result<(String, Matches), ContentError> {
+    let set = current(conn)?;
+    let matches = unblocked(set.matches(title))?;
+    let title = markdown::censor_in_text(title, set.censor.as_ref());
+    Ok((markdown::replace_in_text(&title, &set.replace), matches))
+}
+
+/// Act on what a topic's title matched. The topic is tagged; `require_approval`
+/// and `flag` words hide or flag its first post, as an edit to that post
+/// would. A new topic has no posts yet, so its first post picks them up
+/// when it's created instead (see [`check_post`]).
+pub fn apply_to_title(
+    conn: &mut PgConnection,
+    topic_id: i32,
+    matches: &Matches,
+) -> Result<(), DieselError> {
+    let first: Option<Post> = posts::table
+        .filter(posts::topic_id.eq(topic_id))
+        .filter(posts::post_number.eq(1))
+        .select(Post::as_select())
+        .first(conn)
+        .optional()?;
+    match first {
+        Some(post) => apply(conn, &post, matches),
+        None => add_tags(conn, topic_id, &matches.tags),
+    }
+}
+
+/// Act on what a just-saved post matched: hide it pending approval, flag
+/// it, and tag its topic.
+pub fn apply(conn: &mut PgConnection, post: &Post, matches: &Matches) -> Result<(), DieselError> {
+    if !matches.require_approval.is_empty() {
+        diesel::update(posts::table.find(post.id))
+            .set((
+                posts::hidden.eq(true),
+                posts::hidden_at.eq(Some(Utc::now())),
+            ))
+            .execute(conn)?;
+        flag(
+            conn,
+            post.id,
+            APPROVAL_FLAG_REASON,
+            &matches.require_approval,
+        )?;
+    }
+    if !matches.flag.is_empty() {
+        flag(conn, post.id, FLAG_REASON, &matches.flag)?;
+    }
+    add_tags(conn, post.topic_id, &matches.tags)
+}
+
+fn flag(
+    conn: &mut PgConnection,
+    post_id: i32,
+    reason: &str,
+    words: &[String],
+) -> Result<(), DieselError> {
+    diesel::insert_into(post_flags::table)
+        .values(&NewPostFlag {
+            post_id,
+            user_id: None,
+            reason: reason.to_string(),
+            message: Some(format!("Watched words: {}", words.join(", "))),
+        })
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Tag a topic, skipping tags it already has.
+pub fn add_tags(
+    conn: &mut PgConnection,
+    topic_id: i32,
+    tags: &[String],
+) -> Result<(), DieselError> {
+    if tags.is_empty() {
+        return Ok(());
+    }
+    let rows: Vec<_> = tags
+        .iter()
+        .map(|name| (topic_tags::topic_id.eq(topic_id), topic_tags::name.eq(name)))
+        .collect();
+    diesel::insert_into(topic_tags::table)
+        .values(&rows)
+        .on_conflict_do_nothing()
+        .execute(conn)?;
+    Ok(())
+}
+
+/// A topic's tags, alphabetically.
+pub fn topic_tags(conn: &mut PgConnection, topic_id: i32) -> Result<Vec<String>, DieselError> {
+    topic_tags::table
+        .filter(topic_tags::topic_id.eq(topic_id))
+        .order(topic_tags::name)
+        .select(topic_tags::name)
+        .load(conn)
+}
+
+/// Every watched word, grouped by action.
+pub fn list(conn: &mut PgConnection) -> Result<Vec<WatchedWord>, DieselError> {
+    watched_words::table
+        .order((watched_words::action, watched_words::word))
+        .select(WatchedWord::as_select())
+        .load(conn)
+}
+
+pub fn create(conn: &mut PgConnection, new: NewWatchedWord) -> Result<WatchedWord, DieselError> {
+    diesel::insert_into(watched_words::table)
+        .values(&new)
+        .returning(WatchedWord::as_returning())
+        .get_result(conn)
+}
+
+/// Replace a watched word. `Ok(None)` if it doesn't exist.
+pub fn update(
+    conn: &mut PgConnection,
+    id: i32,
+    new: NewWatchedWord,
+) -> Result<Option<WatchedWord>, DieselError> {
+    diesel::update(watched_words::table.find(id))
+        .set((&new, watched_words::updated_at.eq(Utc::now())))
+        .returning(WatchedWord::as_returning())
+        .get_result(conn)
+        .optional()
+}
+
+/// Remove a watched word. Posts already cooked keep whatever it did to
+/// them until they're next cooked. Returns whether it existed.
+pub fn delete(conn: &mut PgConnection, id: i32) -> Result<bool, DieselError> {
+    let deleted = diesel::delete(watched_words::table.find(id)).execute(conn)?;
+    Ok(deleted > 0)
+}
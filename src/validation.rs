@@ -0,0 +1,331 @@
+//! Request body validation.
+//!
+//! Input structs derive [`validator::Validate`], and handlers take them
+//! through [`ValidatedJson`] instead of `web::Json`. A body that parses but
+//! fails validation is rejected with a 422 [`ApiError::Validation`] listing
+//! every failing field:
+//!
+//! ```json
+//! {
+//!   "error": "Validation failed",
+//!   "code": "validation_failed",
+//!   "fields": {
+//!     "username": [{"code": "username_charset", "message": "may only contain letters, numbers, _, . and -"}],
+//!     "password": [{"code": "length", "message": "must be at least 8 characters"}]
+//!   }
+//! }
+//! ```
+//!
+//! Rules shared between structs (usernames, passwords, slugs, colors) are
+//! the `validate_*` functions and limits here, so registration and the
+//! admin user form can't drift apart.
+
+use actix_web::{FromRequest, HttpRequest, dev::Payload, web};
+use futures::future::LocalBoxFuture;
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value, json};
+
+use crate::error::ApiError;
+use std::borrow::Cow;
+use std::ops::Deref;
+use validator::{Validate, ValidateUrl, ValidationError, ValidationErrors, ValidationErrorsKind};
+
+pub const USERNAME_MIN_LENGTH: u64 = 3;
+pub const USERNAME_MAX_LENGTH: u64 = 20;
+
+pub const PASSWORD_MIN_LENGTH: u64 = 8;
+/// bcrypt ignores everything past 72 bytes, so longer passwords would
+/// silently be truncated.
+pub const PASSWORD_MAX_BYTES: usize = 72;
+
+pub const TITLE_MIN_LENGTH: u64 = 15;
+pub const TITLE_MAX_LENGTH: u64 = 255;
+
+pub const POST_MAX_LENGTH: u64 = 32_000;
+
+/// A JSON body that has passed [`Validate`]. Derefs to the inner value
+/// like `web::Json`.
+pub struct ValidatedJson<T>(pub T);
+
+impl<T> ValidatedJson<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for ValidatedJson<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: DeserializeOwned + Validate + 'static> FromRequest for ValidatedJson<T> {
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let json = web::Json::<T>::from_request(req, payload);
+        Box::pin(async move {
+            let value = json
+                .await
+                .map_err(|e| ApiError::BadRequest(format!("Invalid JSON body: {e}")))?
+                .into_inner();
+            value.validate().map_err(ApiError::Validation)?;
+            Ok(ValidatedJson(value))
+        })
+    }
+}
+
+/// The `fields` object of a 422 body: failing field name to its errors.
+/// Nested structs are flattened into dotted field names and list items
+/// into `field[index]`.
+pub fn field_errors(errors: &ValidationErrors) -> Value {
+    let mut fields = Map::new();
+    collect(errors, "", &mut fields);
+    Value::Object(fields)
+}
+
+fn collect(errors: &ValidationErrors, prefix: &str, out: &mut Map<String, Value>) {
+    for (field, kind) in errors.errors() {
+        let name = if *field == "__all__" {
+            // Struct-level checks have no field of their own.
+            if prefix.is_empty() {
+                "_".to_string()
+            } else {
+                prefix.to_string()
+            }
+        } else if prefix.is_empty() {
+            field.to_string()
+        } else {
+            format!("{prefix}.{field}")
+        };
+        match kind {
+            ValidationErrorsKind::Field(errs) => {
+                let list = out.entry(name).or_insert_with(|| Value::Array(Vec::new()));
+                if let Value::Array(list) = list {
+                    list.extend(
+                        errs.iter()
+                            .map(|e| json!({ "code": e.code, "message": message(e) })),
+                    );
+                }
+            }
+            ValidationErrorsKind::Struct(inner) => collect(inner, &name, out),
+            ValidationErrorsKind::List(items) => {
+                for (index, inner) in items {
+                    collect(inner, &format!("{name}[{index}]"), out);
+                }
+            }
+        }
+    }
+}
+
+/// The error's own message, or one built from the built-in validator's
+/// code and parameters.
+fn message(error: &ValidationError) -> String {
+    if let Some(message) = &error.message {
+        return message.to_string();
+    }
+    let param = |key: &str| error.params.get(key).map(|v| v.to_string());
+    match (error.code.as_ref(), param("min"), param("max")) {
+        ("length", Some(min), Some(max)) => format!("must be {min} to {max} characters"),
+        ("length", Some(min), None) if min == "1" => "can't be blank".to_string(),
+        ("length", Some(min), None) => format!("must be at least {min} characters"),
+        ("length", None, Some(max)) => format!("must be at most {max} characters"),
+        ("range", Some(min), Some(max)) => format!("must be between {min} and {max}"),
+        ("range", Some(min), None) => format!("must be at least {min}"),
+        ("range", None, Some(max)) => format!("must be at most {max}"),
+        ("email", _, _) => "must be a valid email address".to_string(),
+        ("url", _, _) => "must be a valid URL".to_string(),
+        (code, _, _) => format!("is invalid ({code})"),
+    }
+}
+
+fn invalid(code: &'static str, message: &'static str) -> ValidationError {
+    ValidationError::new(code).with_message(Cow::Borrowed(message))
+}
+
+/// Letters, digits, `_`, `.` and `-`, starting and ending with a letter,
+/// digit or `_`. Length is checked separately.
+pub fn validate_username(username: &str) -> Result<(), ValidationError> {
+    if !username
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'))
+    {
+        return Err(invalid(
+            "username_charset",
+            "may only contain letters, numbers, _, . and -",
+        ));
+    }
+    let edge_ok = |c: Option<char>| c.is_some_and(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !edge_ok(username.chars().next()) || !edge_ok(username.chars().last()) {
+        return Err(invalid(
+            "username_edges",
+            "must start and end with a letter, number or _",
+        ));
+    }
+    Ok(())
+}
+
+/// At least [`PASSWORD_MIN_LENGTH`] characters, no more than
+/// [`PASSWORD_MAX_BYTES`] bytes, and not only letters or only digits.
+pub fn validate_password(password: &str) -> Result<(), ValidationError> {
+    if (password.chars().count() as u64) < PASSWORD_MIN_LENGTH {
+        return Err(invalid("length", "must be at least 8 characters"));
+    }
+    if password.len() > PASSWORD_MAX_BYTES {
+        return Err(invalid("length", "must be at most 72 bytes"));
+    }
+    let letters = password.chars().filter(|c| c.is_alphabetic()).count();
+    if letters == 0 || letters == password.chars().count() {
+        return Err(invalid(
+            "password_too_simple",
+            "must mix letters with numbers or symbols",
+        ));
+    }
+    Ok(())
+}
+
+/// Lowercase letters, digits and single hyphens between them, as in
+/// `general-discussion`.
+pub fn validate_slug(slug: &str) -> Result<(), ValidationError> {
+    let ok = !slug.is_empty()
+        && slug.split('-').all(|part| {
+            !part.is_empty()
+                && part
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+        });
+    if ok {
+        Ok(())
+    } else {
+        Err(invalid(
+            "slug",
+            "may only contain lowercase letters, numbers and single hyphens",
+        ))
+    }
+}
+
+/// Six hex digits without the `#`, e.g. `0088CC`.
+pub fn validate_color(color: &str) -> Result<(), ValidationError> {
+    if color.len() == 6 && color.chars().all(|c| c.is_ascii_hexdigit()) {
+        Ok(())
+    } else {
+        Err(invalid("color", "must be 6 hex digits, like 0088CC"))
+    }
+}
+
+/// An emoji shortcode without the colons, e.g. `party_parrot`.
+pub fn validate_emoji_name(name: &str) -> Result<(), ValidationError> {
+    if crate::emoji::is_valid_name(name) {
+        Ok(())
+    } else {
+        Err(invalid(
+            "emoji_name",
+            "must be 1-50 lowercase letters, numbers, _, + or -",
+        ))
+    }
+}
+
+/// An absolute `http` or `https` URL.
+pub fn validate_http_url(url: &str) -> Result<(), ValidationError> {
+    let scheme_ok = ["http://", "https://"]
+        .iter()
+        .any(|s| url.get(..s.len()).is_some_and(|p| p.eq_ignore_ascii_case(s)));
+    if scheme_ok && url.validate_url() {
+        Ok(())
+    } else {
+        Err(invalid("url", "must be a valid http or https URL"))
+    }
+}
+
+/// One of the watched-word actions in [`crate::models::watched_word::ACTIONS`].
+pub fn validate_watched_word_action(action: &str) -> Result<(), ValidationError> {
+    if crate::models::watched_word::ACTIONS.contains(&action) {
+        Ok(())
+    } else {
+        Err(invalid(
+            "watched_word_action",
+            "must be one of block, censor, replace, require_approval, flag or tag",
+        ))
+    }
+}
+
+/// Rejects strings that are empty once whitespace is trimmed.
+pub fn validate_not_blank(value: &str) -> Result<(), ValidationError> {
+    if value.trim().is_empty() {
+        Err(invalid("blank", "can't be blank"))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn usernames() {
+        assert!(validate_username("alice").is_ok());
+        assert!(validate_username("_bob.smith-2").is_ok());
+        assert!(validate_username("bad name").is_err());
+        assert!(validate_username("émile").is_err());
+        assert!(validate_username(".dot").is_err());
+        assert!(validate_username("dash-").is_err());
+    }
+
+    #[test]
+    fn passwords() {
+        assert!(validate_password("password123").is_ok());
+        assert!(validate_password("correct horse").is_ok());
+        assert!(validate_password("short1").is_err());
+        assert!(validate_password("onlyletters").is_err());
+        assert!(validate_password("1234567890").is_err());
+        assert!(validate_password(&format!("a1{}", "x".repeat(71))).is_err());
+    }
+
+    #[test]
+    fn slugs_and_colors() {
+        assert!(validate_slug("general-discussion").is_ok());
+        assert!(validate_slug("v2").is_ok());
+        assert!(validate_slug("Upper").is_err());
+        assert!(validate_slug("double--hyphen").is_err());
+        assert!(validate_slug("-leading").is_err());
+
+        assert!(validate_color("0088CC").is_ok());
+        assert!(validate_color("abcdef").is_ok());
+        assert!(validate_color("#0088CC").is_err());
+        assert!(validate_color("0088CCFF").is_err());
+        assert!(validate_color("zzzzzz").is_err());
+    }
+
+    #[derive(Validate)]
+    struct Form {
+        #[validate(length(min = 3, max = 20), custom(function = "validate_username"))]
+        username: String,
+        #[validate(email)]
+        email: String,
+    }
+
+    #[test]
+    fn field_errors_list_every_failing_field() {
+        let form = Form {
+            username: "@".to_string(),
+            email: "nope".to_string(),
+        };
+        let fields = field_errors(&form.validate().unwrap_err());
+
+        let username = fields["username"].as_array().unwrap();
+        let codes: Vec<_> = username
+            .iter()
+            .map(|e| e["code"].as_str().unwrap())
+            .collect();
+        assert!(codes.contains(&"length"));
+        assert!(codes.contains(&"username_charset"));
+        assert_eq!(
+            fields["email"][0]["message"],
+            "must be a valid email address"
+        );
+    }
+}
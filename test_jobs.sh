@@ -11,8 +11,8 @@ echo "Testing Background Jobs"
 echo "========================================="
 echo ""
 
-# First, register a user and get a token
-echo "1. Registering test user..."
+# Registering a user enqueues their welcome email
+echo "1. Registering test user (enqueues a welcome email job)..."
 RESPONSE=$(curl -s -X POST "$BASE_URL/api/auth/register" \
   -H "Content-Type: application/json" \
   -d '{"username":"jobtest","email":"jobtest@example.com","password":"password123"}')
@@ -21,49 +21,32 @@ TOKEN=$(echo "$RESPONSE" | jq -r '.token')
 echo "Token: ${TOKEN:0:20}..."
 echo ""
 
-# Create a topic
-echo "2. Creating a test topic..."
-TOPIC_RESPONSE=$(curl -s -X POST "$BASE_URL/api/topics" \
-  -H "Content-Type: application/json" \
-  -H "Authorization: Bearer $TOKEN" \
-  -d '{"title":"Test Topic for Jobs","user_id":1}')
-
-TOPIC_ID=$(echo "$TOPIC_RESPONSE" | jq -r '.id')
-echo "Created topic ID: $TOPIC_ID"
-echo ""
+# Anything else is enqueued by name through the admin API, which needs an
+# admin token
+if [ -z "$ADMIN_TOKEN" ]; then
+  echo "Set ADMIN_TOKEN to an admin's JWT to enqueue jobs by name."
+  echo "Check server logs for 'Sending welcome email to user...'."
+  exit 0
+fi
 
-# Enqueue welcome email job
-echo "3. Enqueueing welcome email job..."
-WELCOME_JOB=$(curl -s -X POST "$BASE_URL/api/jobs/welcome_email" \
+echo "2. Enqueueing a rebake of every post as admin..."
+REBAKE_JOB=$(curl -s -X POST "$BASE_URL/api/admin/jobs/rebake_posts" \
   -H "Content-Type: application/json" \
-  -d '{
-    "user_id": 1,
-    "username": "jobtest",
-    "email": "jobtest@example.com"
-  }')
+  -H "Authorization: Bearer $ADMIN_TOKEN" \
+  -d '{"after_id": 0}')
 
-echo "Welcome email job response:"
-echo "$WELCOME_JOB" | jq '.'
+echo "Rebake job response:"
+echo "$REBAKE_JOB" | jq '.'
 echo ""
 
-# Enqueue process topic job
-echo "4. Enqueueing process topic job..."
-PROCESS_JOB=$(curl -s -X POST "$BASE_URL/api/jobs/process_topic" \
-  -H "Content-Type: application/json" \
-  -d "{
-    \"topic_id\": $TOPIC_ID,
-    \"action\": \"index\"
-  }")
-
-echo "Process topic job response:"
-echo "$PROCESS_JOB" | jq '.'
+echo "3. Job stats:"
+curl -s "$BASE_URL/api/admin/jobs/stats" \
+  -H "Authorization: Bearer $ADMIN_TOKEN" | jq '.'
 echo ""
 
-echo "5. Jobs enqueued! Check server logs to see them being processed."
+echo "Check server logs to see the jobs being processed."
 echo "   Look for messages like:"
-echo "   - 'Starting background job worker pool...'"
 echo "   - 'Sending welcome email to user...'"
-echo "   - 'Processing topic...'"
 echo ""
 
 echo "========================================="
@@ -19,7 +19,7 @@ echo "2. Creating test topic..."
 TOPIC_RESPONSE=$(curl -s -X POST 'http://127.0.0.1:8080/api/topics' \
   -H 'Content-Type: application/json' \
   -H "Authorization: Bearer $TOKEN" \
-  -d '{"title":"Test Topic","user_id":1,"category_id":1}')
+  -d '{"title":"Pagination test topic","user_id":1,"category_id":1}')
 TOPIC_ID=$(echo "$TOPIC_RESPONSE" | jq -r '.id')
 echo "Created topic ID: $TOPIC_ID"
 echo ""
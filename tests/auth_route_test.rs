@@ -0,0 +1,366 @@
+//! Route-level tests for /api/auth: registration, email confirmation and
+//! what unconfirmed accounts may do. The token bookkeeping is covered in
+//! `email_confirmation_test.rs`.
+
+mod common;
+
+use actix_web::{test, web};
+use diesel::prelude::*;
+use discourse_rs::jobs::JobQueue;
+use discourse_rs::schema::backie_tasks;
+use serde_json::json;
+use std::sync::Arc;
+
+/// Payloads of pending jobs named `task_name`, oldest first.
+fn pending_payloads(conn: &mut PgConnection, task_name: &str) -> Vec<serde_json::Value> {
+    backie_tasks::table
+        .filter(backie_tasks::task_name.eq(task_name))
+        .filter(backie_tasks::running_at.is_null())
+        .order(backie_tasks::created_at)
+        .select(backie_tasks::payload)
+        .load(conn)
+        .unwrap()
+}
+
+fn register_request(username: &str) -> test::TestRequest {
+    test::TestRequest::post()
+        .uri("/api/auth/register")
+        .set_json(json!({
+            "username": username,
+            "email": format!("{username}@example.com"),
+            "password": "password123",
+        }))
+}
+
+fn login_request(username: &str) -> test::TestRequest {
+    test::TestRequest::post()
+        .uri("/api/auth/login")
+        .set_json(json!({ "username": username, "password": "password123" }))
+}
+
+#[actix_web::test]
+async fn registration_emails_a_confirmation_link() {
+    let mut ctx = common::setup();
+    let app = test::init_service(
+        common::test_app_factory().app_data(web::Data::new(JobQueue::new(Arc::new(ctx.pool())))),
+    )
+    .await;
+
+    let resp = test::call_service(&app, register_request("newcomer").to_request()).await;
+    assert_eq!(resp.status().as_u16(), 201);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["user"]["email_confirmed"], false);
+    // The default access setting lets unconfirmed users log in.
+    assert!(body["token"].is_string());
+
+    let jobs = pending_payloads(&mut ctx.conn, "confirm_email");
+    assert_eq!(jobs.len(), 1);
+    assert_eq!(jobs[0]["user_id"], body["user"]["id"]);
+    assert_eq!(jobs[0]["email"], "newcomer@example.com");
+    assert!(pending_payloads(&mut ctx.conn, "welcome_email").is_empty());
+}
+
+#[actix_web::test]
+async fn confirming_logs_in_and_sends_welcome_once() {
+    let mut ctx = common::setup();
+    let app = test::init_service(
+        common::test_app_factory().app_data(web::Data::new(JobQueue::new(Arc::new(ctx.pool())))),
+    )
+    .await;
+
+    test::call_service(&app, register_request("confirmer").to_request()).await;
+    let token = pending_payloads(&mut ctx.conn, "confirm_email")[0]["token"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/auth/confirm/{token}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status().as_u16(), 200);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["user"]["email_confirmed"], true);
+    assert!(body["token"].is_string());
+    assert_eq!(pending_payloads(&mut ctx.conn, "welcome_email").len(), 1);
+
+    // Links work once.
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/auth/confirm/{token}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status().as_u16(), 400);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["error"], "Invalid or expired confirmation token");
+    assert_eq!(pending_payloads(&mut ctx.conn, "welcome_email").len(), 1);
+}
+
+#[actix_web::test]
+async fn access_none_blocks_login_until_confirmed() {
+    let mut ctx = common::setup();
+    common::set_setting(&mut ctx.conn, "unconfirmed_user_access", "none");
+    let app = test::init_service(
+        common::test_app_factory().app_data(web::Data::new(JobQueue::new(Arc::new(ctx.pool())))),
+    )
+    .await;
+
+    let resp = test::call_service(&app, register_request("locked").to_request()).await;
+    assert_eq!(resp.status().as_u16(), 201);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert!(body.get("token").is_none());
+
+    let resp = test::call_service(&app, login_request("locked").to_request()).await;
+    assert_eq!(resp.status().as_u16(), 403);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["code"], "email_not_confirmed");
+
+    let token = pending_payloads(&mut ctx.conn, "confirm_email")[0]["token"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/auth/confirm/{token}"))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status().as_u16(), 200);
+
+    let resp = test::call_service(&app, login_request("locked").to_request()).await;
+    assert_eq!(resp.status().as_u16(), 200);
+}
+
+#[actix_web::test]
+async fn unconfirmed_users_cannot_edit_and_lose_sessions_under_none() {
+    let mut ctx = common::setup();
+    let user = common::create_user(
+        &mut ctx.conn,
+        common::UserOpts {
+            email_confirmed: false,
+            ..Default::default()
+        },
+    );
+    // Written while `full` allowed it.
+    let topic = common::create_topic(&mut ctx.conn, common::TopicOpts::for_user(user.id));
+    let post = common::create_post(
+        &mut ctx.conn,
+        common::PostOpts::for_topic(topic.id, user.id),
+    );
+    let app = test::init_service(common::test_app_factory()).await;
+    let (hk, hv) = common::auth_header_for(&user);
+
+    let req = test::TestRequest::put()
+        .uri(&format!("/api/posts/{}", post.id))
+        .insert_header((hk, hv.clone()))
+        .set_json(json!({ "raw": "Edited while still unconfirmed" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status().as_u16(), 403);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["code"], "email_not_confirmed");
+
+    let me = || {
+        test::TestRequest::get()
+            .uri("/api/users/me/2fa")
+            .insert_header((hk, hv.clone()))
+            .to_request()
+    };
+    assert_eq!(test::call_service(&app, me()).await.status().as_u16(), 200);
+
+    // The token was issued before the switch, and stops working with it.
+    common::set_setting(&mut ctx.conn, "unconfirmed_user_access", "none");
+    assert_eq!(test::call_service(&app, me()).await.status().as_u16(), 401);
+}
+
+#[actix_web::test]
+async fn unconfirmed_users_cannot_post_by_default() {
+    let mut ctx = common::setup();
+    let user = common::create_user(
+        &mut ctx.conn,
+        common::UserOpts {
+            email_confirmed: false,
+            ..Default::default()
+        },
+    );
+    let app = test::init_service(common::test_app_factory()).await;
+    let (hk, hv) = common::auth_header_for(&user);
+
+    let topic = json!({
+        "title": "Hello from the tests",
+        "slug": "hello",
+        "user_id": user.id,
+        "category_id": null,
+        "views": 0,
+        "posts_count": 0,
+        "pinned": false,
+        "closed": false,
+    });
+    let req = test::TestRequest::post()
+        .uri("/api/topics")
+        .insert_header((hk, hv.clone()))
+        .set_json(&topic)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status().as_u16(), 403);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["error"], "Confirm your email address before posting");
+
+    common::set_setting(&mut ctx.conn, "unconfirmed_user_access", "full");
+    let req = test::TestRequest::post()
+        .uri("/api/topics")
+        .insert_header((hk, hv))
+        .set_json(&topic)
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status().as_u16(), 201);
+}
+
+#[actix_web::test]
+async fn resend_answers_the_same_when_rate_limited() {
+    let mut ctx = common::setup();
+    let app = test::init_service(
+        common::test_app_factory().app_data(web::Data::new(JobQueue::new(Arc::new(ctx.pool())))),
+    )
+    .await;
+    test::call_service(&app, register_request("impatient").to_request()).await;
+
+    // Straight after registration: too soon, so nothing is sent, but the
+    // answer doesn't give away that the address is registered.
+    let req = test::TestRequest::post()
+        .uri("/api/auth/confirm/resend")
+        .set_json(json!({ "email": "impatient@example.com" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status().as_u16(), 202);
+    assert!(!resp.headers().contains_key("Retry-After"));
+    let registered: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(pending_payloads(&mut ctx.conn, "confirm_email").len(), 1);
+
+    // Unknown addresses get the same answer as real ones.
+    let req = test::TestRequest::post()
+        .uri("/api/auth/confirm/resend")
+        .set_json(json!({ "email": "nobody@example.com" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status().as_u16(), 202);
+    let unknown: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(registered, unknown);
+}
+
+#[actix_web::test]
+async fn forgot_password_emails_a_reset_link() {
+    let mut ctx = common::setup();
+    let user = common::create_user(&mut ctx.conn, Default::default());
+    let app = test::init_service(
+        common::test_app_factory().app_data(web::Data::new(JobQueue::new(Arc::new(ctx.pool())))),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/auth/forgot_password")
+        .set_json(json!({ "login": user.email }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status().as_u16(), 202);
+    let jobs = pending_payloads(&mut ctx.conn, "password_reset_email");
+    assert_eq!(jobs.len(), 1);
+    assert_eq!(jobs[0]["user_id"], user.id);
+
+    // Unknown accounts get the same answer, and nothing is sent.
+    let req = test::TestRequest::post()
+        .uri("/api/auth/forgot_password")
+        .set_json(json!({ "login": "nobody" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status().as_u16(), 202);
+    assert_eq!(
+        pending_payloads(&mut ctx.conn, "password_reset_email").len(),
+        1
+    );
+}
+
+#[actix_web::test]
+async fn reset_password_logs_out_old_sessions() {
+    let mut ctx = common::setup();
+    let app = test::init_service(
+        common::test_app_factory().app_data(web::Data::new(JobQueue::new(Arc::new(ctx.pool())))),
+    )
+    .await;
+    let resp = test::call_service(&app, register_request("forgetful").to_request()).await;
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    let old_token = body["token"].as_str().unwrap().to_string();
+
+    let req = test::TestRequest::post()
+        .uri("/api/auth/forgot_password")
+        .set_json(json!({ "login": "forgetful" }))
+        .to_request();
+    test::call_service(&app, req).await;
+    let reset_token = pending_payloads(&mut ctx.conn, "password_reset_email")[0]["token"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let req = test::TestRequest::post()
+        .uri("/api/auth/reset_password")
+        .set_json(json!({ "token": reset_token, "password": "brand-new-pass" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status().as_u16(), 200);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    let new_token = body["token"].as_str().unwrap().to_string();
+    // Following the emailed link proves the address.
+    assert_eq!(body["user"]["email_confirmed"], true);
+
+    // Guardian-guarded routes answer 403 for a revoked token.
+    let req = test::TestRequest::get()
+        .uri("/api/notifications")
+        .insert_header(("Authorization", format!("Bearer {old_token}")))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status().as_u16(), 403);
+    let req = test::TestRequest::get()
+        .uri("/api/notifications")
+        .insert_header(("Authorization", format!("Bearer {new_token}")))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status().as_u16(), 200);
+
+    let req = test::TestRequest::post()
+        .uri("/api/auth/login")
+        .set_json(json!({ "username": "forgetful", "password": "brand-new-pass" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status().as_u16(), 200);
+
+    let req = test::TestRequest::post()
+        .uri("/api/auth/reset_password")
+        .set_json(json!({ "token": reset_token, "password": "another-pass1" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status().as_u16(), 400);
+}
+
+#[actix_web::test]
+async fn change_password_requires_current_and_revokes_sessions() {
+    let _ctx = common::setup();
+    let app = test::init_service(common::test_app_factory()).await;
+    let resp = test::call_service(&app, register_request("changer").to_request()).await;
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    let old_token = body["token"].as_str().unwrap().to_string();
+
+    let change = |token: &str, current: &str| {
+        test::TestRequest::put()
+            .uri("/api/users/me/password")
+            .insert_header(("Authorization", format!("Bearer {token}")))
+            .set_json(json!({ "current_password": current, "new_password": "changed-pass" }))
+            .to_request()
+    };
+
+    let resp = test::call_service(&app, change(&old_token, "wrong")).await;
+    assert_eq!(resp.status().as_u16(), 403);
+
+    let resp = test::call_service(&app, change(&old_token, "password123")).await;
+    assert_eq!(resp.status().as_u16(), 200);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    let new_token = body["token"].as_str().unwrap().to_string();
+
+    // The old token no longer works; the one handed back does.
+    let resp = test::call_service(&app, change(&old_token, "changed-pass")).await;
+    assert_eq!(resp.status().as_u16(), 401);
+
+    let req = test::TestRequest::get()
+        .uri("/api/notifications")
+        .insert_header(("Authorization", format!("Bearer {new_token}")))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status().as_u16(), 200);
+}
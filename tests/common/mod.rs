@@ -12,24 +12,34 @@
 //! share one test database. The `nix-shell` exports a `TEST_DATABASE_URL`
 //! pointing at `discourse_rs_test`; tests will not touch the dev DB.
 
+// Each test binary compiles its own copy of this module and only uses a
+// subset of the helpers, so unused-item warnings are expected here.
+#![allow(dead_code)]
+
 use diesel::pg::PgConnection;
 use diesel::prelude::*;
 use diesel::r2d2::{self, ConnectionManager, PooledConnection};
 use std::env;
 use std::sync::OnceLock;
 
+use discourse_rs::jobs::{FailureOutcome, JobRecord, NewJob, record_failure};
 use discourse_rs::models::{Category, NewCategory, NewPost, NewTopic, NewUser, Post, Topic, User};
-use discourse_rs::schema::{categories, posts, topics, users};
+use discourse_rs::schema::{backie_tasks, categories, posts, topics, users};
 use discourse_rs::DbPool;
 
+/// The test database's URL, for tests that need their own raw connection
+/// (e.g. to `LISTEN`).
+pub fn database_url() -> String {
+    env::var("TEST_DATABASE_URL")
+        .expect("TEST_DATABASE_URL must be set (run `db_test_setup` in nix-shell)")
+}
+
 /// Build (or reuse) the pool. r2d2 pools are cheap to clone, so one
 /// process-wide pool is fine — each test checks out its own connection.
 fn pool() -> &'static DbPool {
     static POOL: OnceLock<DbPool> = OnceLock::new();
     POOL.get_or_init(|| {
-        let url = env::var("TEST_DATABASE_URL")
-            .expect("TEST_DATABASE_URL must be set (run `db_test_setup` in nix-shell)");
-        let manager = ConnectionManager::<PgConnection>::new(url);
+        let manager = ConnectionManager::<PgConnection>::new(database_url());
         r2d2::Pool::builder()
             .max_size(4)
             .build(manager)
@@ -57,15 +67,26 @@ impl TestCtx {
 // in a flaky test.
 const TRUNCATE_SQL: &str = "TRUNCATE TABLE \
     notifications, \
+    post_flags, \
+    queued_posts, \
+    topic_tags, \
+    topic_watches, \
+    watched_words, \
     moderation_actions, \
     post_likes, \
+    post_mentions, \
     posts, \
     topic_views, \
     topics, \
     categories, \
+    custom_emojis, \
     site_settings, \
     backie_tasks, \
+    email_logs, \
+    email_tokens, \
+    link_previews, \
     user_stats, \
+    user_backup_codes, \
     user_suspensions, \
     users \
     RESTART IDENTITY CASCADE";
@@ -100,6 +121,7 @@ pub struct UserOpts {
     pub admin: bool,
     pub moderator: bool,
     pub trust_level: i32,
+    pub email_confirmed: bool,
 }
 
 impl Default for UserOpts {
@@ -110,6 +132,7 @@ impl Default for UserOpts {
             admin: false,
             moderator: false,
             trust_level: 0,
+            email_confirmed: true,
         }
     }
 }
@@ -122,6 +145,7 @@ pub fn create_user(conn: &mut PgConnection, opts: UserOpts) -> User {
         admin: opts.admin,
         moderator: opts.moderator,
         trust_level: opts.trust_level,
+        email_confirmed: opts.email_confirmed,
     };
     let user: User = diesel::insert_into(users::table)
         .values(&new)
@@ -234,6 +258,7 @@ pub fn create_post(conn: &mut PgConnection, opts: PostOpts) -> Post {
         raw: opts.raw.clone(),
         cooked: opts.raw,
         reply_to_post_number: None,
+        cook_version: 0,
     };
     diesel::insert_into(posts::table)
         .values(&new)
@@ -242,6 +267,65 @@ pub fn create_post(conn: &mut PgConnection, opts: PostOpts) -> Post {
         .expect("create_post failed")
 }
 
+/// Insert a pending background job directly, bypassing `JobQueue` (which
+/// needs a pool and a concrete `Job` type). `max_retries` matches the
+/// queue's default.
+pub fn create_job(
+    conn: &mut PgConnection,
+    task_name: &str,
+    payload: serde_json::Value,
+) -> JobRecord {
+    let new = NewJob {
+        task_name: task_name.to_string(),
+        task_hash: format!("test-{}", random_suffix()),
+        payload,
+        timeout_msecs: 30_000,
+        max_retries: 3,
+        scheduled_at: chrono::Utc::now().naive_utc(),
+        queue_name: discourse_rs::jobs::DEFAULT_QUEUE.to_string(),
+        priority: 0,
+    };
+    diesel::insert_into(backie_tasks::table)
+        .values(&new)
+        .returning(JobRecord::as_returning())
+        .get_result(conn)
+        .expect("create_job failed")
+}
+
+/// A job's current row.
+pub fn reload_job(conn: &mut PgConnection, job: &JobRecord) -> JobRecord {
+    backie_tasks::table
+        .find(job.id)
+        .select(JobRecord::as_select())
+        .first(conn)
+        .unwrap()
+}
+
+/// Fail `job` until it's dead, returning the final row.
+pub fn kill_job(conn: &mut PgConnection, job: JobRecord) -> JobRecord {
+    let mut job = job;
+    loop {
+        let outcome = record_failure(conn, &job, "boom").unwrap();
+        job = reload_job(conn, &job);
+        if outcome == FailureOutcome::Dead {
+            return job;
+        }
+    }
+}
+
+/// Upsert a site setting. `setup()` truncates `site_settings`, so tests
+/// start from the code defaults and set only what they depend on.
+pub fn set_setting(conn: &mut PgConnection, key: &str, value: &str) {
+    diesel::sql_query(
+        "INSERT INTO site_settings (key, value) VALUES ($1, $2) \
+         ON CONFLICT (key) DO UPDATE SET value = EXCLUDED.value",
+    )
+    .bind::<diesel::sql_types::Text, _>(key)
+    .bind::<diesel::sql_types::Text, _>(value)
+    .execute(conn)
+    .expect("set_setting failed");
+}
+
 // ─────────────────────────────────────────────────────────────────────────────
 // Route-level harness
 //
@@ -252,8 +336,9 @@ pub fn create_post(conn: &mut PgConnection, opts: PostOpts) -> Post {
 
 #[allow(dead_code)]
 pub fn auth_header_for(user: &User) -> (&'static str, String) {
-    let token = discourse_rs::auth::generate_token(user.id, user.username.clone())
-        .expect("generate_token failed");
+    let token =
+        discourse_rs::auth::generate_token(user.id, user.username.clone(), user.token_version)
+            .expect("generate_token failed");
     ("Authorization", format!("Bearer {token}"))
 }
 
@@ -268,8 +353,7 @@ pub fn auth_header_for(user: &User) -> (&'static str, String) {
 /// owns the `init_service().await` step; this keeps the helper's return
 /// type expressible without naming actix-http types.
 #[allow(dead_code)]
-pub fn test_app_factory()
--> actix_web::App<
+pub fn test_app_factory() -> actix_web::App<
     impl actix_web::dev::ServiceFactory<
         actix_web::dev::ServiceRequest,
         Config = (),
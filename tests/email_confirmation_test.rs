@@ -0,0 +1,207 @@
+//! Tests for the email confirmation service: tokens, confirming,
+//! resend rate limits, and the `unconfirmed_user_access` setting.
+
+mod common;
+
+use chrono::Duration;
+use diesel::prelude::*;
+use discourse_rs::models::{EmailToken, User};
+use discourse_rs::schema::{email_tokens, users};
+use discourse_rs::services::email_confirmation::{
+    self, ConfirmError, MAX_CONFIRMATIONS_PER_DAY, ResendOutcome, UnconfirmedAccess,
+};
+use discourse_rs::services::email_tokens::{self as tokens, CONFIRM_EMAIL};
+
+fn unconfirmed(conn: &mut PgConnection) -> User {
+    common::create_user(
+        conn,
+        common::UserOpts {
+            email_confirmed: false,
+            ..Default::default()
+        },
+    )
+}
+
+/// Pretend every token was issued (and expires) `by` earlier.
+fn age_tokens(conn: &mut PgConnection, by: Duration) {
+    diesel::sql_query(
+        "UPDATE email_tokens SET created_at = created_at - make_interval(secs => $1), \
+         expires_at = expires_at - make_interval(secs => $1)",
+    )
+    .bind::<diesel::sql_types::Double, _>(by.num_seconds() as f64)
+    .execute(conn)
+    .unwrap();
+}
+
+#[test]
+fn confirm_marks_email_confirmed_once() {
+    let mut ctx = common::setup();
+    let user = unconfirmed(&mut ctx.conn);
+    let token = email_confirmation::issue_token(&mut ctx.conn, &user).unwrap();
+
+    let confirmation = email_confirmation::confirm(&mut ctx.conn, &token).unwrap();
+    assert_eq!(confirmation.user.id, user.id);
+    assert!(confirmation.newly_confirmed);
+    assert!(confirmation.user.email_confirmed);
+    assert!(confirmation.user.email_confirmed_at.is_some());
+
+    assert!(matches!(
+        email_confirmation::confirm(&mut ctx.conn, &token),
+        Err(ConfirmError::InvalidToken)
+    ));
+}
+
+#[test]
+fn second_link_confirms_nothing_new() {
+    let mut ctx = common::setup();
+    let user = unconfirmed(&mut ctx.conn);
+    let first = email_confirmation::issue_token(&mut ctx.conn, &user).unwrap();
+    let second = email_confirmation::issue_token(&mut ctx.conn, &user).unwrap();
+
+    email_confirmation::confirm(&mut ctx.conn, &first).unwrap();
+    let again = email_confirmation::confirm(&mut ctx.conn, &second).unwrap();
+    assert!(!again.newly_confirmed);
+    assert!(again.user.email_confirmed);
+}
+
+#[test]
+fn only_the_hash_is_stored() {
+    let mut ctx = common::setup();
+    let user = unconfirmed(&mut ctx.conn);
+    let token = email_confirmation::issue_token(&mut ctx.conn, &user).unwrap();
+
+    let stored: EmailToken = email_tokens::table
+        .select(EmailToken::as_select())
+        .first(&mut ctx.conn)
+        .unwrap();
+    assert_eq!(stored.token_hash, tokens::hash(&token));
+    assert_ne!(stored.token_hash, token);
+    assert_eq!(stored.purpose, CONFIRM_EMAIL);
+    assert_eq!(stored.email, user.email);
+}
+
+#[test]
+fn expired_and_unknown_tokens_are_rejected() {
+    let mut ctx = common::setup();
+    let user = unconfirmed(&mut ctx.conn);
+    let token = email_confirmation::issue_token(&mut ctx.conn, &user).unwrap();
+    age_tokens(&mut ctx.conn, Duration::days(3));
+
+    assert!(matches!(
+        email_confirmation::confirm(&mut ctx.conn, &token),
+        Err(ConfirmError::InvalidToken)
+    ));
+    assert!(matches!(
+        email_confirmation::confirm(&mut ctx.conn, "not-a-token"),
+        Err(ConfirmError::InvalidToken)
+    ));
+}
+
+#[test]
+fn token_for_old_address_does_not_confirm_new_one() {
+    let mut ctx = common::setup();
+    let user = unconfirmed(&mut ctx.conn);
+    let token = email_confirmation::issue_token(&mut ctx.conn, &user).unwrap();
+    diesel::update(users::table.find(user.id))
+        .set(users::email.eq("changed@example.com"))
+        .execute(&mut ctx.conn)
+        .unwrap();
+
+    assert!(matches!(
+        email_confirmation::confirm(&mut ctx.conn, &token),
+        Err(ConfirmError::InvalidToken)
+    ));
+}
+
+#[test]
+fn resend_waits_between_emails() {
+    let mut ctx = common::setup();
+    let user = unconfirmed(&mut ctx.conn);
+    email_confirmation::issue_token(&mut ctx.conn, &user).unwrap();
+
+    match email_confirmation::resend(&mut ctx.conn, &user.email).unwrap() {
+        ResendOutcome::RateLimited { retry_after_secs } => {
+            assert!((1..=60).contains(&retry_after_secs), "{retry_after_secs}")
+        }
+        other => panic!("expected RateLimited, got {other:?}"),
+    }
+
+    age_tokens(&mut ctx.conn, Duration::minutes(2));
+    match email_confirmation::resend(&mut ctx.conn, &user.email).unwrap() {
+        ResendOutcome::Issued {
+            user: issued_to,
+            token,
+        } => {
+            assert_eq!(issued_to.id, user.id);
+            let confirmation = email_confirmation::confirm(&mut ctx.conn, &token).unwrap();
+            assert!(confirmation.newly_confirmed);
+        }
+        other => panic!("expected Issued, got {other:?}"),
+    }
+}
+
+#[test]
+fn resend_caps_emails_per_day() {
+    let mut ctx = common::setup();
+    let user = unconfirmed(&mut ctx.conn);
+    for _ in 0..MAX_CONFIRMATIONS_PER_DAY {
+        email_confirmation::issue_token(&mut ctx.conn, &user).unwrap();
+    }
+    age_tokens(&mut ctx.conn, Duration::hours(1));
+
+    match email_confirmation::resend(&mut ctx.conn, &user.email).unwrap() {
+        ResendOutcome::RateLimited { retry_after_secs } => {
+            // The oldest email ages out in about 23 hours.
+            assert!(retry_after_secs > 22 * 3600, "{retry_after_secs}");
+        }
+        other => panic!("expected RateLimited, got {other:?}"),
+    }
+
+    age_tokens(&mut ctx.conn, Duration::days(1));
+    assert!(matches!(
+        email_confirmation::resend(&mut ctx.conn, &user.email).unwrap(),
+        ResendOutcome::Issued { .. }
+    ));
+}
+
+#[test]
+fn resend_skips_confirmed_and_unknown_addresses() {
+    let mut ctx = common::setup();
+    let confirmed = common::create_user(&mut ctx.conn, common::UserOpts::default());
+
+    assert!(matches!(
+        email_confirmation::resend(&mut ctx.conn, &confirmed.email).unwrap(),
+        ResendOutcome::NotNeeded
+    ));
+    assert!(matches!(
+        email_confirmation::resend(&mut ctx.conn, "nobody@example.com").unwrap(),
+        ResendOutcome::NotNeeded
+    ));
+    let issued: i64 = email_tokens::table
+        .count()
+        .get_result(&mut ctx.conn)
+        .unwrap();
+    assert_eq!(issued, 0);
+}
+
+#[test]
+fn can_post_follows_access_setting() {
+    let mut ctx = common::setup();
+    let pending = unconfirmed(&mut ctx.conn);
+    let confirmed = common::create_user(&mut ctx.conn, common::UserOpts::default());
+
+    // No setting row: the default is read-only.
+    assert_eq!(
+        UnconfirmedAccess::current(&mut ctx.conn).unwrap(),
+        UnconfirmedAccess::Read
+    );
+    assert!(!email_confirmation::can_post(&mut ctx.conn, pending.id).unwrap());
+    assert!(email_confirmation::can_post(&mut ctx.conn, confirmed.id).unwrap());
+
+    common::set_setting(&mut ctx.conn, "unconfirmed_user_access", "full");
+    assert!(email_confirmation::can_post(&mut ctx.conn, pending.id).unwrap());
+
+    common::set_setting(&mut ctx.conn, "unconfirmed_user_access", "none");
+    assert!(!email_confirmation::can_post(&mut ctx.conn, pending.id).unwrap());
+    assert!(email_confirmation::can_post(&mut ctx.conn, confirmed.id).unwrap());
+}
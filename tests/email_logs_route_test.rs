@@ -0,0 +1,105 @@
+//! Route-level tests for /api/admin/email_logs. The bookkeeping is covered
+//! in `mailer_test.rs`; here we check the guard and the JSON contract.
+
+mod common;
+
+use actix_web::test;
+use discourse_rs::models::NewEmailLog;
+use discourse_rs::services::email_logs;
+use serde_json::json;
+
+fn sent_log(conn: &mut diesel::PgConnection, message_id: &str) {
+    email_logs::record(
+        conn,
+        &NewEmailLog {
+            user_id: None,
+            to_address: "erin@example.com".to_string(),
+            email_type: "welcome".to_string(),
+            subject: "Welcome".to_string(),
+            message_id: message_id.to_string(),
+            status: "sent".to_string(),
+            error: None,
+        },
+    )
+    .unwrap();
+}
+
+#[actix_web::test]
+async fn email_log_routes_require_admin() {
+    let mut ctx = common::setup();
+    let moderator = common::create_user(
+        &mut ctx.conn,
+        common::UserOpts {
+            moderator: true,
+            ..Default::default()
+        },
+    );
+
+    let app = test::init_service(common::test_app_factory()).await;
+    let (hk, hv) = common::auth_header_for(&moderator);
+
+    let req = test::TestRequest::get()
+        .uri("/api/admin/email_logs")
+        .insert_header((hk, hv.clone()))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status().as_u16(), 403);
+
+    let req = test::TestRequest::post()
+        .uri("/api/admin/email_logs/bounces")
+        .insert_header((hk, hv))
+        .set_json(json!({ "message_id": "x@localhost", "reason": "550" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status().as_u16(), 403);
+}
+
+#[actix_web::test]
+async fn admin_can_list_logs_and_record_bounce() {
+    let mut ctx = common::setup();
+    let admin = common::create_user(
+        &mut ctx.conn,
+        common::UserOpts {
+            admin: true,
+            ..Default::default()
+        },
+    );
+    sent_log(&mut ctx.conn, "first@localhost");
+    sent_log(&mut ctx.conn, "second@localhost");
+
+    let app = test::init_service(common::test_app_factory()).await;
+    let (hk, hv) = common::auth_header_for(&admin);
+
+    let req = test::TestRequest::post()
+        .uri("/api/admin/email_logs/bounces")
+        .insert_header((hk, hv.clone()))
+        .set_json(json!({ "message_id": "<first@localhost>", "reason": "550 no such user" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status().as_u16(), 200);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["status"], "bounced");
+
+    let req = test::TestRequest::post()
+        .uri("/api/admin/email_logs/bounces")
+        .insert_header((hk, hv.clone()))
+        .set_json(json!({ "message_id": "never-sent@localhost", "reason": "550" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status().as_u16(), 404);
+
+    let req = test::TestRequest::get()
+        .uri("/api/admin/email_logs?status=bounced")
+        .insert_header((hk, hv.clone()))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status().as_u16(), 200);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body.as_array().unwrap().len(), 1);
+    assert_eq!(body[0]["message_id"], "first@localhost");
+    assert_eq!(body[0]["error"], "550 no such user");
+
+    let req = test::TestRequest::get()
+        .uri("/api/admin/email_logs")
+        .insert_header((hk, hv))
+        .to_request();
+    let body: serde_json::Value = test::read_body_json(test::call_service(&app, req).await).await;
+    assert_eq!(body.as_array().unwrap().len(), 2);
+}
@@ -0,0 +1,121 @@
+//! Emoji: the picker listing, admin-managed custom emoji, and custom emoji
+//! in cooked posts.
+
+mod common;
+
+use actix_web::test;
+use discourse_rs::models::CreatePostInput;
+use discourse_rs::services::posts as post_service;
+use serde_json::{Value, json};
+
+#[actix_web::test]
+async fn admins_add_custom_emoji_that_show_up_in_the_picker_and_posts() {
+    let mut ctx = common::setup();
+    let admin = common::create_user(
+        &mut ctx.conn,
+        common::UserOpts {
+            admin: true,
+            ..Default::default()
+        },
+    );
+    let app = test::init_service(common::test_app_factory()).await;
+    let (hk, hv) = common::auth_header_for(&admin);
+
+    let req = test::TestRequest::post()
+        .uri("/api/admin/emojis")
+        .insert_header((hk, hv.clone()))
+        .set_json(json!({
+            "name": "party_parrot",
+            "url": "https://cdn.example.com/parrot.gif",
+            "group": "parrots",
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status().as_u16(), 201);
+
+    let req = test::TestRequest::get().uri("/api/emojis").to_request();
+    let list: Value = test::call_and_read_body_json(&app, req).await;
+    let groups = list["groups"].as_array().unwrap();
+    assert_eq!(groups.first(), Some(&json!("smileys")));
+    assert_eq!(groups.last(), Some(&json!("parrots")));
+    let emojis = list["emojis"].as_array().unwrap();
+    let thumbs = emojis.iter().find(|e| e["name"] == "+1").unwrap();
+    assert_eq!(thumbs["unicode"], "👍");
+    assert_eq!(thumbs["aliases"], json!(["thumbsup"]));
+    let parrot = emojis.last().unwrap();
+    assert_eq!(parrot["name"], "party_parrot");
+    assert_eq!(parrot["custom"], true);
+    assert_eq!(parrot["url"], "https://cdn.example.com/parrot.gif");
+
+    let topic = common::create_topic(&mut ctx.conn, common::TopicOpts::for_user(admin.id));
+    let post = post_service::create(
+        &mut ctx.conn,
+        CreatePostInput {
+            topic_id: topic.id,
+            user_id: admin.id,
+            post_number: 1,
+            raw: ":party_parrot: :tada: :unknown:".to_string(),
+            reply_to_post_number: None,
+        },
+    )
+    .unwrap();
+    assert!(post.cooked.contains(
+        r#"<img src="https://cdn.example.com/parrot.gif" title=":party_parrot:" class="emoji emoji-custom" alt=":party_parrot:"> 🎉 :unknown:"#
+    ));
+
+    let req = test::TestRequest::delete()
+        .uri("/api/admin/emojis/party_parrot")
+        .insert_header((hk, hv.clone()))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status().as_u16(), 204);
+    let req = test::TestRequest::delete()
+        .uri("/api/admin/emojis/party_parrot")
+        .insert_header((hk, hv))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status().as_u16(), 404);
+}
+
+#[actix_web::test]
+async fn custom_emoji_are_validated() {
+    let mut ctx = common::setup();
+    let admin = common::create_user(
+        &mut ctx.conn,
+        common::UserOpts {
+            admin: true,
+            ..Default::default()
+        },
+    );
+    let member = common::create_user(&mut ctx.conn, Default::default());
+    let app = test::init_service(common::test_app_factory()).await;
+
+    let create = |user, body: Value| {
+        let (hk, hv) = common::auth_header_for(user);
+        test::TestRequest::post()
+            .uri("/api/admin/emojis")
+            .insert_header((hk, hv))
+            .set_json(body)
+            .to_request()
+    };
+    let parrot = json!({ "name": "parrot", "url": "https://cdn.example.com/p.gif" });
+
+    let resp = test::call_service(&app, create(&member, parrot.clone())).await;
+    assert_eq!(resp.status().as_u16(), 403);
+
+    let resp = test::call_service(&app, create(&admin, parrot.clone())).await;
+    assert_eq!(resp.status().as_u16(), 201);
+    let resp = test::call_service(&app, create(&admin, parrot)).await;
+    assert_eq!(resp.status().as_u16(), 409, "duplicate custom name");
+
+    let builtin = json!({ "name": "thumbsup", "url": "https://cdn.example.com/t.gif" });
+    let resp = test::call_service(&app, create(&admin, builtin)).await;
+    assert_eq!(resp.status().as_u16(), 409, "shadows a built-in alias");
+
+    for body in [
+        json!({ "name": "Parrot", "url": "https://cdn.example.com/p.gif" }),
+        json!({ "name": "ok", "url": "javascript:alert(1)" }),
+        json!({ "name": "ok", "url": "/relative.png" }),
+    ] {
+        let resp = test::call_service(&app, create(&admin, body)).await;
+        assert_eq!(resp.status().as_u16(), 422);
+    }
+}
@@ -0,0 +1,113 @@
+//! Error responses share one shape, `{"error", "code"}`, with statuses
+//! decided by `ApiError`. The mappings are unit-tested in `src/error.rs`;
+//! these check that real database failures come through them.
+
+mod common;
+
+use actix_governor::{Governor, GovernorConfigBuilder};
+use actix_web::test;
+use discourse_rs::rate_limit::PeerIp;
+use serde_json::json;
+
+#[actix_web::test]
+async fn duplicate_username_is_a_conflict() {
+    let mut ctx = common::setup();
+    let existing = common::create_user(&mut ctx.conn, Default::default());
+    let app = test::init_service(common::test_app_factory()).await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/auth/register")
+        .set_json(json!({
+            "username": existing.username,
+            "email": "someone-else@example.com",
+            "password": "password123",
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status().as_u16(), 409);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["code"], "conflict");
+    assert_eq!(body["error"], "Username is already taken");
+}
+
+#[actix_web::test]
+async fn missing_records_are_not_found() {
+    let mut ctx = common::setup();
+    let user = common::create_user(&mut ctx.conn, Default::default());
+    let app = test::init_service(common::test_app_factory()).await;
+    let (hk, hv) = common::auth_header_for(&user);
+
+    let req = test::TestRequest::get()
+        .uri("/api/topics/999999")
+        .insert_header((hk, hv))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status().as_u16(), 404);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body, json!({ "error": "Topic not found", "code": "not_found" }));
+}
+
+#[actix_web::test]
+async fn unknown_foreign_keys_are_invalid_references() {
+    let mut ctx = common::setup();
+    let user = common::create_user(&mut ctx.conn, Default::default());
+    let app = test::init_service(common::test_app_factory()).await;
+    let (hk, hv) = common::auth_header_for(&user);
+
+    let req = test::TestRequest::post()
+        .uri("/api/topics")
+        .insert_header((hk, hv))
+        .set_json(json!({
+            "title": "A topic in no category",
+            "slug": "a-topic-in-no-category",
+            "user_id": user.id,
+            "category_id": 999999,
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status().as_u16(), 422);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["code"], "invalid_reference");
+    assert_eq!(body["error"], "category_id doesn't match an existing record");
+}
+
+#[actix_web::test]
+async fn missing_credentials_are_unauthorized() {
+    let _ctx = common::setup();
+    let app = test::init_service(common::test_app_factory()).await;
+
+    let req = test::TestRequest::delete().uri("/api/topics/1").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status().as_u16(), 401);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["code"], "unauthorized");
+}
+
+#[actix_web::test]
+async fn rate_limited_requests_get_the_error_body() {
+    let _ctx = common::setup();
+    let limit = GovernorConfigBuilder::default()
+        .key_extractor(PeerIp)
+        .seconds_per_request(60)
+        .burst_size(1)
+        .finish()
+        .unwrap();
+    let app = test::init_service(common::test_app_factory().wrap(Governor::new(&limit))).await;
+    let request = || {
+        test::TestRequest::get()
+            .uri("/api/categories")
+            .peer_addr("203.0.113.7:4000".parse().unwrap())
+            .to_request()
+    };
+
+    assert_eq!(
+        test::call_service(&app, request()).await.status().as_u16(),
+        200
+    );
+    let resp = test::call_service(&app, request()).await;
+    assert_eq!(resp.status().as_u16(), 429);
+    assert!(resp.headers().contains_key("retry-after"));
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["code"], "rate_limited");
+    assert!(body["retry_after"].as_i64().unwrap() > 0);
+}
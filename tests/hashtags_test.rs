@@ -0,0 +1,110 @@
+//! `#slug` hashtags resolved against categories and tags when a post is
+//! cooked.
+
+mod common;
+
+use discourse_rs::services::posts as post_service;
+use discourse_rs::services::watched_words;
+
+#[test]
+fn category_hashtags_link_with_the_category_color() {
+    let mut ctx = common::setup();
+    let user = common::create_user(&mut ctx.conn, Default::default());
+    common::create_category(
+        &mut ctx.conn,
+        common::CategoryOpts {
+            name: "Support".to_string(),
+            slug: "support".to_string(),
+        },
+    );
+
+    let cooked = post_service::cook(
+        &mut ctx.conn,
+        "Try #SUPPORT or #nowhere, not `#support`.",
+        user.id,
+    )
+    .unwrap()
+    .html;
+    assert_eq!(
+        cooked,
+        "<p>Try <a class=\"hashtag\" href=\"/c/support\" data-type=\"category\" title=\"Support\" \
+         style=\"color: #0088CC\">#support</a> or #nowhere, not <code>#support</code>.</p>\n"
+    );
+}
+
+fn support_category(conn: &mut diesel::PgConnection) {
+    common::create_category(
+        conn,
+        common::CategoryOpts {
+            name: "Support".to_string(),
+            slug: "support".to_string(),
+        },
+    );
+}
+
+#[test]
+fn unknown_slugs_stay_text() {
+    let mut ctx = common::setup();
+    let user = common::create_user(&mut ctx.conn, Default::default());
+    support_category(&mut ctx.conn);
+
+    let cooked = post_service::cook(&mut ctx.conn, "#supports #suppor", user.id)
+        .unwrap()
+        .html;
+    assert_eq!(cooked, "<p>#supports #suppor</p>\n");
+}
+
+#[test]
+fn tag_hashtags_link_to_the_tag_and_categories_win_clashes() {
+    let mut ctx = common::setup();
+    let user = common::create_user(&mut ctx.conn, Default::default());
+    support_category(&mut ctx.conn);
+    let topic = common::create_topic(&mut ctx.conn, common::TopicOpts::for_user(user.id));
+    watched_words::add_tags(
+        &mut ctx.conn,
+        topic.id,
+        &["Bugs".to_string(), "support".to_string()],
+    )
+    .unwrap();
+
+    let cooked = post_service::cook(&mut ctx.conn, "#bugs #support", user.id)
+        .unwrap()
+        .html;
+    assert_eq!(
+        cooked,
+        "<p><a class=\"hashtag\" href=\"/tag/bugs\" data-type=\"tag\" title=\"Bugs\">#bugs</a> \
+         <a class=\"hashtag\" href=\"/c/support\" data-type=\"category\" title=\"Support\" \
+         style=\"color: #0088CC\">#support</a></p>\n"
+    );
+}
+
+#[test]
+fn hashtags_in_code_stay_text() {
+    let mut ctx = common::setup();
+    let user = common::create_user(&mut ctx.conn, Default::default());
+    support_category(&mut ctx.conn);
+
+    let cooked = post_service::cook(
+        &mut ctx.conn,
+        "`#support` and ``see #support``\n\n```\n#support\n```",
+        user.id,
+    )
+    .unwrap()
+    .html;
+    assert!(!cooked.contains("hashtag"), "{cooked}");
+    assert!(cooked.contains("<code>#support</code>"));
+    assert!(cooked.contains("<code>see #support</code>"));
+}
+
+#[test]
+fn hashtags_ignore_case_and_link_the_lowercase_slug() {
+    let mut ctx = common::setup();
+    let user = common::create_user(&mut ctx.conn, Default::default());
+    support_category(&mut ctx.conn);
+
+    let cooked = post_service::cook(&mut ctx.conn, "#Support #SUPPORT #sUpPoRt", user.id)
+        .unwrap()
+        .html;
+    assert_eq!(cooked.matches("href=\"/c/support\"").count(), 3, "{cooked}");
+    assert_eq!(cooked.matches(">#support</a>").count(), 3);
+}
@@ -0,0 +1,241 @@
+//! Tests for the admin-side job queries and actions: state filtering,
+//! per-type stats, and cancel / retry / run-now.
+
+mod common;
+
+use chrono::Utc;
+use diesel::prelude::*;
+use discourse_rs::jobs::{
+    DEFAULT_QUEUE, JobActionError, JobFilter, JobRecord, JobState, cancel_job, claim_next,
+    find_job, job_stats, list_jobs, record_failure, retry_job, run_job_now,
+};
+use discourse_rs::schema::backie_tasks;
+use serde_json::json;
+
+fn reload(conn: &mut PgConnection, job: &JobRecord) -> JobRecord {
+    backie_tasks::table
+        .find(job.id)
+        .select(JobRecord::as_select())
+        .first(conn)
+        .unwrap()
+}
+
+fn finish(conn: &mut PgConnection, job: &JobRecord) {
+    let now = Utc::now().naive_utc();
+    diesel::update(backie_tasks::table.find(job.id))
+        .set((
+            backie_tasks::running_at.eq(Some(now - chrono::Duration::seconds(2))),
+            backie_tasks::done_at.eq(Some(now)),
+        ))
+        .execute(conn)
+        .unwrap();
+}
+
+/// One job in each state, all named `welcome_email`.
+struct OneOfEach {
+    pending: JobRecord,
+    running: JobRecord,
+    failed: JobRecord,
+    done: JobRecord,
+    dead: JobRecord,
+}
+
+fn one_of_each(conn: &mut PgConnection) -> OneOfEach {
+    let running = common::create_job(conn, "welcome_email", json!({ "n": 1 }));
+    claim_next(conn, DEFAULT_QUEUE).unwrap().unwrap();
+    let pending = common::create_job(conn, "welcome_email", json!({ "n": 2 }));
+
+    let failed = common::create_job(conn, "welcome_email", json!({ "n": 3 }));
+    record_failure(conn, &failed, "boom").unwrap();
+
+    let done = common::create_job(conn, "welcome_email", json!({ "n": 4 }));
+    finish(conn, &done);
+
+    let dead = common::create_job(conn, "welcome_email", json!({ "n": 5 }));
+    diesel::update(backie_tasks::table.find(dead.id))
+        .set(backie_tasks::retries.eq(dead.max_retries))
+        .execute(conn)
+        .unwrap();
+    let dead = reload(conn, &dead);
+    record_failure(conn, &dead, "boom").unwrap();
+
+    OneOfEach {
+        pending,
+        running,
+        failed,
+        done,
+        dead,
+    }
+}
+
+fn listed(conn: &mut PgConnection, filter: JobFilter) -> Vec<uuid::Uuid> {
+    list_jobs(conn, &filter, 30, 0)
+        .unwrap()
+        .into_iter()
+        .map(|view| view.job.id)
+        .collect()
+}
+
+#[test]
+fn list_jobs_filters_by_state() {
+    let mut ctx = common::setup();
+    let jobs = one_of_each(&mut ctx.conn);
+
+    for (state, expected) in [
+        (JobState::Pending, &jobs.pending),
+        (JobState::Running, &jobs.running),
+        (JobState::Failed, &jobs.failed),
+        (JobState::Done, &jobs.done),
+        (JobState::Dead, &jobs.dead),
+    ] {
+        let filter = JobFilter {
+            state: Some(state),
+            name: None,
+        };
+        assert_eq!(
+            listed(&mut ctx.conn, filter),
+            vec![expected.id],
+            "{state:?}"
+        );
+    }
+
+    assert_eq!(listed(&mut ctx.conn, JobFilter::default()).len(), 5);
+}
+
+#[test]
+fn list_jobs_filters_by_name() {
+    let mut ctx = common::setup();
+    let email = common::create_job(&mut ctx.conn, "welcome_email", json!({}));
+    common::create_job(&mut ctx.conn, "rebake_posts", json!({}));
+
+    let filter = JobFilter {
+        state: None,
+        name: Some("welcome_email".to_string()),
+    };
+    assert_eq!(listed(&mut ctx.conn, filter), vec![email.id]);
+}
+
+#[test]
+fn find_job_includes_state_payload_and_error() {
+    let mut ctx = common::setup();
+    let job = common::create_job(&mut ctx.conn, "welcome_email", json!({ "user_id": 7 }));
+    record_failure(&mut ctx.conn, &job, "smtp down").unwrap();
+
+    let view = find_job(&mut ctx.conn, job.id).unwrap().unwrap();
+    assert_eq!(view.state, JobState::Failed);
+    assert_eq!(view.job.payload, json!({ "user_id": 7 }));
+    assert_eq!(view.job.error.as_deref(), Some("smtp down"));
+
+    assert!(
+        find_job(&mut ctx.conn, uuid::Uuid::new_v4())
+            .unwrap()
+            .is_none()
+    );
+}
+
+#[test]
+fn job_stats_counts_states_and_averages_run_time() {
+    let mut ctx = common::setup();
+    one_of_each(&mut ctx.conn);
+    common::create_job(&mut ctx.conn, "rebake_posts", json!({}));
+
+    let stats = job_stats(&mut ctx.conn).unwrap();
+    let names: Vec<_> = stats.iter().map(|s| s.task_name.as_str()).collect();
+    assert_eq!(names, vec!["rebake_posts", "welcome_email"]);
+
+    let email = &stats[1];
+    assert_eq!(
+        (
+            email.pending,
+            email.running,
+            email.failed,
+            email.done,
+            email.dead
+        ),
+        (1, 1, 1, 1, 1)
+    );
+    // `finish` gives the done job a two-second run.
+    let run_ms = email.avg_run_ms.unwrap();
+    assert!((run_ms - 2000.0).abs() < 1.0, "{run_ms}");
+    assert!(email.avg_wait_ms.is_some());
+
+    assert!(stats[0].avg_run_ms.is_none());
+}
+
+#[test]
+fn cancel_moves_pending_job_to_dead() {
+    let mut ctx = common::setup();
+    let job = common::create_job(&mut ctx.conn, "welcome_email", json!({}));
+
+    let view = cancel_job(&mut ctx.conn, job.id).unwrap();
+    assert_eq!(view.state, JobState::Dead);
+    assert_eq!(view.job.error.as_deref(), Some("cancelled by admin"));
+    assert!(claim_next(&mut ctx.conn, DEFAULT_QUEUE).unwrap().is_none());
+}
+
+#[test]
+fn cancel_refuses_running_and_missing_jobs() {
+    let mut ctx = common::setup();
+    let jobs = one_of_each(&mut ctx.conn);
+
+    assert!(matches!(
+        cancel_job(&mut ctx.conn, jobs.running.id),
+        Err(JobActionError::InvalidState(JobState::Running))
+    ));
+    assert!(matches!(
+        cancel_job(&mut ctx.conn, uuid::Uuid::new_v4()),
+        Err(JobActionError::NotFound)
+    ));
+    assert!(reload(&mut ctx.conn, &jobs.running).dead_at.is_none());
+}
+
+#[test]
+fn retry_requeues_done_job_with_fresh_budget() {
+    let mut ctx = common::setup();
+    let jobs = one_of_each(&mut ctx.conn);
+
+    let view = retry_job(&mut ctx.conn, jobs.done.id).unwrap();
+    assert_eq!(view.state, JobState::Pending);
+
+    let view = retry_job(&mut ctx.conn, jobs.dead.id).unwrap();
+    assert_eq!(view.state, JobState::Pending);
+    assert_eq!(view.job.retries, 0);
+    assert!(view.job.error.is_none());
+
+    assert!(matches!(
+        retry_job(&mut ctx.conn, jobs.pending.id),
+        Err(JobActionError::InvalidState(JobState::Pending))
+    ));
+}
+
+#[test]
+fn run_now_skips_retry_backoff() {
+    let mut ctx = common::setup();
+    let jobs = one_of_each(&mut ctx.conn);
+    assert!(reload(&mut ctx.conn, &jobs.failed).scheduled_at > Utc::now().naive_utc());
+
+    let view = run_job_now(&mut ctx.conn, jobs.failed.id).unwrap();
+    assert_eq!(view.state, JobState::Failed);
+    assert!(view.job.scheduled_at <= Utc::now().naive_utc());
+
+    // The pending job was created first, so it's claimed first.
+    assert_eq!(
+        claim_next(&mut ctx.conn, DEFAULT_QUEUE)
+            .unwrap()
+            .unwrap()
+            .id,
+        jobs.pending.id
+    );
+    assert_eq!(
+        claim_next(&mut ctx.conn, DEFAULT_QUEUE)
+            .unwrap()
+            .unwrap()
+            .id,
+        jobs.failed.id
+    );
+
+    assert!(matches!(
+        run_job_now(&mut ctx.conn, jobs.done.id),
+        Err(JobActionError::InvalidState(JobState::Done))
+    ));
+}
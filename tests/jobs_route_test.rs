@@ -0,0 +1,282 @@
+//! Route-level tests for the admin job endpoints under /api/admin/jobs.
+//! The bookkeeping itself is covered in `jobs_test.rs`; here we check
+//! the guard and the JSON contract.
+
+mod common;
+
+use actix_web::{test, web};
+use diesel::prelude::*;
+use discourse_rs::jobs::{JobQueue, JobRegistry};
+use discourse_rs::schema::backie_tasks;
+use serde_json::json;
+use std::sync::Arc;
+
+#[actix_web::test]
+async fn dead_job_routes_require_admin() {
+    let mut ctx = common::setup();
+    let user = common::create_user(&mut ctx.conn, common::UserOpts::default());
+
+    let app = test::init_service(common::test_app_factory()).await;
+    let (hk, hv) = common::auth_header_for(&user);
+    let req = test::TestRequest::get()
+        .uri("/api/admin/jobs/dead")
+        .insert_header((hk, hv))
+        .to_request();
+
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status().as_u16(), 403);
+    drop(ctx);
+}
+
+#[actix_web::test]
+async fn admin_can_retry_dead_job() {
+    let mut ctx = common::setup();
+    let admin = common::create_user(
+        &mut ctx.conn,
+        common::UserOpts {
+            admin: true,
+            ..Default::default()
+        },
+    );
+    let job = common::create_job(&mut ctx.conn, "welcome_email", json!({}));
+    let job = common::kill_job(&mut ctx.conn, job);
+
+    let app = test::init_service(common::test_app_factory()).await;
+    let (hk, hv) = common::auth_header_for(&admin);
+
+    let req = test::TestRequest::get()
+        .uri("/api/admin/jobs/dead")
+        .insert_header((hk, hv.clone()))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status().as_u16(), 200);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body[0]["id"], job.id.to_string());
+    assert_eq!(body[0]["error"], "boom");
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/admin/jobs/dead/{}/retry", job.id))
+        .insert_header((hk, hv))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status().as_u16(), 200);
+
+    assert!(common::reload_job(&mut ctx.conn, &job).dead_at.is_none());
+    drop(ctx);
+}
+
+#[actix_web::test]
+async fn job_introspection_routes_require_admin() {
+    let mut ctx = common::setup();
+    let moderator = common::create_user(
+        &mut ctx.conn,
+        common::UserOpts {
+            moderator: true,
+            ..Default::default()
+        },
+    );
+    let job = common::create_job(&mut ctx.conn, "welcome_email", json!({}));
+
+    let app = test::init_service(common::test_app_factory()).await;
+    let (hk, hv) = common::auth_header_for(&moderator);
+
+    for uri in [
+        "/api/admin/jobs".to_string(),
+        "/api/admin/jobs/stats".to_string(),
+        format!("/api/admin/jobs/{}", job.id),
+    ] {
+        let req = test::TestRequest::get()
+            .uri(&uri)
+            .insert_header((hk, hv.clone()))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status().as_u16(), 403, "{uri}");
+    }
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/admin/jobs/{}/cancel", job.id))
+        .insert_header((hk, hv))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status().as_u16(), 403);
+    assert!(common::reload_job(&mut ctx.conn, &job).dead_at.is_none());
+    drop(ctx);
+}
+
+#[actix_web::test]
+async fn admin_can_list_inspect_and_cancel_jobs() {
+    let mut ctx = common::setup();
+    let admin = common::create_user(
+        &mut ctx.conn,
+        common::UserOpts {
+            admin: true,
+            ..Default::default()
+        },
+    );
+    let job = common::create_job(&mut ctx.conn, "welcome_email", json!({ "user_id": 7 }));
+    let dead = common::create_job(&mut ctx.conn, "welcome_email", json!({}));
+    let dead = common::kill_job(&mut ctx.conn, dead);
+
+    let app = test::init_service(common::test_app_factory()).await;
+    let (hk, hv) = common::auth_header_for(&admin);
+
+    let req = test::TestRequest::get()
+        .uri("/api/admin/jobs?state=pending&name=welcome_email")
+        .insert_header((hk, hv.clone()))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status().as_u16(), 200);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body.as_array().unwrap().len(), 1);
+    assert_eq!(body[0]["id"], job.id.to_string());
+    assert_eq!(body[0]["state"], "pending");
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/admin/jobs/{}", job.id))
+        .insert_header((hk, hv.clone()))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status().as_u16(), 200);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["payload"], json!({ "user_id": 7 }));
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/admin/jobs/{}/cancel", job.id))
+        .insert_header((hk, hv.clone()))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status().as_u16(), 200);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["state"], "dead");
+
+    // Dead jobs can't be cancelled again, only retried.
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/admin/jobs/{}/cancel", dead.id))
+        .insert_header((hk, hv.clone()))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status().as_u16(), 409);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["error"], "Cannot cancel a job that is dead");
+
+    let req = test::TestRequest::get()
+        .uri("/api/admin/jobs/stats")
+        .insert_header((hk, hv))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status().as_u16(), 200);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body[0]["task_name"], "welcome_email");
+    assert_eq!(body[0]["dead"], 2);
+    drop(ctx);
+}
+
+/// Jobs still waiting to run, as (name, payload).
+fn pending_jobs(conn: &mut PgConnection) -> Vec<(String, serde_json::Value)> {
+    backie_tasks::table
+        .filter(backie_tasks::running_at.is_null())
+        .select((backie_tasks::task_name, backie_tasks::payload))
+        .load(conn)
+        .unwrap()
+}
+
+#[actix_web::test]
+async fn demo_enqueue_routes_are_gone() {
+    let mut ctx = common::setup();
+    let app = test::init_service(
+        common::test_app_factory().app_data(web::Data::new(JobQueue::new(Arc::new(ctx.pool())))),
+    )
+    .await;
+
+    for uri in ["/api/jobs/welcome_email", "/api/jobs/process_topic"] {
+        let req = test::TestRequest::post()
+            .uri(uri)
+            .set_json(json!({ "user_id": 1, "username": "x", "email": "x@example.com" }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status().as_u16(), 404, "{uri}");
+    }
+    assert!(pending_jobs(&mut ctx.conn).is_empty());
+}
+
+#[actix_web::test]
+async fn admin_enqueues_registered_job_by_name() {
+    let mut ctx = common::setup();
+    let admin = common::create_user(
+        &mut ctx.conn,
+        common::UserOpts {
+            admin: true,
+            ..Default::default()
+        },
+    );
+    let moderator = common::create_user(
+        &mut ctx.conn,
+        common::UserOpts {
+            moderator: true,
+            ..Default::default()
+        },
+    );
+
+    let app = test::init_service(
+        common::test_app_factory()
+            .app_data(web::Data::new(JobQueue::new(Arc::new(ctx.pool()))))
+            .app_data(web::Data::new(JobRegistry::builtin().unwrap())),
+    )
+    .await;
+    let payload = json!({ "user_id": 7 });
+
+    let (hk, hv) = common::auth_header_for(&moderator);
+    let req = test::TestRequest::post()
+        .uri("/api/admin/jobs/check_trust_level_promotion")
+        .insert_header((hk, hv))
+        .set_json(&payload)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status().as_u16(), 403);
+    assert!(pending_jobs(&mut ctx.conn).is_empty());
+
+    let (hk, hv) = common::auth_header_for(&admin);
+    let req = test::TestRequest::post()
+        .uri("/api/admin/jobs/check_trust_level_promotion")
+        .insert_header((hk, hv.clone()))
+        .set_json(&payload)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status().as_u16(), 201);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["queued"], true);
+
+    // The job's DropWhilePending policy still applies.
+    let req = test::TestRequest::post()
+        .uri("/api/admin/jobs/check_trust_level_promotion")
+        .insert_header((hk, hv.clone()))
+        .set_json(&payload)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status().as_u16(), 200);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["queued"], false);
+
+    let req = test::TestRequest::post()
+        .uri("/api/admin/jobs/no_such_job")
+        .insert_header((hk, hv.clone()))
+        .set_json(json!({}))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status().as_u16(), 404);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["error"], "Unknown job type: no_such_job");
+
+    let req = test::TestRequest::post()
+        .uri("/api/admin/jobs/check_trust_level_promotion")
+        .insert_header((hk, hv))
+        .set_json(json!({ "user_id": "seven" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status().as_u16(), 422);
+
+    assert_eq!(
+        pending_jobs(&mut ctx.conn),
+        vec![("check_trust_level_promotion".to_string(), payload)]
+    );
+}
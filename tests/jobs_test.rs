@@ -0,0 +1,721 @@
+//! Tests for the job queue's claim / retry / dead-letter bookkeeping.
+//! These call the `jobs` functions directly against the test DB; no
+//! worker runs.
+
+mod common;
+
+use chrono::Utc;
+use diesel::prelude::*;
+use discourse_rs::DbPool;
+use discourse_rs::jobs::{
+    CheckTrustLevelPromotionJob, DEFAULT_QUEUE, EnqueueOutcome, FailureOutcome, Job, JobQueue,
+    JobRecord, JobRegistry, LOW_QUEUE, MAIL_QUEUE, NOTIFY_CHANNEL, QueueConfig, REAPER_GRACE_SECS,
+    RebakePostsJob, Uniqueness, WelcomeEmailJob, WorkerPool, WorkerPoolHandle, claim_next,
+    discard_dead, list_dead, reap_stuck, record_failure, release_abandoned, retry_dead,
+};
+use discourse_rs::schema::backie_tasks;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+
+fn queue(ctx: &common::TestCtx) -> JobQueue {
+    JobQueue::new(Arc::new(ctx.pool()))
+}
+
+fn job_count(conn: &mut PgConnection) -> i64 {
+    backie_tasks::table.count().get_result(conn).unwrap()
+}
+
+/// A job type using the `Replace` policy, with a configurable timeout so
+/// tests can see which enqueue's settings won.
+#[derive(Serialize, Deserialize)]
+struct ReplacingJob {
+    key: i32,
+    #[serde(skip)]
+    timeout_msecs: i64,
+}
+
+impl Job for ReplacingJob {
+    const NAME: &'static str = "replacing_test_job";
+
+    async fn execute(&self, _pool: &DbPool) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn timeout_msecs(&self) -> i64 {
+        self.timeout_msecs
+    }
+
+    fn uniqueness(&self) -> Uniqueness {
+        Uniqueness::Replace
+    }
+}
+
+/// Pretend `job` was claimed `secs_ago` seconds ago by a worker that never
+/// reported back.
+fn mark_running_since(conn: &mut PgConnection, job: &JobRecord, secs_ago: i64) {
+    diesel::update(backie_tasks::table.find(job.id))
+        .set(backie_tasks::running_at.eq(Some(
+            Utc::now().naive_utc() - chrono::Duration::seconds(secs_ago),
+        )))
+        .execute(conn)
+        .unwrap();
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// enqueue uniqueness
+
+#[test]
+fn drop_while_pending_skips_identical_job() {
+    let mut ctx = common::setup();
+    let queue = queue(&ctx);
+
+    let first = queue
+        .enqueue(CheckTrustLevelPromotionJob { user_id: 7 })
+        .unwrap();
+    let second = queue
+        .enqueue(CheckTrustLevelPromotionJob { user_id: 7 })
+        .unwrap();
+
+    assert!(first.is_new());
+    assert_eq!(
+        second,
+        EnqueueOutcome::AlreadyPending {
+            task_hash: first.task_hash().to_string()
+        }
+    );
+    assert_eq!(job_count(&mut ctx.conn), 1);
+
+    // A different payload is a different job.
+    assert!(
+        queue
+            .enqueue(CheckTrustLevelPromotionJob { user_id: 8 })
+            .unwrap()
+            .is_new()
+    );
+    assert_eq!(job_count(&mut ctx.conn), 2);
+}
+
+#[test]
+fn drop_while_pending_queues_again_once_original_is_running() {
+    let mut ctx = common::setup();
+    let queue = queue(&ctx);
+
+    queue
+        .enqueue(CheckTrustLevelPromotionJob { user_id: 7 })
+        .unwrap();
+    claim_next(&mut ctx.conn, DEFAULT_QUEUE)
+        .unwrap()
+        .expect("nothing claimed");
+
+    // The running job may not see whatever prompted this enqueue, so a
+    // fresh one is queued behind it.
+    let again = queue
+        .enqueue(CheckTrustLevelPromotionJob { user_id: 7 })
+        .unwrap();
+    assert!(again.is_new());
+    assert_eq!(job_count(&mut ctx.conn), 2);
+}
+
+#[test]
+fn allow_policy_queues_every_duplicate() {
+    let mut ctx = common::setup();
+    let queue = queue(&ctx);
+    let job = || SleepJob { millis: 0 };
+
+    let first = queue.enqueue(job()).unwrap();
+    let second = queue.enqueue(job()).unwrap();
+
+    assert!(first.is_new() && second.is_new());
+    assert_ne!(first.task_hash(), second.task_hash());
+    assert_eq!(job_count(&mut ctx.conn), 2);
+}
+
+#[test]
+fn replace_policy_updates_pending_job_in_place() {
+    let mut ctx = common::setup();
+    let queue = queue(&ctx);
+
+    queue
+        .enqueue(ReplacingJob {
+            key: 1,
+            timeout_msecs: 1_000,
+        })
+        .unwrap();
+    let replaced = queue
+        .enqueue(ReplacingJob {
+            key: 1,
+            timeout_msecs: 5_000,
+        })
+        .unwrap();
+
+    assert!(replaced.is_new());
+    assert_eq!(job_count(&mut ctx.conn), 1);
+    let timeout: i64 = backie_tasks::table
+        .select(backie_tasks::timeout_msecs)
+        .first(&mut ctx.conn)
+        .unwrap();
+    assert_eq!(timeout, 5_000);
+}
+
+#[test]
+fn retry_dead_refuses_when_duplicate_is_pending() {
+    let mut ctx = common::setup();
+    let queue = queue(&ctx);
+
+    queue
+        .enqueue(CheckTrustLevelPromotionJob { user_id: 7 })
+        .unwrap();
+    let job = claim_next(&mut ctx.conn, DEFAULT_QUEUE).unwrap().unwrap();
+    let job = common::kill_job(&mut ctx.conn, job);
+    queue
+        .enqueue(CheckTrustLevelPromotionJob { user_id: 7 })
+        .unwrap();
+
+    assert!(retry_dead(&mut ctx.conn, job.id).is_err());
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// claim_next
+
+#[test]
+fn claim_next_marks_job_running() {
+    let mut ctx = common::setup();
+    let job = common::create_job(&mut ctx.conn, "welcome_email", json!({}));
+
+    let claimed = claim_next(&mut ctx.conn, DEFAULT_QUEUE)
+        .unwrap()
+        .expect("nothing claimed");
+    assert_eq!(claimed.id, job.id);
+    assert!(claimed.running_at.is_some());
+
+    // The returned row matches what's stored.
+    assert_eq!(
+        common::reload_job(&mut ctx.conn, &job).running_at,
+        claimed.running_at
+    );
+}
+
+#[test]
+fn claim_next_skips_running_and_future_jobs() {
+    let mut ctx = common::setup();
+    let running = common::create_job(&mut ctx.conn, "welcome_email", json!({}));
+    mark_running_since(&mut ctx.conn, &running, 1);
+    let future = common::create_job(&mut ctx.conn, "welcome_email", json!({}));
+    diesel::update(backie_tasks::table.find(future.id))
+        .set(backie_tasks::scheduled_at.eq(Utc::now().naive_utc() + chrono::Duration::hours(1)))
+        .execute(&mut ctx.conn)
+        .unwrap();
+
+    assert!(claim_next(&mut ctx.conn, DEFAULT_QUEUE).unwrap().is_none());
+}
+
+#[test]
+fn claim_next_takes_oldest_first() {
+    let mut ctx = common::setup();
+    let first = common::create_job(&mut ctx.conn, "welcome_email", json!({}));
+    let second = common::create_job(&mut ctx.conn, "welcome_email", json!({}));
+
+    assert_eq!(
+        claim_next(&mut ctx.conn, DEFAULT_QUEUE)
+            .unwrap()
+            .unwrap()
+            .id,
+        first.id
+    );
+    assert_eq!(
+        claim_next(&mut ctx.conn, DEFAULT_QUEUE)
+            .unwrap()
+            .unwrap()
+            .id,
+        second.id
+    );
+    assert!(claim_next(&mut ctx.conn, DEFAULT_QUEUE).unwrap().is_none());
+}
+
+#[test]
+fn claim_next_prefers_higher_priority() {
+    let mut ctx = common::setup();
+    let older = common::create_job(&mut ctx.conn, "welcome_email", json!({}));
+    let urgent = common::create_job(&mut ctx.conn, "welcome_email", json!({}));
+    diesel::update(backie_tasks::table.find(urgent.id))
+        .set(backie_tasks::priority.eq(10))
+        .execute(&mut ctx.conn)
+        .unwrap();
+
+    assert_eq!(
+        claim_next(&mut ctx.conn, DEFAULT_QUEUE)
+            .unwrap()
+            .unwrap()
+            .id,
+        urgent.id
+    );
+    assert_eq!(
+        claim_next(&mut ctx.conn, DEFAULT_QUEUE)
+            .unwrap()
+            .unwrap()
+            .id,
+        older.id
+    );
+}
+
+#[test]
+fn claim_next_only_takes_jobs_from_its_queue() {
+    let mut ctx = common::setup();
+    let job = common::create_job(&mut ctx.conn, "welcome_email", json!({}));
+    diesel::update(backie_tasks::table.find(job.id))
+        .set(backie_tasks::queue_name.eq(MAIL_QUEUE))
+        .execute(&mut ctx.conn)
+        .unwrap();
+
+    assert!(claim_next(&mut ctx.conn, DEFAULT_QUEUE).unwrap().is_none());
+    assert_eq!(
+        claim_next(&mut ctx.conn, MAIL_QUEUE).unwrap().unwrap().id,
+        job.id
+    );
+}
+
+#[test]
+fn enqueue_records_job_queue_and_priority() {
+    let mut ctx = common::setup();
+    queue(&ctx)
+        .enqueue(WelcomeEmailJob {
+            user_id: 1,
+            username: "alice".to_string(),
+            email: "alice@example.com".to_string(),
+        })
+        .unwrap();
+
+    let (queue_name, priority): (String, i32) = backie_tasks::table
+        .select((backie_tasks::queue_name, backie_tasks::priority))
+        .first(&mut ctx.conn)
+        .unwrap();
+    assert_eq!(queue_name, MAIL_QUEUE);
+    assert_eq!(priority, 0);
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// reap_stuck
+
+#[test]
+fn reap_stuck_requeues_job_past_its_timeout() {
+    let mut ctx = common::setup();
+    let job = common::create_job(&mut ctx.conn, "welcome_email", json!({}));
+    let stale = job.timeout_msecs / 1000 + REAPER_GRACE_SECS + 5;
+    mark_running_since(&mut ctx.conn, &job, stale);
+
+    assert_eq!(reap_stuck(&mut ctx.conn).unwrap(), 1);
+
+    let job = common::reload_job(&mut ctx.conn, &job);
+    assert!(job.running_at.is_none());
+    assert!(job.done_at.is_none());
+    assert_eq!(job.retries, 1);
+    assert!(job.error.unwrap().starts_with("abandoned"));
+
+    // Requeued for immediate pickup.
+    assert_eq!(
+        claim_next(&mut ctx.conn, DEFAULT_QUEUE)
+            .unwrap()
+            .unwrap()
+            .id,
+        job.id
+    );
+}
+
+#[test]
+fn reap_stuck_leaves_jobs_within_timeout() {
+    let mut ctx = common::setup();
+    let job = common::create_job(&mut ctx.conn, "welcome_email", json!({}));
+    // Past the job's own timeout but still inside the grace window.
+    mark_running_since(&mut ctx.conn, &job, job.timeout_msecs / 1000 + 1);
+
+    assert_eq!(reap_stuck(&mut ctx.conn).unwrap(), 0);
+    assert!(common::reload_job(&mut ctx.conn, &job).running_at.is_some());
+}
+
+#[test]
+fn reap_stuck_marks_exhausted_job_dead() {
+    let mut ctx = common::setup();
+    let job = common::create_job(&mut ctx.conn, "welcome_email", json!({}));
+    diesel::update(backie_tasks::table.find(job.id))
+        .set(backie_tasks::retries.eq(job.max_retries))
+        .execute(&mut ctx.conn)
+        .unwrap();
+    let stale = job.timeout_msecs / 1000 + REAPER_GRACE_SECS + 5;
+    mark_running_since(&mut ctx.conn, &job, stale);
+
+    assert_eq!(reap_stuck(&mut ctx.conn).unwrap(), 1);
+
+    let job = common::reload_job(&mut ctx.conn, &job);
+    assert!(job.dead_at.is_some());
+    assert!(job.done_at.is_some());
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// record_failure
+
+#[test]
+fn failure_with_retries_left_reschedules_with_backoff() {
+    let mut ctx = common::setup();
+    let job = common::create_job(&mut ctx.conn, "check_trust_level_promotion", json!({}));
+
+    let before = Utc::now().naive_utc();
+    let outcome = record_failure(&mut ctx.conn, &job, "smtp timeout").unwrap();
+
+    let FailureOutcome::Rescheduled(at) = outcome else {
+        panic!("expected reschedule, got {outcome:?}");
+    };
+    // First retry waits between half and all of the 10s base delay.
+    let delay = (at - before).num_seconds();
+    assert!((4..=10).contains(&delay), "unexpected delay {delay}s");
+
+    let job = common::reload_job(&mut ctx.conn, &job);
+    assert_eq!(job.retries, 1);
+    // Postgres stores microseconds; the returned time has nanoseconds.
+    assert_eq!((job.scheduled_at - at).num_milliseconds(), 0);
+    assert!(job.running_at.is_none());
+    assert!(job.done_at.is_none());
+    assert!(job.dead_at.is_none());
+    assert_eq!(job.error.as_deref(), Some("smtp timeout"));
+}
+
+#[test]
+fn failure_after_max_retries_marks_dead() {
+    let mut ctx = common::setup();
+    let job = common::create_job(&mut ctx.conn, "check_trust_level_promotion", json!({}));
+    let max_retries = job.max_retries;
+
+    let job = common::kill_job(&mut ctx.conn, job);
+
+    assert_eq!(job.retries, max_retries);
+    assert!(job.dead_at.is_some());
+    // done_at is set too, so the worker's claim query skips it.
+    assert!(job.done_at.is_some());
+    assert_eq!(job.error.as_deref(), Some("boom"));
+}
+
+#[test]
+fn failure_of_a_requeued_run_is_not_recorded() {
+    let mut ctx = common::setup();
+    common::create_job(&mut ctx.conn, "welcome_email", json!({}));
+    let first = claim_next(&mut ctx.conn, DEFAULT_QUEUE).unwrap().unwrap();
+
+    // The reaper gives up on the first run and another worker claims it.
+    let stale = first.timeout_msecs / 1000 + REAPER_GRACE_SECS + 5;
+    mark_running_since(&mut ctx.conn, &first, stale);
+    assert_eq!(reap_stuck(&mut ctx.conn).unwrap(), 1);
+    diesel::update(backie_tasks::table.find(first.id))
+        .set(backie_tasks::scheduled_at.eq(Utc::now().naive_utc()))
+        .execute(&mut ctx.conn)
+        .unwrap();
+    let second = claim_next(&mut ctx.conn, DEFAULT_QUEUE).unwrap().unwrap();
+    assert_eq!(second.id, first.id);
+
+    let outcome = record_failure(&mut ctx.conn, &first, "late failure").unwrap();
+    assert_eq!(outcome, FailureOutcome::Stale);
+    let job = common::reload_job(&mut ctx.conn, &first);
+    assert_eq!(job.running_at, second.running_at);
+    assert_eq!(job.retries, second.retries);
+    assert_ne!(job.error.as_deref(), Some("late failure"));
+
+    // Finished jobs aren't touched either.
+    diesel::update(backie_tasks::table.find(second.id))
+        .set(backie_tasks::done_at.eq(Some(Utc::now().naive_utc())))
+        .execute(&mut ctx.conn)
+        .unwrap();
+    let outcome = record_failure(&mut ctx.conn, &second, "too late").unwrap();
+    assert_eq!(outcome, FailureOutcome::Stale);
+    assert!(common::reload_job(&mut ctx.conn, &second).dead_at.is_none());
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Dead-letter operations
+
+#[test]
+fn list_dead_only_returns_dead_jobs() {
+    let mut ctx = common::setup();
+    let pending = common::create_job(&mut ctx.conn, "welcome_email", json!({}));
+    let dead = common::create_job(&mut ctx.conn, "welcome_email", json!({}));
+    let dead = common::kill_job(&mut ctx.conn, dead);
+
+    let listed = list_dead(&mut ctx.conn, 30, 0).unwrap();
+    let ids: Vec<_> = listed.iter().map(|j| j.id).collect();
+    assert_eq!(ids, vec![dead.id]);
+    assert!(!ids.contains(&pending.id));
+}
+
+#[test]
+fn retry_dead_requeues_with_fresh_budget() {
+    let mut ctx = common::setup();
+    let job = common::create_job(&mut ctx.conn, "welcome_email", json!({}));
+    let job = common::kill_job(&mut ctx.conn, job);
+
+    let retried = retry_dead(&mut ctx.conn, job.id)
+        .unwrap()
+        .expect("job not retried");
+    assert_eq!(retried.retries, 0);
+    assert!(retried.dead_at.is_none());
+    assert!(retried.done_at.is_none());
+    assert!(retried.running_at.is_none());
+    assert!(retried.error.is_none());
+    assert!(retried.scheduled_at <= Utc::now().naive_utc());
+}
+
+#[test]
+fn retry_dead_ignores_live_jobs() {
+    let mut ctx = common::setup();
+    let job = common::create_job(&mut ctx.conn, "welcome_email", json!({}));
+
+    assert!(retry_dead(&mut ctx.conn, job.id).unwrap().is_none());
+}
+
+#[test]
+fn discard_dead_deletes_only_dead_jobs() {
+    let mut ctx = common::setup();
+    let live = common::create_job(&mut ctx.conn, "welcome_email", json!({}));
+    let dead = common::create_job(&mut ctx.conn, "welcome_email", json!({}));
+    let dead = common::kill_job(&mut ctx.conn, dead);
+
+    assert!(!discard_dead(&mut ctx.conn, live.id).unwrap());
+    assert!(discard_dead(&mut ctx.conn, dead.id).unwrap());
+
+    let remaining: i64 = backie_tasks::table
+        .count()
+        .get_result(&mut ctx.conn)
+        .unwrap();
+    assert_eq!(remaining, 1);
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Registry
+
+#[tokio::test]
+async fn registry_runs_claimed_job_from_its_stored_payload() {
+    use discourse_rs::schema::users;
+    use discourse_rs::services::{trust_levels::TL1_MIN_POSTS, user_stats};
+
+    let mut ctx = common::setup();
+    let user = common::create_user(&mut ctx.conn, common::UserOpts::default());
+    for _ in 0..TL1_MIN_POSTS {
+        user_stats::incr_post_count(&mut ctx.conn, user.id).unwrap();
+    }
+    queue(&ctx)
+        .enqueue(CheckTrustLevelPromotionJob { user_id: user.id })
+        .unwrap();
+    let job = claim_next(&mut ctx.conn, DEFAULT_QUEUE).unwrap().unwrap();
+
+    let registry = JobRegistry::builtin().unwrap();
+    registry
+        .execute(&job.task_name, job.payload, ctx.pool())
+        .await
+        .unwrap();
+
+    let trust_level: i32 = users::table
+        .find(user.id)
+        .select(users::trust_level)
+        .first(&mut ctx.conn)
+        .unwrap();
+    assert_eq!(trust_level, 1);
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Wakeups
+
+#[tokio::test]
+async fn enqueue_notifies_the_job_queue() {
+    use futures::StreamExt;
+    use tokio_postgres::AsyncMessage;
+
+    let ctx = common::setup();
+    let (client, mut connection) =
+        tokio_postgres::connect(&common::database_url(), tokio_postgres::NoTls)
+            .await
+            .unwrap();
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        let mut messages = futures::stream::poll_fn(move |cx| connection.poll_message(cx));
+        while let Some(Ok(message)) = messages.next().await {
+            if let AsyncMessage::Notification(n) = message {
+                let _ = tx.send((n.channel().to_string(), n.payload().to_string()));
+            }
+        }
+    });
+    client
+        .batch_execute(&format!("LISTEN {NOTIFY_CHANNEL}"))
+        .await
+        .unwrap();
+
+    queue(&ctx)
+        .enqueue(WelcomeEmailJob {
+            user_id: 1,
+            username: "alice".to_string(),
+            email: "alice@example.com".to_string(),
+        })
+        .unwrap();
+
+    let received = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+        .await
+        .expect("no notification")
+        .unwrap();
+    assert_eq!(
+        received,
+        (NOTIFY_CHANNEL.to_string(), MAIL_QUEUE.to_string())
+    );
+}
+
+#[tokio::test]
+async fn worker_pool_runs_enqueued_job_without_waiting_for_poll() {
+    let mut ctx = common::setup();
+    let pool = WorkerPool::new(
+        Arc::new(ctx.pool()),
+        JobRegistry::builtin().unwrap(),
+        vec![QueueConfig::new(LOW_QUEUE, 1)],
+    )
+    .listen(common::database_url())
+    .poll_interval(Duration::from_secs(600))
+    .start();
+
+    // Let the worker finish its startup drain and the listener subscribe.
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    queue(&ctx).enqueue(RebakePostsJob { after_id: 0 }).unwrap();
+
+    let mut done = false;
+    for _ in 0..50 {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let done_at: Option<chrono::NaiveDateTime> = backie_tasks::table
+            .select(backie_tasks::done_at)
+            .first(&mut ctx.conn)
+            .unwrap();
+        if done_at.is_some() {
+            done = true;
+            break;
+        }
+    }
+    pool.shutdown().await;
+    assert!(done, "job was not run within 5s of being enqueued");
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Shutdown
+
+/// Sleeps, then succeeds. Stands in for a job that's mid-run at shutdown.
+#[derive(Serialize, Deserialize)]
+struct SleepJob {
+    millis: u64,
+}
+
+impl Job for SleepJob {
+    const NAME: &'static str = "sleep_test_job";
+
+    async fn execute(&self, _pool: &DbPool) -> Result<(), String> {
+        tokio::time::sleep(Duration::from_millis(self.millis)).await;
+        Ok(())
+    }
+
+    fn timeout_msecs(&self) -> i64 {
+        60_000
+    }
+}
+
+/// Start a one-worker pool for `SleepJob`, enqueue one, and return once a
+/// worker has claimed it.
+async fn start_with_running_sleep_job(
+    ctx: &mut common::TestCtx,
+    millis: u64,
+    shutdown_timeout: Duration,
+) -> (WorkerPoolHandle, JobRecord) {
+    let mut registry = JobRegistry::new();
+    registry.register::<SleepJob>().unwrap();
+    queue(ctx).enqueue(SleepJob { millis }).unwrap();
+    let pool = WorkerPool::new(
+        Arc::new(ctx.pool()),
+        registry,
+        vec![QueueConfig::new(DEFAULT_QUEUE, 1)],
+    )
+    .shutdown_timeout(shutdown_timeout)
+    .start();
+
+    for _ in 0..50 {
+        let job: JobRecord = backie_tasks::table
+            .select(JobRecord::as_select())
+            .first(&mut ctx.conn)
+            .unwrap();
+        if job.running_at.is_some() {
+            return (pool, job);
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("job was never claimed");
+}
+
+#[tokio::test]
+async fn shutdown_waits_for_running_job() {
+    let mut ctx = common::setup();
+    let (pool, job) = start_with_running_sleep_job(&mut ctx, 300, Duration::from_secs(5)).await;
+
+    assert_eq!(pool.shutdown().await, 0);
+
+    let job = common::reload_job(&mut ctx.conn, &job);
+    assert!(job.done_at.is_some());
+    assert!(job.error.is_none());
+}
+
+#[tokio::test]
+async fn shutdown_requeues_job_still_running_at_deadline() {
+    let mut ctx = common::setup();
+    let (pool, job) =
+        start_with_running_sleep_job(&mut ctx, 60_000, Duration::from_millis(100)).await;
+
+    assert_eq!(pool.shutdown().await, 1);
+
+    let job = common::reload_job(&mut ctx.conn, &job);
+    assert!(job.running_at.is_none());
+    assert!(job.done_at.is_none());
+    assert_eq!(job.retries, 1);
+    assert_eq!(job.error.as_deref(), Some("abandoned: worker shut down"));
+}
+
+#[tokio::test]
+async fn shutdown_stops_claiming_new_jobs() {
+    let mut ctx = common::setup();
+    let (pool, _) = start_with_running_sleep_job(&mut ctx, 0, Duration::from_secs(5)).await;
+    pool.shutdown().await;
+
+    let late = common::create_job(&mut ctx.conn, "sleep_test_job", json!({ "millis": 0 }));
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(
+        common::reload_job(&mut ctx.conn, &late)
+            .running_at
+            .is_none()
+    );
+}
+
+#[test]
+fn release_abandoned_keeps_last_retry_and_skips_finished_jobs() {
+    let mut ctx = common::setup();
+    let last_try = common::create_job(&mut ctx.conn, "welcome_email", json!({}));
+    diesel::update(backie_tasks::table.find(last_try.id))
+        .set(backie_tasks::retries.eq(last_try.max_retries))
+        .execute(&mut ctx.conn)
+        .unwrap();
+    mark_running_since(&mut ctx.conn, &last_try, 1);
+    let finished = common::create_job(&mut ctx.conn, "welcome_email", json!({}));
+    mark_running_since(&mut ctx.conn, &finished, 1);
+    diesel::update(backie_tasks::table.find(finished.id))
+        .set(backie_tasks::done_at.eq(Some(Utc::now().naive_utc())))
+        .execute(&mut ctx.conn)
+        .unwrap();
+
+    let released = release_abandoned(&mut ctx.conn, &[last_try.id, finished.id]).unwrap();
+    assert_eq!(released, 1);
+
+    let last_try = common::reload_job(&mut ctx.conn, &last_try);
+    assert!(last_try.running_at.is_none());
+    assert_eq!(last_try.retries, last_try.max_retries);
+    assert!(
+        common::reload_job(&mut ctx.conn, &finished)
+            .done_at
+            .is_some()
+    );
+}
@@ -0,0 +1,252 @@
+//! Link previews end to end: a post with a bare link, `FetchLinkPreviewsJob`
+//! fetching from a stub HTTP server on localhost, the cache row, and the
+//! re-cooked post. The default fetcher refuses loopback addresses, so each
+//! test installs one that allows them.
+
+mod common;
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use actix_web::{test, web};
+use diesel::prelude::*;
+use discourse_rs::jobs::{FetchLinkPreviewsJob, Job, JobQueue};
+use discourse_rs::models::{CreatePostInput, LinkPreview, Post};
+use discourse_rs::onebox::{self, HttpFetcher};
+use discourse_rs::schema::{backie_tasks, link_previews, posts};
+use discourse_rs::services::posts as post_service;
+use serde_json::json;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+/// Serves canned responses by path and counts requests. Anything not
+/// registered is a 404.
+#[derive(Clone, Default)]
+struct StubServer {
+    routes: Arc<Mutex<HashMap<String, (&'static str, String)>>>,
+    hits: Arc<AtomicUsize>,
+    base: String,
+}
+
+impl StubServer {
+    async fn start() -> Self {
+        onebox::install(Arc::new(HttpFetcher::new().allow_private_addresses()));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = StubServer {
+            base: format!("http://{}", listener.local_addr().unwrap()),
+            ..Default::default()
+        };
+        let handle = server.clone();
+        tokio::spawn(async move {
+            loop {
+                let Ok((mut socket, _)) = listener.accept().await else {
+                    return;
+                };
+                let handle = handle.clone();
+                tokio::spawn(async move {
+                    let mut buf = vec![0; 4096];
+                    let n = socket.read(&mut buf).await.unwrap_or(0);
+                    let request = String::from_utf8_lossy(&buf[..n]);
+                    let path = request.split_whitespace().nth(1).unwrap_or("/").to_string();
+                    handle.hits.fetch_add(1, Ordering::SeqCst);
+                    let route = handle.routes.lock().unwrap().get(&path).cloned();
+                    let response = match route {
+                        Some((content_type, body)) => format!(
+                            "HTTP/1.1 200 OK\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                            body.len()
+                        ),
+                        None => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                            .to_string(),
+                    };
+                    let _ = socket.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+        server
+    }
+
+    fn serve(&self, path: &str, content_type: &'static str, body: &str) {
+        self.routes
+            .lock()
+            .unwrap()
+            .insert(path.to_string(), (content_type, body.to_string()));
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base, path)
+    }
+
+    fn hits(&self) -> usize {
+        self.hits.load(Ordering::SeqCst)
+    }
+}
+
+fn post_with(conn: &mut PgConnection, raw: &str) -> Post {
+    let user = common::create_user(conn, Default::default());
+    let topic = common::create_topic(conn, common::TopicOpts::for_user(user.id));
+    post_service::create(
+        conn,
+        CreatePostInput {
+            topic_id: topic.id,
+            user_id: user.id,
+            post_number: 1,
+            raw: raw.to_string(),
+            reply_to_post_number: None,
+        },
+    )
+    .expect("create failed")
+}
+
+fn cooked(conn: &mut PgConnection, post_id: i32) -> String {
+    posts::table
+        .find(post_id)
+        .select(posts::cooked)
+        .first(conn)
+        .unwrap()
+}
+
+#[actix_web::test]
+async fn open_graph_preview_is_cached_and_rendered() {
+    let mut ctx = common::setup();
+    let stub = StubServer::start().await;
+    stub.serve(
+        "/article",
+        "text/html",
+        r#"<html><head>
+        <meta property="og:title" content="Stub Article">
+        <meta property="og:description" content="Served locally">
+        <meta property="og:image" content="/cover.png">
+        <meta property="og:site_name" content="Stub">
+        </head></html>"#,
+    );
+    let url = stub.url("/article");
+    let post = post_with(&mut ctx.conn, &format!("look:\n\n{url}\n"));
+    assert!(!post.cooked.contains("onebox"));
+
+    FetchLinkPreviewsJob { post_id: post.id }
+        .execute(&ctx.pool())
+        .await
+        .expect("job failed");
+
+    let preview: LinkPreview = link_previews::table
+        .filter(link_previews::url.eq(&url))
+        .select(LinkPreview::as_select())
+        .first(&mut ctx.conn)
+        .unwrap();
+    assert_eq!(preview.title.as_deref(), Some("Stub Article"));
+    assert_eq!(preview.error, None);
+    assert_eq!(
+        preview.image_url.as_deref(),
+        Some(stub.url("/cover.png").as_str())
+    );
+
+    let html = cooked(&mut ctx.conn, post.id);
+    assert!(html.contains(&format!(r#"<aside class="onebox" data-url="{url}">"#)));
+    assert!(html.contains("Stub Article"));
+
+    // A second post linking the same page renders from the cache without
+    // another request.
+    let hits = stub.hits();
+    let again = post_with(&mut ctx.conn, &url);
+    assert!(again.cooked.contains("Stub Article"));
+    FetchLinkPreviewsJob { post_id: again.id }
+        .execute(&ctx.pool())
+        .await
+        .unwrap();
+    assert_eq!(stub.hits(), hits);
+}
+
+#[actix_web::test]
+async fn oembed_fills_in_when_open_graph_is_missing() {
+    let mut ctx = common::setup();
+    let stub = StubServer::start().await;
+    stub.serve(
+        "/video",
+        "text/html",
+        r#"<link rel="alternate" type="application/json+oembed" href="/oembed">"#,
+    );
+    stub.serve(
+        "/oembed",
+        "application/json",
+        &json!({ "title": "Stub Video", "provider_name": "StubTube" }).to_string(),
+    );
+    let post = post_with(&mut ctx.conn, &stub.url("/video"));
+
+    FetchLinkPreviewsJob { post_id: post.id }
+        .execute(&ctx.pool())
+        .await
+        .unwrap();
+
+    let html = cooked(&mut ctx.conn, post.id);
+    assert!(html.contains("Stub Video"));
+    assert!(html.contains("StubTube"));
+}
+
+#[actix_web::test]
+async fn failed_lookups_are_cached_and_leave_a_plain_link() {
+    let mut ctx = common::setup();
+    let stub = StubServer::start().await;
+    let url = stub.url("/missing");
+    let post = post_with(&mut ctx.conn, &url);
+
+    FetchLinkPreviewsJob { post_id: post.id }
+        .execute(&ctx.pool())
+        .await
+        .expect("a failed lookup doesn't fail the job");
+
+    let error: Option<String> = link_previews::table
+        .filter(link_previews::url.eq(&url))
+        .select(link_previews::error)
+        .first(&mut ctx.conn)
+        .unwrap();
+    assert_eq!(error.as_deref(), Some("HTTP status 404"));
+    assert_eq!(cooked(&mut ctx.conn, post.id), post.cooked);
+
+    let hits = stub.hits();
+    FetchLinkPreviewsJob { post_id: post.id }
+        .execute(&ctx.pool())
+        .await
+        .unwrap();
+    assert_eq!(stub.hits(), hits, "a recent failure isn't retried");
+}
+
+#[actix_web::test]
+async fn creating_a_post_with_a_bare_link_queues_a_lookup() {
+    let mut ctx = common::setup();
+    let user = common::create_user(
+        &mut ctx.conn,
+        common::UserOpts {
+            trust_level: 1,
+            ..Default::default()
+        },
+    );
+    let topic = common::create_topic(&mut ctx.conn, common::TopicOpts::for_user(user.id));
+    let app = test::init_service(
+        common::test_app_factory().app_data(web::Data::new(JobQueue::new(Arc::new(ctx.pool())))),
+    )
+    .await;
+    let (hk, hv) = common::auth_header_for(&user);
+
+    for (number, raw) in [(1, "no links here"), (2, "https://example.com/page")] {
+        let req = test::TestRequest::post()
+            .uri("/api/posts")
+            .insert_header((hk, hv.clone()))
+            .set_json(json!({
+                "topic_id": topic.id,
+                "user_id": user.id,
+                "post_number": number,
+                "raw": raw,
+            }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status().as_u16(), 201);
+    }
+
+    let payloads: Vec<serde_json::Value> = backie_tasks::table
+        .filter(backie_tasks::task_name.eq(FetchLinkPreviewsJob::NAME))
+        .select(backie_tasks::payload)
+        .load(&mut ctx.conn)
+        .unwrap();
+    assert_eq!(payloads.len(), 1);
+}
@@ -0,0 +1,203 @@
+//! Tests for `mailer::deliver` and the email log: what reaches the
+//! transport, what gets recorded, and bounce bookkeeping. The mailer is
+//! process-wide, so each test installs its own.
+
+mod common;
+
+use diesel::prelude::*;
+use discourse_rs::jobs::{Job, WelcomeEmailJob};
+use discourse_rs::mailer::{
+    self, Email, MailError, MaildirMailer, Mailer, MemoryMailer, Recipient, Template,
+};
+use discourse_rs::models::EmailLog;
+use discourse_rs::schema::email_logs;
+use discourse_rs::services::email_logs as logs;
+use futures::future::BoxFuture;
+use std::sync::Arc;
+
+fn install_memory() -> MemoryMailer {
+    let mailer = MemoryMailer::new();
+    mailer::install(Arc::new(mailer.clone()));
+    mailer
+}
+
+/// A transport that refuses everything.
+struct DownMailer;
+
+impl Mailer for DownMailer {
+    fn send<'a>(&'a self, _email: &'a Email) -> BoxFuture<'a, Result<(), MailError>> {
+        Box::pin(async { Err(MailError::Transport("connection refused".to_string())) })
+    }
+}
+
+fn all_logs(conn: &mut PgConnection) -> Vec<EmailLog> {
+    email_logs::table
+        .order(email_logs::id)
+        .select(EmailLog::as_select())
+        .load(conn)
+        .unwrap()
+}
+
+fn welcome(username: &str) -> Template {
+    Template::Welcome {
+        username: username.to_string(),
+    }
+}
+
+#[tokio::test]
+async fn deliver_sends_and_logs() {
+    let mut ctx = common::setup();
+    let user = common::create_user(&mut ctx.conn, common::UserOpts::default());
+    let outbox = install_memory();
+
+    let recipient = Recipient {
+        user_id: Some(user.id),
+        address: user.email.clone(),
+    };
+    let log = mailer::deliver(&ctx.pool(), recipient, welcome(&user.username))
+        .await
+        .unwrap();
+
+    let sent = outbox.take();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].to, user.email);
+    // site_name comes from the seeded setting.
+    assert_eq!(sent[0].subject, "Welcome to Discourse RS!");
+    assert!(sent[0].text.contains(&user.username));
+    assert!(sent[0].html.contains(&user.username));
+
+    assert_eq!(log.status, "sent");
+    assert_eq!(log.user_id, Some(user.id));
+    assert_eq!(log.email_type, "welcome");
+    assert_eq!(log.message_id, sent[0].message_id);
+    assert_eq!(all_logs(&mut ctx.conn).len(), 1);
+}
+
+#[tokio::test]
+async fn failed_send_is_logged_and_returned() {
+    let mut ctx = common::setup();
+    mailer::install(Arc::new(DownMailer));
+
+    let recipient = Recipient {
+        user_id: None,
+        address: "bob@example.com".to_string(),
+    };
+    let err = mailer::deliver(&ctx.pool(), recipient, welcome("bob"))
+        .await
+        .unwrap_err();
+    assert!(err.contains("connection refused"), "{err}");
+
+    let logged = all_logs(&mut ctx.conn);
+    assert_eq!(logged.len(), 1);
+    assert_eq!(logged[0].status, "failed");
+    assert_eq!(
+        logged[0].error.as_deref(),
+        Some("transport error: connection refused")
+    );
+}
+
+#[tokio::test]
+async fn invalid_address_is_a_failed_send() {
+    let mut ctx = common::setup();
+    let outbox = install_memory();
+
+    let recipient = Recipient {
+        user_id: None,
+        address: "not an address".to_string(),
+    };
+    assert!(
+        mailer::deliver(&ctx.pool(), recipient, welcome("bob"))
+            .await
+            .is_err()
+    );
+    assert!(outbox.sent().is_empty());
+    assert_eq!(all_logs(&mut ctx.conn)[0].status, "failed");
+}
+
+#[tokio::test]
+async fn welcome_email_job_delivers() {
+    let mut ctx = common::setup();
+    let user = common::create_user(&mut ctx.conn, common::UserOpts::default());
+    let outbox = install_memory();
+
+    let job = WelcomeEmailJob {
+        user_id: user.id,
+        username: user.username.clone(),
+        email: user.email.clone(),
+    };
+    job.execute(&ctx.pool()).await.unwrap();
+
+    let sent = outbox.take();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].to, user.email);
+    assert_eq!(all_logs(&mut ctx.conn)[0].user_id, Some(user.id));
+}
+
+#[tokio::test]
+async fn bounce_is_matched_by_message_id() {
+    let mut ctx = common::setup();
+    install_memory();
+
+    let recipient = Recipient {
+        user_id: None,
+        address: "carol@example.com".to_string(),
+    };
+    let log = mailer::deliver(&ctx.pool(), recipient, welcome("carol"))
+        .await
+        .unwrap();
+
+    // Bounce reports quote the header with its angle brackets.
+    let quoted = format!("<{}>", log.message_id);
+    let bounced = logs::record_bounce(&mut ctx.conn, &quoted, "550 mailbox unavailable")
+        .unwrap()
+        .unwrap();
+    assert_eq!(bounced.id, log.id);
+    assert_eq!(bounced.status, "bounced");
+    assert_eq!(bounced.error.as_deref(), Some("550 mailbox unavailable"));
+    assert!(bounced.bounced_at.is_some());
+
+    // A repeat report keeps the first reason.
+    let again = logs::record_bounce(&mut ctx.conn, &log.message_id, "later")
+        .unwrap()
+        .unwrap();
+    assert_eq!(again.error.as_deref(), Some("550 mailbox unavailable"));
+
+    assert!(
+        logs::record_bounce(&mut ctx.conn, "unknown@localhost", "x")
+            .unwrap()
+            .is_none()
+    );
+    assert_eq!(
+        logs::list(&mut ctx.conn, Some("bounced"), None, 30, 0)
+            .unwrap()
+            .len(),
+        1
+    );
+}
+
+#[tokio::test]
+async fn maildir_mailer_writes_into_new() {
+    let root = std::env::temp_dir().join(format!("discourse-rs-mail-{}", uuid::Uuid::new_v4()));
+    let mailer = MaildirMailer::new(&root);
+    let email = Email {
+        from: "Forum <noreply@forum.example>".to_string(),
+        to: "dave@example.com".to_string(),
+        subject: "Hi".to_string(),
+        text: "plain".to_string(),
+        html: "<p>html</p>".to_string(),
+        message_id: "maildir-test@forum.example".to_string(),
+    };
+    mailer.send(&email).await.unwrap();
+
+    let delivered: Vec<_> = std::fs::read_dir(root.join("new"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    assert_eq!(delivered.len(), 1);
+    let contents = std::fs::read_to_string(&delivered[0]).unwrap();
+    assert!(contents.contains("To: dave@example.com"));
+    assert!(contents.contains("Message-ID: <maildir-test@forum.example>"));
+    assert_eq!(std::fs::read_dir(root.join("tmp")).unwrap().count(), 0);
+
+    std::fs::remove_dir_all(root).unwrap();
+}
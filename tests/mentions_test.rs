@@ -0,0 +1,156 @@
+//! Service-layer tests for `services::mentions` and the post create/update
+//! path that drives it.
+
+mod common;
+
+use diesel::prelude::*;
+use discourse_rs::jobs::{Job, PropagateUsernameJob};
+use discourse_rs::models::{CreatePostInput, Post, UpdatePostInput};
+use discourse_rs::schema::{notifications, post_mentions, posts};
+use discourse_rs::services::posts as post_service;
+
+fn user_named(conn: &mut PgConnection, username: &str) -> discourse_rs::models::User {
+    common::create_user(
+        conn,
+        common::UserOpts {
+            username: username.to_string(),
+            ..Default::default()
+        },
+    )
+}
+
+fn post_as(conn: &mut PgConnection, topic_id: i32, user_id: i32, number: i32, raw: &str) -> Post {
+    post_service::create(
+        conn,
+        CreatePostInput {
+            topic_id,
+            user_id,
+            post_number: number,
+            raw: raw.to_string(),
+            reply_to_post_number: None,
+        },
+    )
+    .expect("create failed")
+}
+
+fn mention_notifications(conn: &mut PgConnection, user_id: i32) -> i64 {
+    notifications::table
+        .filter(notifications::user_id.eq(user_id))
+        .filter(notifications::notification_type.eq("mentioned"))
+        .count()
+        .get_result(conn)
+        .unwrap()
+}
+
+#[test]
+fn creating_a_post_links_records_and_notifies() {
+    let mut ctx = common::setup();
+    let author = user_named(&mut ctx.conn, "author");
+    let bob = user_named(&mut ctx.conn, "Bob");
+    let topic = common::create_topic(&mut ctx.conn, common::TopicOpts::for_user(author.id));
+
+    let post = post_as(
+        &mut ctx.conn,
+        topic.id,
+        author.id,
+        1,
+        "thanks @bob, and @author, and @ghost",
+    );
+
+    assert!(
+        post.cooked
+            .contains(r#"<a class="mention" href="/u/Bob">@Bob</a>"#)
+    );
+    assert!(post.cooked.contains("@ghost"));
+
+    let mut recorded: Vec<i32> = post_mentions::table
+        .filter(post_mentions::post_id.eq(post.id))
+        .select(post_mentions::user_id)
+        .load(&mut ctx.conn)
+        .unwrap();
+    recorded.sort();
+    assert_eq!(recorded, vec![author.id, bob.id]);
+
+    assert_eq!(mention_notifications(&mut ctx.conn, bob.id), 1);
+    // Mentioning yourself doesn't notify.
+    assert_eq!(mention_notifications(&mut ctx.conn, author.id), 0);
+
+    let (acting, post_id): (Option<i32>, Option<i32>) = notifications::table
+        .filter(notifications::user_id.eq(bob.id))
+        .select((notifications::acting_user_id, notifications::post_id))
+        .first(&mut ctx.conn)
+        .unwrap();
+    assert_eq!(acting, Some(author.id));
+    assert_eq!(post_id, Some(post.id));
+}
+
+#[test]
+fn edits_notify_only_newly_mentioned_users() {
+    let mut ctx = common::setup();
+    let author = user_named(&mut ctx.conn, "author");
+    let bob = user_named(&mut ctx.conn, "bob");
+    let carol = user_named(&mut ctx.conn, "carol");
+    let topic = common::create_topic(&mut ctx.conn, common::TopicOpts::for_user(author.id));
+    let post = post_as(&mut ctx.conn, topic.id, author.id, 1, "hi @bob");
+
+    let edit = |raw: &str| UpdatePostInput {
+        raw: Some(raw.to_string()),
+    };
+    post_service::update(&mut ctx.conn, post.id, author.id, edit("hi @bob and @carol"))
+        .unwrap()
+        .expect("post exists");
+    assert_eq!(mention_notifications(&mut ctx.conn, bob.id), 1);
+    assert_eq!(mention_notifications(&mut ctx.conn, carol.id), 1);
+
+    // Dropping a mention forgets it; adding it back counts as new.
+    post_service::update(&mut ctx.conn, post.id, author.id, edit("hi @carol")).unwrap();
+    post_service::update(&mut ctx.conn, post.id, author.id, edit("hi @carol and @bob")).unwrap();
+    assert_eq!(mention_notifications(&mut ctx.conn, bob.id), 2);
+    assert_eq!(mention_notifications(&mut ctx.conn, carol.id), 1);
+
+    assert!(
+        post_service::update(&mut ctx.conn, 999_999, author.id, edit("x"))
+            .unwrap()
+            .is_none()
+    );
+}
+
+#[actix_web::test]
+async fn renaming_rewrites_only_exact_mentions() {
+    let mut ctx = common::setup();
+    let author = user_named(&mut ctx.conn, "author");
+    let bob = user_named(&mut ctx.conn, "bob");
+    user_named(&mut ctx.conn, "bobby");
+    let topic = common::create_topic(&mut ctx.conn, common::TopicOpts::for_user(author.id));
+    let both = post_as(&mut ctx.conn, topic.id, author.id, 1, "@bob meet @bobby");
+    let only_bobby = post_as(&mut ctx.conn, topic.id, author.id, 2, "@bobby and bob");
+
+    diesel::update(discourse_rs::schema::users::table.find(bob.id))
+        .set(discourse_rs::schema::users::username.eq("robert"))
+        .execute(&mut ctx.conn)
+        .unwrap();
+    PropagateUsernameJob {
+        user_id: bob.id,
+        old_username: "bob".to_string(),
+        new_username: "robert".to_string(),
+    }
+    .execute(&ctx.pool())
+    .await
+    .expect("job failed");
+
+    let (raw, cooked): (String, String) = posts::table
+        .find(both.id)
+        .select((posts::raw, posts::cooked))
+        .first(&mut ctx.conn)
+        .unwrap();
+    assert_eq!(raw, "@robert meet @bobby");
+    assert!(cooked.contains(r#"href="/u/robert">@robert</a>"#));
+    assert!(cooked.contains(r#"href="/u/bobby">@bobby</a>"#));
+
+    let untouched: String = posts::table
+        .find(only_bobby.id)
+        .select(posts::raw)
+        .first(&mut ctx.conn)
+        .unwrap();
+    assert_eq!(untouched, "@bobby and bob");
+}
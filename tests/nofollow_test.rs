@@ -0,0 +1,50 @@
+//! External links in posts are `rel="nofollow ugc"` until the author
+//! reaches the trust level where the forum vouches for them.
+
+mod common;
+
+use discourse_rs::models::CreatePostInput;
+use discourse_rs::services::posts::{self as post_service, FOLLOW_LINKS_TRUST_LEVEL};
+
+const RAW: &str = "[docs](https://docs.example/guide) and [a topic](/t/1)";
+
+fn cooked_by(conn: &mut diesel::PgConnection, opts: common::UserOpts) -> String {
+    let user = common::create_user(conn, opts);
+    let topic = common::create_topic(conn, common::TopicOpts::for_user(user.id));
+    post_service::create(
+        conn,
+        CreatePostInput {
+            topic_id: topic.id,
+            user_id: user.id,
+            post_number: 1,
+            raw: RAW.to_string(),
+            reply_to_post_number: None,
+        },
+    )
+    .unwrap()
+    .cooked
+}
+
+#[test]
+fn low_trust_authors_get_nofollow_on_external_links() {
+    let mut ctx = common::setup();
+
+    let cooked = cooked_by(&mut ctx.conn, Default::default());
+    assert_eq!(
+        cooked,
+        "<p><a href=\"https://docs.example/guide\" rel=\"nofollow ugc\">docs</a> and \
+         <a href=\"/t/1\">a topic</a></p>\n"
+    );
+
+    let regular = common::UserOpts {
+        trust_level: FOLLOW_LINKS_TRUST_LEVEL,
+        ..Default::default()
+    };
+    assert!(!cooked_by(&mut ctx.conn, regular).contains("nofollow"));
+
+    let moderator = common::UserOpts {
+        moderator: true,
+        ..Default::default()
+    };
+    assert!(!cooked_by(&mut ctx.conn, moderator).contains("nofollow"));
+}
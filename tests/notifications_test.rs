@@ -0,0 +1,213 @@
+//! `services::notifications`: who hears about new posts, how likes
+//! consolidate, and the badge and moderator-action notifications.
+
+mod common;
+
+use actix_web::test;
+use diesel::prelude::*;
+use discourse_rs::models::CreatePostInput;
+use discourse_rs::schema::notifications;
+use discourse_rs::services::likes::{like_post, unlike_post};
+use discourse_rs::services::posts as post_service;
+use discourse_rs::services::trust_levels::{TL1_MIN_POSTS, evaluate};
+use discourse_rs::services::user_stats;
+use serde_json::{Value, json};
+
+fn notifications_for(conn: &mut PgConnection, user_id: i32) -> Vec<(String, Value)> {
+    notifications::table
+        .filter(notifications::user_id.eq(user_id))
+        .order(notifications::id)
+        .select((notifications::notification_type, notifications::data))
+        .load(conn)
+        .unwrap()
+}
+
+fn reply(
+    conn: &mut PgConnection,
+    topic_id: i32,
+    user_id: i32,
+    post_number: i32,
+    raw: &str,
+    reply_to_post_number: Option<i32>,
+) {
+    post_service::create(
+        conn,
+        CreatePostInput {
+            topic_id,
+            user_id,
+            post_number,
+            raw: raw.to_string(),
+            reply_to_post_number,
+        },
+    )
+    .expect("create post failed");
+}
+
+#[actix_web::test]
+async fn new_posts_notify_the_replied_to_author_and_watchers_once_each() {
+    let mut ctx = common::setup();
+    let owner = common::create_user(&mut ctx.conn, Default::default());
+    let replier = common::create_user(&mut ctx.conn, Default::default());
+    let watcher = common::create_user(&mut ctx.conn, Default::default());
+    let topic = common::create_topic(&mut ctx.conn, common::TopicOpts::for_user(owner.id));
+    reply(&mut ctx.conn, topic.id, owner.id, 1, "The first post", None);
+
+    let app = test::init_service(common::test_app_factory()).await;
+    let (k, v) = common::auth_header_for(&watcher);
+    let req = test::TestRequest::put()
+        .uri(&format!("/api/topics/{}/watch", topic.id))
+        .insert_header((k, v))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status().as_u16(), 204);
+
+    // Not a reply to a post in particular: the topic's creator hears of it.
+    reply(&mut ctx.conn, topic.id, replier.id, 2, "Nice topic", None);
+    reply(&mut ctx.conn, topic.id, watcher.id, 3, "Agreed", None);
+    // A reply to the watcher, who is told once, as `replied`.
+    reply(&mut ctx.conn, topic.id, replier.id, 4, "Thanks", Some(3));
+
+    let owner_types: Vec<String> = notifications_for(&mut ctx.conn, owner.id)
+        .into_iter()
+        .map(|(kind, _)| kind)
+        .collect();
+    assert_eq!(owner_types, vec!["replied", "replied"]);
+
+    let watcher_notifications = notifications_for(&mut ctx.conn, watcher.id);
+    let watcher_types: Vec<&str> = watcher_notifications
+        .iter()
+        .map(|(kind, _)| kind.as_str())
+        .collect();
+    assert_eq!(watcher_types, vec!["posted", "replied"]);
+    assert_eq!(watcher_notifications[1].1["reply_to_post_number"], 3);
+    assert!(notifications_for(&mut ctx.conn, replier.id).is_empty());
+}
+
+#[actix_web::test]
+async fn likes_consolidate_until_the_notification_is_read() {
+    let mut ctx = common::setup();
+    let author = common::create_user(&mut ctx.conn, Default::default());
+    let topic = common::create_topic(&mut ctx.conn, common::TopicOpts::for_user(author.id));
+    let post = common::create_post(
+        &mut ctx.conn,
+        common::PostOpts::for_topic(topic.id, author.id),
+    );
+
+    let mut likers = Vec::new();
+    for _ in 0..3 {
+        let liker = common::create_user(&mut ctx.conn, Default::default());
+        like_post(&mut ctx.conn, liker.id, post.id).unwrap();
+        likers.push(liker);
+    }
+
+    let rows = notifications_for(&mut ctx.conn, author.id);
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].0, "post_liked");
+    assert_eq!(
+        rows[0].1,
+        json!({
+            "username": likers[2].username,
+            "count": 3,
+            "user_ids": [likers[0].id, likers[1].id, likers[2].id],
+        })
+    );
+
+    diesel::update(notifications::table)
+        .set(notifications::read.eq(true))
+        .execute(&mut ctx.conn)
+        .unwrap();
+    let liker = common::create_user(&mut ctx.conn, Default::default());
+    like_post(&mut ctx.conn, liker.id, post.id).unwrap();
+
+    let rows = notifications_for(&mut ctx.conn, author.id);
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[1].1["count"], 1);
+}
+
+#[actix_web::test]
+async fn liking_again_after_unliking_counts_once() {
+    let mut ctx = common::setup();
+    let author = common::create_user(&mut ctx.conn, Default::default());
+    let alice = common::create_user(&mut ctx.conn, Default::default());
+    let bob = common::create_user(&mut ctx.conn, Default::default());
+    let topic = common::create_topic(&mut ctx.conn, common::TopicOpts::for_user(author.id));
+    let post = common::create_post(
+        &mut ctx.conn,
+        common::PostOpts::for_topic(topic.id, author.id),
+    );
+
+    like_post(&mut ctx.conn, alice.id, post.id).unwrap();
+    like_post(&mut ctx.conn, bob.id, post.id).unwrap();
+    for _ in 0..3 {
+        unlike_post(&mut ctx.conn, alice.id, post.id).unwrap();
+        like_post(&mut ctx.conn, alice.id, post.id).unwrap();
+    }
+
+    let rows = notifications_for(&mut ctx.conn, author.id);
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].1["count"], 2);
+    assert_eq!(rows[0].1["username"], alice.username.as_str());
+
+    unlike_post(&mut ctx.conn, alice.id, post.id).unwrap();
+    let rows = notifications_for(&mut ctx.conn, author.id);
+    assert_eq!(rows[0].1["count"], 1);
+    assert_eq!(rows[0].1["username"], bob.username.as_str());
+
+    unlike_post(&mut ctx.conn, bob.id, post.id).unwrap();
+    assert!(notifications_for(&mut ctx.conn, author.id).is_empty());
+}
+
+#[actix_web::test]
+async fn promotion_grants_a_badge() {
+    let mut ctx = common::setup();
+    let user = common::create_user(&mut ctx.conn, Default::default());
+    for _ in 0..TL1_MIN_POSTS {
+        user_stats::incr_post_count(&mut ctx.conn, user.id).unwrap();
+    }
+
+    evaluate(&mut ctx.conn, user.id).unwrap();
+    evaluate(&mut ctx.conn, user.id).unwrap();
+
+    assert_eq!(
+        notifications_for(&mut ctx.conn, user.id),
+        vec![(
+            "granted_badge".to_string(),
+            json!({ "badge_name": "Basic User" })
+        )]
+    );
+}
+
+#[actix_web::test]
+async fn hiding_a_post_tells_its_author() {
+    let mut ctx = common::setup();
+    let author = common::create_user(&mut ctx.conn, Default::default());
+    let moderator = common::create_user(
+        &mut ctx.conn,
+        common::UserOpts {
+            moderator: true,
+            ..Default::default()
+        },
+    );
+    let topic = common::create_topic(&mut ctx.conn, common::TopicOpts::for_user(author.id));
+    let post = common::create_post(
+        &mut ctx.conn,
+        common::PostOpts::for_topic(topic.id, author.id),
+    );
+
+    let app = test::init_service(common::test_app_factory()).await;
+    let (k, v) = common::auth_header_for(&moderator);
+    let req = test::TestRequest::post()
+        .uri("/api/moderation/posts/hide")
+        .insert_header((k, v))
+        .set_json(json!({ "post_id": post.id }))
+        .to_request();
+    assert!(test::call_service(&app, req).await.status().is_success());
+
+    let (post_id, acting): (Option<i32>, Option<i32>) = notifications::table
+        .filter(notifications::user_id.eq(author.id))
+        .filter(notifications::notification_type.eq("moderator_action"))
+        .select((notifications::post_id, notifications::acting_user_id))
+        .first(&mut ctx.conn)
+        .expect("expected a moderator_action notification");
+    assert_eq!(post_id, Some(post.id));
+    assert_eq!(acting, Some(moderator.id));
+}
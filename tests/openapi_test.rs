@@ -0,0 +1,72 @@
+//! The generated OpenAPI spec documents the error responses each path
+//! can return, all with the shared `ErrorResponse` body.
+
+use discourse_rs::openapi::ApiDoc;
+use serde_json::Value;
+use utoipa::OpenApi;
+
+fn operations(spec: &Value) -> Vec<(String, &Value)> {
+    let mut found = Vec::new();
+    for (path, item) in spec["paths"].as_object().expect("spec has no paths") {
+        for (method, operation) in item.as_object().unwrap() {
+            found.push((format!("{} {path}", method.to_uppercase()), operation));
+        }
+    }
+    found
+}
+
+#[test]
+fn every_operation_documents_its_error_responses() {
+    let spec = serde_json::to_value(ApiDoc::openapi()).unwrap();
+    let operations = operations(&spec);
+    assert!(
+        operations.len() > 70,
+        "only {} operations",
+        operations.len()
+    );
+
+    for (name, operation) in operations {
+        let responses = operation["responses"].as_object().unwrap();
+        assert!(
+            responses.keys().any(|status| status.starts_with('2')),
+            "{name} has no success response"
+        );
+        assert!(responses.contains_key("429"), "{name} doesn't document 429");
+        for (status, response) in responses.iter().filter(|(s, _)| !s.starts_with('2')) {
+            assert_eq!(
+                response["content"]["application/json"]["schema"]["$ref"],
+                "#/components/schemas/ErrorResponse",
+                "{name} {status} isn't an ErrorResponse"
+            );
+        }
+    }
+}
+
+#[test]
+fn responses_match_what_the_handlers_return() {
+    let spec = serde_json::to_value(ApiDoc::openapi()).unwrap();
+    assert_eq!(spec["servers"][0]["url"], "/api");
+    let statuses = |path: &str, method: &str| -> Vec<String> {
+        let mut statuses: Vec<String> = spec["paths"][path][method]["responses"]
+            .as_object()
+            .unwrap_or_else(|| panic!("{method} {path} isn't documented"))
+            .keys()
+            .cloned()
+            .collect();
+        statuses.sort();
+        statuses
+    };
+
+    assert_eq!(
+        statuses("/posts", "post"),
+        ["201", "202", "400", "401", "403", "422", "429", "503"]
+    );
+    assert_eq!(
+        statuses("/admin/emojis", "post"),
+        ["201", "400", "401", "403", "409", "422", "429", "503"]
+    );
+    assert_eq!(
+        statuses("/categories/{id}", "get"),
+        ["200", "404", "429", "503"]
+    );
+}
@@ -0,0 +1,196 @@
+//! Tests for the password service: reset requests and their rate limit,
+//! resetting with a token, changing with the current password, and token
+//! revocation via `token_version`.
+
+mod common;
+
+use diesel::prelude::*;
+use discourse_rs::auth::{Claims, hash_password, verify_password};
+use discourse_rs::models::User;
+use discourse_rs::schema::{email_tokens, users};
+use discourse_rs::services::email_tokens::PASSWORD_RESET;
+use discourse_rs::services::passwords::{self, PasswordError};
+
+fn with_password(conn: &mut PgConnection, user: &User, password: &str) {
+    diesel::update(users::table.find(user.id))
+        .set(users::password_hash.eq(hash_password(password).unwrap()))
+        .execute(conn)
+        .unwrap();
+}
+
+fn reload(conn: &mut PgConnection, user: &User) -> User {
+    users::table
+        .find(user.id)
+        .select(User::as_select())
+        .first(conn)
+        .unwrap()
+}
+
+fn claims_for(user: &User, token_version: i32) -> Claims {
+    Claims {
+        user_id: user.id,
+        username: user.username.clone(),
+        exp: 0,
+        token_version,
+    }
+}
+
+#[test]
+fn reset_can_be_requested_by_username_or_email() {
+    let mut ctx = common::setup();
+    let alice = common::create_user(&mut ctx.conn, Default::default());
+    let bob = common::create_user(&mut ctx.conn, Default::default());
+
+    let (user, _) = passwords::request_reset(&mut ctx.conn, &alice.username)
+        .unwrap()
+        .unwrap();
+    assert_eq!(user.id, alice.id);
+    let (user, _) = passwords::request_reset(&mut ctx.conn, &bob.email)
+        .unwrap()
+        .unwrap();
+    assert_eq!(user.id, bob.id);
+
+    assert!(
+        passwords::request_reset(&mut ctx.conn, "nobody")
+            .unwrap()
+            .is_none()
+    );
+}
+
+#[test]
+fn reset_requests_are_rate_limited() {
+    let mut ctx = common::setup();
+    let user = common::create_user(&mut ctx.conn, Default::default());
+
+    assert!(
+        passwords::request_reset(&mut ctx.conn, &user.username)
+            .unwrap()
+            .is_some()
+    );
+    assert!(
+        passwords::request_reset(&mut ctx.conn, &user.username)
+            .unwrap()
+            .is_none()
+    );
+    let issued: i64 = email_tokens::table
+        .filter(email_tokens::purpose.eq(PASSWORD_RESET))
+        .count()
+        .get_result(&mut ctx.conn)
+        .unwrap();
+    assert_eq!(issued, 1);
+}
+
+#[test]
+fn reset_sets_password_and_revokes_sessions() {
+    let mut ctx = common::setup();
+    let user = common::create_user(&mut ctx.conn, Default::default());
+    let (_, token) = passwords::request_reset(&mut ctx.conn, &user.username)
+        .unwrap()
+        .unwrap();
+    let old_session = claims_for(&user, user.token_version);
+    assert!(passwords::token_is_current(&mut ctx.conn, &old_session).unwrap());
+
+    let updated = passwords::reset(&mut ctx.conn, &token, "new-password").unwrap();
+    assert!(verify_password("new-password", &updated.password_hash).unwrap());
+    assert_eq!(updated.token_version, user.token_version + 1);
+    assert!(updated.password_changed_at.is_some());
+
+    assert!(!passwords::token_is_current(&mut ctx.conn, &old_session).unwrap());
+    let new_session = claims_for(&user, updated.token_version);
+    assert!(passwords::token_is_current(&mut ctx.conn, &new_session).unwrap());
+
+    // Links work once.
+    assert!(matches!(
+        passwords::reset(&mut ctx.conn, &token, "another-password"),
+        Err(PasswordError::InvalidToken)
+    ));
+}
+
+#[test]
+fn reset_revokes_older_links_and_confirms_email() {
+    let mut ctx = common::setup();
+    let user = common::create_user(
+        &mut ctx.conn,
+        common::UserOpts {
+            email_confirmed: false,
+            ..Default::default()
+        },
+    );
+    let first = discourse_rs::services::email_tokens::issue(
+        &mut ctx.conn,
+        user.id,
+        &user.email,
+        PASSWORD_RESET,
+        chrono::Duration::hours(1),
+    )
+    .unwrap();
+    let second = discourse_rs::services::email_tokens::issue(
+        &mut ctx.conn,
+        user.id,
+        &user.email,
+        PASSWORD_RESET,
+        chrono::Duration::hours(1),
+    )
+    .unwrap();
+
+    let updated = passwords::reset(&mut ctx.conn, &second, "new-password").unwrap();
+    assert!(updated.email_confirmed);
+    assert!(matches!(
+        passwords::reset(&mut ctx.conn, &first, "other-password"),
+        Err(PasswordError::InvalidToken)
+    ));
+}
+
+#[test]
+fn expired_reset_links_are_rejected() {
+    let mut ctx = common::setup();
+    let user = common::create_user(&mut ctx.conn, Default::default());
+    let token = discourse_rs::services::email_tokens::issue(
+        &mut ctx.conn,
+        user.id,
+        &user.email,
+        PASSWORD_RESET,
+        chrono::Duration::minutes(-1),
+    )
+    .unwrap();
+
+    assert!(matches!(
+        passwords::reset(&mut ctx.conn, &token, "new-password"),
+        Err(PasswordError::InvalidToken)
+    ));
+    assert_eq!(
+        reload(&mut ctx.conn, &user).token_version,
+        user.token_version
+    );
+}
+
+#[test]
+fn change_requires_current_password() {
+    let mut ctx = common::setup();
+    let user = common::create_user(&mut ctx.conn, Default::default());
+    with_password(&mut ctx.conn, &user, "old-password");
+
+    assert!(matches!(
+        passwords::change(&mut ctx.conn, user.id, "wrong", "new-password"),
+        Err(PasswordError::WrongPassword)
+    ));
+    assert_eq!(
+        reload(&mut ctx.conn, &user).token_version,
+        user.token_version
+    );
+
+    let updated =
+        passwords::change(&mut ctx.conn, user.id, "old-password", "new-password").unwrap();
+    assert!(verify_password("new-password", &updated.password_hash).unwrap());
+    assert_eq!(updated.token_version, user.token_version + 1);
+}
+
+#[test]
+fn deleted_users_tokens_are_not_current() {
+    let mut ctx = common::setup();
+    let user = common::create_user(&mut ctx.conn, Default::default());
+    diesel::delete(users::table.find(user.id))
+        .execute(&mut ctx.conn)
+        .unwrap();
+    assert!(!passwords::token_is_current(&mut ctx.conn, &claims_for(&user, 0)).unwrap());
+}
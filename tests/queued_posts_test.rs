@@ -0,0 +1,224 @@
+//! The approval queue: posts that need a moderator's OK are queued instead
+//! of created, and approving or rejecting them notifies the author.
+
+mod common;
+
+use actix_web::test;
+use diesel::prelude::*;
+use discourse_rs::models::WatchedWordInput;
+use discourse_rs::schema::{notifications, posts, queued_posts};
+use discourse_rs::services::watched_words;
+use serde_json::{Value, json};
+
+fn post_body(topic_id: i32, user_id: i32, raw: &str) -> Value {
+    json!({ "topic_id": topic_id, "user_id": user_id, "post_number": 1, "raw": raw })
+}
+
+fn notifications_for(conn: &mut PgConnection, user_id: i32) -> Vec<(String, Value)> {
+    notifications::table
+        .filter(notifications::user_id.eq(user_id))
+        .order(notifications::id)
+        .select((notifications::notification_type, notifications::data))
+        .load(conn)
+        .unwrap()
+}
+
+#[actix_web::test]
+async fn low_trust_posts_wait_for_approval() {
+    let mut ctx = common::setup();
+    common::set_setting(&mut ctx.conn, "approve_unless_trust_level", "1");
+    let newcomer = common::create_user(&mut ctx.conn, Default::default());
+    let moderator = common::create_user(
+        &mut ctx.conn,
+        common::UserOpts {
+            moderator: true,
+            ..Default::default()
+        },
+    );
+    let topic = common::create_topic(&mut ctx.conn, common::TopicOpts::for_user(moderator.id));
+    common::create_post(
+        &mut ctx.conn,
+        common::PostOpts::for_topic(topic.id, moderator.id),
+    );
+    let app = test::init_service(common::test_app_factory()).await;
+    let (nk, nv) = common::auth_header_for(&newcomer);
+    let (mk, mv) = common::auth_header_for(&moderator);
+
+    let req = test::TestRequest::post()
+        .uri("/api/posts")
+        .insert_header((nk, nv.clone()))
+        .set_json(post_body(topic.id, newcomer.id, "Hello, first post!"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status().as_u16(), 202);
+    let queued: Value = test::read_body_json(resp).await;
+    assert_eq!(queued["reason"], "trust_level");
+    assert_eq!(queued["status"], "pending");
+    let post_count: i64 = posts::table.count().get_result(&mut ctx.conn).unwrap();
+    assert_eq!(post_count, 1, "nothing published yet");
+
+    let req = test::TestRequest::post()
+        .uri("/api/posts")
+        .insert_header((mk, mv.clone()))
+        .set_json(json!({ "topic_id": topic.id, "user_id": moderator.id, "post_number": 2, "raw": "Staff post" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status().as_u16(), 201);
+
+    let req = test::TestRequest::get()
+        .uri("/api/queued_posts")
+        .insert_header((nk, nv))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status().as_u16(), 403);
+    let req = test::TestRequest::get()
+        .uri("/api/queued_posts")
+        .insert_header((mk, mv.clone()))
+        .to_request();
+    let pending: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(pending.as_array().unwrap().len(), 1);
+    assert_eq!(pending[0]["id"], queued["id"]);
+
+    let approve = || {
+        test::TestRequest::post()
+            .uri(&format!("/api/queued_posts/{}/approve", queued["id"]))
+            .insert_header((mk, mv.clone()))
+            .to_request()
+    };
+    let resp = test::call_service(&app, approve()).await;
+    assert_eq!(resp.status().as_u16(), 201);
+    let post: Value = test::read_body_json(resp).await;
+    assert_eq!(post["post_number"], 3);
+    assert_eq!(post["user_id"], newcomer.id);
+    assert_eq!(post["cooked"], "<p>Hello, first post!</p>\n");
+    assert_eq!(
+        test::call_service(&app, approve()).await.status().as_u16(),
+        409
+    );
+
+    let notes = notifications_for(&mut ctx.conn, newcomer.id);
+    assert_eq!(notes.len(), 1);
+    assert_eq!(notes[0].0, "queued_post_approved");
+    assert_eq!(notes[0].1["queued_post_id"], queued["id"]);
+
+    let req = test::TestRequest::get()
+        .uri("/api/queued_posts?status=approved")
+        .insert_header((mk, mv))
+        .to_request();
+    let approved: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(approved[0]["post_id"], post["id"]);
+    assert_eq!(approved[0]["reviewed_by_user_id"], moderator.id);
+}
+
+#[actix_web::test]
+async fn category_and_watched_word_posts_are_queued_and_can_be_rejected() {
+    let mut ctx = common::setup();
+    let member = common::create_user(
+        &mut ctx.conn,
+        common::UserOpts {
+            trust_level: 2,
+            ..Default::default()
+        },
+    );
+    let admin = common::create_user(
+        &mut ctx.conn,
+        common::UserOpts {
+            admin: true,
+            ..Default::default()
+        },
+    );
+    let category = common::create_category(&mut ctx.conn, Default::default());
+    common::set_setting(
+        &mut ctx.conn,
+        "approve_post_categories",
+        &format!("999|{}", category.id),
+    );
+    let moderated = common::create_topic(
+        &mut ctx.conn,
+        common::TopicOpts {
+            category_id: Some(category.id),
+            ..common::TopicOpts::for_user(admin.id)
+        },
+    );
+    let open = common::create_topic(&mut ctx.conn, common::TopicOpts::for_user(admin.id));
+    let input = WatchedWordInput {
+        word: "giveaway".to_string(),
+        action: "require_approval".to_string(),
+        replacement: None,
+        regex: false,
+    };
+    watched_words::create(&mut ctx.conn, input.into_new_watched_word()).unwrap();
+    let app = test::init_service(common::test_app_factory()).await;
+    let (hk, hv) = common::auth_header_for(&member);
+    let (ak, av) = common::auth_header_for(&admin);
+
+    let submit = |topic_id: i32, raw: &str| {
+        test::TestRequest::post()
+            .uri("/api/posts")
+            .insert_header((hk, hv.clone()))
+            .set_json(post_body(topic_id, member.id, raw))
+            .to_request()
+    };
+    let resp = test::call_service(&app, submit(open.id, "Plain post")).await;
+    assert_eq!(resp.status().as_u16(), 201);
+    let resp = test::call_service(&app, submit(moderated.id, "In a moderated category")).await;
+    assert_eq!(resp.status().as_u16(), 202);
+    let by_category: Value = test::read_body_json(resp).await;
+    assert_eq!(by_category["reason"], "category");
+    let resp = test::call_service(&app, submit(open.id, "Free GIVEAWAY inside")).await;
+    assert_eq!(resp.status().as_u16(), 202);
+    let by_word: Value = test::read_body_json(resp).await;
+    assert_eq!(by_word["reason"], "watched_word");
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/queued_posts/{}/reject", by_category["id"]))
+        .insert_header((ak, av.clone()))
+        .set_json(json!({ "reason": "Off topic" }))
+        .to_request();
+    let rejected: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(rejected["status"], "rejected");
+    assert_eq!(rejected["reject_reason"], "Off topic");
+
+    // Approving publishes it as-is, even though it still matches the word.
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/queued_posts/{}/approve", by_word["id"]))
+        .insert_header((ak, av))
+        .to_request();
+    let post: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(post["hidden"], false);
+    assert_eq!(post["post_number"], 2);
+
+    let notes = notifications_for(&mut ctx.conn, member.id);
+    let kinds: Vec<&str> = notes.iter().map(|(kind, _)| kind.as_str()).collect();
+    assert_eq!(kinds, vec!["queued_post_rejected", "queued_post_approved"]);
+    assert_eq!(notes[0].1["reason"], "Off topic");
+}
+
+#[actix_web::test]
+async fn posting_as_someone_else_is_forbidden() {
+    let mut ctx = common::setup();
+    common::set_setting(&mut ctx.conn, "approve_unless_trust_level", "1");
+    let newcomer = common::create_user(&mut ctx.conn, Default::default());
+    let admin = common::create_user(
+        &mut ctx.conn,
+        common::UserOpts {
+            admin: true,
+            ..Default::default()
+        },
+    );
+    let topic = common::create_topic(&mut ctx.conn, common::TopicOpts::for_user(admin.id));
+    let app = test::init_service(common::test_app_factory()).await;
+    let (k, v) = common::auth_header_for(&newcomer);
+
+    let req = test::TestRequest::post()
+        .uri("/api/posts")
+        .insert_header((k, v))
+        .set_json(post_body(topic.id, admin.id, "Skipping the queue"))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status().as_u16(), 403);
+
+    let post_count: i64 = posts::table.count().get_result(&mut ctx.conn).unwrap();
+    let queued_count: i64 = queued_posts::table
+        .count()
+        .get_result(&mut ctx.conn)
+        .unwrap();
+    assert_eq!((post_count, queued_count), (0, 0));
+}
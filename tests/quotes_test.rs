@@ -0,0 +1,109 @@
+//! Service-layer tests for `services::quotes`, through the post
+//! create/update path.
+
+mod common;
+
+use diesel::prelude::*;
+use discourse_rs::models::{CreatePostInput, Post, UpdatePostInput};
+use discourse_rs::schema::{notifications, posts};
+use discourse_rs::services::posts as post_service;
+
+fn post_as(conn: &mut PgConnection, topic_id: i32, user_id: i32, number: i32, raw: &str) -> Post {
+    post_service::create(
+        conn,
+        CreatePostInput {
+            topic_id,
+            user_id,
+            post_number: number,
+            raw: raw.to_string(),
+            reply_to_post_number: None,
+        },
+    )
+    .expect("create failed")
+}
+
+fn quoted_notifications(
+    conn: &mut PgConnection,
+    user_id: i32,
+) -> Vec<(Option<i32>, serde_json::Value)> {
+    notifications::table
+        .filter(notifications::user_id.eq(user_id))
+        .filter(notifications::notification_type.eq("quoted"))
+        .select((notifications::acting_user_id, notifications::data))
+        .load(conn)
+        .unwrap()
+}
+
+#[test]
+fn quoting_a_post_links_it_and_notifies_its_author() {
+    let mut ctx = common::setup();
+    let alice = common::create_user(&mut ctx.conn, Default::default());
+    let bob = common::create_user(&mut ctx.conn, Default::default());
+    let topic = common::create_topic(&mut ctx.conn, common::TopicOpts::for_user(alice.id));
+    let original = post_as(&mut ctx.conn, topic.id, alice.id, 1, "the original");
+
+    let raw = format!(
+        "[quote=\"{}, post:1, topic:{}\"]\nthe original\n[/quote]\n\nagreed",
+        alice.username, topic.id
+    );
+    let reply = post_as(&mut ctx.conn, topic.id, bob.id, 2, &raw);
+
+    assert!(reply.cooked.contains(&format!(
+        "<a href=\"/t/{}/1\">{}</a>:",
+        topic.id, alice.username
+    )));
+    let notes = quoted_notifications(&mut ctx.conn, alice.id);
+    assert_eq!(notes.len(), 1);
+    assert_eq!(notes[0].0, Some(bob.id));
+    assert_eq!(notes[0].1["quoted_post_id"], original.id);
+
+    // Saving the same quote again doesn't notify twice.
+    post_service::update(
+        &mut ctx.conn,
+        reply.id,
+        bob.id,
+        UpdatePostInput {
+            raw: Some(format!("{raw}\n\nedited")),
+        },
+    )
+    .unwrap();
+    assert_eq!(quoted_notifications(&mut ctx.conn, alice.id).len(), 1);
+}
+
+#[test]
+fn quotes_of_hidden_or_missing_posts_are_not_linked() {
+    let mut ctx = common::setup();
+    let alice = common::create_user(&mut ctx.conn, Default::default());
+    let bob = common::create_user(&mut ctx.conn, Default::default());
+    let topic = common::create_topic(&mut ctx.conn, common::TopicOpts::for_user(alice.id));
+    let original = post_as(&mut ctx.conn, topic.id, alice.id, 1, "secret");
+    diesel::update(posts::table.find(original.id))
+        .set(posts::hidden.eq(true))
+        .execute(&mut ctx.conn)
+        .unwrap();
+
+    let raw = format!(
+        "[quote=\"alice, post:1, topic:{t}\"]\nsecret\n[/quote]\n\n[quote=\"ghost, post:9, topic:{t}\"]\nboo\n[/quote]",
+        t = topic.id
+    );
+    let reply = post_as(&mut ctx.conn, topic.id, bob.id, 2, &raw);
+
+    assert!(reply.cooked.contains("<div class=\"title\">alice:</div>"));
+    assert!(!reply.cooked.contains("/t/"));
+    assert!(quoted_notifications(&mut ctx.conn, alice.id).is_empty());
+}
+
+#[test]
+fn quoting_yourself_notifies_no_one() {
+    let mut ctx = common::setup();
+    let alice = common::create_user(&mut ctx.conn, Default::default());
+    let topic = common::create_topic(&mut ctx.conn, common::TopicOpts::for_user(alice.id));
+    post_as(&mut ctx.conn, topic.id, alice.id, 1, "first");
+
+    let raw = format!(
+        "[quote=\"me, post:1, topic:{}\"]\nfirst\n[/quote]",
+        topic.id
+    );
+    post_as(&mut ctx.conn, topic.id, alice.id, 2, &raw);
+    assert!(quoted_notifications(&mut ctx.conn, alice.id).is_empty());
+}
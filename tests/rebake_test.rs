@@ -0,0 +1,74 @@
+//! Rebaking: `RebakePostsJob` walks posts whose `cook_version` is behind the
+//! current pipeline, re-cooks them and chains the next batch.
+
+mod common;
+
+use diesel::prelude::*;
+use discourse_rs::jobs::{Job, RebakePostsJob};
+use discourse_rs::markdown::COOK_VERSION;
+use discourse_rs::models::CreatePostInput;
+use discourse_rs::schema::{backie_tasks, posts};
+use discourse_rs::services::posts as post_service;
+
+fn pending_rebakes(conn: &mut PgConnection) -> Vec<serde_json::Value> {
+    backie_tasks::table
+        .filter(backie_tasks::task_name.eq(RebakePostsJob::NAME))
+        .select(backie_tasks::payload)
+        .load(conn)
+        .unwrap()
+}
+
+#[actix_web::test]
+async fn stale_posts_are_recooked_and_the_next_batch_is_queued() {
+    let mut ctx = common::setup();
+    let user = common::create_user(&mut ctx.conn, Default::default());
+    let topic = common::create_topic(&mut ctx.conn, common::TopicOpts::for_user(user.id));
+    // Fixture posts are inserted with `cooked` = raw and version 0, as if
+    // an old pipeline had produced them.
+    let stale = common::create_post(
+        &mut ctx.conn,
+        common::PostOpts {
+            raw: "**bold**".to_string(),
+            ..common::PostOpts::for_topic(topic.id, user.id)
+        },
+    );
+    let current = post_service::create(
+        &mut ctx.conn,
+        CreatePostInput {
+            topic_id: topic.id,
+            user_id: user.id,
+            post_number: 2,
+            raw: "already *current*".to_string(),
+            reply_to_post_number: None,
+        },
+    )
+    .unwrap();
+    assert_eq!(current.cook_version, COOK_VERSION);
+
+    RebakePostsJob { after_id: 0 }
+        .execute(&ctx.pool())
+        .await
+        .expect("rebake failed");
+
+    let (cooked, version): (String, i32) = posts::table
+        .find(stale.id)
+        .select((posts::cooked, posts::cook_version))
+        .first(&mut ctx.conn)
+        .unwrap();
+    assert_eq!(cooked, "<p><strong>bold</strong></p>\n");
+    assert_eq!(version, COOK_VERSION);
+    assert_eq!(
+        pending_rebakes(&mut ctx.conn),
+        vec![serde_json::json!({ "after_id": stale.id })]
+    );
+
+    // The chained batch finds nothing stale and stops the chain.
+    diesel::delete(backie_tasks::table)
+        .execute(&mut ctx.conn)
+        .unwrap();
+    RebakePostsJob { after_id: stale.id }
+        .execute(&ctx.pool())
+        .await
+        .unwrap();
+    assert!(pending_rebakes(&mut ctx.conn).is_empty());
+}
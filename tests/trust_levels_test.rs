@@ -82,8 +82,10 @@ fn evaluate_is_idempotent_after_promotion() {
 fn already_tl2_user_is_not_demoted_by_low_post_count() {
     // Simulate a manually-promoted user with insufficient posts.
     let mut ctx = common::setup();
-    let mut opts = common::UserOpts::default();
-    opts.trust_level = 2;
+    let opts = common::UserOpts {
+        trust_level: 2,
+        ..Default::default()
+    };
     let user = common::create_user(&mut ctx.conn, opts);
 
     let outcome = evaluate(&mut ctx.conn, user.id).unwrap();
@@ -0,0 +1,211 @@
+//! Route-level tests for two-factor login and enrollment, and for the
+//! setting that makes staff enroll. Service rules are covered in
+//! `two_factor_test.rs`.
+
+mod common;
+
+use actix_web::test;
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+use discourse_rs::auth::hash_password;
+use discourse_rs::models::User;
+use discourse_rs::schema::users;
+use discourse_rs::services::two_factor;
+use serde_json::json;
+
+fn with_password(conn: &mut PgConnection, user: &User) {
+    diesel::update(users::table.find(user.id))
+        .set(users::password_hash.eq(hash_password("password123").unwrap()))
+        .execute(conn)
+        .unwrap();
+}
+
+fn login_request(user: &User) -> test::TestRequest {
+    test::TestRequest::post()
+        .uri("/api/auth/login")
+        .set_json(json!({ "username": user.username, "password": "password123" }))
+}
+
+fn second_step(pending_token: &str, code: &str) -> test::TestRequest {
+    test::TestRequest::post()
+        .uri("/api/auth/login/2fa")
+        .set_json(json!({ "pending_token": pending_token, "code": code }))
+}
+
+#[actix_web::test]
+async fn enrolled_users_log_in_in_two_steps() {
+    let mut ctx = common::setup();
+    let user = common::create_user(&mut ctx.conn, Default::default());
+    with_password(&mut ctx.conn, &user);
+    let app = test::init_service(common::test_app_factory()).await;
+    let (hk, hv) = common::auth_header_for(&user);
+
+    // Enroll and enable through the API.
+    let req = test::TestRequest::post()
+        .uri("/api/users/me/2fa/totp")
+        .insert_header((hk, hv.clone()))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status().as_u16(), 200);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    let secret = body["secret"].as_str().unwrap().to_string();
+    assert!(
+        body["otpauth_uri"]
+            .as_str()
+            .unwrap()
+            .starts_with("otpauth://totp/")
+    );
+
+    let code = two_factor::code_at(&secret, Utc::now()).unwrap();
+    let req = test::TestRequest::post()
+        .uri("/api/users/me/2fa/totp/enable")
+        .insert_header((hk, hv.clone()))
+        .set_json(json!({ "code": code }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status().as_u16(), 200);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    let backup_code = body["backup_codes"][0].as_str().unwrap().to_string();
+
+    // The password alone no longer yields a session.
+    let resp = test::call_service(&app, login_request(&user).to_request()).await;
+    assert_eq!(resp.status().as_u16(), 200);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["two_factor_required"], true);
+    assert!(body.get("token").is_none());
+    let pending = body["pending_token"].as_str().unwrap().to_string();
+
+    // The pending token isn't a session token.
+    let req = test::TestRequest::get()
+        .uri("/api/users/me/2fa")
+        .insert_header(("Authorization", format!("Bearer {pending}")))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status().as_u16(), 401);
+
+    let resp = test::call_service(&app, second_step(&pending, "000000").to_request()).await;
+    assert_eq!(resp.status().as_u16(), 401);
+
+    let next = two_factor::code_at(&secret, Utc::now() + Duration::seconds(30)).unwrap();
+    let resp = test::call_service(&app, second_step(&pending, &next).to_request()).await;
+    assert_eq!(resp.status().as_u16(), 200);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert!(body["token"].is_string());
+
+    // Backup codes work once.
+    let resp = test::call_service(&app, second_step(&pending, &backup_code).to_request()).await;
+    assert_eq!(resp.status().as_u16(), 200);
+    let resp = test::call_service(&app, second_step(&pending, &backup_code).to_request()).await;
+    assert_eq!(resp.status().as_u16(), 401);
+
+    let req = test::TestRequest::get()
+        .uri("/api/users/me/2fa")
+        .insert_header((hk, hv))
+        .to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["enabled"], true);
+    assert_eq!(
+        body["backup_codes_remaining"],
+        two_factor::BACKUP_CODE_COUNT - 1
+    );
+}
+
+#[actix_web::test]
+async fn tampered_pending_tokens_are_rejected() {
+    let _ctx = common::setup();
+    let app = test::init_service(common::test_app_factory()).await;
+
+    let resp = test::call_service(&app, second_step("not-a-token", "123456").to_request()).await;
+    assert_eq!(resp.status().as_u16(), 401);
+}
+
+#[actix_web::test]
+async fn staff_must_enroll_when_the_setting_is_on() {
+    let mut ctx = common::setup();
+    let admin = common::create_user(
+        &mut ctx.conn,
+        common::UserOpts {
+            admin: true,
+            ..Default::default()
+        },
+    );
+    let app = test::init_service(common::test_app_factory()).await;
+    let (hk, hv) = common::auth_header_for(&admin);
+    let admin_route = || {
+        test::TestRequest::get()
+            .uri("/api/admin/email_logs")
+            .insert_header((hk, hv.clone()))
+            .to_request()
+    };
+
+    assert_eq!(
+        test::call_service(&app, admin_route())
+            .await
+            .status()
+            .as_u16(),
+        200
+    );
+
+    common::set_setting(&mut ctx.conn, "enforce_2fa_for_staff", "true");
+    let resp = test::call_service(&app, admin_route()).await;
+    assert_eq!(resp.status().as_u16(), 403);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["code"], "two_factor_required");
+
+    // Enrolling is still allowed, and lifts the block.
+    let enrollment = two_factor::begin_enrollment(&mut ctx.conn, admin.id).unwrap();
+    let code = two_factor::code_at(&enrollment.secret, Utc::now()).unwrap();
+    two_factor::enable(&mut ctx.conn, admin.id, &code).unwrap();
+    assert_eq!(
+        test::call_service(&app, admin_route())
+            .await
+            .status()
+            .as_u16(),
+        200
+    );
+}
+
+#[actix_web::test]
+async fn repeated_wrong_codes_lock_the_second_step() {
+    let mut ctx = common::setup();
+    let user = common::create_user(&mut ctx.conn, Default::default());
+    with_password(&mut ctx.conn, &user);
+    let secret = two_factor::begin_enrollment(&mut ctx.conn, user.id)
+        .unwrap()
+        .secret;
+    let code = two_factor::code_at(&secret, Utc::now()).unwrap();
+    two_factor::enable(&mut ctx.conn, user.id, &code).unwrap();
+    let app = test::init_service(common::test_app_factory()).await;
+
+    macro_rules! pending {
+        () => {{
+            let resp = test::call_service(&app, login_request(&user).to_request()).await;
+            let body: serde_json::Value = test::read_body_json(resp).await;
+            body["pending_token"].as_str().unwrap().to_string()
+        }};
+    }
+
+    // Spread over fresh pending tokens, as a guesser would.
+    for _ in 0..two_factor::MAX_FAILED_ATTEMPTS {
+        let resp = test::call_service(&app, second_step(&pending!(), "000000").to_request()).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    let next = two_factor::code_at(&secret, Utc::now() + Duration::seconds(30)).unwrap();
+    let resp = test::call_service(&app, second_step(&pending!(), &next).to_request()).await;
+    assert_eq!(resp.status().as_u16(), 429);
+    assert!(resp.headers().contains_key("retry-after"));
+
+    // Once the lockout ends, the right code works and the count starts over.
+    diesel::update(users::table.find(user.id))
+        .set(users::totp_locked_until.eq(Utc::now() - Duration::seconds(1)))
+        .execute(&mut ctx.conn)
+        .unwrap();
+    let resp = test::call_service(&app, second_step(&pending!(), &next).to_request()).await;
+    assert_eq!(resp.status().as_u16(), 200);
+    let failed: i32 = users::table
+        .find(user.id)
+        .select(users::totp_failed_attempts)
+        .first(&mut ctx.conn)
+        .unwrap();
+    assert_eq!(failed, 0);
+}
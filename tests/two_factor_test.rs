@@ -0,0 +1,147 @@
+//! Tests for the two-factor service: enrollment, TOTP verification with
+//! replay protection, and single-use backup codes. The TOTP algorithm
+//! itself is unit-tested against the RFC vectors in the module.
+
+mod common;
+
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+use discourse_rs::auth::hash_password;
+use discourse_rs::models::User;
+use discourse_rs::schema::{user_backup_codes, users};
+use discourse_rs::services::two_factor::{self, BACKUP_CODE_COUNT, TwoFactorError};
+
+fn reload(conn: &mut PgConnection, user: &User) -> User {
+    users::table
+        .find(user.id)
+        .select(User::as_select())
+        .first(conn)
+        .unwrap()
+}
+
+/// Enroll and enable 2FA, returning the secret and backup codes.
+fn enable_2fa(conn: &mut PgConnection, user: &User) -> (String, Vec<String>) {
+    let enrollment = two_factor::begin_enrollment(conn, user.id).unwrap();
+    let code = two_factor::code_at(&enrollment.secret, Utc::now()).unwrap();
+    let backup_codes = two_factor::enable(conn, user.id, &code).unwrap();
+    (enrollment.secret, backup_codes)
+}
+
+#[test]
+fn enrollment_only_takes_effect_once_confirmed() {
+    let mut ctx = common::setup();
+    let user = common::create_user(&mut ctx.conn, Default::default());
+
+    let enrollment = two_factor::begin_enrollment(&mut ctx.conn, user.id).unwrap();
+    assert!(enrollment.otpauth_uri.contains(&enrollment.secret));
+    assert!(enrollment.otpauth_uri.contains(&user.username));
+    let pending = reload(&mut ctx.conn, &user);
+    assert_eq!(
+        pending.totp_secret.as_deref(),
+        Some(enrollment.secret.as_str())
+    );
+    assert!(!pending.totp_enabled);
+
+    assert!(matches!(
+        two_factor::enable(&mut ctx.conn, user.id, "000000"),
+        Err(TwoFactorError::InvalidCode)
+    ));
+    assert!(!reload(&mut ctx.conn, &user).totp_enabled);
+
+    let code = two_factor::code_at(&enrollment.secret, Utc::now()).unwrap();
+    let backup_codes = two_factor::enable(&mut ctx.conn, user.id, &code).unwrap();
+    assert_eq!(backup_codes.len(), BACKUP_CODE_COUNT);
+    assert!(reload(&mut ctx.conn, &user).totp_enabled);
+
+    assert!(matches!(
+        two_factor::begin_enrollment(&mut ctx.conn, user.id),
+        Err(TwoFactorError::AlreadyEnabled)
+    ));
+}
+
+#[test]
+fn enabling_needs_an_enrollment() {
+    let mut ctx = common::setup();
+    let user = common::create_user(&mut ctx.conn, Default::default());
+    assert!(matches!(
+        two_factor::enable(&mut ctx.conn, user.id, "123456"),
+        Err(TwoFactorError::NotEnrolled)
+    ));
+}
+
+#[test]
+fn totp_codes_cannot_be_replayed() {
+    let mut ctx = common::setup();
+    let user = common::create_user(&mut ctx.conn, Default::default());
+    let (secret, _) = enable_2fa(&mut ctx.conn, &user);
+
+    // The enabling code's step is already used.
+    let now = two_factor::code_at(&secret, Utc::now()).unwrap();
+    assert!(!two_factor::verify(&mut ctx.conn, user.id, &now).unwrap());
+
+    let next = two_factor::code_at(&secret, Utc::now() + Duration::seconds(30)).unwrap();
+    assert!(two_factor::verify(&mut ctx.conn, user.id, &next).unwrap());
+    assert!(!two_factor::verify(&mut ctx.conn, user.id, &next).unwrap());
+}
+
+#[test]
+fn backup_codes_are_hashed_and_single_use() {
+    let mut ctx = common::setup();
+    let user = common::create_user(&mut ctx.conn, Default::default());
+    let (_, backup_codes) = enable_2fa(&mut ctx.conn, &user);
+
+    let stored: Vec<String> = user_backup_codes::table
+        .filter(user_backup_codes::user_id.eq(user.id))
+        .select(user_backup_codes::code_hash)
+        .load(&mut ctx.conn)
+        .unwrap();
+    assert_eq!(stored.len(), BACKUP_CODE_COUNT);
+    assert!(stored.iter().all(|hash| !backup_codes.contains(hash)));
+
+    assert!(two_factor::verify(&mut ctx.conn, user.id, &backup_codes[0]).unwrap());
+    assert!(!two_factor::verify(&mut ctx.conn, user.id, &backup_codes[0]).unwrap());
+    assert_eq!(
+        two_factor::backup_codes_remaining(&mut ctx.conn, user.id).unwrap(),
+        BACKUP_CODE_COUNT as i64 - 1
+    );
+    assert!(!two_factor::verify(&mut ctx.conn, user.id, "not-a-backup-code").unwrap());
+}
+
+#[test]
+fn regenerating_backup_codes_retires_the_old_ones() {
+    let mut ctx = common::setup();
+    let user = common::create_user(&mut ctx.conn, Default::default());
+    let (secret, old_codes) = enable_2fa(&mut ctx.conn, &user);
+
+    assert!(matches!(
+        two_factor::regenerate_backup_codes(&mut ctx.conn, user.id, "000000"),
+        Err(TwoFactorError::InvalidCode)
+    ));
+    let code = two_factor::code_at(&secret, Utc::now() + Duration::seconds(30)).unwrap();
+    let new_codes = two_factor::regenerate_backup_codes(&mut ctx.conn, user.id, &code).unwrap();
+
+    assert!(!two_factor::verify(&mut ctx.conn, user.id, &old_codes[0]).unwrap());
+    assert!(two_factor::verify(&mut ctx.conn, user.id, &new_codes[0]).unwrap());
+}
+
+#[test]
+fn disabling_needs_the_password_and_clears_everything() {
+    let mut ctx = common::setup();
+    let user = common::create_user(&mut ctx.conn, Default::default());
+    diesel::update(users::table.find(user.id))
+        .set(users::password_hash.eq(hash_password("password123").unwrap()))
+        .execute(&mut ctx.conn)
+        .unwrap();
+    let (_, backup_codes) = enable_2fa(&mut ctx.conn, &user);
+
+    assert!(matches!(
+        two_factor::disable(&mut ctx.conn, user.id, "wrong"),
+        Err(TwoFactorError::WrongPassword)
+    ));
+    two_factor::disable(&mut ctx.conn, user.id, "password123").unwrap();
+
+    let user = reload(&mut ctx.conn, &user);
+    assert!(!user.totp_enabled);
+    assert!(user.totp_secret.is_none());
+    assert!(!two_factor::verify(&mut ctx.conn, user.id, &backup_codes[1]).unwrap());
+}
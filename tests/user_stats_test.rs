@@ -110,7 +110,7 @@ async fn post_topic_route_bumps_topic_count() {
     let app = test::init_service(common::test_app_factory()).await;
     let (hk, hv) = common::auth_header_for(&user);
     let body = serde_json::json!({
-        "title": "Hello",
+        "title": "Hello from the tests",
         "slug": "hello-world",
         "user_id": user.id,
         "category_id": null,
@@ -129,6 +129,38 @@ async fn post_topic_route_bumps_topic_count() {
     drop(ctx);
 }
 
+#[actix_web::test]
+async fn opening_a_topic_as_someone_else_is_forbidden() {
+    use actix_web::test;
+    use discourse_rs::schema::topics;
+
+    let mut ctx = common::setup();
+    let user = common::create_user(&mut ctx.conn, common::UserOpts::default());
+    let other = common::create_user(&mut ctx.conn, common::UserOpts::default());
+
+    let app = test::init_service(common::test_app_factory()).await;
+    let (hk, hv) = common::auth_header_for(&user);
+    let body = serde_json::json!({
+        "title": "Hello from someone else",
+        "slug": "hello-someone-else",
+        "user_id": other.id,
+        "category_id": null,
+    });
+    let req = test::TestRequest::post()
+        .uri("/api/topics")
+        .insert_header((hk, hv))
+        .set_json(&body)
+        .to_request();
+
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status().as_u16(), 403);
+
+    let topic_count: i64 = topics::table.count().get_result(&mut ctx.conn).unwrap();
+    assert_eq!(topic_count, 0);
+    assert_eq!(svc::get(&mut ctx.conn, other.id).unwrap().topic_count, 0);
+    drop(ctx);
+}
+
 #[actix_web::test]
 async fn post_post_route_bumps_post_count() {
     use actix_web::test;
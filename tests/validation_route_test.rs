@@ -0,0 +1,140 @@
+//! Request validation at the HTTP layer: invalid bodies get a 422 naming
+//! every failing field, and nothing is written. The rules themselves are
+//! unit-tested in `src/validation.rs`.
+
+mod common;
+
+use actix_web::test;
+use diesel::prelude::*;
+use discourse_rs::schema::{categories, users};
+use serde_json::json;
+
+#[actix_web::test]
+async fn registration_reports_every_invalid_field() {
+    let mut ctx = common::setup();
+    let app = test::init_service(common::test_app_factory()).await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/auth/register")
+        .set_json(json!({
+            "username": "",
+            "email": "not-an-email",
+            "password": "x",
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status().as_u16(), 422);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["error"], "Validation failed");
+    for field in ["username", "email", "password"] {
+        assert!(
+            body["fields"][field].as_array().is_some_and(|e| !e.is_empty()),
+            "expected errors for {field}: {body}"
+        );
+    }
+    assert_eq!(
+        body["fields"]["email"][0]["code"], "email",
+        "each error carries a stable code"
+    );
+
+    let count: i64 = users::table.count().get_result(&mut ctx.conn).unwrap();
+    assert_eq!(count, 0);
+}
+
+#[actix_web::test]
+async fn weak_passwords_and_bad_usernames_are_rejected() {
+    let _ctx = common::setup();
+    let app = test::init_service(common::test_app_factory()).await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/auth/register")
+        .set_json(json!({
+            "username": "has space",
+            "email": "ok@example.com",
+            "password": "onlyletters",
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status().as_u16(), 422);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["fields"]["username"][0]["code"], "username_charset");
+    assert_eq!(body["fields"]["password"][0]["code"], "password_too_simple");
+    assert!(body["fields"].get("email").is_none());
+}
+
+#[actix_web::test]
+async fn category_color_and_slug_are_checked() {
+    let mut ctx = common::setup();
+    let moderator = common::create_user(
+        &mut ctx.conn,
+        common::UserOpts {
+            moderator: true,
+            ..Default::default()
+        },
+    );
+    let app = test::init_service(common::test_app_factory()).await;
+    let (hk, hv) = common::auth_header_for(&moderator);
+
+    let req = test::TestRequest::post()
+        .uri("/api/categories")
+        .insert_header((hk, hv.clone()))
+        .set_json(json!({
+            "name": "General",
+            "slug": "General Stuff",
+            "color": "#0088CCFF",
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status().as_u16(), 422);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["fields"]["slug"][0]["code"], "slug");
+    assert_eq!(body["fields"]["color"][0]["code"], "color");
+    let count: i64 = categories::table.count().get_result(&mut ctx.conn).unwrap();
+    assert_eq!(count, 0);
+
+    let req = test::TestRequest::post()
+        .uri("/api/categories")
+        .insert_header((hk, hv))
+        .set_json(json!({ "name": "General", "slug": "general", "color": "0088CC" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status().as_u16(), 201);
+}
+
+#[actix_web::test]
+async fn short_titles_and_blank_posts_are_rejected() {
+    let mut ctx = common::setup();
+    let user = common::create_user(&mut ctx.conn, Default::default());
+    let topic = common::create_topic(&mut ctx.conn, common::TopicOpts::for_user(user.id));
+    let app = test::init_service(common::test_app_factory()).await;
+    let (hk, hv) = common::auth_header_for(&user);
+
+    let req = test::TestRequest::post()
+        .uri("/api/topics")
+        .insert_header((hk, hv.clone()))
+        .set_json(json!({
+            "title": "Too short",
+            "slug": "too-short",
+            "user_id": user.id,
+            "category_id": null,
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status().as_u16(), 422);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["fields"]["title"][0]["code"], "length");
+
+    let req = test::TestRequest::post()
+        .uri("/api/posts")
+        .insert_header((hk, hv))
+        .set_json(json!({
+            "topic_id": topic.id,
+            "user_id": user.id,
+            "post_number": 1,
+            "raw": "   ",
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status().as_u16(), 422);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["fields"]["raw"][0]["message"], "can't be blank");
+}
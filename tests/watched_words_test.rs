@@ -0,0 +1,418 @@
+//! Watched words: admin CRUD and the test endpoint, and what each action
+//! does to posts and topic titles.
+
+mod common;
+
+use actix_web::test;
+use diesel::prelude::*;
+use discourse_rs::models::queued_post::REASON_WATCHED_WORD;
+use discourse_rs::models::{CreatePostInput, UpdatePostInput, WatchedWordInput};
+use discourse_rs::schema::{post_flags, posts};
+use discourse_rs::services::posts as post_service;
+use discourse_rs::services::queued_posts::{self, Submitted};
+use discourse_rs::services::watched_words::{self, ContentError};
+use serde_json::{Value, json};
+
+fn watch(
+    conn: &mut PgConnection,
+    word: &str,
+    action: &str,
+    replacement: Option<&str>,
+    regex: bool,
+) {
+    let input = WatchedWordInput {
+        word: word.to_string(),
+        action: action.to_string(),
+        replacement: replacement.map(str::to_string),
+        regex,
+    };
+    input.check().unwrap();
+    watched_words::create(conn, input.into_new_watched_word()).unwrap();
+}
+
+fn create_post(
+    conn: &mut PgConnection,
+    topic_id: i32,
+    user_id: i32,
+    post_number: i32,
+    raw: &str,
+) -> Result<discourse_rs::models::Post, ContentError> {
+    post_service::create(
+        conn,
+        CreatePostInput {
+            topic_id,
+            user_id,
+            post_number,
+            raw: raw.to_string(),
+            reply_to_post_number: None,
+        },
+    )
+}
+
+#[actix_web::test]
+async fn watched_words_act_on_posts() {
+    let mut ctx = common::setup();
+    let user = common::create_user(&mut ctx.conn, Default::default());
+    let topic = common::create_topic(&mut ctx.conn, common::TopicOpts::for_user(user.id));
+    watch(&mut ctx.conn, "spam", "block", None, false);
+    watch(&mut ctx.conn, "darn", "censor", None, false);
+    watch(&mut ctx.conn, "heck", "censor", None, false);
+    watch(&mut ctx.conn, "colour", "replace", Some("color"), false);
+    watch(
+        &mut ctx.conn,
+        r"\d{3}-\d{4}",
+        "require_approval",
+        None,
+        true,
+    );
+    watch(&mut ctx.conn, "crypto", "flag", None, false);
+    watch(&mut ctx.conn, "rust(acean)?s?", "tag", Some("rust"), true);
+
+    match create_post(&mut ctx.conn, topic.id, user.id, 1, "Buy SPAM now") {
+        Err(ContentError::Blocked(words)) => assert_eq!(words, vec!["SPAM"]),
+        other => panic!("expected a block, got {other:?}"),
+    }
+    assert_eq!(
+        posts::table
+            .count()
+            .get_result::<i64>(&mut ctx.conn)
+            .unwrap(),
+        0
+    );
+
+    let post = create_post(
+        &mut ctx.conn,
+        topic.id,
+        user.id,
+        1,
+        "Darn it, what the heck. Heckle. Any colour, fellow Rustaceans?",
+    )
+    .unwrap();
+    assert_eq!(
+        post.cooked,
+        "<p><span class=\"censored\">■■■■</span> it, what the <span class=\"censored\">■■■■</span>. \
+         Heckle. Any color, fellow Rustaceans?</p>\n"
+    );
+    assert!(!post.hidden);
+    assert_eq!(
+        watched_words::topic_tags(&mut ctx.conn, topic.id).unwrap(),
+        vec!["rust"]
+    );
+
+    let post = create_post(
+        &mut ctx.conn,
+        topic.id,
+        user.id,
+        2,
+        "Call 555-1234 about crypto",
+    )
+    .unwrap();
+    let hidden: bool = posts::table
+        .find(post.id)
+        .select(posts::hidden)
+        .first(&mut ctx.conn)
+        .unwrap();
+    assert!(hidden, "held for approval");
+    let flags: Vec<(String, Option<i32>, Option<String>)> = post_flags::table
+        .filter(post_flags::post_id.eq(post.id))
+        .order(post_flags::id)
+        .select((post_flags::reason, post_flags::user_id, post_flags::message))
+        .load(&mut ctx.conn)
+        .unwrap();
+    assert_eq!(
+        flags,
+        vec![
+            (
+                "watched_word_approval".to_string(),
+                None,
+                Some("Watched words: 555-1234".to_string())
+            ),
+            (
+                "watched_word".to_string(),
+                None,
+                Some("Watched words: crypto".to_string())
+            ),
+        ]
+    );
+
+    let edit = post_service::update(
+        &mut ctx.conn,
+        post.id,
+        user.id,
+        UpdatePostInput {
+            raw: Some("now with spam".to_string()),
+        },
+    );
+    assert!(matches!(edit, Err(ContentError::Blocked(_))));
+}
+
+#[actix_web::test]
+async fn topic_titles_are_blocked_replaced_and_tagged() {
+    let mut ctx = common::setup();
+    let user = common::create_user(&mut ctx.conn, Default::default());
+    watch(&mut ctx.conn, "spam", "block", None, false);
+    watch(&mut ctx.conn, "colour", "replace", Some("color"), false);
+    watch(&mut ctx.conn, "rust", "tag", Some("rust"), false);
+    let app = test::init_service(common::test_app_factory()).await;
+    let (hk, hv) = common::auth_header_for(&user);
+
+    let create = |title: &str, slug: &str| {
+        test::TestRequest::post()
+            .uri("/api/topics")
+            .insert_header((hk, hv.clone()))
+            .set_json(
+                json!({ "title": title, "slug": slug, "user_id": user.id, "category_id": null }),
+            )
+            .to_request()
+    };
+    let resp = test::call_service(&app, create("Cheap spam for sale", "cheap")).await;
+    assert_eq!(resp.status().as_u16(), 422);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["error"], "Contains words that aren't allowed: spam");
+
+    let resp = test::call_service(&app, create("Colour schemes in Rust", "colour-schemes")).await;
+    assert_eq!(resp.status().as_u16(), 201);
+    let topic: Value = test::read_body_json(resp).await;
+    assert_eq!(topic["title"], "color schemes in Rust");
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/topics/{}/tags", topic["id"]))
+        .to_request();
+    let tags: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(tags, json!(["rust"]));
+
+    let req = test::TestRequest::put()
+        .uri(&format!("/api/topics/{}", topic["id"]))
+        .insert_header((hk, hv.clone()))
+        .set_json(json!({ "title": "Now with SPAM" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status().as_u16(), 422);
+}
+
+#[actix_web::test]
+async fn topic_titles_are_censored_and_held_or_flagged_with_their_first_post() {
+    let mut ctx = common::setup();
+    let user = common::create_user(&mut ctx.conn, Default::default());
+    watch(&mut ctx.conn, "darn", "censor", None, false);
+    watch(&mut ctx.conn, "giveaway", "require_approval", None, false);
+    watch(&mut ctx.conn, "crypto", "flag", None, false);
+    let app = test::init_service(common::test_app_factory()).await;
+    let (hk, hv) = common::auth_header_for(&user);
+
+    let create = |title: &str, slug: &str| {
+        test::TestRequest::post()
+            .uri("/api/topics")
+            .insert_header((hk, hv.clone()))
+            .set_json(
+                json!({ "title": title, "slug": slug, "user_id": user.id, "category_id": null }),
+            )
+            .to_request()
+    };
+    let held: Value =
+        test::call_and_read_body_json(&app, create("Darn good giveaway", "giveaway")).await;
+    assert_eq!(held["title"], "■■■■ good giveaway");
+    let first_post = CreatePostInput {
+        topic_id: held["id"].as_i64().unwrap() as i32,
+        user_id: user.id,
+        post_number: 1,
+        raw: "Enter below".to_string(),
+        reply_to_post_number: None,
+    };
+    match queued_posts::submit(&mut ctx.conn, first_post).unwrap() {
+        Submitted::Queued(queued) => assert_eq!(queued.reason, REASON_WATCHED_WORD),
+        Submitted::Created(_) => panic!("the first post should wait for approval"),
+    }
+
+    let flagged: Value =
+        test::call_and_read_body_json(&app, create("Crypto tips for beginners", "crypto")).await;
+    let topic_id = flagged["id"].as_i64().unwrap() as i32;
+    let post = create_post(&mut ctx.conn, topic_id, user.id, 1, "Buy low").unwrap();
+    let flags = |conn: &mut PgConnection| -> Vec<(String, Option<String>)> {
+        post_flags::table
+            .filter(post_flags::post_id.eq(post.id))
+            .order(post_flags::id)
+            .select((post_flags::reason, post_flags::message))
+            .load(conn)
+            .unwrap()
+    };
+    assert_eq!(
+        flags(&mut ctx.conn),
+        vec![(
+            "watched_word".to_string(),
+            Some("Watched words: Crypto".to_string())
+        )]
+    );
+
+    // A title edit acts on the first post the way an edit to the post would.
+    let req = test::TestRequest::put()
+        .uri(&format!("/api/topics/{topic_id}"))
+        .insert_header((hk, hv.clone()))
+        .set_json(json!({ "title": "Crypto giveaway for beginners" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status().as_u16(), 200);
+    let hidden: bool = posts::table
+        .find(post.id)
+        .select(posts::hidden)
+        .first(&mut ctx.conn)
+        .unwrap();
+    assert!(hidden);
+    assert_eq!(flags(&mut ctx.conn).len(), 3);
+    assert_eq!(flags(&mut ctx.conn)[1].0, "watched_word_approval");
+}
+
+#[actix_web::test]
+async fn admins_manage_watched_words() {
+    let mut ctx = common::setup();
+    let admin = common::create_user(
+        &mut ctx.conn,
+        common::UserOpts {
+            admin: true,
+            ..Default::default()
+        },
+    );
+    let member = common::create_user(&mut ctx.conn, Default::default());
+    let app = test::init_service(common::test_app_factory()).await;
+    let (hk, hv) = common::auth_header_for(&admin);
+
+    let create = |user, body: Value| {
+        let (hk, hv) = common::auth_header_for(user);
+        test::TestRequest::post()
+            .uri("/api/admin/watched_words")
+            .insert_header((hk, hv))
+            .set_json(body)
+            .to_request()
+    };
+
+    let darn = json!({ "word": "Darn", "action": "censor" });
+    let resp = test::call_service(&app, create(&member, darn.clone())).await;
+    assert_eq!(resp.status().as_u16(), 403);
+    let resp = test::call_service(&app, create(&admin, darn.clone())).await;
+    assert_eq!(resp.status().as_u16(), 201);
+    let word: Value = test::read_body_json(resp).await;
+    assert_eq!(word["word"], "darn");
+    let resp = test::call_service(&app, create(&admin, darn)).await;
+    assert_eq!(resp.status().as_u16(), 409, "already watched for censor");
+    let resp = test::call_service(
+        &app,
+        create(&admin, json!({ "word": "darn", "action": "flag" })),
+    )
+    .await;
+    assert_eq!(resp.status().as_u16(), 201, "same word, another action");
+
+    for body in [
+        json!({ "word": "x", "action": "explode" }),
+        json!({ "word": " ", "action": "block" }),
+        json!({ "word": "colour", "action": "replace" }),
+        json!({ "word": "rust", "action": "tag", "replacement": "  " }),
+        json!({ "word": "(unclosed", "action": "block", "regex": true }),
+    ] {
+        let resp = test::call_service(&app, create(&admin, body.clone())).await;
+        assert_eq!(resp.status().as_u16(), 422, "{body}");
+    }
+
+    let req = test::TestRequest::put()
+        .uri(&format!("/api/admin/watched_words/{}", word["id"]))
+        .insert_header((hk, hv.clone()))
+        .set_json(
+            json!({ "word": "colou?r", "action": "tag", "replacement": "colors", "regex": true }),
+        )
+        .to_request();
+    let updated: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(updated["action"], "tag");
+    assert_eq!(updated["replacement"], "colors");
+
+    let req = test::TestRequest::post()
+        .uri("/api/admin/watched_words/test")
+        .insert_header((hk, hv.clone()))
+        .set_json(json!({ "text": "Darn, what COLOUR is it? darn color" }))
+        .to_request();
+    let matches: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(
+        matches,
+        json!({
+            "block": [],
+            "censor": [],
+            "replace": [],
+            "require_approval": [],
+            "flag": ["Darn"],
+            "tags": ["colors"],
+        })
+    );
+
+    let req = test::TestRequest::get()
+        .uri("/api/admin/watched_words")
+        .insert_header((hk, hv.clone()))
+        .to_request();
+    let list: Value = test::call_and_read_body_json(&app, req).await;
+    let actions: Vec<&str> = list
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|w| w["action"].as_str().unwrap())
+        .collect();
+    assert_eq!(actions, vec!["flag", "tag"]);
+
+    let delete = || {
+        test::TestRequest::delete()
+            .uri(&format!("/api/admin/watched_words/{}", word["id"]))
+            .insert_header((hk, hv.clone()))
+            .to_request()
+    };
+    assert_eq!(
+        test::call_service(&app, delete()).await.status().as_u16(),
+        204
+    );
+    assert_eq!(
+        test::call_service(&app, delete()).await.status().as_u16(),
+        404
+    );
+}
+
+#[actix_web::test]
+async fn staff_posts_and_edits_skip_require_approval_words() {
+    let mut ctx = common::setup();
+    let moderator = common::create_user(
+        &mut ctx.conn,
+        common::UserOpts {
+            moderator: true,
+            ..Default::default()
+        },
+    );
+    let user = common::create_user(&mut ctx.conn, Default::default());
+    let topic = common::create_topic(&mut ctx.conn, common::TopicOpts::for_user(moderator.id));
+    watch(&mut ctx.conn, "giveaway", "require_approval", None, false);
+    let edit = |conn: &mut PgConnection, post_id: i32, editor_id: i32| {
+        post_service::update(
+            conn,
+            post_id,
+            editor_id,
+            UpdatePostInput {
+                raw: Some("Updated giveaway rules".to_string()),
+            },
+        )
+        .unwrap();
+    };
+    let hidden = |conn: &mut PgConnection, post_id: i32| -> bool {
+        posts::table
+            .find(post_id)
+            .select(posts::hidden)
+            .first(conn)
+            .unwrap()
+    };
+    let flags =
+        |conn: &mut PgConnection| -> i64 { post_flags::table.count().get_result(conn).unwrap() };
+
+    // What counts is who edits, not who wrote the post.
+    let staff_post =
+        create_post(&mut ctx.conn, topic.id, moderator.id, 1, "Giveaway rules").unwrap();
+    let user_post = create_post(&mut ctx.conn, topic.id, user.id, 2, "Nice rules").unwrap();
+    edit(&mut ctx.conn, staff_post.id, moderator.id);
+    edit(&mut ctx.conn, user_post.id, moderator.id);
+    assert!(!hidden(&mut ctx.conn, staff_post.id));
+    assert!(!hidden(&mut ctx.conn, user_post.id));
+    assert_eq!(flags(&mut ctx.conn), 0);
+
+    edit(&mut ctx.conn, staff_post.id, user.id);
+    assert!(hidden(&mut ctx.conn, staff_post.id));
+    assert_eq!(flags(&mut ctx.conn), 1);
+}